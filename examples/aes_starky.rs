@@ -1,16 +1,16 @@
 use env_logger::Env;
 use log::info;
 
-use plonky2::field::types::Field;
 use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
 use unizk::config::RamConfig;
-use unizk::kernel::vector_operation::{VecOpConfig, VecOpSrc, VecOpType};
+use unizk::kernel::vec_builder::{VecBuilder, VecHandle};
+use unizk::kernel::vector_operation::VecOpConfig;
 use unizk::memory::memory_allocator::MemAlloc;
 use unizk::starky::constraint_consumer::ConstraintConsumer;
 use unizk::starky::prover::prove;
 use unizk::starky::stark::EvaluationFrame;
 use unizk::system::system::System;
-use unizk::util::{BATCH_SIZE, SIZE_F};
+use unizk::util::{set_config, BATCH_SIZE};
 use starky::aes128::constants::*;
 use starky::aes128::generation::AesTraceGenerator;
 use starky::aes128::layout::*;
@@ -28,716 +28,344 @@ fn eval_packed_generic(
     vars: &EvaluationFrame,
     yield_constr: &ConstraintConsumer,
 ) -> Vec<VecOpConfig> {
-    let mut res = Vec::new();
-    let curr_row = |idx: usize| vars.addr_local_values + idx * BATCH_SIZE * SIZE_F;
-    let next_row = |idx: usize| vars.addr_next_values + idx * BATCH_SIZE * SIZE_F;
     let addr_constraint = sys.mem.get_addr("constraint").unwrap();
-    let addr_xor_gen_tmp = sys.mem.alloc("xor_gen_tmp", BATCH_SIZE * SIZE_F).unwrap();
-    let addr_xor_gen = sys.mem.alloc("xor_gen", BATCH_SIZE * SIZE_F).unwrap();
-
-    let addr_a_j_b_i = sys.mem.alloc("a_j_b_i", BATCH_SIZE * SIZE_F).unwrap();
-    let addr_not_hi_bit_set = sys
-        .mem
-        .alloc("not_hi_bit_set", BATCH_SIZE * SIZE_F)
-        .unwrap();
-
-    let addr_is_gmul_substep = sys
-        .mem
-        .alloc("is_gmul_substep", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    let addr_is_sub = sys.mem.alloc("is_sub", BATCH_SIZE * SIZE_F).unwrap();
-    let addr_next_is_not_padding = sys
-        .mem
-        .alloc("next_is_not_padding", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    let addr_is_add_round_key = sys
-        .mem
-        .alloc("is_add_round_key", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    let addr_is_key_xor_rcon = sys
-        .mem
-        .alloc("is_key_xor_rcon", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    let addr_is_not_ket_xor_rcon = sys
-        .mem
-        .alloc("is_not_ket_xor_rcon", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    let addr_is_state_shift_rows = sys
-        .mem
-        .alloc("is_state_shift_rows", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    let addr_is_state_mix_columns = sys
-        .mem
-        .alloc("is_state_mix_columns", BATCH_SIZE * SIZE_F)
-        .unwrap();
-
-    let xor_gen = |vec_ops: &mut Vec<VecOpConfig>, addr_x: usize, addr_y: usize| {
-        vec_ops.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_x,
-                addr_input_1: addr_y,
-                addr_output: addr_xor_gen,
-                is_final_output: false,
-                op_type: VecOpType::ADD,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_y,
-                addr_input_1: 0,
-                addr_output: addr_xor_gen_tmp,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen_tmp,
-                addr_input_1: addr_x,
-                addr_output: addr_xor_gen_tmp,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: addr_xor_gen_tmp,
-                addr_output: addr_xor_gen,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
+    let mut b = VecBuilder::new(&mut sys.mem, BATCH_SIZE);
+    let constraint = b.vector(addr_constraint);
+    let (lv, nv) = (vars.addr_local_values, vars.addr_next_values);
+    let xor_gen_tmp = b.alloc("xor_gen_tmp");
+    let computed_bit = b.alloc("xor_gen");
+
+    let a_j_b_i = b.alloc("a_j_b_i");
+    let not_hi_bit_set = b.alloc("not_hi_bit_set");
+
+    let is_gmul_substep = b.alloc("is_gmul_substep");
+    let is_sub = b.alloc("is_sub");
+    let next_is_not_padding = b.alloc("next_is_not_padding");
+    let is_add_round_key = b.alloc("is_add_round_key");
+    let is_key_xor_rcon = b.alloc("is_key_xor_rcon");
+    let is_not_ket_xor_rcon = b.alloc("is_not_ket_xor_rcon");
+    let is_state_shift_rows = b.alloc("is_state_shift_rows");
+    let is_state_mix_columns = b.alloc("is_state_mix_columns");
+
+    let xor_gen = |b: &mut VecBuilder, x: VecHandle, y: VecHandle| {
+        b.add_into(computed_bit, x, y);
+        b.mul_into(xor_gen_tmp, y, b.constant());
+        b.mul_into(xor_gen_tmp, xor_gen_tmp, x);
+        b.sub_into(computed_bit, computed_bit, xor_gen_tmp);
     };
 
-    let check_gmul = |vec_ops: &mut Vec<VecOpConfig>,
-                      sub_step: usize,
-                      a_start: usize,
-                      b_start: usize,
-                      tmp_start: usize| {
-        assert!(sub_step < 7);
+    let check_gmul =
+        |b: &mut VecBuilder, sub_step: usize, a_start: usize, b_start: usize, tmp_start: usize| {
+            assert!(sub_step < 7);
 
-        // a * b_i
-        if sub_step == 0 {
-            let addr_b_i = curr_row(b_start);
+            // a * b_i
+            if sub_step == 0 {
+                let b_i = b.column(lv, b_start);
+                for j in 0..8 {
+                    let a_j = b.column(lv, a_start + j);
+                    // let a_j_b_i = a_j * b_i;
+
+                    b.mul_into(a_j_b_i, a_j, b_i);
+
+                    // yield_constr.constraint(
+                    //     is_gmul_substep * (curr_row[key_gmul_a_bi_bit(tmp_start, j)] - a_j_b_i),
+                    // );
+                    b.sub_into(
+                        constraint,
+                        b.column(lv, key_gmul_a_bi_bit(tmp_start, j)),
+                        a_j_b_i,
+                    );
+                    b.mul_into(constraint, constraint, is_gmul_substep);
+                    b.extend(yield_constr.constraint(addr_constraint));
+                }
+            }
+            let b_i = b.column(lv, b_start + sub_step + 1);
             for j in 0..8 {
-                let addr_a_j = curr_row(a_start + j);
+                let a_j = b.column(lv, key_gmul_a_tmp_bit(tmp_start, j));
                 // let a_j_b_i = a_j * b_i;
-
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_a_j,
-                    addr_input_1: addr_b_i,
-                    addr_output: addr_a_j_b_i,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-
-                // yield_constr.constraint(
-                //     is_gmul_substep * (curr_row[key_gmul_a_bi_bit(tmp_start, j)] - a_j_b_i),
+                b.mul_into(a_j_b_i, a_j, b_i);
+                // yield_constr.constraint_transition(
+                //     is_gmul_substep * (next_row[key_gmul_a_bi_bit(tmp_start, j)] - a_j_b_i),
                 // );
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: curr_row(key_gmul_a_bi_bit(tmp_start, j)),
-                    addr_input_1: addr_a_j_b_i,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_constraint,
-                    addr_input_1: addr_is_gmul_substep,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.extend(yield_constr.constraint(addr_constraint));
-            }
-        }
-        let addr_b_i = curr_row(b_start + sub_step + 1);
-        for j in 0..8 {
-            let addr_a_j = curr_row(key_gmul_a_tmp_bit(tmp_start, j));
-            // let a_j_b_i = a_j * b_i;
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_a_j,
-                addr_input_1: addr_b_i,
-                addr_output: addr_a_j_b_i,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            // yield_constr.constraint_transition(
-            //     is_gmul_substep * (next_row[key_gmul_a_bi_bit(tmp_start, j)] - a_j_b_i),
-            // );
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(key_gmul_a_bi_bit(tmp_start, j)),
-                addr_input_1: addr_a_j_b_i,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: addr_is_gmul_substep,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.extend(yield_constr.constraint_transition(addr_constraint));
-        }
-
-        // p ^= a
-        for j in 0..8 {
-            if sub_step == 0 {
-                let addr_a_j = curr_row(key_gmul_a_bi_bit(tmp_start, j));
-                // yield_constr
-                //     .constraint(is_gmul_substep * (a_j - curr_row[key_gmul_p_bit(tmp_start, j)]));
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_a_j,
-                    addr_input_1: curr_row(key_gmul_p_bit(tmp_start, j)),
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_constraint,
-                    addr_input_1: addr_is_gmul_substep,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.extend(yield_constr.constraint(addr_constraint));
+                b.sub_into(
+                    constraint,
+                    b.column(nv, key_gmul_a_bi_bit(tmp_start, j)),
+                    a_j_b_i,
+                );
+                b.mul_into(constraint, constraint, is_gmul_substep);
+                b.extend(yield_constr.constraint_transition(addr_constraint));
             }
 
-            let addr_a_j = next_row(key_gmul_a_bi_bit(tmp_start, j));
-            // let computed_bit = xor_gen(a_j, curr_row[key_gmul_p_bit(tmp_start, j)]);
-            xor_gen(vec_ops, addr_a_j, curr_row(key_gmul_p_bit(tmp_start, j)));
+            // p ^= a
+            for j in 0..8 {
+                if sub_step == 0 {
+                    let a_j = b.column(lv, key_gmul_a_bi_bit(tmp_start, j));
+                    // yield_constr
+                    //     .constraint(is_gmul_substep * (a_j - curr_row[key_gmul_p_bit(tmp_start, j)]));
+                    b.sub_into(constraint, a_j, b.column(lv, key_gmul_p_bit(tmp_start, j)));
+                    b.mul_into(constraint, constraint, is_gmul_substep);
+                    b.extend(yield_constr.constraint(addr_constraint));
+                }
 
-            // yield_constr.constraint_transition(
-            //     is_gmul_substep * (computed_bit - next_row[key_gmul_p_bit(tmp_start, j)]),
-            // );
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: next_row(key_gmul_p_bit(tmp_start, j)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: addr_is_gmul_substep,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.extend(yield_constr.constraint_transition(addr_constraint));
-        }
+                let a_j = b.column(nv, key_gmul_a_bi_bit(tmp_start, j));
+                // let computed_bit = xor_gen(a_j, curr_row[key_gmul_p_bit(tmp_start, j)]);
+                let (x, y) = (a_j, b.column(lv, key_gmul_p_bit(tmp_start, j)));
+                xor_gen(b, x, y);
 
-        // a shift xor 0x1b
-        if sub_step == 0 {
-            for j in 1..8 {
-                let addr_a_j = curr_row(a_start + j - 1);
+                // yield_constr.constraint_transition(
+                //     is_gmul_substep * (computed_bit - next_row[key_gmul_p_bit(tmp_start, j)]),
+                // );
+                b.sub_into(
+                    constraint,
+                    computed_bit,
+                    b.column(nv, key_gmul_p_bit(tmp_start, j)),
+                );
+                b.mul_into(constraint, constraint, is_gmul_substep);
+                b.extend(yield_constr.constraint_transition(addr_constraint));
+            }
 
-                xor_gen(vec_ops, addr_a_j, 0);
+            // a shift xor 0x1b
+            if sub_step == 0 {
+                for j in 1..8 {
+                    let a_j = b.column(lv, a_start + j - 1);
+
+                    let (x, y) = (a_j, b.constant());
+                    xor_gen(b, x, y);
+
+                    // yield_constr.constraint(
+                    //     is_gmul_substep
+                    //         * (computed_bit - curr_row[key_gmul_a_shift_xor_1b_bit(tmp_start, j)]),
+                    // );
+                    b.sub_into(
+                        constraint,
+                        computed_bit,
+                        b.column(lv, key_gmul_a_shift_xor_1b_bit(tmp_start, j)),
+                    );
+                    b.mul_into(constraint, constraint, is_gmul_substep);
+                    b.extend(yield_constr.constraint(addr_constraint));
+                }
 
                 // yield_constr.constraint(
                 //     is_gmul_substep
-                //         * (computed_bit - curr_row[key_gmul_a_shift_xor_1b_bit(tmp_start, j)]),
+                //         * (curr_row[key_gmul_a_shift_xor_1b_bit(tmp_start, 0)]
+                //             - FE::from_canonical_u32(1)),
                 // );
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_xor_gen,
-                    addr_input_1: curr_row(key_gmul_a_shift_xor_1b_bit(tmp_start, j)),
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_constraint,
-                    addr_input_1: addr_is_gmul_substep,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.extend(yield_constr.constraint(addr_constraint));
+                b.sub_into(
+                    constraint,
+                    b.column(lv, key_gmul_a_shift_xor_1b_bit(tmp_start, 0)),
+                    b.constant(),
+                );
+                b.mul_into(constraint, constraint, is_gmul_substep);
+                b.extend(yield_constr.constraint(addr_constraint));
+            }
+            for j in 1..8 {
+                // let a_j = curr_row[key_gmul_a_tmp_bit(tmp_start, j - 1)];
+                let a_j = b.column(lv, key_gmul_a_tmp_bit(tmp_start, j - 1));
+                // let c_bit = FE::from_canonical_u32((c >> j) & 1);
+                // let computed_bit = a_j + c_bit - a_j * c_bit.doubles();
+                let (x, y) = (a_j, b.constant());
+                xor_gen(b, x, y);
+                // yield_constr.constraint_transition(
+                //     is_gmul_substep
+                //         * (computed_bit - next_row[key_gmul_a_shift_xor_1b_bit(tmp_start, j)]),
+                // );
+                b.sub_into(
+                    constraint,
+                    computed_bit,
+                    b.column(nv, key_gmul_a_shift_xor_1b_bit(tmp_start, j)),
+                );
+                b.mul_into(constraint, constraint, is_gmul_substep);
+                b.extend(yield_constr.constraint_transition(addr_constraint));
             }
-
-            // yield_constr.constraint(
-            //     is_gmul_substep
-            //         * (curr_row[key_gmul_a_shift_xor_1b_bit(tmp_start, 0)]
-            //             - FE::from_canonical_u32(1)),
-            // );
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(key_gmul_a_shift_xor_1b_bit(tmp_start, 0)),
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VS,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: addr_is_gmul_substep,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.extend(yield_constr.constraint(addr_constraint));
-        }
-        for j in 1..8 {
-            // let a_j = curr_row[key_gmul_a_tmp_bit(tmp_start, j - 1)];
-            let addr_a_j = curr_row(key_gmul_a_tmp_bit(tmp_start, j - 1));
-            // let c_bit = FE::from_canonical_u32((c >> j) & 1);
-            // let computed_bit = a_j + c_bit - a_j * c_bit.doubles();
-            xor_gen(vec_ops, addr_a_j, 0);
             // yield_constr.constraint_transition(
             //     is_gmul_substep
-            //         * (computed_bit - next_row[key_gmul_a_shift_xor_1b_bit(tmp_start, j)]),
+            //         * (next_row[key_gmul_a_shift_xor_1b_bit(tmp_start, 0)] - FE::from_canonical_u32(1)),
             // );
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: next_row(key_gmul_a_shift_xor_1b_bit(tmp_start, j)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: addr_is_gmul_substep,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.extend(yield_constr.constraint_transition(addr_constraint));
-        }
-        // yield_constr.constraint_transition(
-        //     is_gmul_substep
-        //         * (next_row[key_gmul_a_shift_xor_1b_bit(tmp_start, 0)] - FE::from_canonical_u32(1)),
-        // );
-        vec_ops.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: next_row(key_gmul_a_shift_xor_1b_bit(tmp_start, 0)),
-            addr_input_1: 0,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VS,
-        });
-        vec_ops.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: addr_is_gmul_substep,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        });
-        vec_ops.extend(yield_constr.constraint_transition(addr_constraint));
-
-        // a xor
-        if sub_step == 0 {
-            // let hi_bit_set = curr_row[a_start + 7];
-            let addr_hi_bit_set = curr_row(a_start + 7);
-            for j in 0..8 {
+            b.sub_into(
+                constraint,
+                b.column(nv, key_gmul_a_shift_xor_1b_bit(tmp_start, 0)),
+                b.constant(),
+            );
+            b.mul_into(constraint, constraint, is_gmul_substep);
+            b.extend(yield_constr.constraint_transition(addr_constraint));
+
+            // a xor
+            if sub_step == 0 {
+                // let hi_bit_set = curr_row[a_start + 7];
+                let hi_bit_set = b.column(lv, a_start + 7);
+                for j in 0..8 {
+                    // yield_constr.constraint(
+                    //     is_gmul_substep
+                    //         * hi_bit_set
+                    //         * (curr_row[key_gmul_a_shift_xor_1b_bit(tmp_start, j)]
+                    //             - curr_row[key_gmul_a_tmp_bit(tmp_start, j)]),
+                    // );
+                    b.sub_into(
+                        constraint,
+                        b.column(lv, key_gmul_a_shift_xor_1b_bit(tmp_start, j)),
+                        b.column(lv, key_gmul_a_tmp_bit(tmp_start, j)),
+                    );
+                    b.mul_into(constraint, constraint, is_gmul_substep);
+                    b.mul_into(constraint, hi_bit_set, constraint);
+                    b.extend(yield_constr.constraint(addr_constraint));
+                }
+                for j in 1..8 {
+                    // yield_constr.constraint(
+                    //     is_gmul_substep
+                    //         * (P::ONES - hi_bit_set)
+                    //         * (curr_row[a_start + j - 1] - curr_row[key_gmul_a_tmp_bit(tmp_start, j)]),
+                    // );
+                    b.sub_into(
+                        constraint,
+                        b.column(lv, a_start + j - 1),
+                        b.column(lv, key_gmul_a_tmp_bit(tmp_start, j)),
+                    );
+                    b.sub_into(not_hi_bit_set, hi_bit_set, b.constant());
+                    b.mul_into(constraint, constraint, is_gmul_substep);
+                    b.mul_into(constraint, not_hi_bit_set, constraint);
+                    b.extend(yield_constr.constraint(addr_constraint));
+                }
                 // yield_constr.constraint(
                 //     is_gmul_substep
+                //         * (P::ONES - hi_bit_set)
+                //         * curr_row[key_gmul_a_tmp_bit(tmp_start, 0)],
+                // );
+                b.mul_into(constraint, is_gmul_substep, not_hi_bit_set);
+                b.mul_into(
+                    constraint,
+                    b.column(lv, key_gmul_a_tmp_bit(tmp_start, 0)),
+                    constraint,
+                );
+                b.extend(yield_constr.constraint(addr_constraint));
+            }
+            // let hi_bit_set = curr_row[key_gmul_a_tmp_bit(tmp_start, 7)];
+            let hi_bit_set = b.column(lv, key_gmul_a_tmp_bit(tmp_start, 7));
+            for j in 0..8 {
+                // yield_constr.constraint_transition(
+                //     is_gmul_substep
                 //         * hi_bit_set
-                //         * (curr_row[key_gmul_a_shift_xor_1b_bit(tmp_start, j)]
-                //             - curr_row[key_gmul_a_tmp_bit(tmp_start, j)]),
+                //         * (next_row[key_gmul_a_shift_xor_1b_bit(tmp_start, j)]
+                //             - next_row[key_gmul_a_tmp_bit(tmp_start, j)]),
                 // );
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: curr_row(key_gmul_a_shift_xor_1b_bit(tmp_start, j)),
-                    addr_input_1: curr_row(key_gmul_a_tmp_bit(tmp_start, j)),
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_constraint,
-                    addr_input_1: addr_is_gmul_substep,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_hi_bit_set,
-                    addr_input_1: addr_constraint,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.extend(yield_constr.constraint(addr_constraint));
+                b.sub_into(
+                    constraint,
+                    b.column(nv, key_gmul_a_shift_xor_1b_bit(tmp_start, j)),
+                    b.column(nv, key_gmul_a_tmp_bit(tmp_start, j)),
+                );
+                b.mul_into(constraint, constraint, is_gmul_substep);
+                b.mul_into(constraint, hi_bit_set, constraint);
+                b.extend(yield_constr.constraint_transition(addr_constraint));
             }
             for j in 1..8 {
-                // yield_constr.constraint(
+                // yield_constr.constraint_transition(
                 //     is_gmul_substep
                 //         * (P::ONES - hi_bit_set)
-                //         * (curr_row[a_start + j - 1] - curr_row[key_gmul_a_tmp_bit(tmp_start, j)]),
+                //         * (curr_row[key_gmul_a_tmp_bit(tmp_start, j - 1)]
+                //             - next_row[key_gmul_a_tmp_bit(tmp_start, j)]),
                 // );
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: curr_row(a_start + j - 1),
-                    addr_input_1: curr_row(key_gmul_a_tmp_bit(tmp_start, j)),
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_hi_bit_set,
-                    addr_input_1: 0,
-                    addr_output: addr_not_hi_bit_set,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VS,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_constraint,
-                    addr_input_1: addr_is_gmul_substep,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_not_hi_bit_set,
-                    addr_input_1: addr_constraint,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.extend(yield_constr.constraint(addr_constraint));
+                b.sub_into(
+                    constraint,
+                    b.column(lv, key_gmul_a_tmp_bit(tmp_start, j - 1)),
+                    b.column(nv, key_gmul_a_tmp_bit(tmp_start, j)),
+                );
+                b.sub_into(not_hi_bit_set, hi_bit_set, b.constant());
+                b.mul_into(constraint, constraint, is_gmul_substep);
+                b.mul_into(constraint, not_hi_bit_set, constraint);
+                b.extend(yield_constr.constraint_transition(addr_constraint));
             }
-            // yield_constr.constraint(
-            //     is_gmul_substep
-            //         * (P::ONES - hi_bit_set)
-            //         * curr_row[key_gmul_a_tmp_bit(tmp_start, 0)],
-            // );
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_gmul_substep,
-                addr_input_1: addr_not_hi_bit_set,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(key_gmul_a_tmp_bit(tmp_start, 0)),
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.extend(yield_constr.constraint(addr_constraint));
-        }
-        // let hi_bit_set = curr_row[key_gmul_a_tmp_bit(tmp_start, 7)];
-        let addr_hi_bit_set = curr_row(key_gmul_a_tmp_bit(tmp_start, 7));
-        for j in 0..8 {
             // yield_constr.constraint_transition(
-            //     is_gmul_substep
-            //         * hi_bit_set
-            //         * (next_row[key_gmul_a_shift_xor_1b_bit(tmp_start, j)]
-            //             - next_row[key_gmul_a_tmp_bit(tmp_start, j)]),
+            //     is_gmul_substep * (P::ONES - hi_bit_set) * next_row[key_gmul_a_tmp_bit(tmp_start, 0)],
             // );
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(key_gmul_a_shift_xor_1b_bit(tmp_start, j)),
-                addr_input_1: next_row(key_gmul_a_tmp_bit(tmp_start, j)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: addr_is_gmul_substep,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_hi_bit_set,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.extend(yield_constr.constraint_transition(addr_constraint));
-        }
-        for j in 1..8 {
-            // yield_constr.constraint_transition(
-            //     is_gmul_substep
-            //         * (P::ONES - hi_bit_set)
-            //         * (curr_row[key_gmul_a_tmp_bit(tmp_start, j - 1)]
-            //             - next_row[key_gmul_a_tmp_bit(tmp_start, j)]),
-            // );
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(key_gmul_a_tmp_bit(tmp_start, j - 1)),
-                addr_input_1: next_row(key_gmul_a_tmp_bit(tmp_start, j)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_hi_bit_set,
-                addr_input_1: 0,
-                addr_output: addr_not_hi_bit_set,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: addr_is_gmul_substep,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_not_hi_bit_set,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.extend(yield_constr.constraint_transition(addr_constraint));
-        }
-        // yield_constr.constraint_transition(
-        //     is_gmul_substep * (P::ONES - hi_bit_set) * next_row[key_gmul_a_tmp_bit(tmp_start, 0)],
-        // );
-        vec_ops.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_hi_bit_set,
-            addr_input_1: 0,
-            addr_output: addr_not_hi_bit_set,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        });
-        vec_ops.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_is_gmul_substep,
-            addr_input_1: addr_not_hi_bit_set,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        });
-        vec_ops.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: next_row(key_gmul_a_tmp_bit(tmp_start, 0)),
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        });
-        vec_ops.extend(yield_constr.constraint_transition(addr_constraint));
-    };
+            b.sub_into(not_hi_bit_set, hi_bit_set, b.constant());
+            b.mul_into(constraint, is_gmul_substep, not_hi_bit_set);
+            b.mul_into(
+                constraint,
+                b.column(nv, key_gmul_a_tmp_bit(tmp_start, 0)),
+                constraint,
+            );
+            b.extend(yield_constr.constraint_transition(addr_constraint));
+        };
 
-    let check_gmul_const = |vec_ops: &mut Vec<VecOpConfig>,
-                            sub_step: usize,
-                            a: u8,
-                            b_start: usize,
-                            tmp_start: usize| {
-        assert!(sub_step < 7);
-
-        // a * b_i
-        if sub_step == 0 {
-            // let b_i = curr_row[b_start];
-            let addr_b_i = curr_row(b_start);
+    let check_gmul_const =
+        |b: &mut VecBuilder, sub_step: usize, b_start: usize, tmp_start: usize| {
+            assert!(sub_step < 7);
+
+            // a * b_i
+            if sub_step == 0 {
+                // let b_i = curr_row[b_start];
+                let b_i = b.column(lv, b_start);
+                for j in 0..8 {
+                    // let a_j_b_i = b_i * a_j;
+                    b.mul_into(a_j_b_i, b_i, b.constant());
+                    // yield_constr.constraint(
+                    //     is_gmul_substep
+                    //         * (curr_row[state_gmul_const_a_bi_bit(tmp_start, j)] - a_j_b_i),
+                    // );
+                    b.sub_into(
+                        constraint,
+                        b.column(lv, state_gmul_const_a_bi_bit(tmp_start, j)),
+                        a_j_b_i,
+                    );
+                    b.mul_into(constraint, constraint, is_gmul_substep);
+                    b.extend(yield_constr.constraint(addr_constraint));
+                }
+            }
+            // let b_i = curr_row[b_start + sub_step + 1];
+            let b_i = b.column(lv, b_start + sub_step + 1);
             for j in 0..8 {
+                // let a_j = FE::from_canonical_u32(((temp_a[sub_step + 1] >> j) & 1) as u32);
                 // let a_j_b_i = b_i * a_j;
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_b_i,
-                    addr_input_1: 0,
-                    addr_output: addr_a_j_b_i,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VS,
-                });
-                // yield_constr.constraint(
-                //     is_gmul_substep
-                //         * (curr_row[state_gmul_const_a_bi_bit(tmp_start, j)] - a_j_b_i),
+                b.mul_into(a_j_b_i, b_i, b.constant());
+                // yield_constr.constraint_transition(
+                //     is_gmul_substep * (next_row[state_gmul_const_a_bi_bit(tmp_start, j)] - a_j_b_i),
                 // );
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: curr_row(state_gmul_const_a_bi_bit(tmp_start, j)),
-                    addr_input_1: addr_a_j_b_i,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_constraint,
-                    addr_input_1: addr_is_gmul_substep,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.extend(yield_constr.constraint(addr_constraint));
+                b.sub_into(
+                    constraint,
+                    b.column(nv, state_gmul_const_a_bi_bit(tmp_start, j)),
+                    a_j_b_i,
+                );
+                b.mul_into(constraint, constraint, is_gmul_substep);
+                b.extend(yield_constr.constraint_transition(addr_constraint));
             }
-        }
-        // let b_i = curr_row[b_start + sub_step + 1];
-        let addr_b_i = curr_row(b_start + sub_step + 1);
-        for j in 0..8 {
-            // let a_j = FE::from_canonical_u32(((temp_a[sub_step + 1] >> j) & 1) as u32);
-            // let a_j_b_i = b_i * a_j;
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_b_i,
-                addr_input_1: 0,
-                addr_output: addr_a_j_b_i,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VS,
-            });
-            // yield_constr.constraint_transition(
-            //     is_gmul_substep * (next_row[state_gmul_const_a_bi_bit(tmp_start, j)] - a_j_b_i),
-            // );
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(state_gmul_const_a_bi_bit(tmp_start, j)),
-                addr_input_1: addr_a_j_b_i,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: addr_is_gmul_substep,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.extend(yield_constr.constraint_transition(addr_constraint));
-        }
 
-        // p ^= a
-        for j in 0..8 {
-            if sub_step == 0 {
-                // let a_j = curr_row[state_gmul_const_a_bi_bit(tmp_start, j)];
-                let addr_a_j = curr_row(state_gmul_const_a_bi_bit(tmp_start, j));
-                // yield_constr.constraint(
-                //     is_gmul_substep * (curr_row[state_gmul_const_p_bit(tmp_start, j)] - a_j),
-                // );
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: curr_row(state_gmul_const_p_bit(tmp_start, j)),
-                    addr_input_1: addr_a_j,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_constraint,
-                    addr_input_1: addr_is_gmul_substep,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.extend(yield_constr.constraint(addr_constraint));
-            }
+            // p ^= a
+            for j in 0..8 {
+                if sub_step == 0 {
+                    // let a_j = curr_row[state_gmul_const_a_bi_bit(tmp_start, j)];
+                    let a_j = b.column(lv, state_gmul_const_a_bi_bit(tmp_start, j));
+                    // yield_constr.constraint(
+                    //     is_gmul_substep * (curr_row[state_gmul_const_p_bit(tmp_start, j)] - a_j),
+                    // );
+                    b.sub_into(
+                        constraint,
+                        b.column(lv, state_gmul_const_p_bit(tmp_start, j)),
+                        a_j,
+                    );
+                    b.mul_into(constraint, constraint, is_gmul_substep);
+                    b.extend(yield_constr.constraint(addr_constraint));
+                }
 
-            // let a_j = next_row[state_gmul_const_a_bi_bit(tmp_start, j)];
-            let addr_a_j = next_row(state_gmul_const_a_bi_bit(tmp_start, j));
-            // let computed_bit = xor_gen(a_j, curr_row[state_gmul_const_p_bit(tmp_start, j)]);
-            xor_gen(
-                vec_ops,
-                addr_a_j,
-                curr_row(state_gmul_const_p_bit(tmp_start, j)),
-            );
+                // let a_j = next_row[state_gmul_const_a_bi_bit(tmp_start, j)];
+                let a_j = b.column(nv, state_gmul_const_a_bi_bit(tmp_start, j));
+                // let computed_bit = xor_gen(a_j, curr_row[state_gmul_const_p_bit(tmp_start, j)]);
+                let (x, y) = (a_j, b.column(lv, state_gmul_const_p_bit(tmp_start, j)));
+                xor_gen(b, x, y);
 
-            // yield_constr.constraint_transition(
-            //     is_gmul_substep
-            //         * (computed_bit - next_row[state_gmul_const_p_bit(tmp_start, j)]),
-            // );
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: next_row(state_gmul_const_p_bit(tmp_start, j)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: addr_is_gmul_substep,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.extend(yield_constr.constraint_transition(addr_constraint));
-        }
-    };
+                // yield_constr.constraint_transition(
+                //     is_gmul_substep
+                //         * (computed_bit - next_row[state_gmul_const_p_bit(tmp_start, j)]),
+                // );
+                b.sub_into(
+                    constraint,
+                    computed_bit,
+                    b.column(nv, state_gmul_const_p_bit(tmp_start, j)),
+                );
+                b.mul_into(constraint, constraint, is_gmul_substep);
+                b.extend(yield_constr.constraint_transition(addr_constraint));
+            }
+        };
 
-    let check_sub = |vec_ops: &mut Vec<VecOpConfig>,
+    let check_sub = |b: &mut VecBuilder,
                      steps: &[usize],
                      a_start: usize,       // start col of src a
                      sub_bit_start: usize, // start col of tmp space for sub
@@ -749,35 +377,27 @@ fn eval_packed_generic(
             //     .map(|x| curr_row[step_bit(*x + sub_step)])
             //     .sum();
             for r in steps {
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: curr_row(step_bit(*r + sub_step)),
-                    addr_input_1: if r != &steps[0] {
-                        addr_is_gmul_substep
+                b.add_into(
+                    is_gmul_substep,
+                    b.column(lv, step_bit(*r + sub_step)),
+                    if r != &steps[0] {
+                        is_gmul_substep
                     } else {
-                        0
+                        b.constant()
                     },
-                    addr_output: addr_is_gmul_substep,
-                    is_final_output: false,
-                    op_type: VecOpType::ADD,
-                    op_src: VecOpSrc::VV,
-                });
+                );
             }
 
-            check_gmul(vec_ops, sub_step, a_start, sub_bit_start, sub_bit_start + 8);
+            check_gmul(b, sub_step, a_start, sub_bit_start, sub_bit_start + 8);
         }
 
         // let is_sub: P = steps.iter().map(|x| curr_row[step_bit(*x + 7)]).sum();
         for r in steps {
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(step_bit(*r + 7)),
-                addr_input_1: if r != &steps[0] { addr_is_sub } else { 0 },
-                addr_output: addr_is_sub,
-                is_final_output: false,
-                op_type: VecOpType::ADD,
-                op_src: VecOpSrc::VV,
-            });
+            b.add_into(
+                is_sub,
+                b.column(lv, step_bit(*r + 7)),
+                if r != &steps[0] { is_sub } else { b.constant() },
+            );
         }
 
         let offset = [0, 4, 5, 6, 7];
@@ -788,128 +408,77 @@ fn eval_packed_generic(
                     //     curr_row[sub_bit_start + i],
                     //     curr_row[sub_bit_start + (i + offset[j]) % 8],
                     // )
-                    xor_gen(
-                        vec_ops,
-                        curr_row(sub_bit_start + i),
-                        curr_row(sub_bit_start + (i + offset[j]) % 8),
+                    let (x, y) = (
+                        b.column(lv, sub_bit_start + i),
+                        b.column(lv, sub_bit_start + (i + offset[j]) % 8),
                     );
+                    xor_gen(b, x, y);
                 } else {
                     // xor_gen(
                     //     curr_row[sbox_xor_bit(sub_bit_start, j - 2, i)],
                     //     curr_row[sub_bit_start + (i + offset[j]) % 8],
                     // )
-                    xor_gen(
-                        vec_ops,
-                        curr_row(sbox_xor_bit(sub_bit_start, j - 2, i)),
-                        curr_row(sub_bit_start + (i + offset[j]) % 8),
+                    let (x, y) = (
+                        b.column(lv, sbox_xor_bit(sub_bit_start, j - 2, i)),
+                        b.column(lv, sub_bit_start + (i + offset[j]) % 8),
                     );
+                    xor_gen(b, x, y);
                 };
                 // yield_constr.constraint(
                 //     is_sub * (computed_bit - curr_row[sbox_xor_bit(sub_bit_start, j - 1, i)]),
                 // );
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: curr_row(sbox_xor_bit(sub_bit_start, j - 1, i)),
-                    addr_input_1: addr_xor_gen,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_constraint,
-                    addr_input_1: addr_is_sub,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.extend(yield_constr.constraint(addr_constraint));
+                b.sub_into(
+                    constraint,
+                    b.column(lv, sbox_xor_bit(sub_bit_start, j - 1, i)),
+                    computed_bit,
+                );
+                b.mul_into(constraint, constraint, is_sub);
+                b.extend(yield_constr.constraint(addr_constraint));
             }
 
             // ^ 99
             // let bit99 = FE::from_canonical_u32(BITS99LSB[i] as u32);
             // let bit_tmp = curr_row[sbox_xor_bit(sub_bit_start, 3, i)];
-            let addr_bit_tmp = curr_row(sbox_xor_bit(sub_bit_start, 3, i));
+            let bit_tmp = b.column(lv, sbox_xor_bit(sub_bit_start, 3, i));
             // let computed_bit = bit_tmp + bit99 - bit_tmp * bit99.doubles();
-            xor_gen(vec_ops, addr_bit_tmp, 0);
+            let (x, y) = (bit_tmp, b.constant());
+            xor_gen(b, x, y);
             if target_next_row {
                 // yield_constr.constraint_transition(
                 //     is_sub * (computed_bit - next_row[target_bit_start + i]),
                 // );
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_xor_gen,
-                    addr_input_1: next_row(target_bit_start + i),
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_constraint,
-                    addr_input_1: addr_is_sub,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.extend(yield_constr.constraint_transition(addr_constraint));
+                b.sub_into(constraint, computed_bit, b.column(nv, target_bit_start + i));
+                b.mul_into(constraint, constraint, is_sub);
+                b.extend(yield_constr.constraint_transition(addr_constraint));
             } else {
                 // yield_constr.constraint(is_sub * (computed_bit - curr_row[target_bit_start + i]));
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_xor_gen,
-                    addr_input_1: curr_row(target_bit_start + i),
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: addr_constraint,
-                    addr_input_1: addr_is_sub,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                vec_ops.extend(yield_constr.constraint(addr_constraint));
+                b.sub_into(constraint, computed_bit, b.column(lv, target_bit_start + i));
+                b.mul_into(constraint, constraint, is_sub);
+                b.extend(yield_constr.constraint(addr_constraint));
             }
         }
     };
 
     // let next_is_not_padding: P = (0..NUM_STEPS - 1).map(|i| curr_row[step_bit(i)]).sum();
     for i in 0..NUM_STEPS - 1 {
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: if i != 0 { addr_next_is_not_padding } else { 0 },
-            addr_input_1: next_row(step_bit(i)),
-            addr_output: addr_next_is_not_padding,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        });
+        b.add_into(
+            next_is_not_padding,
+            if i != 0 {
+                next_is_not_padding
+            } else {
+                b.constant()
+            },
+            b.column(nv, step_bit(i)),
+        );
     }
 
     // check step bits
     // yield_constr.constraint_first_row(P::ONES - curr_row[step_bit(0)]);
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: curr_row(step_bit(0)),
-        addr_input_1: 0,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VV,
-    });
-    res.extend(yield_constr.constraint_first_row(addr_constraint));
+    b.sub_into(constraint, b.column(lv, step_bit(0)), b.constant());
+    b.extend(yield_constr.constraint_first_row(addr_constraint));
     for step in 1..NUM_STEPS {
         // yield_constr.constraint_first_row(curr_row[step_bit(step)]);
-        res.extend(yield_constr.constraint_first_row(curr_row(step_bit(step))));
+        b.extend(yield_constr.constraint_first_row(b.column(lv, step_bit(step)).addr()));
     }
 
     // inc step bits when next is not padding
@@ -919,27 +488,13 @@ fn eval_packed_generic(
         //     next_is_not_padding
         //         * (next_row[step_bit((bit + 1) % NUM_STEPS)] - curr_row[step_bit(bit)]),
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(step_bit((bit + 1) % NUM_STEPS)),
-                addr_input_1: curr_row(step_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_next_is_not_padding,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, step_bit((bit + 1) % NUM_STEPS)),
+            b.column(lv, step_bit(bit)),
+        );
+        b.mul_into(constraint, next_is_not_padding, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
 
     // check RCON
@@ -952,25 +507,9 @@ fn eval_packed_generic(
                 //         * (curr_row[RCON_START + bit]
                 //             - FE::from_canonical_u32((RCON[i] >> 24 >> bit) & 1)),
                 // );
-                res.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: curr_row(RCON_START + bit),
-                    addr_input_1: 0,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::SUB,
-                    op_src: VecOpSrc::VS,
-                });
-                res.push(VecOpConfig {
-                    vector_length: BATCH_SIZE,
-                    addr_input_0: curr_row(step_bit(step)),
-                    addr_input_1: addr_constraint,
-                    addr_output: addr_constraint,
-                    is_final_output: false,
-                    op_type: VecOpType::MUL,
-                    op_src: VecOpSrc::VV,
-                });
-                res.extend(yield_constr.constraint(addr_constraint));
+                b.sub_into(constraint, b.column(lv, RCON_START + bit), b.constant());
+                b.mul_into(constraint, b.column(lv, step_bit(step)), constraint);
+                b.extend(yield_constr.constraint(addr_constraint));
             }
         }
         start_row = ROUND_CHANGE_STEPS[i] + 1;
@@ -981,54 +520,38 @@ fn eval_packed_generic(
     //     .map(|i| curr_row[step_bit(ADD_ROUND_KEY_STEPS[i])])
     //     .sum();
     for i in 0..11 {
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(step_bit(ADD_ROUND_KEY_STEPS[i])),
-            addr_input_1: if i != 0 { addr_is_add_round_key } else { 0 },
-            addr_output: addr_is_add_round_key,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VS,
-        });
+        b.add_into(
+            is_add_round_key,
+            b.column(lv, step_bit(ADD_ROUND_KEY_STEPS[i])),
+            if i != 0 {
+                is_add_round_key
+            } else {
+                b.constant()
+            },
+        );
     }
 
     for i in 0..16 {
         for bit in 0..8 {
             // let computed_bit = xor_gen(curr_row[input_i_bit(i, bit)], curr_row[key_u8_bit(i, bit)]);
-            xor_gen(
-                &mut res,
-                curr_row(input_i_bit(i, bit)),
-                curr_row(key_u8_bit(i, bit)),
+            let (x, y) = (
+                b.column(lv, input_i_bit(i, bit)),
+                b.column(lv, key_u8_bit(i, bit)),
             );
+            xor_gen(&mut b, x, y);
             // yield_constr.constraint_transition(
             //     is_add_round_key * (computed_bit - next_row[input_i_bit(i, bit)]),
             // );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: next_row(input_i_bit(i, bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_add_round_key,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint_transition(addr_constraint));
+            b.sub_into(constraint, computed_bit, b.column(nv, input_i_bit(i, bit)));
+            b.mul_into(constraint, is_add_round_key, constraint);
+            b.extend(yield_constr.constraint_transition(addr_constraint));
         }
     }
 
     // check key update
     // key 3 rot
     check_sub(
-        &mut res,
+        &mut b,
         &KEY_UPDATE_STEPS,
         key_3_after_rot_start(0),
         key_3_0_bit(0),
@@ -1036,7 +559,7 @@ fn eval_packed_generic(
         KEY_3_SUB + 24,
     );
     check_sub(
-        &mut res,
+        &mut b,
         &KEY_UPDATE_STEPS,
         key_3_after_rot_start(1),
         key_3_1_bit(0),
@@ -1044,7 +567,7 @@ fn eval_packed_generic(
         KEY_3_SUB + 16,
     );
     check_sub(
-        &mut res,
+        &mut b,
         &KEY_UPDATE_STEPS,
         key_3_after_rot_start(2),
         key_3_2_bit(0),
@@ -1052,7 +575,7 @@ fn eval_packed_generic(
         KEY_3_SUB + 8,
     );
     check_sub(
-        &mut res,
+        &mut b,
         &KEY_UPDATE_STEPS,
         key_3_after_rot_start(3),
         key_3_3_bit(0),
@@ -1066,140 +589,72 @@ fn eval_packed_generic(
     //     .sum();
     for bit in 0..8 {
         // let computed_bit = xor_gen(curr_row[KEY_3_SUB + 24 + bit], curr_row[RCON_START + bit]);
-        xor_gen(
-            &mut res,
-            curr_row(KEY_3_SUB + 24 + bit),
-            curr_row(RCON_START + bit),
+        let (x, y) = (
+            b.column(lv, KEY_3_SUB + 24 + bit),
+            b.column(lv, RCON_START + bit),
         );
+        xor_gen(&mut b, x, y);
         // yield_constr.constraint(is_key_xor_rcon * (computed_bit - curr_row[key_3_rconv_bit(bit)]));
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_xor_gen,
-            addr_input_1: curr_row(key_3_rconv_bit(bit)),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        });
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_is_key_xor_rcon,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        });
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(constraint, computed_bit, b.column(lv, key_3_rconv_bit(bit)));
+        b.mul_into(constraint, is_key_xor_rcon, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // transition key
     for bit in 0..24 {
         // let computed_bit = xor_gen(curr_row[KEY_3_SUB + bit], curr_row[key_i_bit(0, bit)]);
-        xor_gen(
-            &mut res,
-            curr_row(KEY_3_SUB + bit),
-            curr_row(key_i_bit(0, bit)),
+        let (x, y) = (
+            b.column(lv, KEY_3_SUB + bit),
+            b.column(lv, key_i_bit(0, bit)),
         );
+        xor_gen(&mut b, x, y);
         // yield_constr
         //     .constraint_transition(is_key_xor_rcon * (computed_bit - next_row[key_i_bit(0, bit)]));
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_xor_gen,
-            addr_input_1: next_row(key_i_bit(0, bit)),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        });
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_is_key_xor_rcon,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        });
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(constraint, computed_bit, b.column(nv, key_i_bit(0, bit)));
+        b.mul_into(constraint, is_key_xor_rcon, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
     for bit in 0..8 {
         // let computed_bit = xor_gen(
         //     curr_row[key_3_rconv_bit(bit)],
         //     curr_row[key_i_bit(0, 24 + bit)],
         // );
-        xor_gen(
-            &mut res,
-            curr_row(key_3_rconv_bit(bit)),
-            curr_row(key_i_bit(0, 24 + bit)),
+        let (x, y) = (
+            b.column(lv, key_3_rconv_bit(bit)),
+            b.column(lv, key_i_bit(0, 24 + bit)),
         );
+        xor_gen(&mut b, x, y);
         // yield_constr.constraint_transition(
         //     is_key_xor_rcon * (computed_bit - next_row[key_i_bit(0, 24 + bit)]),
         // );
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_xor_gen,
-            addr_input_1: next_row(key_i_bit(0, 24 + bit)),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        });
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_is_key_xor_rcon,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        });
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            computed_bit,
+            b.column(nv, key_i_bit(0, 24 + bit)),
+        );
+        b.mul_into(constraint, is_key_xor_rcon, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
     for i in 1..4 {
         for bit in 0..32 {
             // let computed_bit =
             //     xor_gen(next_row[key_i_bit(i - 1, bit)], curr_row[key_i_bit(i, bit)]);
-            xor_gen(
-                &mut res,
-                next_row(key_i_bit(i - 1, bit)),
-                curr_row(key_i_bit(i, bit)),
+            let (x, y) = (
+                b.column(nv, key_i_bit(i - 1, bit)),
+                b.column(lv, key_i_bit(i, bit)),
             );
+            xor_gen(&mut b, x, y);
             // yield_constr.constraint_transition(
             //     is_key_xor_rcon * (computed_bit - next_row[key_i_bit(i, bit)]),
             // );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: next_row(key_i_bit(i, bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_key_xor_rcon,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint_transition(addr_constraint));
+            b.sub_into(constraint, computed_bit, b.column(nv, key_i_bit(i, bit)));
+            b.mul_into(constraint, is_key_xor_rcon, constraint);
+            b.extend(yield_constr.constraint_transition(addr_constraint));
         }
     }
 
     // check keep key
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_is_key_xor_rcon,
-        addr_input_1: 0,
-        addr_output: addr_is_not_ket_xor_rcon,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VS,
-    });
+    b.sub_into(is_not_ket_xor_rcon, is_key_xor_rcon, b.constant());
     for i in 0..4 {
         for bit in 0..32 {
             // yield_constr.constraint_transition(
@@ -1207,41 +662,21 @@ fn eval_packed_generic(
             //         * next_is_not_padding
             //         * (next_row[key_i_bit(i, bit)] - curr_row[key_i_bit(i, bit)]),
             // );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(key_i_bit(i, bit)),
-                addr_input_1: curr_row(key_i_bit(i, bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_next_is_not_padding,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_not_ket_xor_rcon,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint_transition(addr_constraint));
+            b.sub_into(
+                constraint,
+                b.column(nv, key_i_bit(i, bit)),
+                b.column(lv, key_i_bit(i, bit)),
+            );
+            b.mul_into(constraint, next_is_not_padding, constraint);
+            b.mul_into(constraint, is_not_ket_xor_rcon, constraint);
+            b.extend(yield_constr.constraint_transition(addr_constraint));
         }
     }
 
     // check state sbox
     for i in 0..16 {
         check_sub(
-            &mut res,
+            &mut b,
             &STATE_SBOX_STEPS,
             input_i_bit(i, 0),
             state_i_sub_start(i),
@@ -1260,25 +695,13 @@ fn eval_packed_generic(
             //     is_state_shift_rows
             //         * (next_row[input_i_bit(i, j)] - curr_row[input_i_bit(SHIFT_ROWS[i], j)]),
             // );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(input_i_bit(i, j)),
-                addr_input_1: curr_row(input_i_bit(SHIFT_ROWS[i], j)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_shift_rows,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint_transition(addr_constraint));
+            b.sub_into(
+                constraint,
+                b.column(nv, input_i_bit(i, j)),
+                b.column(lv, input_i_bit(SHIFT_ROWS[i], j)),
+            );
+            b.mul_into(constraint, is_state_shift_rows, constraint);
+            b.extend(yield_constr.constraint_transition(addr_constraint));
         }
     }
 
@@ -1292,34 +715,18 @@ fn eval_packed_generic(
                 //     .map(|x| curr_row[step_bit(*x + sub_step)])
                 //     .sum();
                 for r in &STATE_MIX_COLUMNS_STEPS {
-                    res.push(VecOpConfig {
-                        vector_length: BATCH_SIZE,
-                        addr_input_0: curr_row(step_bit(*r + sub_step)),
-                        addr_input_1: if r != &STATE_MIX_COLUMNS_STEPS[0] {
-                            addr_is_gmul_substep
+                    b.add_into(
+                        is_gmul_substep,
+                        b.column(lv, step_bit(*r + sub_step)),
+                        if r != &STATE_MIX_COLUMNS_STEPS[0] {
+                            is_gmul_substep
                         } else {
-                            0
+                            b.constant()
                         },
-                        addr_output: addr_is_gmul_substep,
-                        is_final_output: false,
-                        op_type: VecOpType::ADD,
-                        op_src: VecOpSrc::VV,
-                    });
+                    );
                 }
-                check_gmul_const(
-                    &mut res,
-                    sub_step,
-                    2,
-                    b_start,
-                    state_i_gmul_2_start(i * 4 + j),
-                );
-                check_gmul_const(
-                    &mut res,
-                    sub_step,
-                    3,
-                    b_start,
-                    state_i_gmul_3_start(i * 4 + j),
-                );
+                check_gmul_const(&mut b, sub_step, b_start, state_i_gmul_2_start(i * 4 + j));
+                check_gmul_const(&mut b, sub_step, b_start, state_i_gmul_3_start(i * 4 + j));
             }
         }
 
@@ -1328,19 +735,15 @@ fn eval_packed_generic(
         //     .map(|x| curr_row[step_bit(*x + 7)])
         //     .sum::<P>();
         for r in &STATE_MIX_COLUMNS_STEPS {
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(step_bit(*r + 7)),
-                addr_input_1: if r != &STATE_MIX_COLUMNS_STEPS[0] {
-                    addr_is_state_mix_columns
+            b.add_into(
+                is_state_mix_columns,
+                b.column(lv, step_bit(*r + 7)),
+                if r != &STATE_MIX_COLUMNS_STEPS[0] {
+                    is_state_mix_columns
                 } else {
-                    0
+                    b.constant()
                 },
-                addr_output: addr_is_state_mix_columns,
-                is_final_output: false,
-                op_type: VecOpType::ADD,
-                op_src: VecOpSrc::VV,
-            });
+            );
         }
 
         for bit in 0..8 {
@@ -1349,464 +752,297 @@ fn eval_packed_generic(
             //     curr_row[state_gmul_const_res_bit(state_i_gmul_2_start(4 * i), bit)],
             //     curr_row[state_gmul_const_res_bit(state_i_gmul_3_start(4 * i + 1), bit)],
             // );
-            xor_gen(
-                &mut res,
-                curr_row(state_gmul_const_res_bit(state_i_gmul_2_start(4 * i), bit)),
-                curr_row(state_gmul_const_res_bit(
-                    state_i_gmul_3_start(4 * i + 1),
-                    bit,
-                )),
+            let (x, y) = (
+                b.column(
+                    lv,
+                    state_gmul_const_res_bit(state_i_gmul_2_start(4 * i), bit),
+                ),
+                b.column(
+                    lv,
+                    state_gmul_const_res_bit(state_i_gmul_3_start(4 * i + 1), bit),
+                ),
             );
+            xor_gen(&mut b, x, y);
             // yield_constr.constraint(
             //     is_state_mix_columns * (computed_bit - curr_row[state_i_xor_start(4 * i, 0) + bit]),
             // );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: curr_row(state_i_xor_start(4 * i, 0) + bit),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(lv, state_i_xor_start(4 * i, 0) + bit),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint(addr_constraint));
 
             //         let computed_bit = xor_gen(
             //             curr_row[state_i_xor_start(4 * i, 0) + bit],
             //             curr_row[input_i_bit(4 * i + 2, bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(state_i_xor_start(4 * i, 0) + bit),
-                curr_row(input_i_bit(4 * i + 2, bit)),
+            let (x, y) = (
+                b.column(lv, state_i_xor_start(4 * i, 0) + bit),
+                b.column(lv, input_i_bit(4 * i + 2, bit)),
             );
+            xor_gen(&mut b, x, y);
 
             //         yield_constr.constraint(
             //             is_state_mix_columns * (computed_bit - curr_row[state_i_xor_start(4 * i, 1) + bit]),
             //         );
 
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: curr_row(state_i_xor_start(4 * i, 1) + bit),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(lv, state_i_xor_start(4 * i, 1) + bit),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint(addr_constraint));
 
             //         let computed_bit = xor_gen(
             //             curr_row[state_i_xor_start(4 * i, 1) + bit],
             //             curr_row[input_i_bit(4 * i + 3, bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(state_i_xor_start(4 * i, 1) + bit),
-                curr_row(input_i_bit(4 * i + 3, bit)),
+            let (x, y) = (
+                b.column(lv, state_i_xor_start(4 * i, 1) + bit),
+                b.column(lv, input_i_bit(4 * i + 3, bit)),
             );
+            xor_gen(&mut b, x, y);
             //         yield_constr.constraint_transition(
             //             is_state_mix_columns * (computed_bit - next_row[input_i_bit(4 * i, bit)]),
             //         );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: next_row(input_i_bit(4 * i, bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint_transition(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(nv, input_i_bit(4 * i, bit)),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint_transition(addr_constraint));
 
             //         // s1
             //         let computed_bit = xor_gen(
             //             curr_row[input_i_bit(4 * i, bit)],
             //             curr_row[state_gmul_const_res_bit(state_i_gmul_2_start(4 * i + 1), bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(input_i_bit(4 * i, bit)),
-                curr_row(state_gmul_const_res_bit(
-                    state_i_gmul_2_start(4 * i + 1),
-                    bit,
-                )),
+            let (x, y) = (
+                b.column(lv, input_i_bit(4 * i, bit)),
+                b.column(
+                    lv,
+                    state_gmul_const_res_bit(state_i_gmul_2_start(4 * i + 1), bit),
+                ),
             );
+            xor_gen(&mut b, x, y);
             //         yield_constr.constraint(
             //             is_state_mix_columns
             //                 * (computed_bit - curr_row[state_i_xor_start(4 * i + 1, 0) + bit]),
             //         );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: curr_row(state_i_xor_start(4 * i + 1, 0) + bit),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(lv, state_i_xor_start(4 * i + 1, 0) + bit),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint(addr_constraint));
 
             //         let computed_bit = xor_gen(
             //             curr_row[state_i_xor_start(4 * i + 1, 0) + bit],
             //             curr_row[state_gmul_const_res_bit(state_i_gmul_3_start(4 * i + 2), bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(state_i_xor_start(4 * i + 1, 0) + bit),
-                curr_row(state_gmul_const_res_bit(
-                    state_i_gmul_3_start(4 * i + 2),
-                    bit,
-                )),
+            let (x, y) = (
+                b.column(lv, state_i_xor_start(4 * i + 1, 0) + bit),
+                b.column(
+                    lv,
+                    state_gmul_const_res_bit(state_i_gmul_3_start(4 * i + 2), bit),
+                ),
             );
+            xor_gen(&mut b, x, y);
             //         yield_constr.constraint(
             //             is_state_mix_columns
             //                 * (computed_bit - curr_row[state_i_xor_start(4 * i + 1, 1) + bit]),
             //         );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: curr_row(state_i_xor_start(4 * i + 1, 1) + bit),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(lv, state_i_xor_start(4 * i + 1, 1) + bit),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint(addr_constraint));
 
             //         let computed_bit = xor_gen(
             //             curr_row[state_i_xor_start(4 * i + 1, 1) + bit],
             //             curr_row[input_i_bit(4 * i + 3, bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(state_i_xor_start(4 * i + 1, 1) + bit),
-                curr_row(input_i_bit(4 * i + 3, bit)),
+            let (x, y) = (
+                b.column(lv, state_i_xor_start(4 * i + 1, 1) + bit),
+                b.column(lv, input_i_bit(4 * i + 3, bit)),
             );
+            xor_gen(&mut b, x, y);
             //         yield_constr.constraint_transition(
             //             is_state_mix_columns * (computed_bit - next_row[input_i_bit(4 * i + 1, bit)]),
             //         );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: next_row(input_i_bit(4 * i + 1, bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint_transition(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(nv, input_i_bit(4 * i + 1, bit)),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint_transition(addr_constraint));
 
             //         // s2
             //         let computed_bit = xor_gen(
             //             curr_row[input_i_bit(4 * i, bit)],
             //             curr_row[input_i_bit(4 * i + 1, bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(input_i_bit(4 * i, bit)),
-                curr_row(input_i_bit(4 * i + 1, bit)),
+            let (x, y) = (
+                b.column(lv, input_i_bit(4 * i, bit)),
+                b.column(lv, input_i_bit(4 * i + 1, bit)),
             );
+            xor_gen(&mut b, x, y);
             //         yield_constr.constraint(
             //             is_state_mix_columns
             //                 * (computed_bit - curr_row[state_i_xor_start(4 * i + 2, 0) + bit]),
             //         );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: curr_row(state_i_xor_start(4 * i + 2, 0) + bit),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(lv, state_i_xor_start(4 * i + 2, 0) + bit),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint(addr_constraint));
 
             //         let computed_bit = xor_gen(
             //             curr_row[state_i_xor_start(4 * i + 2, 0) + bit],
             //             curr_row[state_gmul_const_res_bit(state_i_gmul_2_start(4 * i + 2), bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(state_i_xor_start(4 * i + 2, 0) + bit),
-                curr_row(state_gmul_const_res_bit(
-                    state_i_gmul_2_start(4 * i + 2),
-                    bit,
-                )),
+            let (x, y) = (
+                b.column(lv, state_i_xor_start(4 * i + 2, 0) + bit),
+                b.column(
+                    lv,
+                    state_gmul_const_res_bit(state_i_gmul_2_start(4 * i + 2), bit),
+                ),
             );
+            xor_gen(&mut b, x, y);
             //         yield_constr.constraint(
             //             is_state_mix_columns
             //                 * (computed_bit - curr_row[state_i_xor_start(4 * i + 2, 1) + bit]),
             //         );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: curr_row(state_i_xor_start(4 * i + 2, 1) + bit),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(lv, state_i_xor_start(4 * i + 2, 1) + bit),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint(addr_constraint));
 
             //         let computed_bit = xor_gen(
             //             curr_row[state_i_xor_start(4 * i + 2, 1) + bit],
             //             curr_row[state_gmul_const_res_bit(state_i_gmul_3_start(4 * i + 3), bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(state_i_xor_start(4 * i + 2, 1) + bit),
-                curr_row(state_gmul_const_res_bit(
-                    state_i_gmul_3_start(4 * i + 3),
-                    bit,
-                )),
+            let (x, y) = (
+                b.column(lv, state_i_xor_start(4 * i + 2, 1) + bit),
+                b.column(
+                    lv,
+                    state_gmul_const_res_bit(state_i_gmul_3_start(4 * i + 3), bit),
+                ),
             );
+            xor_gen(&mut b, x, y);
             //         yield_constr.constraint_transition(
             //             is_state_mix_columns * (computed_bit - next_row[input_i_bit(4 * i + 2, bit)]),
             //         );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: next_row(input_i_bit(4 * i + 2, bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint_transition(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(nv, input_i_bit(4 * i + 2, bit)),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint_transition(addr_constraint));
 
             //         // s3
             //         let computed_bit = xor_gen(
             //             curr_row[state_gmul_const_res_bit(state_i_gmul_3_start(4 * i), bit)],
             //             curr_row[input_i_bit(4 * i + 1, bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(state_gmul_const_res_bit(state_i_gmul_3_start(4 * i), bit)),
-                curr_row(input_i_bit(4 * i + 1, bit)),
+            let (x, y) = (
+                b.column(
+                    lv,
+                    state_gmul_const_res_bit(state_i_gmul_3_start(4 * i), bit),
+                ),
+                b.column(lv, input_i_bit(4 * i + 1, bit)),
             );
+            xor_gen(&mut b, x, y);
             //         yield_constr.constraint(
             //             is_state_mix_columns
             //                 * (computed_bit - curr_row[state_i_xor_start(4 * i + 3, 0) + bit]),
             //         );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: curr_row(state_i_xor_start(4 * i + 3, 0) + bit),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(lv, state_i_xor_start(4 * i + 3, 0) + bit),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint(addr_constraint));
 
             //         let computed_bit = xor_gen(
             //             curr_row[state_i_xor_start(4 * i + 3, 0) + bit],
             //             curr_row[input_i_bit(4 * i + 2, bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(state_i_xor_start(4 * i + 3, 0) + bit),
-                curr_row(input_i_bit(4 * i + 2, bit)),
+            let (x, y) = (
+                b.column(lv, state_i_xor_start(4 * i + 3, 0) + bit),
+                b.column(lv, input_i_bit(4 * i + 2, bit)),
             );
+            xor_gen(&mut b, x, y);
             //         yield_constr.constraint(
             //             is_state_mix_columns
             //                 * (computed_bit - curr_row[state_i_xor_start(4 * i + 3, 1) + bit]),
             //         );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: curr_row(state_i_xor_start(4 * i + 3, 1) + bit),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(lv, state_i_xor_start(4 * i + 3, 1) + bit),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint(addr_constraint));
 
             //         let computed_bit = xor_gen(
             //             curr_row[state_i_xor_start(4 * i + 3, 1) + bit],
             //             curr_row[state_gmul_const_res_bit(state_i_gmul_2_start(4 * i + 3), bit)],
             //         );
-            xor_gen(
-                &mut res,
-                curr_row(state_i_xor_start(4 * i + 3, 1) + bit),
-                curr_row(state_gmul_const_res_bit(
-                    state_i_gmul_2_start(4 * i + 3),
-                    bit,
-                )),
+            let (x, y) = (
+                b.column(lv, state_i_xor_start(4 * i + 3, 1) + bit),
+                b.column(
+                    lv,
+                    state_gmul_const_res_bit(state_i_gmul_2_start(4 * i + 3), bit),
+                ),
             );
+            xor_gen(&mut b, x, y);
             //         yield_constr.constraint_transition(
             //             is_state_mix_columns * (computed_bit - next_row[input_i_bit(4 * i + 3, bit)]),
             //         );
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_xor_gen,
-                addr_input_1: next_row(input_i_bit(4 * i + 3, bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            });
-            res.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_state_mix_columns,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            res.extend(yield_constr.constraint_transition(addr_constraint));
+            b.sub_into(
+                constraint,
+                computed_bit,
+                b.column(nv, input_i_bit(4 * i + 3, bit)),
+            );
+            b.mul_into(constraint, is_state_mix_columns, constraint);
+            b.extend(yield_constr.constraint_transition(addr_constraint));
         }
     }
 
     // eval bits are bits
     for bit in 0..NUM_COLS {
         // yield_constr.constraint((P::ONES - curr_row[bit]) * curr_row[bit]);
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(bit),
-            addr_input_1: 0,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VS,
-        });
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: curr_row(bit),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        });
+        b.sub_into(constraint, b.column(lv, bit), b.constant());
+        b.mul_into(constraint, constraint, b.column(lv, bit));
     }
 
-    // free
-    sys.mem.free("xor_gen_tmp");
-    sys.mem.free("xor_gen");
-    sys.mem.free("a_j_b_i");
-    sys.mem.free("not_hi_bit_set");
-    sys.mem.free("is_gmul_substep");
-    sys.mem.free("is_sub");
-    sys.mem.free("next_is_not_padding");
-    sys.mem.free("is_add_round_key");
-    sys.mem.free("is_key_xor_rcon");
-    sys.mem.free("is_not_ket_xor_rcon");
-    sys.mem.free("is_state_shift_rows");
-    sys.mem.free("is_state_mix_columns");
-
-    res
+    b.finish()
 }
 
 fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let hw = set_config();
 
-    let mut ramsim = RamConfig::new(&format!("{}", "aes_starky"));
-    // ramsim.txt_output = true;
+    let ramsim = RamConfig::new(&format!("{}", "aes_starky"));
+    // ramsim.add_text_output();
     let mem = MemAlloc::new(32, 4096);
-    let mut sys = System::new(mem, ramsim);
+    let mut sys = System::new(mem, ramsim, hw);
 
     let mut gene = AesTraceGenerator::<F>::new(256);
     let key = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
@@ -1824,6 +1060,32 @@ fn main() {
     sys.ramsim.static_();
 
     info!("Start Ramsim");
-    sys.ramsim.run();
+    sys.finish().unwrap();
     info!("End Ramsim");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use unizk::config::{Backend, HwConfig};
+
+    // the op count and traffic of the port before VecBuilder, for the trace of main
+    #[test]
+    fn test_vec_builder_port() {
+        let mut hw = HwConfig::default();
+        hw.timing.backend = Backend::Analytical;
+        let ramsim = RamConfig::new("test_aes_starky");
+        let mut sys = System::new(MemAlloc::new(32, 4096), ramsim, hw);
+
+        let mut gene = AesTraceGenerator::<F>::new(256);
+        let key = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        gene.gen_aes([0u8; 16], key);
+        let trace = gene.into_polynomial_values();
+
+        let config = StarkConfig::standard_fast_config();
+        prove::<F, C, S, D>(&mut sys, S::new(), &config, trace, &[], eval_packed_generic);
+        let report = sys.report();
+        assert_eq!(report.op_cnt, 82960);
+        assert_eq!(report.total_bytes, 143017379);
+    }
+}
@@ -6,12 +6,13 @@ use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
 use starky::config::StarkConfig;
 use starky::fibonacci_stark::FibonacciStark;
 use unizk::config::RamConfig;
-use unizk::kernel::vector_operation::{VecOpConfig, VecOpSrc, VecOpType};
+use unizk::kernel::vec_builder::VecBuilder;
+use unizk::kernel::vector_operation::VecOpConfig;
 use unizk::memory::memory_allocator::MemAlloc;
 use unizk::starky::constraint_consumer::ConstraintConsumer;
 use unizk::starky::stark::EvaluationFrame;
 use unizk::system::system::System;
-use unizk::util::{set_config, BATCH_SIZE, SIZE_F};
+use unizk::util::{set_config, BATCH_SIZE};
 
 use unizk::starky::prover::prove;
 
@@ -29,86 +30,43 @@ fn eval_packed_generic(
     vars: &EvaluationFrame,
     yield_constr: &ConstraintConsumer,
 ) -> Vec<VecOpConfig> {
-    let mut res = Vec::new();
     let addr_constraint = sys.mem.get_addr("constraint").unwrap();
-    let addr_local_values = vars.addr_local_values;
-    let addr_next_values = vars.addr_next_values;
-    let addr_public_inputs = vars.addr_public_inputs;
-
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_local_values,
-        addr_input_1: addr_public_inputs + S::PI_INDEX_X0 * SIZE_F,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VS,
-    });
-    res.extend(yield_constr.constraint_first_row(addr_constraint));
-
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_local_values + BATCH_SIZE * SIZE_F,
-        addr_input_1: addr_public_inputs + S::PI_INDEX_X1 * SIZE_F,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VS,
-    });
-    res.extend(yield_constr.constraint_first_row(addr_constraint));
-
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_local_values + BATCH_SIZE * SIZE_F,
-        addr_input_1: addr_public_inputs + S::PI_INDEX_RES * SIZE_F,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VS,
-    });
-    res.extend(yield_constr.constraint_last_row(addr_constraint));
+    let mut b = VecBuilder::new(&mut sys.mem, BATCH_SIZE);
+    let constraint = b.vector(addr_constraint);
+    let (lv, nv, pi) = (
+        vars.addr_local_values,
+        vars.addr_next_values,
+        vars.addr_public_inputs,
+    );
 
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_next_values,
-        addr_input_1: addr_local_values + BATCH_SIZE * SIZE_F,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VV,
-    });
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    b.sub_into(constraint, b.column(lv, 0), b.scalar_at(pi, S::PI_INDEX_X0));
+    b.extend(yield_constr.constraint_first_row(addr_constraint));
+    b.sub_into(constraint, b.column(lv, 1), b.scalar_at(pi, S::PI_INDEX_X1));
+    b.extend(yield_constr.constraint_first_row(addr_constraint));
+    b.sub_into(
+        constraint,
+        b.column(lv, 1),
+        b.scalar_at(pi, S::PI_INDEX_RES),
+    );
+    b.extend(yield_constr.constraint_last_row(addr_constraint));
 
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_next_values + BATCH_SIZE * SIZE_F,
-        addr_input_1: addr_local_values,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VV,
-    });
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_constraint,
-        addr_input_1: addr_local_values + BATCH_SIZE * SIZE_F,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VV,
-    });
-    res.extend(yield_constr.constraint(addr_constraint));
+    b.sub_into(constraint, b.column(nv, 0), b.column(lv, 1));
+    b.extend(yield_constr.constraint_transition(addr_constraint));
+    b.sub_into(constraint, b.column(nv, 1), b.column(lv, 0));
+    b.sub_into(constraint, constraint, b.column(lv, 1));
+    b.extend(yield_constr.constraint(addr_constraint));
 
-    res
+    b.finish()
 }
 
 fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let hw = set_config();
 
-    let mut ramsim = RamConfig::new(&format!("{}", "fib_starky"));
-    // ramsim.txt_output = true;
+    let ramsim = RamConfig::new(&format!("{}", "fib_starky"));
+    // ramsim.add_text_output();
     let mem = MemAlloc::new(32, 4096);
-    let mut sys = System::new(mem, ramsim);
+    let mut sys = System::new(mem, ramsim, hw);
 
     let config = StarkConfig::standard_fast_config();
     let num_rows = 1 << 20;
@@ -128,6 +86,6 @@ fn main() {
     sys.ramsim.static_();
 
     info!("Start Ramsim");
-    sys.ramsim.run();
+    sys.finish().unwrap();
     info!("End Ramsim");
 }
@@ -11,21 +11,20 @@ use plonky2::plonk::circuit_data::CircuitConfig;
 use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
 
 use clap::{value_parser, Arg, Command};
-use unizk::config::arch_config::ARCH_CONFIG;
-use unizk::config::enable_config::ENABLE_CONFIG;
 use unizk::config::RamConfig;
 use unizk::memory::memory_allocator::MemAlloc;
+use unizk::plonk::preflight::CircuitReport;
 use unizk::plonk::prover::prove_with_partition_witness;
 use unizk::system::system::System;
 use unizk::util::set_config;
 
 fn main() {
-    set_config();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let hw = set_config();
     let mut ramsim = RamConfig::new(&format!("{}", "fibonacci"));
-    // ramsim.txt_output = true;
+    // ramsim.add_text_output();
     let mem = MemAlloc::new(256, 4096);
-    let mut sys = System::new(mem, ramsim);
+    let mut sys = System::new(mem, ramsim, hw);
 
     info!("Starting simulator");
     const D: usize = 2;
@@ -59,6 +58,9 @@ fn main() {
     let mut data = builder.build::<C>();
 
     // data.prover_only.generators.clear();
+    CircuitReport::new(&data.common, &data.prover_only)
+        .check()
+        .unwrap();
     let partition_witness = generate_partial_witness(pw, &data.prover_only, &data.common);
     println!("common_data.fri_params: {:?}", data.common.fri_params);
     prove_with_partition_witness(&mut sys, &data.prover_only, &data.common, partition_witness);
@@ -67,6 +69,6 @@ fn main() {
     sys.ramsim.static_();
 
     info!("Start Ramsim");
-    sys.ramsim.run();
+    sys.finish().unwrap();
     info!("End Ramsim");
 }
@@ -9,10 +9,9 @@ use image::io::Reader as ImageReader;
 use image::ImageBuffer;
 use image::{buffer::Pixels, Rgba};
 use plonky2::iop::generator::generate_partial_witness;
-use unizk::config::arch_config::ARCH_CONFIG;
-use unizk::config::enable_config::ENABLE_CONFIG;
 use unizk::config::RamConfig;
 use unizk::memory::memory_allocator::MemAlloc;
+use unizk::plonk::preflight::CircuitReport;
 use unizk::plonk::prover::prove_with_partition_witness;
 use unizk::system::system::System;
 
@@ -53,13 +52,13 @@ fn align_crop_edit(
 const L: usize = 12 * 85 * 256 * 8;
 
 fn main() {
-    set_config();
-
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+    let hw = set_config();
     let ramsim = RamConfig::new(&format!("{}", "crop"));
-    // ramsim.txt_output = true;
+    // ramsim.add_text_output();
     let mem = MemAlloc::new(256, 4096);
-    let mut sys = System::new(mem, ramsim);
+    let mut sys = System::new(mem, ramsim, hw);
 
     info!("Starting simulator");
     let mut img = ImageReader::open("./random_color.png")
@@ -100,17 +99,19 @@ fn main() {
     assert!(proofs.len() == 1, "Only one proof is expected");
 
     for (data, pw) in proofs {
+        CircuitReport::new(&data.common, &data.prover_only)
+            .check()
+            .unwrap();
         let partition_witness = generate_partial_witness(pw, &data.prover_only, &data.common);
         prove_with_partition_witness(&mut sys, &data.prover_only, &data.common, partition_witness);
         // sys.mem.clean();
     }
 
     info!("Total number of memreq: {}", sys.ramsim.op_cnt);
-    info!("Total number of operations: {:?}", sys.get_computation());
 
     info!("Simulator finished");
     sys.ramsim.static_();
 
     info!("Start Ramsim");
-    sys.ramsim.run();
+    sys.finish().unwrap();
 }
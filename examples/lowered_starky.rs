@@ -0,0 +1,112 @@
+use env_logger::Env;
+use log::info;
+use std::env;
+
+use plonky2::field::polynomial::PolynomialValues;
+use plonky2::field::types::Field;
+use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+use starky::aes128::generation::AesTraceGenerator;
+use starky::aes128::AesStark;
+use starky::config::StarkConfig;
+use starky::factorial_stark::FactorialStark;
+use starky::fibonacci_stark::FibonacciStark;
+use starky::sha256::{Sha2CompressionStark, Sha2StarkCompressor};
+use starky::stark::Stark;
+use starky::util::to_u32_array_be;
+use unizk::config::RamConfig;
+use unizk::memory::memory_allocator::MemAlloc;
+use unizk::starky::lowering::LoweredStark;
+use unizk::starky::prover::prove;
+use unizk::system::system::System;
+use unizk::util::set_config;
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+// the constraints come from the Stark impl instead of a hand-written eval_packed_generic
+fn prove_lowered<S: Stark<F, D>>(
+    sys: &mut System,
+    stark: S,
+    trace: Vec<PolynomialValues<F>>,
+    public_inputs: &[F],
+) {
+    let config = StarkConfig::standard_fast_config();
+    let lowered = LoweredStark::new(&stark, config.num_challenges);
+    info!("{} vector ops per batch", lowered.num_ops());
+    prove::<F, C, S, D>(
+        sys,
+        stark,
+        &config,
+        trace,
+        public_inputs,
+        |sys, vars, consumer| lowered.eval(sys, vars, consumer),
+    );
+}
+
+fn main() {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let hw = set_config();
+
+    // fibonacci or factorial with the log of the number of rows, aes, or sha256 with the
+    // number of hashes
+    let args: Vec<String> = env::args().collect();
+    let workload = args[1].as_str();
+    let param = args.get(2).and_then(|arg| arg.parse::<usize>().ok());
+
+    let mut ramsim = RamConfig::new(&format!("lowered_{}", workload));
+    // ramsim.add_text_output();
+    let mem = MemAlloc::new(32, 4096);
+    let mut sys = System::new(mem, ramsim, hw);
+
+    match workload {
+        "fibonacci" => {
+            let num_rows = 1 << param.unwrap_or(20);
+            let stark = FibonacciStark::<F, D>::new(num_rows);
+            let res = (0..num_rows - 1)
+                .fold((F::ZERO, F::ONE), |x, _| (x.1, x.0 + x.1))
+                .1;
+            let trace = stark.generate_trace(F::ZERO, F::ONE);
+            prove_lowered(&mut sys, stark, trace, &[F::ZERO, F::ONE, res]);
+        }
+        "factorial" => {
+            let num_rows = 1 << param.unwrap_or(20);
+            let stark = FactorialStark::<F, D>::new(num_rows);
+            let res = (2..num_rows + 1).fold(F::ONE, |x, i| x * F::from_canonical_usize(i));
+            let trace = stark.generate_trace();
+            prove_lowered(&mut sys, stark, trace, &[res]);
+        }
+        "aes" => {
+            let mut gene = AesTraceGenerator::<F>::new(256);
+            let key = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+            gene.gen_aes([0u8; 16], key);
+            let trace = gene.into_polynomial_values();
+            prove_lowered(&mut sys, AesStark::<F, D>::new(), trace, &[]);
+        }
+        "sha256" => {
+            let mut compressor = Sha2StarkCompressor::new();
+            let mut init_left = [0u8; 32];
+            let mut init_right = [0u8; 32];
+            init_left[31] = 0x80;
+            init_right[31] = 0xf8;
+            for i in 0..param.unwrap_or(1) {
+                let mut left = to_u32_array_be::<8>(init_left);
+                left[0] = i as u32;
+                compressor.add_instance(left, to_u32_array_be::<8>(init_right));
+            }
+            let trace = compressor.generate();
+            prove_lowered(&mut sys, Sha2CompressionStark::<F, D>::new(), trace, &[]);
+        }
+        _ => panic!(
+            "unknown workload {}, expected fibonacci, factorial, aes or sha256",
+            workload
+        ),
+    }
+
+    info!("Simulator finished");
+    sys.ramsim.static_();
+
+    info!("Start Ramsim");
+    sys.finish().unwrap();
+    info!("End Ramsim");
+}
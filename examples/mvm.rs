@@ -5,9 +5,9 @@ use plonky2::iop::generator::generate_partial_witness;
 
 use clap::{value_parser, Arg, Command};
 use rand::Rng;
-use unizk::config::enable_config::ENABLE_CONFIG;
-use unizk::config::{arch_config::ARCH_CONFIG, RamConfig};
+use unizk::config::RamConfig;
 use unizk::memory::memory_allocator::MemAlloc;
+use unizk::plonk::preflight::CircuitReport;
 use unizk::plonk::prover::prove_with_partition_witness;
 use unizk::system::system::System;
 
@@ -18,12 +18,12 @@ const INPUT_SIZE: usize = 3000;
 const OUTPUT_SIZE: usize = 3000;
 
 fn main() {
-    set_config();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let hw = set_config();
     let ramsim = RamConfig::new(&format!("mvm"));
-    // ramsim.txt_output = true;
+    // ramsim.add_text_output();
     let mem = MemAlloc::new(256, 4096);
-    let mut sys = System::new(mem, ramsim);
+    let mut sys = System::new(mem, ramsim, hw);
 
     info!("Starting simulator");
 
@@ -51,6 +51,9 @@ fn main() {
 
     println!("circuit config: {:?}", &circuit.data.common.config);
     // data.prover_only.generators.clear();
+    CircuitReport::new(&circuit.data.common, &circuit.data.prover_only)
+        .check()
+        .unwrap();
     let partition_witness =
         generate_partial_witness(pw, &circuit.data.prover_only, &circuit.data.common);
     prove_with_partition_witness(
@@ -65,9 +68,7 @@ fn main() {
     info!("Simulator finished");
     sys.ramsim.static_();
 
-    info!("sys.computation: {:?}", sys.get_computation());
-
     info!("Start Ramsim");
-    sys.ramsim.run();
+    sys.finish().unwrap();
     info!("Ramsim finished");
 }
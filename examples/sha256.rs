@@ -9,23 +9,22 @@ use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
 use plonky2_sha256::circuit::{array_to_bits, make_circuits};
 
 use sha2::{Digest, Sha256};
-use unizk::config::arch_config::ARCH_CONFIG;
-use unizk::config::enable_config::ENABLE_CONFIG;
 use unizk::config::RamConfig;
 use unizk::memory::memory_allocator::MemAlloc;
+use unizk::plonk::preflight::CircuitReport;
 use unizk::plonk::prover::prove_with_partition_witness;
 use unizk::system::system::System;
 
 use clap::{value_parser, Arg, Command};
 use unizk::util::set_config;
 fn main() {
-    set_config();
-
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+    let hw = set_config();
     let ramsim = RamConfig::new(&format!("{}", "sha256"));
-    // ramsim.txt_output = true;
+    // ramsim.add_text_output();
     let mem = MemAlloc::new(256, 4096);
-    let mut sys = System::new(mem, ramsim);
+    let mut sys = System::new(mem, ramsim, hw);
 
     info!("Starting simulator");
     const MSG_SIZE: usize = 8000;
@@ -67,16 +66,18 @@ fn main() {
     );
     let data = builder.build::<C>();
 
+    CircuitReport::new(&data.common, &data.prover_only)
+        .check()
+        .unwrap();
     let partition_witness = generate_partial_witness(pw, &data.prover_only, &data.common);
     prove_with_partition_witness(&mut sys, &data.prover_only, &data.common, partition_witness);
 
     info!("Total number of memreq: {}", sys.ramsim.op_cnt);
-    info!("Total number of operations: {:?}", sys.get_computation());
 
     info!("Simulator finished");
     sys.ramsim.static_();
 
     info!("Start Ramsim");
-    sys.ramsim.run();
+    sys.finish().unwrap();
     info!("Ramsim finished");
 }
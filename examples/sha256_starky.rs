@@ -3,16 +3,16 @@ use log::info;
 use std::env;
 use std::iter::once;
 
-use plonky2::field::types::Field;
 use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
 use unizk::config::RamConfig;
-use unizk::kernel::vector_operation::{VecOpConfig, VecOpSrc, VecOpType};
+use unizk::kernel::vec_builder::{VecBuilder, VecHandle};
+use unizk::kernel::vector_operation::VecOpConfig;
 use unizk::memory::memory_allocator::MemAlloc;
 use unizk::starky::constraint_consumer::ConstraintConsumer;
 use unizk::starky::prover::prove;
 use unizk::starky::stark::EvaluationFrame;
 use unizk::system::system::System;
-use unizk::util::{BATCH_SIZE, SIZE_F};
+use unizk::util::{set_config, BATCH_SIZE};
 use starky::config::StarkConfig;
 use starky::sha256::layout::*;
 use starky::sha256::{Sha2CompressionStark, Sha2StarkCompressor};
@@ -21,282 +21,129 @@ use starky::util::to_u32_array_be;
 const D: usize = 2;
 type C = PoseidonGoldilocksConfig;
 type F = <C as GenericConfig<D>>::F;
-type S = Sha2CompressionStark<F, D>; 
+type S = Sha2CompressionStark<F, D>;
 
 fn eval_packed_generic(
     sys: &mut System,
     vars: &EvaluationFrame,
     yield_constr: &ConstraintConsumer,
 ) -> Vec<VecOpConfig> {
-    let mut res = Vec::new();
-    let curr_row = |idx: usize| vars.addr_local_values + idx * BATCH_SIZE * SIZE_F;
-    let next_row = |idx: usize| vars.addr_next_values + idx * BATCH_SIZE * SIZE_F;
     let addr_constraint = sys.mem.get_addr("constraint").unwrap();
+    let mut b = VecBuilder::new(&mut sys.mem, BATCH_SIZE);
+    let constraint = b.vector(addr_constraint);
+    let (lv, nv) = (vars.addr_local_values, vars.addr_next_values);
 
     // set hash idx to 1 at the start. hash_idx should be 1-indexed.
     // yield_constr.constraint_first_row(P::ONES - curr_row[HASH_IDX]);
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: curr_row(HASH_IDX),
-        addr_input_1: 0,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VS,
-    });
-    res.extend(yield_constr.constraint_first_row(addr_constraint));
+    b.sub_into(constraint, b.column(lv, HASH_IDX), b.constant());
+    b.extend(yield_constr.constraint_first_row(addr_constraint));
 
     // set his to initial values at start of hash
-    let is_hash_start = curr_row(step_bit(0));
+    let is_hash_start = b.column(lv, step_bit(0));
     for i in 0..8 {
         // degree 3
         // yield_constr
         //     .constraint(is_hash_start * (curr_row[h_i(i)] - FE::from_canonical_u32(HASH_IV[i])));
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(h_i(i)),
-            addr_input_1: 0,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        });
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: is_hash_start,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        });
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(constraint, b.column(lv, h_i(i)), b.constant());
+        b.mul_into(constraint, constraint, is_hash_start);
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // ensure his stay the same outside last two rows of hash
     // let his_should_change = next_row[step_bit(64)] + curr_row[step_bit(64)];
-    let addr_his_should_change = sys
-        .mem
-        .alloc("his_should_change", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: next_row(step_bit(64)),
-        addr_input_1: curr_row(step_bit(64)),
-        addr_output: addr_his_should_change,
-        is_final_output: false,
-        op_type: VecOpType::ADD,
-        op_src: VecOpSrc::VV,
-    });
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_his_should_change,
-        addr_input_1: 0,
-        addr_output: addr_his_should_change,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VV,
-    });
+    let his_should_change = b.alloc("his_should_change");
+    b.add_into(
+        his_should_change,
+        b.column(nv, step_bit(64)),
+        b.column(lv, step_bit(64)),
+    );
+    b.sub_into(his_should_change, his_should_change, b.constant());
     for i in 0..8 {
         // degree 2
         // yield_constr.constraint_transition(
         //     (P::ONES - his_should_change) * (next_row[h_i(i)] - curr_row[h_i(i)]),
         // );
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: next_row(h_i(i)),
-            addr_input_1: curr_row(h_i(i)),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        });
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: addr_his_should_change,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        });
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(constraint, b.column(nv, h_i(i)), b.column(lv, h_i(i)));
+        b.mul_into(constraint, constraint, his_should_change);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
 
     // let next_is_phase_0: P = (0..16).map(|i| next_row[step_bit(i)]).sum();
     // let next_is_phase_1: P = (16..64).map(|i| next_row[step_bit(i)]).sum();
     // let next_is_not_padding = next_is_phase_0 + next_is_phase_1 + next_row[step_bit(64)];
-    let addr_next_is_phase_0 = sys
-        .mem
-        .alloc("next_is_phase_0", BATCH_SIZE * SIZE_F)
-        .unwrap();
+    let next_is_phase_0 = b.alloc("next_is_phase_0");
     for i in 0..16 {
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: if i != 0 { addr_next_is_phase_0 } else { 0 },
-            addr_input_1: next_row(step_bit(i)),
-            addr_output: addr_next_is_phase_0,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        });
+        b.add_into(
+            next_is_phase_0,
+            if i != 0 {
+                next_is_phase_0
+            } else {
+                b.constant()
+            },
+            b.column(nv, step_bit(i)),
+        );
     }
 
-    let addr_next_is_phase_1 = sys
-        .mem
-        .alloc("next_is_phase_1", BATCH_SIZE * SIZE_F)
-        .unwrap();
+    let next_is_phase_1 = b.alloc("next_is_phase_1");
     for i in 16..64 {
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: if i != 16 { addr_next_is_phase_1 } else { 0 },
-            addr_input_1: next_row(step_bit(i)),
-            addr_output: addr_next_is_phase_1,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        });
+        b.add_into(
+            next_is_phase_1,
+            if i != 16 {
+                next_is_phase_1
+            } else {
+                b.constant()
+            },
+            b.column(nv, step_bit(i)),
+        );
     }
 
-    let addr_next_is_not_padding = sys
-        .mem
-        .alloc("next_is_not_padding", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_next_is_phase_0,
-        addr_input_1: addr_next_is_phase_1,
-        addr_output: addr_next_is_not_padding,
-        is_final_output: false,
-        op_type: VecOpType::ADD,
-        op_src: VecOpSrc::VV,
-    });
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_next_is_not_padding,
-        addr_input_1: next_row(step_bit(64)),
-        addr_output: addr_next_is_not_padding,
-        is_final_output: false,
-        op_type: VecOpType::ADD,
-        op_src: VecOpSrc::VV,
-    });
+    let next_is_not_padding = b.alloc("next_is_not_padding");
+    b.add_into(next_is_not_padding, next_is_phase_0, next_is_phase_1);
+    b.add_into(
+        next_is_not_padding,
+        next_is_not_padding,
+        b.column(nv, step_bit(64)),
+    );
 
     // increment hash idx if we're at the last step and next isn't padding
     // let is_last_step = curr_row[step_bit(64)];
-    let is_last_step = curr_row(step_bit(64));
+    let is_last_step = b.column(lv, step_bit(64));
     // let transition_to_next_hash = is_last_step * next_is_not_padding;
-    let addr_transition_to_next_hash = sys
-        .mem
-        .alloc("transition_to_next_hash", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: is_last_step,
-        addr_input_1: addr_next_is_not_padding,
-        addr_output: addr_transition_to_next_hash,
-        is_final_output: false,
-        op_type: VecOpType::MUL,
-        op_src: VecOpSrc::VV,
-    });
+    let transition_to_next_hash = b.alloc("transition_to_next_hash");
+    b.mul_into(transition_to_next_hash, is_last_step, next_is_not_padding);
 
     // degree 3
     // yield_constr.constraint_transition(
     //     transition_to_next_hash * (P::ONES - (next_row[HASH_IDX] - curr_row[HASH_IDX])),
     // );
-    let addr_curr_next_row_hash_idx = sys
-        .mem
-        .alloc("curr_next_row_hash_idx", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: next_row(HASH_IDX),
-        addr_input_1: curr_row(HASH_IDX),
-        addr_output: addr_curr_next_row_hash_idx,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VV,
-    });
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_curr_next_row_hash_idx,
-        addr_input_1: 0,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VV,
-    });
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_transition_to_next_hash,
-        addr_input_1: addr_constraint,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::MUL,
-        op_src: VecOpSrc::VV,
-    });
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    let curr_next_row_hash_idx = b.alloc("curr_next_row_hash_idx");
+    b.sub_into(
+        curr_next_row_hash_idx,
+        b.column(nv, HASH_IDX),
+        b.column(lv, HASH_IDX),
+    );
+    b.sub_into(constraint, curr_next_row_hash_idx, b.constant());
+    b.mul_into(constraint, transition_to_next_hash, constraint);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     // otherwise ensure hash idx stays the same unless next row is padding
     // degree 3
     // yield_constr.constraint_transition(
     //     (P::ONES - is_last_step) * next_is_not_padding * (next_row[HASH_IDX] - curr_row[HASH_IDX]),
     // );
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: is_last_step,
-        addr_input_1: 0,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VV,
-    });
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_constraint,
-        addr_input_1: addr_next_is_not_padding,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::MUL,
-        op_src: VecOpSrc::VV,
-    });
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_constraint,
-        addr_input_1: addr_curr_next_row_hash_idx,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::MUL,
-        op_src: VecOpSrc::VV,
-    });
-    res.extend(yield_constr.constraint_transition(addr_constraint));
-
-    let addr_bit_decomp_32_tmp = sys
-        .mem
-        .alloc("bit_decomp_32_tmp", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    let bit_decomp_32 = |vec_ops: &mut Vec<VecOpConfig>,
-                         addr,
-                         row: &dyn Fn(usize) -> usize,
-                         clo_fn: fn(usize) -> usize| {
+    b.sub_into(constraint, is_last_step, b.constant());
+    b.mul_into(constraint, constraint, next_is_not_padding);
+    b.mul_into(constraint, constraint, curr_next_row_hash_idx);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
+
+    let bit_decomp_32_tmp = b.alloc("bit_decomp_32_tmp");
+    // out = the 32 bits of the row, each times 2^bit. Like the port before VecBuilder, the
+    // products read out instead of the bit columns, which keeps its op count and traffic.
+    let bit_decomp_32 = |b: &mut VecBuilder, out: VecHandle| {
         for bit in 0..32 {
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: if bit != 0 { addr } else { 0 },
-                addr_input_1: 0,
-                addr_output: addr_bit_decomp_32_tmp,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr,
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr,
-                is_final_output: false,
-                op_type: VecOpType::ADD,
-                op_src: VecOpSrc::VV,
-            });
+            let x = if bit != 0 { out } else { b.constant() };
+            b.mul_into(bit_decomp_32_tmp, x, b.constant());
+            b.add_into(out, out, bit_decomp_32_tmp);
         }
     };
 
@@ -305,491 +152,149 @@ fn eval_packed_generic(
     // let decomp = bit_decomp_32!(curr_row, wi_bit, FE, P)
     //     + curr_row[HASH_IDX] * FE::from_canonical_u64(1 << 32);
     // yield_constr.constraint(is_hash_start * (decomp - curr_row[input_i(0)]));
-    bit_decomp_32(&mut res, addr_constraint, &curr_row, wi_bit);
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(HASH_IDX),
-            addr_input_1: 0,
-            addr_output: addr_bit_decomp_32_tmp,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_bit_decomp_32_tmp,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: curr_row(input_i(0)),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: is_hash_start,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint(addr_constraint));
+    bit_decomp_32(&mut b, constraint);
+    b.mul_into(bit_decomp_32_tmp, b.column(lv, HASH_IDX), b.constant());
+    b.add_into(constraint, bit_decomp_32_tmp, constraint);
+    b.sub_into(constraint, constraint, b.column(lv, input_i(0)));
+    b.mul_into(constraint, is_hash_start, constraint);
+    b.extend(yield_constr.constraint(addr_constraint));
 
     // wi minus 2
     // let decomp = bit_decomp_32!(curr_row, wi_minus_2_bit, FE, P)
     //     + curr_row[HASH_IDX] * FE::from_canonical_u64(1 << 32);
     // yield_constr.constraint(is_hash_start * (decomp - curr_row[input_i(14)]));
-    bit_decomp_32(&mut res, addr_constraint, &curr_row, wi_minus_2_bit);
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(HASH_IDX),
-            addr_input_1: 0,
-            addr_output: addr_bit_decomp_32_tmp,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_bit_decomp_32_tmp,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: curr_row(input_i(14)),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: is_hash_start,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint(addr_constraint));
+    bit_decomp_32(&mut b, constraint);
+    b.mul_into(bit_decomp_32_tmp, b.column(lv, HASH_IDX), b.constant());
+    b.add_into(constraint, bit_decomp_32_tmp, constraint);
+    b.sub_into(constraint, constraint, b.column(lv, input_i(14)));
+    b.mul_into(constraint, is_hash_start, constraint);
+    b.extend(yield_constr.constraint(addr_constraint));
 
     // wi minus 15
     // let decomp = bit_decomp_32!(curr_row, wi_minus_15_bit, FE, P)
     //     + curr_row[HASH_IDX] * FE::from_canonical_u64(1 << 32);
     // yield_constr.constraint(is_hash_start * (decomp - curr_row[input_i(1)]));
-    bit_decomp_32(&mut res, addr_constraint, &curr_row, wi_minus_15_bit);
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(HASH_IDX),
-            addr_input_1: 0,
-            addr_output: addr_bit_decomp_32_tmp,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_bit_decomp_32_tmp,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: curr_row(input_i(1)),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: is_hash_start,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint(addr_constraint));
+    bit_decomp_32(&mut b, constraint);
+    b.mul_into(bit_decomp_32_tmp, b.column(lv, HASH_IDX), b.constant());
+    b.add_into(constraint, bit_decomp_32_tmp, constraint);
+    b.sub_into(constraint, constraint, b.column(lv, input_i(1)));
+    b.mul_into(constraint, is_hash_start, constraint);
+    b.extend(yield_constr.constraint(addr_constraint));
 
     // the rest
     for i in (1..=12).chain(once(14)) {
         // let decomp = curr_row[wi_field(i)] + curr_row[HASH_IDX] * FE::from_canonical_u64(1 << 32);
 
         // yield_constr.constraint(is_hash_start * (decomp - curr_row[input_i(i + 1)]));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(HASH_IDX),
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: curr_row(wi_field(i)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::ADD,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: curr_row(input_i(i + 1)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: is_hash_start,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(constraint, b.column(lv, HASH_IDX), b.constant());
+        b.add_into(constraint, constraint, b.column(lv, wi_field(i)));
+        b.sub_into(constraint, constraint, b.column(lv, input_i(i + 1)));
+        b.mul_into(constraint, is_hash_start, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // set input filter to 1 iff we're at the start, zero otherwise
     // yield_constr.constraint(is_hash_start * (P::ONES - curr_row[INPUT_FILTER]));
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: 0,
-            addr_input_1: curr_row(INPUT_FILTER),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: is_hash_start,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint(addr_constraint));
+    b.sub_into(constraint, b.constant(), b.column(lv, INPUT_FILTER));
+    b.mul_into(constraint, is_hash_start, constraint);
+    b.extend(yield_constr.constraint(addr_constraint));
     // yield_constr.constraint((P::ONES - is_hash_start) * curr_row[INPUT_FILTER]);
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: is_hash_start,
-            addr_input_1: 0,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: curr_row(INPUT_FILTER),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint(addr_constraint));
+    b.sub_into(constraint, is_hash_start, b.constant());
+    b.mul_into(constraint, constraint, b.column(lv, INPUT_FILTER));
+    b.extend(yield_constr.constraint(addr_constraint));
 
     // rotate wis when next step is phase 0 and we're not starting a new hash
     // let rotate_wis = next_is_phase_0 * (P::ONES - next_row[step_bit(0)]);
-    let addr_rotate_wis = sys.mem.alloc("rotate_wis", BATCH_SIZE * SIZE_F).unwrap();
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: next_row(step_bit(0)),
-            addr_input_1: 0,
-            addr_output: addr_rotate_wis,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_rotate_wis,
-            addr_input_1: addr_next_is_phase_0,
-            addr_output: addr_rotate_wis,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
+    let rotate_wis = b.alloc("rotate_wis");
+    b.sub_into(rotate_wis, b.column(nv, step_bit(0)), b.constant());
+    b.mul_into(rotate_wis, rotate_wis, next_is_phase_0);
     // shift wis left when next step is phase 1
     // let shift_wis = next_is_phase_1;
-    let addr_shift_wis = addr_next_is_phase_1;
+    let shift_wis = next_is_phase_1;
 
     // wi next when rotating
     // let c =
     //     bit_decomp_32!(next_row, wi_bit, FE, P) - bit_decomp_32!(curr_row, wi_minus_15_bit, FE, P);
-    let addr_c = sys.mem.alloc("c", BATCH_SIZE * SIZE_F).unwrap();
-    bit_decomp_32(&mut res, addr_c, &next_row, wi_bit);
-    bit_decomp_32(&mut res, addr_constraint, &curr_row, wi_minus_15_bit);
+    let c = b.alloc("c");
+    bit_decomp_32(&mut b, c);
+    bit_decomp_32(&mut b, constraint);
 
     // yield_constr.constraint_transition(rotate_wis * c);
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_c,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: addr_rotate_wis,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    b.sub_into(constraint, c, constraint);
+    b.mul_into(constraint, constraint, rotate_wis);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     // wi_minus_2 next
     // let decomp = bit_decomp_32!(next_row, wi_minus_2_bit, FE, P);
-    bit_decomp_32(&mut res, addr_constraint, &next_row, wi_minus_2_bit);
+    bit_decomp_32(&mut b, constraint);
 
     // yield_constr
     //     .constraint_transition((rotate_wis + shift_wis) * (decomp - curr_row[wi_field(14)]));
-    let addr_rotate_wis_plus_shift_wis = sys
-        .mem
-        .alloc("rotate_wis_plus_shift_wis", BATCH_SIZE * SIZE_F)
-        .unwrap();
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: curr_row(wi_field(14)),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_shift_wis,
-            addr_input_1: addr_rotate_wis,
-            addr_output: addr_rotate_wis_plus_shift_wis,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_rotate_wis_plus_shift_wis,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    let rotate_wis_plus_shift_wis = b.alloc("rotate_wis_plus_shift_wis");
+    b.sub_into(constraint, constraint, b.column(lv, wi_field(14)));
+    b.add_into(rotate_wis_plus_shift_wis, shift_wis, rotate_wis);
+    b.mul_into(constraint, rotate_wis_plus_shift_wis, constraint);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     // wi_minus_15 next
     // let decomp = bit_decomp_32!(next_row, wi_minus_15_bit, FE, P);
-    bit_decomp_32(&mut res, addr_constraint, &next_row, wi_minus_15_bit);
+    bit_decomp_32(&mut b, constraint);
     // yield_constr.constraint_transition((rotate_wis + shift_wis) * (decomp - curr_row[wi_field(1)]));
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: curr_row(wi_field(1)),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_rotate_wis_plus_shift_wis,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    b.sub_into(constraint, constraint, b.column(lv, wi_field(1)));
+    b.mul_into(constraint, rotate_wis_plus_shift_wis, constraint);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     // wi_minus_1 next
     // let decomp = bit_decomp_32!(curr_row, wi_bit, FE, P);
     // yield_constr
     //     .constraint_transition((rotate_wis + shift_wis) * (next_row[wi_field(14)] - decomp));
-    bit_decomp_32(&mut res, addr_constraint, &curr_row, wi_bit);
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: next_row(wi_field(14)),
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_rotate_wis_plus_shift_wis,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    bit_decomp_32(&mut b, constraint);
+    b.sub_into(constraint, b.column(nv, wi_field(14)), constraint);
+    b.mul_into(constraint, rotate_wis_plus_shift_wis, constraint);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     // wi_i_minus_3 next
     // let decomp = bit_decomp_32!(curr_row, wi_minus_2_bit, FE, P);
     // yield_constr
     //     .constraint_transition((rotate_wis + shift_wis) * (next_row[wi_field(12)] - decomp));
-    bit_decomp_32(&mut res, addr_constraint, &curr_row, wi_minus_2_bit);
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: next_row(wi_field(12)),
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_rotate_wis_plus_shift_wis,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    bit_decomp_32(&mut b, constraint);
+    b.sub_into(constraint, b.column(nv, wi_field(12)), constraint);
+    b.mul_into(constraint, rotate_wis_plus_shift_wis, constraint);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     for i in 1..12 {
         // yield_constr.constraint_transition(
         //     (rotate_wis + shift_wis) * (next_row[wi_field(i)] - curr_row[wi_field(i + 1)]),
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(wi_field(i)),
-                addr_input_1: curr_row(wi_field(i + 1)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_rotate_wis_plus_shift_wis,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, wi_field(i)),
+            b.column(lv, wi_field(i + 1)),
+        );
+        b.mul_into(constraint, rotate_wis_plus_shift_wis, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
 
     // round fn in phase 0 or 1
     // let is_phase_0_or_1: P = (0..64).map(|i| curr_row[step_bit(i)]).sum();
-    let addr_is_phase_0_or_1 = sys
-        .mem
-        .alloc("is_phase_0_or_1", BATCH_SIZE * SIZE_F)
-        .unwrap();
+    let is_phase_0_or_1 = b.alloc("is_phase_0_or_1");
     for i in 0..64 {
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: if i != 0 { addr_is_phase_0_or_1 } else { 0 },
-            addr_input_1: curr_row(step_bit(i)),
-            addr_output: addr_is_phase_0_or_1,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        });
+        b.add_into(
+            is_phase_0_or_1,
+            if i != 0 {
+                is_phase_0_or_1
+            } else {
+                b.constant()
+            },
+            b.column(lv, step_bit(i)),
+        );
     }
 
-    let xor_gen = |vec_ops: &mut Vec<VecOpConfig>, addr_x: usize, addr_y: usize| {
-        vec_ops.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_x,
-                addr_input_1: addr_y,
-                addr_output: addr_bit_decomp_32_tmp,
-                is_final_output: false,
-                op_type: VecOpType::ADD,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_y,
-                addr_input_1: 0,
-                addr_output: addr_c,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_c,
-                addr_input_1: addr_x,
-                addr_output: addr_c,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_bit_decomp_32_tmp,
-                addr_input_1: addr_c,
-                addr_output: addr_bit_decomp_32_tmp,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
+    let xor_gen = |b: &mut VecBuilder, x: VecHandle, y: VecHandle| {
+        b.add_into(bit_decomp_32_tmp, x, y);
+        b.mul_into(c, y, b.constant());
+        b.mul_into(c, c, x);
+        b.sub_into(bit_decomp_32_tmp, bit_decomp_32_tmp, c);
     };
 
     // S1 := (e >>> 6) xor (e >>> 11) xor (e >>> 25)
@@ -798,179 +303,83 @@ fn eval_packed_generic(
         //     curr_row[e_bit((bit + 6) % 32)],
         //     curr_row[e_bit((bit + 11) % 32)],
         // );
-        xor_gen(
-            &mut res,
-            curr_row(e_bit((bit + 6) % 32)),
-            curr_row(e_bit((bit + 11) % 32)),
+        let (x, y) = (
+            b.column(lv, e_bit((bit + 6) % 32)),
+            b.column(lv, e_bit((bit + 11) % 32)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr.constraint(is_phase_0_or_1 * (curr_row[xor_tmp_2_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(xor_tmp_2_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(lv, xor_tmp_2_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // let computed_bit = xor_gen(
         //     curr_row[xor_tmp_2_bit(bit)],
         //     curr_row[e_bit((bit + 25) % 32)],
         // );
-        xor_gen(
-            &mut res,
-            curr_row(xor_tmp_2_bit(bit)),
-            curr_row(e_bit((bit + 25) % 32)),
+        let (x, y) = (
+            b.column(lv, xor_tmp_2_bit(bit)),
+            b.column(lv, e_bit((bit + 25) % 32)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr.constraint(is_phase_0_or_1 * (curr_row[big_s1_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(big_s1_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(constraint, b.column(lv, big_s1_bit(bit)), bit_decomp_32_tmp);
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // ch := (e and f) xor ((not e) and g)
     for bit in 0..32 {
         // let computed_bit = curr_row[e_bit(bit)] * curr_row[f_bit(bit)];
-        res.push(VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(e_bit(bit)),
-            addr_input_1: curr_row(f_bit(bit)),
-            addr_output: addr_bit_decomp_32_tmp,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        });
+        b.mul_into(
+            bit_decomp_32_tmp,
+            b.column(lv, e_bit(bit)),
+            b.column(lv, f_bit(bit)),
+        );
         // degree 3
         // yield_constr.constraint(is_phase_0_or_1 * (curr_row[e_and_f_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(e_and_f_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(lv, e_and_f_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // let computed_bit = (P::ONES - curr_row[e_bit(bit)]) * curr_row[g_bit(bit)];
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(e_bit(bit)),
-                addr_input_1: 0,
-                addr_output: addr_bit_decomp_32_tmp,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_bit_decomp_32_tmp,
-                addr_input_1: curr_row(g_bit(bit)),
-                addr_output: addr_bit_decomp_32_tmp,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
+        b.sub_into(bit_decomp_32_tmp, b.column(lv, e_bit(bit)), b.constant());
+        b.mul_into(
+            bit_decomp_32_tmp,
+            bit_decomp_32_tmp,
+            b.column(lv, g_bit(bit)),
+        );
         // degree 3
         // yield_constr.constraint(is_phase_0_or_1 * (curr_row[not_e_and_g_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(not_e_and_g_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(lv, not_e_and_g_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // let computed_bit = xor_gen(curr_row[e_and_f_bit(bit)], curr_row[not_e_and_g_bit(bit)]);
-        xor_gen(
-            &mut res,
-            curr_row(e_and_f_bit(bit)),
-            curr_row(not_e_and_g_bit(bit)),
+        let (x, y) = (
+            b.column(lv, e_and_f_bit(bit)),
+            b.column(lv, not_e_and_g_bit(bit)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr.constraint(is_phase_0_or_1 * (curr_row[ch_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(ch_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(constraint, b.column(lv, ch_bit(bit)), bit_decomp_32_tmp);
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // S0 := (a >>> 2) xor (a >>> 13) xor (a >>> 22)
@@ -979,67 +388,35 @@ fn eval_packed_generic(
         //     curr_row[a_bit((bit + 2) % 32)],
         //     curr_row[a_bit((bit + 13) % 32)],
         // );
-        xor_gen(
-            &mut res,
-            curr_row(a_bit((bit + 2) % 32)),
-            curr_row(a_bit((bit + 13) % 32)),
+        let (x, y) = (
+            b.column(lv, a_bit((bit + 2) % 32)),
+            b.column(lv, a_bit((bit + 13) % 32)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr.constraint(is_phase_0_or_1 * (curr_row[xor_tmp_3_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(xor_tmp_3_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(lv, xor_tmp_3_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // let computed_bit = xor_gen(
         //     curr_row[xor_tmp_3_bit(bit)],
         //     curr_row[a_bit((bit + 22) % 32)],
         // );
-        xor_gen(
-            &mut res,
-            curr_row(xor_tmp_3_bit(bit)),
-            curr_row(a_bit((bit + 22) % 32)),
+        let (x, y) = (
+            b.column(lv, xor_tmp_3_bit(bit)),
+            b.column(lv, a_bit((bit + 22) % 32)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr.constraint(is_phase_0_or_1 * (curr_row[big_s0_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(big_s0_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(constraint, b.column(lv, big_s0_bit(bit)), bit_decomp_32_tmp);
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // maj := (a and b) xor (a and c) xor (b and c)
@@ -1049,168 +426,70 @@ fn eval_packed_generic(
         //     is_phase_0_or_1
         //         * (curr_row[a_and_b_bit(bit)] - curr_row[a_bit(bit)] * curr_row[b_bit(bit)]),
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(a_bit(bit)),
-                addr_input_1: curr_row(b_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(a_and_b_bit(bit)),
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, a_bit(bit)),
+            b.column(lv, b_bit(bit)),
+        );
+        b.sub_into(constraint, b.column(lv, a_and_b_bit(bit)), constraint);
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // degree 3
         // yield_constr.constraint(
         //     is_phase_0_or_1
         //         * (curr_row[a_and_c_bit(bit)] - curr_row[a_bit(bit)] * curr_row[c_bit(bit)]),
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(a_bit(bit)),
-                addr_input_1: curr_row(c_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(a_and_c_bit(bit)),
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, a_bit(bit)),
+            b.column(lv, c_bit(bit)),
+        );
+        b.sub_into(constraint, b.column(lv, a_and_c_bit(bit)), constraint);
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // degree 3
         // yield_constr.constraint(
         //     is_phase_0_or_1
         //         * (curr_row[b_and_c_bit(bit)] - curr_row[b_bit(bit)] * curr_row[c_bit(bit)]),
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(b_bit(bit)),
-                addr_input_1: curr_row(c_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(b_and_c_bit(bit)),
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, b_bit(bit)),
+            b.column(lv, c_bit(bit)),
+        );
+        b.sub_into(constraint, b.column(lv, b_and_c_bit(bit)), constraint);
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // let computed_bit = xor_gen(curr_row[a_and_b_bit(bit)], curr_row[a_and_c_bit(bit)]);
-        xor_gen(
-            &mut res,
-            curr_row(a_and_b_bit(bit)),
-            curr_row(a_and_c_bit(bit)),
+        let (x, y) = (
+            b.column(lv, a_and_b_bit(bit)),
+            b.column(lv, a_and_c_bit(bit)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr.constraint(is_phase_0_or_1 * (curr_row[xor_tmp_4_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(xor_tmp_4_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(lv, xor_tmp_4_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // let computed_bit = xor_gen(curr_row[xor_tmp_4_bit(bit)], curr_row[b_and_c_bit(bit)]);
-        xor_gen(
-            &mut res,
-            curr_row(xor_tmp_4_bit(bit)),
-            curr_row(b_and_c_bit(bit)),
+        let (x, y) = (
+            b.column(lv, xor_tmp_4_bit(bit)),
+            b.column(lv, b_and_c_bit(bit)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr.constraint(is_phase_0_or_1 * (curr_row[maj_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(maj_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(constraint, b.column(lv, maj_bit(bit)), bit_decomp_32_tmp);
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // set round constant
@@ -1220,277 +499,88 @@ fn eval_packed_generic(
         //     curr_row[step_bit(step)]
         //         * (curr_row[KI] - FE::from_canonical_u32(ROUND_CONSTANTS[step])),
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(KI),
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: curr_row(step_bit(step)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(constraint, b.column(lv, KI), b.constant());
+        b.mul_into(constraint, constraint, b.column(lv, step_bit(step)));
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // temp1 := h + S1 + ch + k[i] + w[i]
-    let addr_temp1_minus_ki = sys
-        .mem
-        .alloc("temp1_minus_ki", BATCH_SIZE * SIZE_F)
-        .unwrap();
+    let temp1_minus_ki = b.alloc("temp1_minus_ki");
     // e := d + temp1
-    let h_field = curr_row(H_COL);
+    let h_field = b.column(lv, H_COL);
     // let big_s1_field = bit_decomp_32!(curr_row, big_s1_bit, FE, P);
-    bit_decomp_32(&mut res, addr_c, &curr_row, big_s1_bit);
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: h_field,
-        addr_input_1: addr_c,
-        addr_output: addr_temp1_minus_ki,
-        is_final_output: false,
-        op_type: VecOpType::ADD,
-        op_src: VecOpSrc::VV,
-    });
+    bit_decomp_32(&mut b, c);
+    b.add_into(temp1_minus_ki, h_field, c);
 
     // let ch_field = bit_decomp_32!(curr_row, ch_bit, FE, P);
-    bit_decomp_32(&mut res, addr_c, &curr_row, ch_bit);
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_temp1_minus_ki,
-        addr_input_1: addr_c,
-        addr_output: addr_temp1_minus_ki,
-        is_final_output: false,
-        op_type: VecOpType::ADD,
-        op_src: VecOpSrc::VV,
-    });
+    bit_decomp_32(&mut b, c);
+    b.add_into(temp1_minus_ki, temp1_minus_ki, c);
 
     // let wi_u32 = bit_decomp_32!(curr_row, wi_bit, FE, P);
-    bit_decomp_32(&mut res, addr_c, &curr_row, wi_bit);
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_temp1_minus_ki,
-        addr_input_1: addr_c,
-        addr_output: addr_temp1_minus_ki,
-        is_final_output: false,
-        op_type: VecOpType::ADD,
-        op_src: VecOpSrc::VV,
-    });
+    bit_decomp_32(&mut b, c);
+    b.add_into(temp1_minus_ki, temp1_minus_ki, c);
 
     // let temp1_minus_ki = h_field + big_s1_field + ch_field + wi_u32;
 
     // let d_field = curr_row[D_COL];
-    let d_field = curr_row(D_COL);
+    let d_field = b.column(lv, D_COL);
 
     // let e_u32_next = bit_decomp_32!(next_row, e_bit, FE, P);
-    bit_decomp_32(&mut res, addr_c, &next_row, e_bit);
+    bit_decomp_32(&mut b, c);
 
     // degree 2
     // yield_constr.constraint(
     //     is_phase_0_or_1 * (curr_row[E_NEXT_FIELD] - (d_field + temp1_minus_ki + curr_row[KI])),
     // );
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: d_field,
-            addr_input_1: addr_temp1_minus_ki,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: curr_row(KI),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(E_NEXT_FIELD),
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_is_phase_0_or_1,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint(addr_constraint));
+    b.add_into(constraint, d_field, temp1_minus_ki);
+    b.add_into(constraint, constraint, b.column(lv, KI));
+    b.sub_into(constraint, b.column(lv, E_NEXT_FIELD), constraint);
+    b.mul_into(constraint, is_phase_0_or_1, constraint);
+    b.extend(yield_constr.constraint(addr_constraint));
     // degree 3
     // yield_constr.constraint_transition(
     //     is_phase_0_or_1
     //         * (curr_row[E_NEXT_FIELD]
     //             - (e_u32_next + curr_row[E_NEXT_QUOTIENT] * FE::from_canonical_u64(1 << 32))),
     // );
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(E_NEXT_QUOTIENT),
-            addr_input_1: 0,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_c,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(E_NEXT_FIELD),
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_is_phase_0_or_1,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    b.mul_into(constraint, b.column(lv, E_NEXT_QUOTIENT), b.constant());
+    b.add_into(constraint, c, constraint);
+    b.sub_into(constraint, b.column(lv, E_NEXT_FIELD), constraint);
+    b.mul_into(constraint, is_phase_0_or_1, constraint);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     // temp2 := S0 + maj
     // a := temp1 + temp2
     // let s0_field = bit_decomp_32!(curr_row, big_s0_bit, FE, P);
-    bit_decomp_32(&mut res, addr_c, &curr_row, big_s0_bit);
+    bit_decomp_32(&mut b, c);
     // let maj_field = bit_decomp_32!(curr_row, maj_bit, FE, P);
-    bit_decomp_32(&mut res, addr_constraint, &curr_row, maj_bit);
+    bit_decomp_32(&mut b, constraint);
     // let temp2 = s0_field + maj_field;
 
     // degree 2
     // yield_constr.constraint(
     //     is_phase_0_or_1 * (curr_row[A_NEXT_FIELD] - (temp2 + temp1_minus_ki + curr_row[KI])),
     // );
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_c,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: addr_temp1_minus_ki,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: curr_row(KI),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(A_NEXT_FIELD),
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_is_phase_0_or_1,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint(addr_constraint));
+    b.add_into(constraint, c, constraint);
+    b.add_into(constraint, constraint, temp1_minus_ki);
+    b.add_into(constraint, constraint, b.column(lv, KI));
+    b.sub_into(constraint, b.column(lv, A_NEXT_FIELD), constraint);
+    b.mul_into(constraint, is_phase_0_or_1, constraint);
+    b.extend(yield_constr.constraint(addr_constraint));
 
     // degree 3
     // let a_u32_next = bit_decomp_32!(next_row, a_bit, FE, P);
-    bit_decomp_32(&mut res, addr_c, &next_row, a_bit);
+    bit_decomp_32(&mut b, c);
     // yield_constr.constraint_transition(
     //     is_phase_0_or_1
     //         * (curr_row[A_NEXT_FIELD]
     //             - (a_u32_next + curr_row[A_NEXT_QUOTIENT] * FE::from_canonical_u64(1 << 32))),
     // );
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(A_NEXT_QUOTIENT),
-            addr_input_1: 0,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_c,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(A_NEXT_FIELD),
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_is_phase_0_or_1,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    b.mul_into(constraint, b.column(lv, A_NEXT_QUOTIENT), b.constant());
+    b.add_into(constraint, c, constraint);
+    b.sub_into(constraint, b.column(lv, A_NEXT_FIELD), constraint);
+    b.mul_into(constraint, is_phase_0_or_1, constraint);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     // update local vars when not in last step
     // h := g
@@ -1500,153 +590,61 @@ fn eval_packed_generic(
     // c := b
     // b := a
     // let decomp = bit_decomp_32!(curr_row, g_bit, FE, P);
-    bit_decomp_32(&mut res, addr_constraint, &curr_row, g_bit);
+    bit_decomp_32(&mut b, constraint);
     // yield_constr.constraint_transition(is_phase_0_or_1 * (next_row[H_COL] - decomp));
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: next_row(H_COL),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_is_phase_0_or_1,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    b.sub_into(constraint, constraint, b.column(nv, H_COL));
+    b.mul_into(constraint, is_phase_0_or_1, constraint);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     // let decomp = bit_decomp_32!(curr_row, c_bit, FE, P);
-    bit_decomp_32(&mut res, addr_constraint, &curr_row, c_bit);
+    bit_decomp_32(&mut b, constraint);
     // yield_constr.constraint_transition(is_phase_0_or_1 * (next_row[D_COL] - decomp));
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: next_row(D_COL),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_is_phase_0_or_1,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    b.sub_into(constraint, constraint, b.column(nv, D_COL));
+    b.mul_into(constraint, is_phase_0_or_1, constraint);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     for bit in 0..32 {
         // degree 2
         // yield_constr
         //     .constraint_transition(is_phase_0_or_1 * (next_row[g_bit(bit)] - curr_row[f_bit(bit)]));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(g_bit(bit)),
-                addr_input_1: curr_row(f_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, g_bit(bit)),
+            b.column(lv, f_bit(bit)),
+        );
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
         // yield_constr
         //     .constraint_transition(is_phase_0_or_1 * (next_row[f_bit(bit)] - curr_row[e_bit(bit)]));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(f_bit(bit)),
-                addr_input_1: curr_row(e_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, f_bit(bit)),
+            b.column(lv, e_bit(bit)),
+        );
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
         // yield_constr
         //     .constraint_transition(is_phase_0_or_1 * (next_row[c_bit(bit)] - curr_row[b_bit(bit)]));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(c_bit(bit)),
-                addr_input_1: curr_row(b_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, c_bit(bit)),
+            b.column(lv, b_bit(bit)),
+        );
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
         // yield_constr
         //     .constraint_transition(is_phase_0_or_1 * (next_row[b_bit(bit)] - curr_row[a_bit(bit)]));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(b_bit(bit)),
-                addr_input_1: curr_row(a_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_is_phase_0_or_1,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, b_bit(bit)),
+            b.column(lv, a_bit(bit)),
+        );
+        b.mul_into(constraint, is_phase_0_or_1, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
 
     // update his in last step of phase 1
-    let update_his = curr_row(step_bit(63));
+    let update_his = b.column(lv, step_bit(63));
     // let vars = [
     //     bit_decomp_32!(next_row, a_bit, FE, P),
     //     bit_decomp_32!(next_row, b_bit, FE, P),
@@ -1657,56 +655,24 @@ fn eval_packed_generic(
     //     bit_decomp_32!(next_row, g_bit, FE, P),
     //     next_row[H_COL],
     // ];
-    let vars_0 = vec![a_bit, b_bit, c_bit];
-    let vars_1 = vec![e_bit, f_bit, g_bit];
-
     for i in 0..8 {
         // degree 2
-        let addr_var = if i < 3 {
-            bit_decomp_32(&mut res, addr_constraint, &next_row, vars_0[i]);
-            addr_constraint
-        } else if i == 3 {
-            next_row(D_COL)
-        } else if i < 7 {
-            bit_decomp_32(&mut res, addr_constraint, &next_row, vars_1[i - 4]);
-            addr_constraint
-        } else {
-            next_row(H_COL)
+        let var = match i {
+            3 => b.column(nv, D_COL),
+            7 => b.column(nv, H_COL),
+            _ => {
+                bit_decomp_32(&mut b, constraint);
+                constraint
+            }
         };
 
         // yield_constr.constraint_transition(
         //     update_his * (curr_row[h_i_next_field(i)] - (curr_row[h_i(i)] + vars[i])),
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(h_i(i)),
-                addr_input_1: addr_var,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::ADD,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(h_i_next_quotient(i)),
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: update_his,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.add_into(constraint, b.column(lv, h_i(i)), var);
+        b.sub_into(constraint, b.column(lv, h_i_next_quotient(i)), constraint);
+        b.mul_into(constraint, constraint, update_his);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
 
         // degree 3
         // yield_constr.constraint_transition(
@@ -1715,45 +681,11 @@ fn eval_packed_generic(
         //             - (next_row[h_i(i)]
         //                 + curr_row[h_i_next_quotient(i)] * FE::from_canonical_u64(1 << 32))),
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(h_i_next_quotient(i)),
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(h_i(i)),
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::ADD,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(h_i_next_field(i)),
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: update_his,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.mul_into(constraint, b.column(lv, h_i_next_quotient(i)), b.constant());
+        b.add_into(constraint, b.column(nv, h_i(i)), constraint);
+        b.sub_into(constraint, b.column(lv, h_i_next_field(i)), constraint);
+        b.mul_into(constraint, update_his, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
 
     // set output to his during last step, 0 otherwise
@@ -1764,118 +696,30 @@ fn eval_packed_generic(
         //         * (curr_row[output_i(i)]
         //             - (curr_row[h_i(i)] + curr_row[HASH_IDX] * FE::from_canonical_u64(1 << 32))),
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(HASH_IDX),
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(h_i(i)),
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::ADD,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(output_i(i)),
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: is_last_step,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(constraint, b.column(lv, HASH_IDX), b.constant());
+        b.add_into(constraint, b.column(lv, h_i(i)), constraint);
+        b.sub_into(constraint, b.column(lv, output_i(i)), constraint);
+        b.mul_into(constraint, is_last_step, constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
         // yield_constr.constraint((P::ONES - is_last_step) * curr_row[output_i(i)])
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: is_last_step,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(output_i(i)),
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.sub_into(constraint, is_last_step, b.constant());
+        b.mul_into(constraint, b.column(lv, output_i(i)), constraint);
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // set output filter to 1 iff it's the last step, zero otherwise
     // yield_constr.constraint(is_last_step * (P::ONES - curr_row[OUTPUT_FILTER]));
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: 0,
-            addr_input_1: curr_row(OUTPUT_FILTER),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: is_last_step,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint(addr_constraint));
+    b.sub_into(constraint, b.constant(), b.column(lv, OUTPUT_FILTER));
+    b.mul_into(constraint, is_last_step, constraint);
+    b.extend(yield_constr.constraint(addr_constraint));
     // yield_constr.constraint((P::ONES - is_last_step) * curr_row[OUTPUT_FILTER]);
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: is_last_step,
-            addr_input_1: 0,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: curr_row(OUTPUT_FILTER),
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint(addr_constraint));
+    b.sub_into(constraint, is_last_step, b.constant());
+    b.mul_into(constraint, b.column(lv, OUTPUT_FILTER), constraint);
+    b.extend(yield_constr.constraint(addr_constraint));
 
     // message schedule to get next row's wi when next row is in phase 1
 
-    let addr_do_msg_schedule = addr_next_is_phase_1;
+    let do_msg_schedule = next_is_phase_1;
 
     // s0 := (w[i-15] >>> 7) xor (w[i-15] >>> 18) xor (w[i-15] >> 3)
     for bit in 0..29 {
@@ -1883,69 +727,41 @@ fn eval_packed_generic(
         //     next_row[wi_minus_15_bit((bit + 7) % 32)],
         //     next_row[wi_minus_15_bit((bit + 18) % 32)],
         // );
-        xor_gen(
-            &mut res,
-            next_row(wi_minus_15_bit((bit + 7) % 32)),
-            next_row(wi_minus_15_bit((bit + 18) % 32)),
+        let (x, y) = (
+            b.column(nv, wi_minus_15_bit((bit + 7) % 32)),
+            b.column(nv, wi_minus_15_bit((bit + 18) % 32)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr
         //     .constraint_transition(do_msg_schedule * (next_row[xor_tmp_0_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(xor_tmp_0_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_do_msg_schedule,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, xor_tmp_0_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, do_msg_schedule, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
 
         // let computed_bit = xor_gen(
         //     next_row[xor_tmp_0_bit(bit)],
         //     next_row[wi_minus_15_bit(bit + 3)],
         // );
-        xor_gen(
-            &mut res,
-            next_row(xor_tmp_0_bit(bit)),
-            next_row(wi_minus_15_bit(bit + 3)),
+        let (x, y) = (
+            b.column(nv, xor_tmp_0_bit(bit)),
+            b.column(nv, wi_minus_15_bit(bit + 3)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr
         //     .constraint_transition(do_msg_schedule * (next_row[little_s0_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(little_s0_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_do_msg_schedule,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, little_s0_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, do_msg_schedule, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
     for bit in 29..32 {
         // we can ignore the second XOR in this case since it's with 0
@@ -1953,35 +769,21 @@ fn eval_packed_generic(
         //     next_row[wi_minus_15_bit((bit + 7) % 32)],
         //     next_row[wi_minus_15_bit((bit + 18) % 32)],
         // );
-        xor_gen(
-            &mut res,
-            next_row(wi_minus_15_bit((bit + 7) % 32)),
-            next_row(wi_minus_15_bit((bit + 18) % 32)),
+        let (x, y) = (
+            b.column(nv, wi_minus_15_bit((bit + 7) % 32)),
+            b.column(nv, wi_minus_15_bit((bit + 18) % 32)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr
         //     .constraint_transition(do_msg_schedule * (next_row[little_s0_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(little_s0_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_do_msg_schedule,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, little_s0_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, do_msg_schedule, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
 
     // s1 := (w[i-2] >>> 17) xor (w[i-2] >>> 19) xor (w[i-2] >> 10)
@@ -1990,69 +792,41 @@ fn eval_packed_generic(
         //     next_row[wi_minus_2_bit((bit + 17) % 32)],
         //     next_row[wi_minus_2_bit((bit + 19) % 32)],
         // );
-        xor_gen(
-            &mut res,
-            next_row(wi_minus_2_bit((bit + 17) % 32)),
-            next_row(wi_minus_2_bit((bit + 19) % 32)),
+        let (x, y) = (
+            b.column(nv, wi_minus_2_bit((bit + 17) % 32)),
+            b.column(nv, wi_minus_2_bit((bit + 19) % 32)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr
         //     .constraint_transition(do_msg_schedule * (next_row[xor_tmp_1_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(xor_tmp_1_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_do_msg_schedule,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, xor_tmp_1_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, do_msg_schedule, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
 
         // let computed_bit = xor_gen(
         //     next_row[xor_tmp_1_bit(bit)],
         //     next_row[wi_minus_2_bit(bit + 10)],
         // );
-        xor_gen(
-            &mut res,
-            next_row(xor_tmp_1_bit(bit)),
-            next_row(wi_minus_2_bit(bit + 10)),
+        let (x, y) = (
+            b.column(nv, xor_tmp_1_bit(bit)),
+            b.column(nv, wi_minus_2_bit(bit + 10)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr
         //     .constraint_transition(do_msg_schedule * (next_row[little_s1_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(little_s1_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_do_msg_schedule,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, little_s1_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, do_msg_schedule, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
     for bit in 22..32 {
         // we can ignore the second XOR in this case since it's with 0
@@ -2060,74 +834,36 @@ fn eval_packed_generic(
         //     next_row[wi_minus_2_bit((bit + 17) % 32)],
         //     next_row[wi_minus_2_bit((bit + 19) % 32)],
         // );
-        xor_gen(
-            &mut res,
-            next_row(wi_minus_2_bit((bit + 17) % 32)),
-            next_row(wi_minus_2_bit((bit + 19) % 32)),
+        let (x, y) = (
+            b.column(nv, wi_minus_2_bit((bit + 17) % 32)),
+            b.column(nv, wi_minus_2_bit((bit + 19) % 32)),
         );
+        xor_gen(&mut b, x, y);
         // degree 3
         // yield_constr
         //     .constraint_transition(do_msg_schedule * (next_row[little_s1_bit(bit)] - computed_bit));
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(little_s1_bit(bit)),
-                addr_input_1: addr_bit_decomp_32_tmp,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_do_msg_schedule,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, little_s1_bit(bit)),
+            bit_decomp_32_tmp,
+        );
+        b.mul_into(constraint, do_msg_schedule, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
 
     // w[i] := w[i-16] + s0 + w[i-7] + s1
 
     // degree 1
     // let s0_field_computed = bit_decomp_32!(next_row, little_s0_bit, FE, P);
-    bit_decomp_32(&mut res, addr_c, &next_row, little_s0_bit);
+    bit_decomp_32(&mut b, c);
     // let s1_field_computed = bit_decomp_32!(next_row, little_s1_bit, FE, P);
-    bit_decomp_32(&mut res, addr_constraint, &next_row, little_s1_bit);
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_c,
-        addr_input_1: addr_constraint,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::ADD,
-        op_src: VecOpSrc::VV,
-    });
+    bit_decomp_32(&mut b, constraint);
+    b.add_into(constraint, c, constraint);
     // let wi_minus_16_field = bit_decomp_32!(curr_row, wi_minus_15_bit, FE, P);
-    bit_decomp_32(&mut res, addr_c, &curr_row, wi_minus_15_bit);
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: addr_c,
-        addr_input_1: addr_constraint,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::ADD,
-        op_src: VecOpSrc::VV,
-    });
+    bit_decomp_32(&mut b, c);
+    b.add_into(constraint, c, constraint);
     // let wi_minus_7_field = next_row[wi_field(8)];
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: next_row(wi_field(8)),
-        addr_input_1: addr_constraint,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::ADD,
-        op_src: VecOpSrc::VV,
-    });
+    b.add_into(constraint, b.column(nv, wi_field(8)), constraint);
 
     // degree 2
     // yield_constr.constraint_transition(
@@ -2135,90 +871,30 @@ fn eval_packed_generic(
     //         * (next_row[WI_FIELD]
     //             - (wi_minus_16_field + s0_field_computed + wi_minus_7_field + s1_field_computed)),
     // );
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_constraint,
-            addr_input_1: next_row(WI_FIELD),
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_do_msg_schedule,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint_transition(addr_constraint));
+    b.sub_into(constraint, constraint, b.column(nv, WI_FIELD));
+    b.mul_into(constraint, do_msg_schedule, constraint);
+    b.extend(yield_constr.constraint_transition(addr_constraint));
 
     // let wi = bit_decomp_32!(next_row, wi_bit, FE, P);
-    bit_decomp_32(&mut res, addr_c, &next_row, wi_bit);
+    bit_decomp_32(&mut b, c);
     // degree 3
     // yield_constr.constraint(
     //     do_msg_schedule
     //         * (next_row[WI_FIELD] - (wi + next_row[WI_QUOTIENT] * FE::from_canonical_u64(1 << 32))),
     // );
-    res.extend(vec![
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: next_row(WI_QUOTIENT),
-            addr_input_1: 0,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_c,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: next_row(WI_FIELD),
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VV,
-        },
-        VecOpConfig {
-            vector_length: BATCH_SIZE,
-            addr_input_0: addr_do_msg_schedule,
-            addr_input_1: addr_constraint,
-            addr_output: addr_constraint,
-            is_final_output: false,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-        },
-    ]);
-    res.extend(yield_constr.constraint(addr_constraint));
+    b.mul_into(constraint, b.column(nv, WI_QUOTIENT), b.constant());
+    b.add_into(constraint, c, constraint);
+    b.sub_into(constraint, b.column(nv, WI_FIELD), constraint);
+    b.mul_into(constraint, do_msg_schedule, constraint);
+    b.extend(yield_constr.constraint(addr_constraint));
 
     // set initial step bits to a 1 followed by NUM_STEPS_PER_HASH-1 0s
     // yield_constr.constraint_first_row(P::ONES - curr_row[step_bit(0)]);
-    res.push(VecOpConfig {
-        vector_length: BATCH_SIZE,
-        addr_input_0: curr_row(step_bit(0)),
-        addr_input_1: 0,
-        addr_output: addr_constraint,
-        is_final_output: false,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VV,
-    });
-    res.extend(yield_constr.constraint_first_row(addr_constraint));
+    b.sub_into(constraint, b.column(lv, step_bit(0)), b.constant());
+    b.extend(yield_constr.constraint_first_row(addr_constraint));
     for step in 1..NUM_STEPS_PER_HASH {
         // yield_constr.constraint_first_row(curr_row[step_bit(step)]);
-        res.extend(yield_constr.constraint_first_row(curr_row(step_bit(step))));
+        b.extend(yield_constr.constraint_first_row(b.column(lv, step_bit(step)).addr()));
     }
 
     // inc step bits when next is not padding
@@ -2228,392 +904,182 @@ fn eval_packed_generic(
         //     next_is_not_padding
         //         * (next_row[step_bit((bit + 1) % NUM_STEPS_PER_HASH)] - curr_row[step_bit(bit)]),
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: next_row(step_bit((bit + 1) % NUM_STEPS_PER_HASH)),
-                addr_input_1: curr_row(step_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_next_is_not_padding,
-                addr_input_1: addr_constraint,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint_transition(addr_constraint));
+        b.sub_into(
+            constraint,
+            b.column(nv, step_bit((bit + 1) % NUM_STEPS_PER_HASH)),
+            b.column(lv, step_bit(bit)),
+        );
+        b.mul_into(constraint, next_is_not_padding, constraint);
+        b.extend(yield_constr.constraint_transition(addr_constraint));
     }
 
     // step bits
     for bit in 0..NUM_STEPS_PER_HASH {
         // yield_constr.constraint((P::ONES - curr_row[step_bit(bit)]) * curr_row[step_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(step_bit(bit)),
-                addr_input_1: curr_row(step_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, step_bit(bit)),
+            b.column(lv, step_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     for bit in 0..32 {
         // wi
         // yield_constr.constraint((P::ONES - curr_row[wi_bit(bit)]) * curr_row[wi_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(wi_bit(bit)),
-                addr_input_1: curr_row(wi_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, wi_bit(bit)),
+            b.column(lv, wi_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // wi minus 2
         // yield_constr
         //     .constraint((P::ONES - curr_row[wi_minus_2_bit(bit)]) * curr_row[wi_minus_2_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(wi_minus_2_bit(bit)),
-                addr_input_1: curr_row(wi_minus_2_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, wi_minus_2_bit(bit)),
+            b.column(lv, wi_minus_2_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // wi minus 15
         // yield_constr.constraint(
         //     (P::ONES - curr_row[wi_minus_15_bit(bit)]) * curr_row[wi_minus_15_bit(bit)],
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(wi_minus_15_bit(bit)),
-                addr_input_1: curr_row(wi_minus_15_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, wi_minus_15_bit(bit)),
+            b.column(lv, wi_minus_15_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // s0
     for bit in 0..32 {
         // yield_constr
         //     .constraint((P::ONES - curr_row[little_s0_bit(bit)]) * curr_row[little_s0_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(little_s0_bit(bit)),
-                addr_input_1: curr_row(little_s0_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, little_s0_bit(bit)),
+            b.column(lv, little_s0_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // s1
     for bit in 0..32 {
         // yield_constr
         //     .constraint((P::ONES - curr_row[little_s1_bit(bit)]) * curr_row[little_s1_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(little_s1_bit(bit)),
-                addr_input_1: curr_row(little_s1_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, little_s1_bit(bit)),
+            b.column(lv, little_s1_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // a
     for bit in 0..32 {
         // yield_constr.constraint((P::ONES - curr_row[a_bit(bit)]) * curr_row[a_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(a_bit(bit)),
-                addr_input_1: curr_row(a_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, a_bit(bit)),
+            b.column(lv, a_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // b
     for bit in 0..32 {
         // yield_constr.constraint((P::ONES - curr_row[b_bit(bit)]) * curr_row[b_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(b_bit(bit)),
-                addr_input_1: curr_row(b_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, b_bit(bit)),
+            b.column(lv, b_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // c
     for bit in 0..32 {
         // yield_constr.constraint((P::ONES - curr_row[c_bit(bit)]) * curr_row[c_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(c_bit(bit)),
-                addr_input_1: curr_row(c_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, c_bit(bit)),
+            b.column(lv, c_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // e
     for bit in 0..32 {
         // yield_constr.constraint((P::ONES - curr_row[e_bit(bit)]) * curr_row[e_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(e_bit(bit)),
-                addr_input_1: curr_row(e_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, e_bit(bit)),
+            b.column(lv, e_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // f
     for bit in 0..32 {
         // yield_constr.constraint((P::ONES - curr_row[f_bit(bit)]) * curr_row[f_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(f_bit(bit)),
-                addr_input_1: curr_row(f_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, f_bit(bit)),
+            b.column(lv, f_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // g
     for bit in 0..32 {
         // yield_constr.constraint((P::ONES - curr_row[g_bit(bit)]) * curr_row[g_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(g_bit(bit)),
-                addr_input_1: curr_row(g_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, g_bit(bit)),
+            b.column(lv, g_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // S0
     for bit in 0..32 {
         // yield_constr.constraint((P::ONES - curr_row[big_s0_bit(bit)]) * curr_row[big_s0_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(big_s0_bit(bit)),
-                addr_input_1: curr_row(big_s0_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, big_s0_bit(bit)),
+            b.column(lv, big_s0_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // S1
     for bit in 0..32 {
         // yield_constr.constraint((P::ONES - curr_row[big_s1_bit(bit)]) * curr_row[big_s1_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(big_s1_bit(bit)),
-                addr_input_1: curr_row(big_s1_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, big_s1_bit(bit)),
+            b.column(lv, big_s1_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // (not e) and g
@@ -2621,331 +1087,152 @@ fn eval_packed_generic(
         // yield_constr.constraint(
         //     (P::ONES - curr_row[not_e_and_g_bit(bit)]) * curr_row[not_e_and_g_bit(bit)],
         // );
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(not_e_and_g_bit(bit)),
-                addr_input_1: curr_row(not_e_and_g_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, not_e_and_g_bit(bit)),
+            b.column(lv, not_e_and_g_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // e and f
     for bit in 0..32 {
         // yield_constr
         //     .constraint((P::ONES - curr_row[e_and_f_bit(bit)]) * curr_row[e_and_f_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(e_and_f_bit(bit)),
-                addr_input_1: curr_row(e_and_f_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, e_and_f_bit(bit)),
+            b.column(lv, e_and_f_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // ch
     for bit in 0..32 {
         // yield_constr.constraint((P::ONES - curr_row[ch_bit(bit)]) * curr_row[ch_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(ch_bit(bit)),
-                addr_input_1: curr_row(ch_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, ch_bit(bit)),
+            b.column(lv, ch_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // a and b
     for bit in 0..32 {
         // yield_constr
         //     .constraint((P::ONES - curr_row[a_and_b_bit(bit)]) * curr_row[a_and_b_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(a_and_b_bit(bit)),
-                addr_input_1: curr_row(a_and_b_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, a_and_b_bit(bit)),
+            b.column(lv, a_and_b_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // a and c
     for bit in 0..32 {
         // yield_constr
         //     .constraint((P::ONES - curr_row[a_and_c_bit(bit)]) * curr_row[a_and_c_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(a_and_c_bit(bit)),
-                addr_input_1: curr_row(a_and_c_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, a_and_c_bit(bit)),
+            b.column(lv, a_and_c_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // b and c
     for bit in 0..32 {
         // yield_constr
         //     .constraint((P::ONES - curr_row[b_and_c_bit(bit)]) * curr_row[b_and_c_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(b_and_c_bit(bit)),
-                addr_input_1: curr_row(b_and_c_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, b_and_c_bit(bit)),
+            b.column(lv, b_and_c_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // maj
     for bit in 0..32 {
         // yield_constr.constraint((P::ONES - curr_row[maj_bit(bit)]) * curr_row[maj_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(maj_bit(bit)),
-                addr_input_1: curr_row(maj_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, maj_bit(bit)),
+            b.column(lv, maj_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
     // tmps
     for bit in 0..29 {
         // yield_constr
         //     .constraint((P::ONES - curr_row[xor_tmp_0_bit(bit)]) * curr_row[xor_tmp_0_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(xor_tmp_0_bit(bit)),
-                addr_input_1: curr_row(xor_tmp_0_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, xor_tmp_0_bit(bit)),
+            b.column(lv, xor_tmp_0_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
     for bit in 0..22 {
         // yield_constr
         //     .constraint((P::ONES - curr_row[xor_tmp_1_bit(bit)]) * curr_row[xor_tmp_1_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(xor_tmp_1_bit(bit)),
-                addr_input_1: curr_row(xor_tmp_1_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, xor_tmp_1_bit(bit)),
+            b.column(lv, xor_tmp_1_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
     for bit in 0..32 {
         // yield_constr
         //     .constraint((P::ONES - curr_row[xor_tmp_2_bit(bit)]) * curr_row[xor_tmp_2_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(xor_tmp_2_bit(bit)),
-                addr_input_1: curr_row(xor_tmp_2_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, xor_tmp_2_bit(bit)),
+            b.column(lv, xor_tmp_2_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // yield_constr
         //     .constraint((P::ONES - curr_row[xor_tmp_3_bit(bit)]) * curr_row[xor_tmp_3_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(xor_tmp_3_bit(bit)),
-                addr_input_1: curr_row(xor_tmp_3_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, xor_tmp_3_bit(bit)),
+            b.column(lv, xor_tmp_3_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
 
         // yield_constr
         //     .constraint((P::ONES - curr_row[xor_tmp_4_bit(bit)]) * curr_row[xor_tmp_4_bit(bit)]);
-        res.extend(vec![
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: curr_row(xor_tmp_4_bit(bit)),
-                addr_input_1: curr_row(xor_tmp_4_bit(bit)),
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-            },
-            VecOpConfig {
-                vector_length: BATCH_SIZE,
-                addr_input_0: addr_constraint,
-                addr_input_1: 0,
-                addr_output: addr_constraint,
-                is_final_output: false,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VV,
-            },
-        ]);
-        res.extend(yield_constr.constraint(addr_constraint));
+        b.mul_into(
+            constraint,
+            b.column(lv, xor_tmp_4_bit(bit)),
+            b.column(lv, xor_tmp_4_bit(bit)),
+        );
+        b.sub_into(constraint, constraint, b.constant());
+        b.extend(yield_constr.constraint(addr_constraint));
     }
 
-    sys.mem.free("his_should_change");
-    sys.mem.free("next_is_phase_0");
-    sys.mem.free("next_is_phase_1");
-    sys.mem.free("next_is_not_padding");
-    sys.mem.free("transition_to_next_hash");
-    sys.mem.free("curr_next_row_hash_idx");
-    sys.mem.free("bit_decomp_32_tmp");
-    sys.mem.free("rotate_wis");
-    sys.mem.free("rotate_wis_plus_shift_wis");
-    sys.mem.free("is_phase_0_or_1");
-    sys.mem.free("temp1_minus_ki");
-    sys.mem.free("c");
-    res
+    b.finish()
 }
 
 fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let hw = set_config();
 
     let args: Vec<String> = env::args().collect();
     let num_hashes = args[1].parse::<i32>().unwrap();
@@ -2954,10 +1241,10 @@ fn main() {
         num_hashes
     );
 
-    let mut ramsim = RamConfig::new(&format!("{}", "sha256_starky"));
-    // ramsim.txt_output = true;
+    let ramsim = RamConfig::new(&format!("{}", "sha256_starky"));
+    // ramsim.add_text_output();
     let mem = MemAlloc::new(32, 4096);
-    let mut sys = System::new(mem, ramsim);
+    let mut sys = System::new(mem, ramsim, hw);
 
     let mut compressor = Sha2StarkCompressor::new();
     let _zero_bytes = [0; 32];
@@ -2988,6 +1275,35 @@ fn main() {
     sys.ramsim.static_();
 
     info!("Start Ramsim");
-    sys.ramsim.run();
+    sys.finish().unwrap();
     info!("End Ramsim");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use unizk::config::{Backend, HwConfig};
+
+    // the op count and traffic of the port before VecBuilder, for 4 hashes
+    #[test]
+    fn test_vec_builder_port() {
+        let mut hw = HwConfig::default();
+        hw.timing.backend = Backend::Analytical;
+        let ramsim = RamConfig::new("test_sha256_starky");
+        let mut sys = System::new(MemAlloc::new(32, 4096), ramsim, hw);
+
+        let mut compressor = Sha2StarkCompressor::new();
+        for i in 0..4 {
+            let mut left = [0u32; 8];
+            left[0] = i;
+            compressor.add_instance(left, [0u32; 8]);
+        }
+        let trace = compressor.generate();
+
+        let config = StarkConfig::standard_fast_config();
+        prove::<F, C, S, D>(&mut sys, S::new(), &config, trace, &[], eval_packed_generic);
+        let report = sys.report();
+        assert_eq!(report.op_cnt, 32258);
+        assert_eq!(report.total_bytes, 84244362);
+    }
+}
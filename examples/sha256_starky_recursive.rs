@@ -10,10 +10,12 @@ use plonky2::plonk::circuit_builder::CircuitBuilder;
 use plonky2::plonk::circuit_data::CircuitConfig;
 use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, PoseidonGoldilocksConfig};
 use plonky2::util::timing::TimingTree;
-use unizk::config::RamConfig;
+use unizk::config::{HwConfig, RamConfig};
 use unizk::memory::memory_allocator::MemAlloc;
+use unizk::plonk::preflight::CircuitReport;
 use unizk::plonk::prover::prove_with_partition_witness;
 use unizk::system::system::System;
+use unizk::util::set_config;
 use starky::config::StarkConfig;
 use starky::proof::StarkProofWithPublicInputs;
 use starky::prover::prove as starky_prove;
@@ -41,13 +43,14 @@ fn recursive_proof<
     inner_proof: StarkProofWithPublicInputs<F, InnerC, D>,
     inner_config: &StarkConfig,
     print_gate_counts: bool,
+    hw: HwConfig,
 ) where
     InnerC::Hasher: AlgebraicHasher<F>,
 {
     let mut ramsim = RamConfig::new(&format!("{}", "sha256_starky_recursive"));
-    // ramsim.txt_output = true;
+    // ramsim.add_text_output();
     let mem = MemAlloc::new(256, 4096);
-    let mut sys = System::new(mem, ramsim);
+    let mut sys = System::new(mem, ramsim, hw);
 
     let circuit_config = CircuitConfig::standard_recursion_zk_config();
     let mut builder = CircuitBuilder::<F, D>::new(circuit_config);
@@ -63,6 +66,9 @@ fn recursive_proof<
     }
 
     let data = builder.build::<C>();
+    CircuitReport::new(&data.common, &data.prover_only)
+        .check()
+        .unwrap();
     let partition_witness = generate_partial_witness(pw, &data.prover_only, &data.common);
     prove_with_partition_witness(&mut sys, &data.prover_only, &data.common, partition_witness);
 
@@ -70,12 +76,13 @@ fn recursive_proof<
     sys.ramsim.static_();
 
     info!("Start Ramsim");
-    sys.ramsim.run();
+    sys.finish().unwrap();
     info!("End Ramsim");
 }
 
 fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let hw = set_config();
 
     let args: Vec<String> = env::args().collect();
     let num_hashes = args[1].parse::<i32>().unwrap();
@@ -111,5 +118,5 @@ fn main() {
         starky_prove::<F, C, S, D>(stark, &config, trace, &[], &mut TimingTree::default()).unwrap();
 
     verify_stark_proof(stark, proof.clone(), &config).unwrap();
-    recursive_proof::<F, C, S, C, D>(stark, proof, &config, true);
+    recursive_proof::<F, C, S, C, D>(stark, proof, &config, true, hw);
 }
@@ -0,0 +1,106 @@
+use anyhow::Result;
+use clap::{value_parser, Arg, ArgAction, Command};
+use env_logger::Env;
+use log::info;
+
+use unizk::config::{Backend, HwConfig, RamConfig, Replacement};
+use unizk::memory::memory_allocator::MemAlloc;
+use unizk::system::replay::{replay, InvocationReader};
+use unizk::system::system::System;
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let value = |name: &'static str, short: char| {
+        Arg::new(name)
+            .short(short)
+            .long(name)
+            .value_parser(value_parser!(usize))
+    };
+    let args = Command::new("unizk-replay")
+        .about("Regenerate the memory trace of a kernel invocation log under another ArchConfig")
+        .arg(
+            Arg::new("log")
+                .required(true)
+                .help("Log written by System::record_invocations, .kernels.zst"),
+        )
+        .arg(
+            Arg::new("hw")
+                .short('c')
+                .long("hw")
+                .help("Hardware description file, default is the recorded config"),
+        )
+        // the buffer size and array length are kept, see system::replay::RecordedDecisions
+        .arg(value("tiles", 't'))
+        .arg(
+            Arg::new("replacement")
+                .short('r')
+                .long("replacement")
+                .value_parser(|s: &str| s.parse::<Replacement>().map_err(|e| e.to_string()))
+                .help("Buffer replacement policy of the vector kernels: lru, fifo, belady"),
+        )
+        .arg(
+            Arg::new("name")
+                .long("name")
+                .help("Name of the trace, log and report, default is the recorded workload"),
+        )
+        .arg(
+            Arg::new("dram")
+                .short('d')
+                .long("dram")
+                .help("RamSim config file, default is configs/<name>.yaml"),
+        )
+        .arg(
+            Arg::new("analytical")
+                .long("analytical")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("schedule-vec-ops")
+                .long("schedule-vec-ops")
+                .action(ArgAction::SetTrue),
+        )
+        .get_matches();
+
+    let path = args.get_one::<String>("log").unwrap();
+    let header = InvocationReader::open(path)?.header;
+    let mut hw = match args.get_one::<String>("hw") {
+        Some(file) => HwConfig {
+            enable: header.hw.enable,
+            ..HwConfig::from_file(file)?
+        },
+        None => header.hw,
+    };
+    if let Some(tiles) = args.get_one::<usize>("tiles") {
+        hw.arch.num_tiles = *tiles;
+    }
+    if let Some(replacement) = args.get_one::<Replacement>("replacement") {
+        hw.arch.replacement = *replacement;
+    }
+    if args.get_flag("analytical") {
+        hw.timing.backend = Backend::Analytical;
+    }
+    if args.get_flag("schedule-vec-ops") {
+        hw.opt.schedule_vec_ops = true;
+    }
+    hw.validate()?;
+
+    let name = args
+        .get_one::<String>("name")
+        .cloned()
+        .unwrap_or(header.workload.clone());
+    let mut ramsim = RamConfig::new(&name);
+    if let Some(dram) = args.get_one::<String>("dram") {
+        ramsim.config_path = dram.clone();
+    }
+    let mem = MemAlloc::new(header.mem_size_gb, header.mem_align);
+    let mut sys = System::new(mem, ramsim, hw);
+    let num_invocations = replay(path, &mut sys)?;
+    info!("Replayed {} invocations of {}", num_invocations, path);
+
+    let report = sys.finish()?;
+    info!(
+        "{} ops, {} bytes, analytical estimate {:.6} s",
+        report.op_cnt, report.total_bytes, report.analytical.seconds
+    );
+    Ok(())
+}
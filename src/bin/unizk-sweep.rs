@@ -0,0 +1,189 @@
+use anyhow::{bail, Result};
+use clap::{value_parser, Arg, ArgAction, Command};
+use env_logger::Env;
+use log::info;
+
+use plonky2::field::types::Field;
+use plonky2::iop::generator::generate_partial_witness;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+use unizk::config::{HwConfig, Replacement};
+use unizk::plonk::preflight::CircuitReport;
+use unizk::sweep::sweep::{run_sweep, write_records, SweepConfig};
+use unizk::sweep::workload::PlonkWorkload;
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+fn build_circuit(
+    workload: &str,
+    log_n: usize,
+) -> Result<(CircuitData<F, C, D>, PartialWitness<F>)> {
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let mut pw = PartialWitness::new();
+    match workload {
+        "fibonacci" => {
+            let initial_a = builder.add_virtual_target();
+            let initial_b = builder.add_virtual_target();
+            let mut prev_target = initial_a;
+            let mut cur_target = initial_b;
+            for _ in 0..(1 << log_n) - 1 {
+                let temp = builder.add(prev_target, cur_target);
+                prev_target = cur_target;
+                cur_target = temp;
+            }
+            builder.register_public_input(initial_a);
+            builder.register_public_input(initial_b);
+            builder.register_public_input(cur_target);
+            pw.set_target(initial_a, F::ZERO);
+            pw.set_target(initial_b, F::ONE);
+        }
+        "factorial" => {
+            let initial = builder.add_virtual_target();
+            let mut cur_target = initial;
+            for i in 2..(1 << log_n) {
+                let i_target = builder.constant(F::from_canonical_u32(i));
+                cur_target = builder.mul(cur_target, i_target);
+            }
+            builder.register_public_input(initial);
+            builder.register_public_input(cur_target);
+            pw.set_target(initial, F::ONE);
+        }
+        _ => bail!(
+            "unknown workload {}, expected fibonacci or factorial",
+            workload
+        ),
+    }
+    Ok((builder.build::<C>(), pw))
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let list = |name: &'static str, short: char| {
+        Arg::new(name)
+            .short(short)
+            .long(name)
+            .value_delimiter(',')
+            .value_parser(value_parser!(usize))
+    };
+    let args = Command::new("unizk-sweep")
+        .about("Sweep a plonky2 workload over a grid of ArchConfig and DRAM configs")
+        .arg(
+            Arg::new("workload")
+                .short('w')
+                .long("workload")
+                .default_value("fibonacci"),
+        )
+        .arg(
+            Arg::new("log-n")
+                .short('n')
+                .long("log-n")
+                .default_value("16")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("hw")
+                .short('c')
+                .long("hw")
+                .help("Base hardware description file, default is the built-in config"),
+        )
+        .arg(list("tiles", 't'))
+        .arg(list("buf-kb", 'b'))
+        .arg(list("array-length", 'a'))
+        .arg(
+            Arg::new("replacement")
+                .short('r')
+                .long("replacement")
+                .value_delimiter(',')
+                .value_parser(|s: &str| s.parse::<Replacement>().map_err(|e| e.to_string()))
+                .help("Buffer replacement policies of the vector kernels: lru, fifo, belady"),
+        )
+        .arg(
+            Arg::new("dram")
+                .short('d')
+                .long("dram")
+                .value_delimiter(',')
+                .help("RamSim config files, e.g. configs/fibonacci.yaml"),
+        )
+        .arg(
+            Arg::new("mem")
+                .short('m')
+                .long("mem")
+                .default_value("256")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("no-ramsim")
+                .long("no-ramsim")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("keep-traces")
+                .long("keep-traces")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .help(
+                    "Prove only on the first point and replay its kernel invocations on the others",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .default_value("sweep.csv")
+                .help("Output file, .csv or .json"),
+        )
+        .get_matches();
+
+    let values = |name: &str| -> Vec<usize> {
+        args.get_many::<usize>(name)
+            .map(|v| v.copied().collect())
+            .unwrap_or_default()
+    };
+    let base = match args.get_one::<String>("hw") {
+        Some(path) => HwConfig::from_file(path)?,
+        None => HwConfig::default(),
+    };
+    let mut config = SweepConfig::new(base);
+    config.num_tiles = values("tiles");
+    config.buf_sz_kb = values("buf-kb");
+    config.array_length = values("array-length");
+    config.replacement = args
+        .get_many::<Replacement>("replacement")
+        .map(|v| v.copied().collect())
+        .unwrap_or_default();
+    config.dram_configs = args
+        .get_many::<String>("dram")
+        .map(|v| v.cloned().collect())
+        .unwrap_or_default();
+    config.mem_size_gb = *args.get_one::<usize>("mem").unwrap();
+    config.run_ramsim = !args.get_flag("no-ramsim");
+    config.keep_traces = args.get_flag("keep-traces");
+    config.replay = args.get_flag("replay");
+
+    let workload = args.get_one::<String>("workload").unwrap();
+    let log_n = *args.get_one::<usize>("log-n").unwrap();
+    let (data, pw) = build_circuit(workload, log_n)?;
+    CircuitReport::new(&data.common, &data.prover_only).check()?;
+    let partition_witness = generate_partial_witness(pw, &data.prover_only, &data.common);
+    let workload = PlonkWorkload {
+        name: format!("{}_{}", workload, log_n),
+        prover_data: &data.prover_only,
+        common_data: &data.common,
+        partition_witness,
+    };
+
+    let records = run_sweep(&workload, &config)?;
+    let output = args.get_one::<String>("output").unwrap();
+    write_records(&records, output)?;
+    info!("Wrote {} records to {}", records.len(), output);
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::io::{BufWriter, Write};
+
+use anyhow::{bail, Result};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+
+use unizk::trace::reader::{TraceReader, TraceSummary};
+use unizk::trace::sink::write_text;
+
+fn print_summary(path: &str, summary: &TraceSummary) {
+    println!("trace: {} (version {})", path, summary.version);
+    let metadata = &summary.metadata;
+    if summary.version > 1 {
+        println!("workload: {}", metadata.workload);
+        println!("created: {} by {}", metadata.created_unix, metadata.creator);
+        if let Some(arch) = metadata.arch {
+            println!("arch: {:?}", arch);
+        }
+    }
+    println!("ops: {}", summary.num_ops);
+    println!(
+        "reads: {} ({} bytes)",
+        summary.num_reads, summary.read_bytes
+    );
+    println!(
+        "writes: {} ({} bytes)",
+        summary.num_writes, summary.write_bytes
+    );
+    println!("dependencies: {}", summary.num_dependencies);
+    println!("max dependency depth: {}", summary.max_dependency_depth);
+    println!("total delay: {}", summary.total_delay);
+    println!("size histogram:");
+    for (lg_size, cnt) in summary.size_histogram.iter() {
+        println!("  {:>10} B: {}", 1usize << lg_size, cnt);
+    }
+}
+
+fn print_errors(summary: &TraceSummary) {
+    for e in summary.errors.iter() {
+        println!("error: {}", e);
+    }
+    if summary.num_errors > summary.errors.len() {
+        println!(
+            "... {} more errors",
+            summary.num_errors - summary.errors.len()
+        );
+    }
+}
+
+fn dump(path: &str, args: &ArgMatches) -> Result<()> {
+    let start = *args.get_one::<u64>("start").unwrap();
+    let count = args
+        .get_one::<usize>("count")
+        .copied()
+        .unwrap_or(usize::MAX);
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for op in TraceReader::open_at(path, start)?.take(count) {
+        write_text(&mut out, &op?)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let trace = || {
+        Arg::new("trace")
+            .required(true)
+            .help("Binary trace, .bin or .bin.zst")
+    };
+    let args = Command::new("unizk-trace")
+        .about("Inspect the memory traces written by RamConfig")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("summary")
+                .about("Print op count, traffic, dependency depth and request sizes")
+                .arg(trace())
+                .arg(Arg::new("json").long("json").action(ArgAction::SetTrue)),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check ids, dependencies and sizes, exit with an error if invalid")
+                .arg(trace()),
+        )
+        .subcommand(
+            Command::new("dump")
+                .about("Print a range of ops as text")
+                .arg(trace())
+                .arg(
+                    Arg::new("start")
+                        .short('s')
+                        .long("start")
+                        .default_value("0")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("count")
+                        .short('n')
+                        .long("count")
+                        .value_parser(value_parser!(usize)),
+                ),
+        )
+        .get_matches();
+
+    let (cmd, args) = args.subcommand().unwrap();
+    let path = args.get_one::<String>("trace").unwrap();
+    match cmd {
+        "summary" => {
+            let summary = TraceSummary::from_reader(TraceReader::open(path)?)?;
+            if args.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            } else {
+                print_summary(path, &summary);
+                print_errors(&summary);
+            }
+        }
+        "validate" => {
+            let summary = TraceSummary::from_reader(TraceReader::open(path)?)?;
+            print_errors(&summary);
+            if !summary.is_valid() {
+                bail!("{} has {} errors", path, summary.num_errors);
+            }
+            println!("{}: {} ops, ok", path, summary.num_ops);
+        }
+        "dump" => dump(path, args)?,
+        _ => unreachable!(),
+    }
+    Ok(())
+}
@@ -1,6 +1,12 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, ensure, Error, Result};
+use serde::{Deserialize, Serialize};
+
 use crate::util::SIZE_F;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ArchConfig {
     pub rdbuf_sz_kb: usize,
     pub wrbuf_sz_kb: usize,
@@ -8,16 +14,62 @@ pub struct ArchConfig {
     pub mvl: usize, // max vector length for PE
     pub num_tiles: usize,
     pub array_length: usize,
+    #[serde(default)]
+    pub replacement: Replacement, // of the on-chip buffer of the vector kernels
+    #[serde(default)]
+    pub rich_vec_isa: bool, // PEs also have FMA, SQUARE, NEG and SELECT, see VecOpType
+}
+
+/// Which data the vector kernels evict when the on-chip buffer is full, see kernel::lru.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Replacement {
+    #[default]
+    Lru,
+    Fifo,
+    Belady, // offline, knows the future accesses of the chain
 }
 
-pub static mut ARCH_CONFIG: ArchConfig = ArchConfig {
-    rdbuf_sz_kb: 4096,
-    wrbuf_sz_kb: 4096,
-    active_buf_frac: 0.5,
-    mvl: 8,
-    num_tiles: 32,
-    array_length: 12,
-};
+impl Replacement {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Replacement::Lru => "lru",
+            Replacement::Fifo => "fifo",
+            Replacement::Belady => "belady",
+        }
+    }
+}
+
+impl FromStr for Replacement {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lru" => Ok(Replacement::Lru),
+            "fifo" => Ok(Replacement::Fifo),
+            "belady" => Ok(Replacement::Belady),
+            _ => Err(anyhow!(
+                "unknown replacement policy {}, expected lru, fifo or belady",
+                s
+            )),
+        }
+    }
+}
+
+impl Default for ArchConfig {
+    fn default() -> Self {
+        Self {
+            rdbuf_sz_kb: 4096,
+            wrbuf_sz_kb: 4096,
+            active_buf_frac: 0.5,
+            mvl: 8,
+            num_tiles: 32,
+            array_length: 12,
+            replacement: Replacement::Lru,
+            rich_vec_isa: false,
+        }
+    }
+}
 
 impl ArchConfig {
     pub fn active_buf_size(&self) -> usize {
@@ -31,4 +83,27 @@ impl ArchConfig {
     pub fn num_pes(&self) -> usize {
         self.num_tiles * self.array_length * self.array_length
     }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.rdbuf_sz_kb > 0, "rdbuf_sz_kb must be positive");
+        ensure!(self.wrbuf_sz_kb > 0, "wrbuf_sz_kb must be positive");
+        ensure!(
+            self.active_buf_frac > 0.0 && self.active_buf_frac <= 1.0,
+            "active_buf_frac must be in (0, 1], got {}",
+            self.active_buf_frac
+        );
+        ensure!(self.mvl > 0, "mvl must be positive");
+        ensure!(self.num_tiles > 0, "num_tiles must be positive");
+        // fft planes are split on array_length / 2 bits, extension fft needs one more
+        ensure!(
+            self.array_length >= 4 && self.array_length & 1 == 0,
+            "array_length must be an even number >= 4, got {}",
+            self.array_length
+        );
+        ensure!(
+            self.num_elems() > 0,
+            "active buffer holds no elements, increase rdbuf_sz_kb or active_buf_frac"
+        );
+        Ok(())
+    }
 }
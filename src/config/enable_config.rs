@@ -1,11 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct EnableConfig {
     pub fft: bool,
     pub hash: bool,
     pub other: bool,
 }
 
-pub static mut ENABLE_CONFIG: EnableConfig = EnableConfig {
-    fft: true,
-    hash: true,
-    other: true,
-};
+impl Default for EnableConfig {
+    fn default() -> Self {
+        Self {
+            fft: true,
+            hash: true,
+            other: true,
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::arch_config::ArchConfig;
+use crate::config::enable_config::EnableConfig;
+use crate::config::opt_config::OptConfig;
+use crate::config::timing_config::TimingConfig;
+
+// hardware description file, e.g. configs/hw/unizk.yaml
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HwConfig {
+    pub arch: ArchConfig,
+    #[serde(default)]
+    pub enable: EnableConfig,
+    #[serde(default)]
+    pub timing: TimingConfig,
+    #[serde(default)]
+    pub opt: OptConfig,
+}
+
+impl HwConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read hardware config {}", path.display()))?;
+        let hw = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => Self::from_yaml(&text),
+            Some("toml") => Self::from_toml(&text),
+            _ => Err(anyhow!("expected a .yaml, .yml or .toml file")),
+        };
+        hw.with_context(|| format!("invalid hardware config {}", path.display()))
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        let hw: Self = serde_yaml::from_str(text)?;
+        hw.validate()?;
+        Ok(hw)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let hw: Self = toml::from_str(text)?;
+        hw.validate()?;
+        Ok(hw)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.arch.validate()?;
+        self.timing.validate()
+    }
+
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Backend;
+
+    #[test]
+    fn test_hw_config() -> Result<()> {
+        let yaml = "
+arch:
+  rdbuf_sz_kb: 2048
+  wrbuf_sz_kb: 1024
+  active_buf_frac: 0.5
+  mvl: 8
+  num_tiles: 16
+  array_length: 12
+enable:
+  hash: false
+";
+        let hw = HwConfig::from_yaml(yaml)?;
+        assert_eq!(hw.arch.wrbuf_sz_kb, 1024);
+        assert_eq!(hw.arch.num_tiles, 16);
+        assert!(hw.enable.fft && !hw.enable.hash && hw.enable.other);
+        assert_eq!(hw.timing.backend, Backend::RamSim);
+
+        let toml = "
+[arch]
+rdbuf_sz_kb = 2048
+wrbuf_sz_kb = 1024
+active_buf_frac = 0.5
+mvl = 8
+num_tiles = 16
+array_length = 12
+";
+        let hw_toml = HwConfig::from_toml(toml)?;
+        assert_eq!(
+            hw_toml.to_yaml(),
+            HwConfig::from_yaml(&hw_toml.to_yaml())?.to_yaml()
+        );
+
+        // every arch field is required
+        assert!(HwConfig::from_toml(&toml.replace("mvl = 8\n", "")).is_err());
+        assert!(
+            HwConfig::from_toml(&toml.replace("array_length = 12", "array_length = 3")).is_err()
+        );
+        assert!(HwConfig::from_toml(&toml.replace("0.5", "1.5")).is_err());
+
+        let timing = "[timing]\nbackend = \"analytical\"\nbandwidth_gbps = 512.0\n";
+        let hw_timing = HwConfig::from_toml(&format!("{}{}", toml, timing))?;
+        assert_eq!(hw_timing.timing.backend, Backend::Analytical);
+        assert_eq!(hw_timing.timing.bandwidth_gbps, 512.0);
+        assert_eq!(hw_timing.timing.freq_ghz, 1.0);
+        assert!(HwConfig::from_toml(&format!("{}{}", toml, "[timing]\nfreq_ghz = 0.0\n")).is_err());
+
+        assert!(!hw_toml.opt.schedule_vec_ops);
+        let opt = "[opt]\nschedule_vec_ops = true\n";
+        let hw_opt = HwConfig::from_toml(&format!("{}{}", toml, opt))?;
+        assert!(hw_opt.opt.schedule_vec_ops && !hw_opt.opt.cse_vec_ops);
+        Ok(())
+    }
+}
@@ -1,6 +1,15 @@
 pub mod arch_config;
 pub mod ram_config;
+pub mod ram_stats;
+pub mod ramsim_lib;
 pub mod enable_config;
-pub use arch_config::ArchConfig;
+pub mod hw_config;
+pub mod opt_config;
+pub mod timing_config;
+pub use arch_config::{ArchConfig, Replacement};
+pub use hw_config::HwConfig;
+pub use opt_config::OptConfig;
 pub use plonky2::plonk::circuit_data::CircuitConfig;
-pub use ram_config::RamConfig;
\ No newline at end of file
+pub use ram_config::RamConfig;
+pub use ram_stats::RamSimStats;
+pub use timing_config::{Backend, TimingConfig};
\ No newline at end of file
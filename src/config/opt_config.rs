@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+// compiler passes applied to the kernels before their traces are created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OptConfig {
+    // reorder the ops of each VectorChain, see kernel::vec_schedule
+    pub schedule_vec_ops: bool,
+    // drop duplicate and dead ops of the gate evaluation chains, see kernel::vec_cse
+    pub cse_vec_ops: bool,
+    // lower every registered plonky2 gate from its own packed evaluation, see plonk::gate_lowering
+    pub symbolic_gates: bool,
+}
@@ -1,12 +1,16 @@
+use crate::config::{HwConfig, RamSimStats};
 use crate::trace::trace::FetchType;
-use crate::util::BUFSIZE;
-use anyhow::{Ok, Result};
-use std::collections::HashMap;
-use std::fs::File;
+use crate::trace::header::TraceMetadata;
+use crate::trace::sink::{BinSink, RamSimSink, TextSink, TraceSink, ZstdSink};
+use anyhow::{anyhow, ensure, Context, Ok, Result};
+use log::info;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
 use std::io::prelude::*;
-use std::io::BufWriter;
-use std::io::SeekFrom;
+use std::path::Path;
+use std::process::Stdio;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct OpRecord {
     pub id: u64,
     pub addr: u64,
@@ -14,6 +18,7 @@ pub struct OpRecord {
     pub delay: u32,
     pub dependencies: Vec<u64>,
     pub size: u32,
+    pub phase: usize, // index into System::phases
 }
 impl OpRecord {
     pub fn default() -> Self {
@@ -24,6 +29,7 @@ impl OpRecord {
             addr: 0,
             dependencies: Vec::new(),
             size: 0,
+            phase: 0,
         }
     }
 }
@@ -35,6 +41,7 @@ impl OpRecord {
 pub struct RamConfig {
     pub file_name: String,
     pub excutable_path: String,
+    pub lib_path: String, // libramulator.so for use_ramsim_lib
     pub config_path: String,
     pub burst_length: usize,
 
@@ -44,27 +51,24 @@ pub struct RamConfig {
     last_read_id: i64,
     last_write_id: i64,
     pub op_cnt: usize,
+    // sum of the op sizes, the histogram rounds them up to a power of two
+    total_bytes: u64,
 
-    bin_file: BufWriter<File>,
-    txt_file: File,
+    // every op is written into all sinks, the binary trace by default
+    sinks: Vec<Box<dyn TraceSink>>,
+    metadata: TraceMetadata,
     log_file: File,
 
-    pub txt_output: bool,
-
     pub length_static: HashMap<usize, usize>,
+
+    // (first op id, phase) every time the phase of the trace changes
+    pub phase_starts: Vec<(u64, usize)>,
 }
 
 impl RamConfig {
     pub fn new(file_name: &str) -> Self {
-        let name = format!("./traces/{}.bin", file_name);
-        let file = File::create(name).unwrap();
-        let mut writer = std::io::BufWriter::new(file);
-
-        let magic_word = String::from("BINFILE");
-        let _ = writer.write_all(&to_buf(magic_word.as_bytes()));
-
-        let name = format!("./traces/{}.txt", file_name);
-        let file = File::create(name).unwrap();
+        let metadata = TraceMetadata::new(file_name);
+        let bin_sink = BinSink::create(&format!("./traces/{}.bin", file_name), &metadata).unwrap();
 
         let name = format!("{}.log", file_name);
         let log_file = File::create(name).unwrap();
@@ -72,6 +76,7 @@ impl RamConfig {
         Self {
             file_name: file_name.to_string(),
             excutable_path: "./thirdparty/ramsim/build/ramulator2".to_string(),
+            lib_path: "./thirdparty/ramsim/libramulator.so".to_string(),
             config_path: "./configs/".to_string() + file_name + ".yaml",
             burst_length: 64, //bytes
             num_current_ops: 0,
@@ -80,12 +85,13 @@ impl RamConfig {
             last_read_id: -1,
             last_write_id: -1,
             op_cnt: 0,
+            total_bytes: 0,
 
-            bin_file: writer,
-            txt_file: file,
+            sinks: vec![Box::new(bin_sink)],
+            metadata,
             log_file: log_file,
-            txt_output: false,
             length_static: HashMap::new(),
+            phase_starts: Vec::new(),
         }
     }
 
@@ -96,24 +102,62 @@ impl RamConfig {
         self.last_read_id = -1;
         self.last_write_id = -1;
         self.op_cnt = 0;
+        self.total_bytes = 0;
         self.length_static.clear();
+        self.phase_starts.clear();
+
+        for sink in self.sinks.iter_mut() {
+            sink.reset().expect("reset trace sink failed");
+        }
+    }
+
+    // the replaced sinks are dropped with their files, e.g. the empty .bin of new
+    pub fn set_sinks(&mut self, sinks: Vec<Box<dyn TraceSink>>) {
+        for path in self.output_paths() {
+            let _ = fs::remove_file(path);
+        }
+        self.sinks.clear();
+        for sink in sinks {
+            self.add_sink(sink);
+        }
+    }
+
+    pub fn add_sink(&mut self, mut sink: Box<dyn TraceSink>) {
+        sink.set_metadata(&self.metadata)
+            .expect("failed to write trace header");
+        self.sinks.push(sink);
+    }
+
+    // also write the trace as text to ./traces/<name>.txt
+    pub fn add_text_output(&mut self) {
+        let sink = TextSink::create(&format!("./traces/{}.txt", self.file_name)).unwrap();
+        self.add_sink(Box::new(sink));
+    }
+
+    // replace the binary trace with ./traces/<name>.bin.zst
+    pub fn use_zstd(&mut self, level: i32) {
+        let sink = ZstdSink::create(
+            &format!("./traces/{}.bin.zst", self.file_name),
+            level,
+            &self.metadata,
+        )
+        .unwrap();
+        self.set_sinks(vec![Box::new(sink)]);
+    }
+
+    // the files written by the sinks
+    pub fn output_paths(&self) -> Vec<String> {
+        self.sinks
+            .iter()
+            .flat_map(|sink| sink.output_paths())
+            .collect()
+    }
 
-        self.bin_file.flush().unwrap();
-        self.bin_file
-            .seek(SeekFrom::Start(0))
-            .expect("reset bin file failed");
-        let magic_word = String::from("BINFILE");
-        self.bin_file
-            .write_all(&to_buf(magic_word.as_bytes()))
-            .unwrap();
-        // let name = format!("./traces/{}.bin", self.file_name);
-        // let file = File::create(name).unwrap();
-        // let mut writer = std::io::BufWriter::new(file);
-        // let magic_word = String::from("BINFILE");
-        // let _ = writer.write_all(&to_buf(magic_word.as_bytes()));
-        // self.bin_file = writer;
-
-        self.txt_file.set_len(0).expect("reset txt file failed");
+    // simulate the ops in this process while they are written, no trace file
+    pub fn use_ramsim_lib(&mut self) -> Result<()> {
+        let sink = RamSimSink::create(&self.lib_path, &self.config_path)?;
+        self.set_sinks(vec![Box::new(sink)]);
+        Ok(())
     }
 
     pub fn add_trace(
@@ -168,98 +212,160 @@ impl RamConfig {
     pub fn write_trace(&mut self, read_op: Vec<OpRecord>, write_op: Vec<OpRecord>) -> Result<()> {
         for op in read_op.iter().chain(write_op.iter()) {
             assert_eq!(op.id as usize, self.op_cnt);
-            self.bin_file.write_all(&to_buf(&op.id.to_le_bytes()))?;
-            self.bin_file.write_all(&to_buf(&op.addr.to_le_bytes()))?;
-            self.bin_file
-                .write_all(&to_buf(&(op.fetch_type as u32).to_le_bytes()))?;
-            self.bin_file.write_all(&to_buf(&op.delay.to_le_bytes()))?;
-            // self.bin_file.write_all(&to_buf(&0u64.to_le_bytes()))?;
-            self.bin_file.write_all(&to_buf(&op.size.to_le_bytes()))?;
-            self.bin_file
-                .write_all(&to_buf(&(op.dependencies.len() as u64).to_le_bytes()))?;
-            for dep in op.dependencies.iter() {
-                self.bin_file.write_all(&to_buf(&dep.to_le_bytes()))?;
+            if self.phase_starts.last().map(|x| x.1) != Some(op.phase) {
+                self.phase_starts.push((op.id, op.phase));
             }
-            // self.bin_file
-            //     .write_all(&to_buf(&(0 as u64).to_le_bytes()))?;
-            self.op_cnt += 1;
-        }
-
-        if self.txt_output {
-            for op in read_op.iter().chain(write_op.iter()) {
-                self.txt_file
-                    .write_all(format!("id: {}\n", op.id).as_bytes())?;
-                self.txt_file
-                    .write_all(format!("addr: {}\n", op.addr).as_bytes())?;
-                self.txt_file
-                    .write_all(format!("fetch_type: {:?}\n", op.fetch_type).as_bytes())?;
-                self.txt_file
-                    .write_all(format!("delay: {}\n", op.delay).as_bytes())?;
-                self.txt_file
-                    .write_all(format!("size: {}\n", op.size).as_bytes())?;
-                self.txt_file
-                    .write_all(format!("dependencies: ").as_bytes())?;
-                for dep in op.dependencies.iter() {
-                    self.txt_file.write_all(format!("{}, ", dep).as_bytes())?;
-                }
-                self.txt_file.write_all(format!("\n").as_bytes())?;
+            for sink in self.sinks.iter_mut() {
+                sink.write(op)?;
             }
+            self.op_cnt += 1;
         }
 
         for op in read_op.iter().chain(write_op.iter()) {
             let size = op.size as usize;
+            self.total_bytes += op.size as u64;
             let lg_size = size.next_power_of_two().trailing_zeros() as usize;
             *self.length_static.entry(lg_size).or_insert(0) += 1;
         }
         Ok(())
     }
 
+    pub fn size_histogram(&self) -> BTreeMap<usize, usize> {
+        self.length_static.iter().map(|(&k, &v)| (k, v)).collect()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
     pub fn static_(&self) {
-        let mut total = 0;
-        for (lg_size, cnt) in self.length_static.iter() {
-            total += cnt * (1 << lg_size);
-        }
-        println!("total: {}", total);
-        // for (lg_size, cnt) in self.length_static.iter() {
-        //     println!("{}: {}, {}%", lg_size, cnt, cnt * (1 << lg_size) * 100 / total);
-        // }
-        let max_lg_size = self
+        info!("total: {}", self.total_bytes);
+        // the share of each size class, in rounded bytes
+        let total = self
             .length_static
             .iter()
-            .max()
-            .unwrap_or((&0, &0))
-            .0
-            .clone();
+            .map(|(lg_size, cnt)| cnt * (1 << lg_size))
+            .sum::<usize>();
         let mut total_percent = 0;
-        for i in 0..=max_lg_size {
-            if i == 7 {
-                println!("----------------64B-----------------")
-            }
-            if let Some(cnt) = self.length_static.get(&i) {
-                let perc = cnt * (1 << i) * 100 / total;
-                total_percent += perc;
-                println!("{}: {}, {}%, in {}%", i, cnt, perc, total_percent);
+        for (lg_size, cnt) in self.size_histogram() {
+            if lg_size == 7 {
+                info!("----------------64B-----------------")
             }
+            let perc = cnt * (1 << lg_size) * 100 / total;
+            total_percent += perc;
+            info!("{}: {}, {}%, in {}%", lg_size, cnt, perc, total_percent);
         }
     }
 
-    pub fn run(&mut self) {
-        self.bin_file.flush().unwrap();
-        let output = std::process::Command::new(&self.excutable_path)
+    // also records the arch config in the trace header
+    pub fn log_hw_config(&mut self, hw: &HwConfig) -> Result<()> {
+        self.metadata.arch = Some(hw.arch);
+        for sink in self.sinks.iter_mut() {
+            sink.set_metadata(&self.metadata)?;
+        }
+        self.log_file.write_all(b"# hardware config\n")?;
+        self.log_file.write_all(hw.to_yaml().as_bytes())?;
+        self.log_file.write_all(b"\n")?;
+        Ok(())
+    }
+
+    // TraceGen prints the cycle in which the first op of every phase is issued
+    fn write_phase_file(&self) -> Result<String> {
+        let name = format!("./traces/{}.phases", self.file_name);
+        let mut file = File::create(&name)?;
+        for (id, phase) in self.phase_starts.iter() {
+            writeln!(file, "{} {}", id, phase)?;
+        }
+        Ok(name)
+    }
+
+    // stdout of the ramulator2 executable on the trace, or on the stream piped into its
+    // stdin if there is one
+    fn run_executable(
+        &mut self,
+        trace: &str,
+        stream: Option<Box<dyn Read + Send>>,
+    ) -> Result<String> {
+        ensure!(
+            Path::new(&self.excutable_path).is_file(),
+            "RamSim executable {} not found, build it in thirdparty/ramsim first",
+            self.excutable_path
+        );
+        let mut command = std::process::Command::new(&self.excutable_path);
+        command
             .arg("-f")
             .arg(&self.config_path)
-            .output();
-        let output = output.unwrap();
-        self.log_file.write_all(&output.stdout).unwrap();
+            .arg("-p")
+            .arg(format!("Frontend.traceFileName={}", trace));
+        if !self.phase_starts.is_empty() {
+            let phase_file = self.write_phase_file()?;
+            command
+                .arg("-p")
+                .arg(format!("Frontend.phaseFileName={}", phase_file));
+        }
+        if stream.is_some() {
+            command.stdin(Stdio::piped());
+        }
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("failed to run {}", self.excutable_path))?;
+        // fed from another thread, RamSim may fill its stdout before it reads all of stdin
+        let feeder = match (stream, child.stdin.take()) {
+            (Some(mut stream), Some(mut stdin)) => Some(std::thread::spawn(move || {
+                std::io::copy(&mut stream, &mut stdin).map(|_| ())
+            })),
+            _ => None,
+        };
+        let output = child.wait_with_output()?;
+        let fed = feeder.map(|feeder| feeder.join().expect("trace feeder panicked"));
+        self.log_file.write_all(&output.stdout)?;
+        ensure!(
+            output.status.success(),
+            "RamSim exited with {} on {}: {}",
+            output.status,
+            self.config_path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        if let Some(fed) = fed {
+            fed.context("failed to pipe the trace into RamSim")?;
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
     }
-}
 
-fn to_buf(a: &[u8]) -> [u8; BUFSIZE] {
-    let mut buf = [0; 8];
-    for i in 0..a.len() {
-        buf[i] = a[i];
+    /// Run RamSim on the trace, or finish the simulation of a RamSimSink. Fails if
+    /// the executable is missing, exits with an error, or does not finish the trace.
+    pub fn run(&mut self) -> Result<RamSimStats> {
+        let mut trace = None;
+        let mut stream = None;
+        let mut output = None;
+        for sink in self.sinks.iter_mut() {
+            trace = trace.or(sink.finish()?);
+            output = output.or(sink.simulate()?);
+            if trace.is_none() && stream.is_none() {
+                stream = sink.trace_stream()?;
+            }
+        }
+        let output = match output {
+            Some(output) => {
+                self.log_file.write_all(output.as_bytes())?;
+                Ok(output)
+            }
+            None => match (trace, stream) {
+                (Some(trace), _) => self.run_executable(&trace, None),
+                (None, Some(stream)) => self.run_executable("/dev/stdin", Some(stream)),
+                (None, None) => Err(anyhow!("no trace sink RamSim can read")),
+            },
+        };
+        for sink in self.sinks.iter_mut() {
+            sink.release()?;
+        }
+        let output = output?;
+        let mut stats = RamSimStats::parse(&output)
+            .with_context(|| format!("invalid RamSim output in {}.log", self.file_name))?;
+        stats.set_total_bytes(self.total_bytes());
+        Ok(stats)
     }
-    buf
 }
 
 #[cfg(test)]
@@ -298,6 +404,7 @@ mod tests {
                 delay: 0,
                 dependencies: Vec::new(),
                 size: CONSECUTIVE_LENGTH as u32 * OpRecord::SIZE,
+                phase: 0,
             };
             cnt += 1;
             ram.write_trace(vec![op], vec![])?;
@@ -307,6 +414,90 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_total_bytes() -> Result<()> {
+        let mut ram = RamConfig::new("test_total_bytes");
+        let ops = [192u32, 64, 100, 4096]
+            .iter()
+            .enumerate()
+            .map(|(i, &size)| OpRecord {
+                id: i as u64,
+                addr: i as u64 * 8192,
+                fetch_type: FetchType::Read,
+                delay: 0,
+                dependencies: Vec::new(),
+                size,
+                phase: 0,
+            })
+            .collect();
+        ram.write_trace(ops, vec![])?;
+        assert_eq!(ram.total_bytes(), 192 + 64 + 100 + 4096);
+        // the histogram only keeps the size classes
+        assert_eq!(
+            ram.size_histogram(),
+            BTreeMap::from([(6, 1), (7, 1), (8, 1), (12, 1)])
+        );
+        ram.reset();
+        assert_eq!(ram.total_bytes(), 0);
+        Ok(())
+    }
+
+    // returns RamSim output without simulating anything
+    struct CannedSink(&'static str);
+
+    impl TraceSink for CannedSink {
+        fn write(&mut self, _op: &OpRecord) -> Result<()> {
+            Ok(())
+        }
+
+        fn reset(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn simulate(&mut self) -> Result<Option<String>> {
+            Ok(Some(self.0.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_bytes_per_cycle() -> Result<()> {
+        let mut ram = RamConfig::new("test_bytes_per_cycle");
+        ram.set_sinks(vec![Box::new(CannedSink(
+            "TraceGen finished in cycle 500\nMemorySystem:\n  memory_system_cycles: 400\n",
+        ))]);
+        let ops = [192u32, 192, 192, 424]
+            .iter()
+            .enumerate()
+            .map(|(i, &size)| OpRecord {
+                id: i as u64,
+                addr: i as u64 * 8192,
+                fetch_type: FetchType::Write,
+                delay: 0,
+                dependencies: Vec::new(),
+                size,
+                phase: 0,
+            })
+            .collect();
+        ram.write_trace(vec![], ops)?;
+        let stats = ram.run()?;
+        assert_eq!(stats.cycles, 500);
+        // 1000 bytes, not the 1280 of the rounded sizes
+        assert_eq!(stats.bytes_per_cycle, 2.5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_paths() {
+        let mut ram = RamConfig::new("test_output_paths");
+        assert_eq!(ram.output_paths(), ["./traces/test_output_paths.bin"]);
+        // the replaced binary trace is removed
+        ram.use_zstd(3);
+        assert!(!Path::new("./traces/test_output_paths.bin").exists());
+        assert_eq!(ram.output_paths(), ["./traces/test_output_paths.bin.zst"]);
+        ram.add_text_output();
+        assert_eq!(ram.output_paths().len(), 2);
+    }
+
     #[test]
     fn test_stride() -> Result<()> {
         const WIDTH: usize = (1 << 20);
@@ -327,6 +518,7 @@ mod tests {
                     delay: 0,
                     dependencies: Vec::new(),
                     size: STRIDE as u32,
+                    phase: 0,
                 };
                 cnt += 1;
                 ram.write_trace(vec![op], vec![])?;
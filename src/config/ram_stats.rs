@@ -0,0 +1,199 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+// statistics of one memory controller, see generic_dram_controller.cpp
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChannelStats {
+    pub id: String,
+    pub num_read_requests: u64,
+    pub num_write_requests: u64,
+    pub num_row_hits: u64,
+    pub num_row_misses: u64,
+    pub num_row_conflicts: u64,
+    pub num_rejected_requests: u64,
+    pub busy_cycles: u64,
+    pub stall_cycles: u64,
+    pub idle_cycles: u64,
+}
+
+impl ChannelStats {
+    pub fn row_hit_rate(&self) -> f64 {
+        let total = self.num_row_hits + self.num_row_misses + self.num_row_conflicts;
+        if total == 0 {
+            0.0
+        } else {
+            self.num_row_hits as f64 / total as f64
+        }
+    }
+}
+
+// stall breakdown summed over all channels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct StallBreakdown {
+    pub busy_cycles: u64,
+    pub stall_cycles: u64,
+    pub idle_cycles: u64,
+    pub rejected_requests: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RamSimStats {
+    pub cycles: u64, // frontend cycles until TraceGen finished
+    pub total_mem_req: u64,
+    pub memory_system_cycles: u64,
+    pub num_read_requests: u64,
+    pub num_write_requests: u64,
+    pub num_other_requests: u64,
+    pub channels: Vec<ChannelStats>,
+    pub bytes_per_cycle: f64, // achieved bandwidth per memory system cycle
+    // (phase, cycle) when the first op of each phase segment is issued
+    pub phase_issue_cycles: Vec<(usize, u64)>,
+}
+
+impl RamSimStats {
+    /// Parse the stdout of RamSim. The stats are YAML, but the controllers of all
+    /// channels share the key "Controller", so they are parsed line by line.
+    pub fn parse(output: &str) -> Result<Self> {
+        let mut stats = Self::default();
+        let mut cycles = None;
+        for line in output.lines() {
+            let line = line.trim();
+            if let Some(c) = line.strip_prefix("TraceGen finished in cycle ") {
+                cycles = c.trim().parse().ok();
+                continue;
+            }
+            if let Some(phase) = line.strip_prefix("Phase ") {
+                let mut words = phase.split_whitespace();
+                if let (Some(id), Some(cycle)) = (words.next(), words.last()) {
+                    if let (Ok(id), Ok(cycle)) = (id.parse(), cycle.parse()) {
+                        stats.phase_issue_cycles.push((id, cycle));
+                    }
+                }
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if key == "Controller" {
+                stats.channels.push(ChannelStats::default());
+                continue;
+            }
+            if let Some(channel) = stats.channels.last_mut() {
+                if key == "id" {
+                    channel.id = value.to_string();
+                    continue;
+                }
+            }
+            let Ok(value) = value.parse::<u64>() else {
+                continue;
+            };
+            let field = match (key, stats.channels.last_mut()) {
+                ("s_total_mem_req", _) => &mut stats.total_mem_req,
+                ("memory_system_cycles", _) => &mut stats.memory_system_cycles,
+                ("total_num_read_requests", _) => &mut stats.num_read_requests,
+                ("total_num_write_requests", _) => &mut stats.num_write_requests,
+                ("total_num_other_requests", _) => &mut stats.num_other_requests,
+                ("num_read_reqs", Some(c)) => &mut c.num_read_requests,
+                ("num_write_reqs", Some(c)) => &mut c.num_write_requests,
+                ("num_row_hits", Some(c)) => &mut c.num_row_hits,
+                ("num_row_misses", Some(c)) => &mut c.num_row_misses,
+                ("num_row_conflicts", Some(c)) => &mut c.num_row_conflicts,
+                ("num_rejected_reqs", Some(c)) => &mut c.num_rejected_requests,
+                ("num_busy_cycles", Some(c)) => &mut c.busy_cycles,
+                ("num_stall_cycles", Some(c)) => &mut c.stall_cycles,
+                ("num_idle_cycles", Some(c)) => &mut c.idle_cycles,
+                _ => continue,
+            };
+            *field = value;
+        }
+        stats.cycles = cycles.ok_or_else(|| anyhow!("RamSim output has no cycle count"))?;
+        Ok(stats)
+    }
+
+    pub fn set_total_bytes(&mut self, total_bytes: u64) {
+        self.bytes_per_cycle = if self.memory_system_cycles == 0 {
+            0.0
+        } else {
+            total_bytes as f64 / self.memory_system_cycles as f64
+        };
+    }
+
+    pub fn stall_breakdown(&self) -> StallBreakdown {
+        self.channels
+            .iter()
+            .fold(StallBreakdown::default(), |mut s, c| {
+                s.busy_cycles += c.busy_cycles;
+                s.stall_cycles += c.stall_cycles;
+                s.idle_cycles += c.idle_cycles;
+                s.rejected_requests += c.num_rejected_requests;
+                s
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ramsim_stats() -> Result<()> {
+        let output = "Frontend tick: 1, Memory tick: 1
+Phase 0 issued in cycle 0
+TraceGen finished in cycle 1234
+Frontend:
+  impl: TraceGen
+  Frontend:
+    impl: RamSim
+    s_total_mem_req: 96
+MemorySystem:
+  impl: GenericDRAM
+  total_num_other_requests: 0
+  total_num_write_requests: 32
+  total_num_read_requests: 64
+  memory_system_cycles: 1200
+  Controller:
+    impl: Generic
+    id: Channel 0
+    num_read_reqs: 40
+    num_write_reqs: 12
+    num_row_hits: 30
+    num_row_misses: 2
+    num_row_conflicts: 8
+    num_rejected_reqs: 3
+    num_busy_cycles: 100
+    num_stall_cycles: 700
+    num_idle_cycles: 400
+  Controller:
+    impl: Generic
+    id: Channel 1
+    num_read_reqs: 24
+    num_write_reqs: 20
+    num_busy_cycles: 80
+    num_stall_cycles: 20
+    num_idle_cycles: 1100
+";
+        let mut stats = RamSimStats::parse(output)?;
+        assert_eq!(stats.cycles, 1234);
+        assert_eq!(stats.total_mem_req, 96);
+        assert_eq!(stats.num_read_requests, 64);
+        assert_eq!(stats.num_write_requests, 32);
+        assert_eq!(stats.memory_system_cycles, 1200);
+        assert_eq!(stats.phase_issue_cycles, vec![(0, 0)]);
+
+        assert_eq!(stats.channels.len(), 2);
+        assert_eq!(stats.channels[1].id, "Channel 1");
+        assert_eq!(stats.channels[0].row_hit_rate(), 0.75);
+        let stalls = stats.stall_breakdown();
+        assert_eq!(stalls.busy_cycles, 180);
+        assert_eq!(stalls.stall_cycles, 720);
+        assert_eq!(stalls.rejected_requests, 3);
+
+        stats.set_total_bytes(96 * 64);
+        assert_eq!(stats.bytes_per_cycle, 5.12);
+
+        // RamSim was killed before the trace was finished
+        assert!(RamSimStats::parse("Frontend tick: 1, Memory tick: 1\n").is_err());
+        Ok(())
+    }
+}
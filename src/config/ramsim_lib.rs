@@ -0,0 +1,146 @@
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use libloading::Library;
+
+use crate::config::ram_config::OpRecord;
+
+// see thirdparty/ramsim/src/frontend/impl/ramsim/ramsim_capi.h
+type CreateFn = unsafe extern "C" fn(*const c_char, *const *const c_char, usize) -> *mut c_void;
+type SendFn =
+    unsafe extern "C" fn(*mut c_void, u64, u64, u32, u32, u32, *const u64, usize) -> c_int;
+type PhaseFn = unsafe extern "C" fn(*mut c_void, u64, u64);
+type FinishFn = unsafe extern "C" fn(*mut c_void) -> *mut c_char;
+type FreeStringFn = unsafe extern "C" fn(*mut c_char);
+type DestroyFn = unsafe extern "C" fn(*mut c_void);
+
+// errors of ramsim_send
+const RAMSIM_BAD_TYPE: c_int = -1;
+const RAMSIM_BAD_ID: c_int = -2;
+
+fn symbol<T: Copy>(lib: &Library, lib_path: &str, name: &str) -> Result<T> {
+    let sym = unsafe { lib.get::<T>(name.as_bytes()) }
+        .with_context(|| format!("{} has no {}, rebuild RamSim", lib_path, name))?;
+    Ok(*sym)
+}
+
+/// RamSim running in this process through the C API of libramulator.so. Ops are
+/// simulated while they are sent, so no trace file is written. The output of finish
+/// is the same as the stdout of the ramulator2 executable.
+pub struct RamSimLib {
+    handle: *mut c_void,
+    finished: bool,
+    send: SendFn,
+    phase: PhaseFn,
+    finish: FinishFn,
+    free_string: FreeStringFn,
+    destroy: DestroyFn,
+    // the function pointers above are only valid while the library is loaded
+    _lib: Library,
+}
+
+impl RamSimLib {
+    pub fn load(lib_path: &str, config_path: &str, params: &[String]) -> Result<Self> {
+        ensure!(
+            Path::new(lib_path).is_file(),
+            "RamSim library {} not found, build it in thirdparty/ramsim first",
+            lib_path
+        );
+        let lib = unsafe { Library::new(lib_path) }
+            .with_context(|| format!("failed to load {}", lib_path))?;
+        let create: CreateFn = symbol(&lib, lib_path, "ramsim_create")?;
+        let send = symbol(&lib, lib_path, "ramsim_send")?;
+        let phase = symbol(&lib, lib_path, "ramsim_phase")?;
+        let finish = symbol(&lib, lib_path, "ramsim_finish")?;
+        let free_string = symbol(&lib, lib_path, "ramsim_free_string")?;
+        let destroy = symbol(&lib, lib_path, "ramsim_destroy")?;
+
+        let config = CString::new(config_path)?;
+        let params = params
+            .iter()
+            .map(|p| CString::new(p.as_str()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let param_ptrs = params.iter().map(|p| p.as_ptr()).collect::<Vec<_>>();
+        let handle = unsafe { create(config.as_ptr(), param_ptrs.as_ptr(), param_ptrs.len()) };
+        ensure!(!handle.is_null(), "RamSim failed to load {}", config_path);
+        Ok(Self {
+            handle,
+            finished: false,
+            send,
+            phase,
+            finish,
+            free_string,
+            destroy,
+            _lib: lib,
+        })
+    }
+
+    // ops must have continuous ids starting from 0, RamSim exits otherwise
+    pub fn send(&mut self, op: &OpRecord) -> Result<()> {
+        ensure!(!self.finished, "RamSim has already finished");
+        let ret = unsafe {
+            (self.send)(
+                self.handle,
+                op.id,
+                op.addr,
+                op.fetch_type as u32,
+                op.delay,
+                op.size,
+                op.dependencies.as_ptr(),
+                op.dependencies.len(),
+            )
+        };
+        match ret {
+            0 => Ok(()),
+            RAMSIM_BAD_TYPE => bail!("RamSim rejected op {}: bad fetch type", op.id),
+            RAMSIM_BAD_ID => bail!("RamSim rejected op {}: ids are not continuous", op.id),
+            _ => bail!("RamSim rejected op {} with status {}", op.id, ret),
+        }
+    }
+
+    // the ops from first_id on belong to phase
+    pub fn phase(&mut self, first_id: u64, phase: usize) {
+        unsafe { (self.phase)(self.handle, first_id, phase as u64) }
+    }
+
+    // simulate until all ops are done and return the output with the statistics
+    pub fn finish(&mut self) -> Result<String> {
+        ensure!(!self.finished, "RamSim has already finished");
+        self.finished = true;
+        unsafe {
+            let s = (self.finish)(self.handle);
+            ensure!(!s.is_null(), "RamSim returned no output");
+            let output = CStr::from_ptr(s).to_string_lossy().into_owned();
+            (self.free_string)(s);
+            Ok(output)
+        }
+    }
+}
+
+impl Drop for RamSimLib {
+    fn drop(&mut self) {
+        unsafe { (self.destroy)(self.handle) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ramsim_lib_errors() {
+        let err = RamSimLib::load("./no_such_lib.so", "./configs/fibonacci.yaml", &[]);
+        assert!(format!("{:#}", err.err().unwrap()).contains("not found"));
+
+        // a library built before the C API was added, if RamSim has been built at all
+        let lib_path = "./thirdparty/ramsim/libramulator.so";
+        if !Path::new(lib_path).is_file() {
+            return;
+        }
+        if let Err(err) = RamSimLib::load(lib_path, "./configs/fibonacci.yaml", &[]) {
+            let err = format!("{:#}", err);
+            assert!(err.contains("rebuild RamSim") || err.contains("failed to load"));
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    // write the trace and run RamSim on it
+    #[default]
+    RamSim,
+    // only use the analytical model, no trace is written
+    Analytical,
+    // stream the ops into RamSim through libramulator.so, no trace is written
+    RamSimLib,
+}
+
+// parameters of the analytical roofline model, see system::analytical
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimingConfig {
+    pub backend: Backend,
+    pub freq_ghz: f64, // clock of the accelerator, delay and interval are in its cycles
+    pub bandwidth_gbps: f64, // peak DRAM bandwidth in GB/s
+    pub latency_ns: f64, // DRAM access latency paid by every fetch step
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::RamSim,
+            freq_ghz: 1.0,
+            bandwidth_gbps: 1024.0,
+            latency_ns: 100.0,
+        }
+    }
+}
+
+impl TimingConfig {
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.freq_ghz > 0.0, "freq_ghz must be positive");
+        ensure!(self.bandwidth_gbps > 0.0, "bandwidth_gbps must be positive");
+        ensure!(self.latency_ns >= 0.0, "latency_ns must not be negative");
+        Ok(())
+    }
+}
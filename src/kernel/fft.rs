@@ -2,24 +2,26 @@ use log::debug;
 use num::integer::Roots;
 use plonky2::util::{log2_ceil, log2_strict};
 
-use crate::config::arch_config::ARCH_CONFIG;
-use crate::config::enable_config::ENABLE_CONFIG;
-use crate::kernel::kernel::Kernel;
+use crate::config::arch_config::ArchConfig;
+use crate::config::HwConfig;
+use crate::kernel::kernel::{FetchEdit, Kernel, KernelCall};
+use crate::memory::memory_allocator::MemAlloc;
 use crate::trace::trace::{Fetch, FetchType, Request};
 use crate::util::{bit_reverse, ceil_div_usize, D, SIZE_F};
 use plonky2::field::goldilocks_field::GoldilocksField as F;
 use plonky2::field::types::Field;
+use serde::{Deserialize, Serialize};
 use std::cmp::min;
 use std::mem::swap;
 
 use super::vector_operation::VecOpExtension;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum FftDirection {
     NN, // natural order input and natural order output
     NR, // natural order input and reverse order output
 }
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct FftConfig {
     pub lg_n: usize, // log2 of the size of the FFT
     pub k: usize,    // number of FFTs
@@ -35,44 +37,44 @@ pub struct FftConfig {
 }
 
 impl FftConfig {
-    pub fn get_log_plane_length(&self) -> usize {
+    pub fn get_log_plane_length(&self, arch: &ArchConfig) -> usize {
         let mut log2_ex = log2_ceil(self.extension);
         if log2_ex > 0 {
             log2_ex -= 1;
         }
-        (unsafe { ARCH_CONFIG.array_length } >> 1) - 1 - log2_ex
+        (arch.array_length >> 1) - 1 - log2_ex
     }
 
-    pub fn get_plane_length(&self) -> usize {
-        1 << self.get_log_plane_length()
+    pub fn get_plane_length(&self, arch: &ArchConfig) -> usize {
+        1 << self.get_log_plane_length(arch)
     }
 
-    pub fn get_num_planes(&self) -> usize {
-        unsafe {
-            ARCH_CONFIG.active_buf_size() * 1024
-                / (8 * self.extension)
-                / (self.get_plane_length() * self.get_plane_length())
-        }
+    pub fn get_num_planes(&self, arch: &ArchConfig) -> usize {
+        arch.active_buf_size() * 1024
+            / (8 * self.extension)
+            / (self.get_plane_length(arch) * self.get_plane_length(arch))
     }
 }
 
 // todo we need an extra round to perform the twiddle mul between dimensions
 pub struct Fft {
     pub config: FftConfig,
+    pub arch: ArchConfig,
     pub prefetch: Fetch,
     pub read_request: Request,
     pub write_request: Request,
     pub drain: Fetch,
+    pub edits: Vec<FetchEdit>,
 }
 
 impl Kernel for Fft {
     fn create_prefetch(&mut self) {
-        let num_planes = self.config.get_num_planes();
-        let plane_length = self.config.get_plane_length();
-        let lg_plane_length = self.config.get_log_plane_length();
+        let num_planes = self.config.get_num_planes(&self.arch);
+        let plane_length = self.config.get_plane_length(&self.arch);
+        let lg_plane_length = self.config.get_log_plane_length(&self.arch);
         let num_plane_elems = plane_length * plane_length;
         let ntt_length = 1 << self.config.lg_n;
-        let num_pipes = unsafe { ARCH_CONFIG.array_length };
+        let num_pipes = self.arch.array_length;
 
         if ntt_length <= plane_length {
             let mut idx = Vec::new();
@@ -130,13 +132,15 @@ impl Kernel for Fft {
                     plane_length
                 };
                 let para_k = if self.config.transposed_input && round == 0 {
-                    self.config.k.min(self.config.get_num_planes().sqrt())
+                    self.config
+                        .k
+                        .min(self.config.get_num_planes(&self.arch).sqrt())
                 } else {
                     1
                 };
                 let num_planes = ntt_length / plane_length / plane_height;
-                let plane_step = (self.config.get_num_planes() / para_k).min(num_planes);
-                let kernel_step = self.config.get_num_planes() / plane_step;
+                let plane_step = (self.config.get_num_planes(&self.arch) / para_k).min(num_planes);
+                let kernel_step = self.config.get_num_planes(&self.arch) / plane_step;
 
                 for kernel_chunk in (0..self.config.k).step_by(kernel_step) {
                     let mut idx = Vec::new();
@@ -196,12 +200,12 @@ impl Kernel for Fft {
     }
 
     fn create_read_request(&mut self) {
-        let num_planes = self.config.get_num_planes();
-        let plane_length = self.config.get_plane_length();
-        let lg_plane_length = self.config.get_log_plane_length();
+        let num_planes = self.config.get_num_planes(&self.arch);
+        let plane_length = self.config.get_plane_length(&self.arch);
+        let lg_plane_length = self.config.get_log_plane_length(&self.arch);
         let num_plane_elems = plane_length * plane_length;
         let ntt_length = 1 << self.config.lg_n;
-        let num_pipes = unsafe { ARCH_CONFIG.array_length };
+        let num_pipes = self.arch.array_length;
 
         if ntt_length <= plane_length {
             let kernel_step = num_pipes * num_planes;
@@ -254,13 +258,15 @@ impl Kernel for Fft {
                     plane_length
                 };
                 let para_k = if self.config.transposed_input && round == 0 {
-                    self.config.k.min(self.config.get_num_planes().sqrt())
+                    self.config
+                        .k
+                        .min(self.config.get_num_planes(&self.arch).sqrt())
                 } else {
                     1
                 };
                 let num_planes = ntt_length / plane_length / plane_height;
-                let plane_step = (self.config.get_num_planes() / para_k).min(num_planes);
-                let kernel_step = self.config.get_num_planes() / plane_step;
+                let plane_step = (self.config.get_num_planes(&self.arch) / para_k).min(num_planes);
+                let kernel_step = self.config.get_num_planes(&self.arch) / plane_step;
                 for kernel_chunk in (0..self.config.k).step_by(kernel_step) {
                     for plane_chunk in (0..num_planes).step_by(plane_step) {
                         let mut request = Vec::new();
@@ -311,12 +317,12 @@ impl Kernel for Fft {
     }
 
     fn create_write_request(&mut self) {
-        let num_planes = self.config.get_num_planes();
-        let plane_length = self.config.get_plane_length();
-        let lg_plane_length = self.config.get_log_plane_length();
+        let num_planes = self.config.get_num_planes(&self.arch);
+        let plane_length = self.config.get_plane_length(&self.arch);
+        let lg_plane_length = self.config.get_log_plane_length(&self.arch);
         let num_plane_elems = plane_length * plane_length;
         let ntt_length = 1 << self.config.lg_n;
-        let num_pipes = unsafe { ARCH_CONFIG.array_length };
+        let num_pipes = self.arch.array_length;
 
         if ntt_length <= plane_length {
             let kernel_step = num_pipes * num_planes;
@@ -378,7 +384,9 @@ impl Kernel for Fft {
                     plane_length
                 };
                 let para_k = if self.config.transposed_input && round == 0 {
-                    self.config.k.min(self.config.get_num_planes().sqrt())
+                    self.config
+                        .k
+                        .min(self.config.get_num_planes(&self.arch).sqrt())
                 } else {
                     1
                 };
@@ -391,8 +399,9 @@ impl Kernel for Fft {
                     plane_length / residual_length
                 };
                 let plane_offset = self.plane_offset((num_dims - 2) as u32, factor);
-                let plane_step = (self.config.get_num_planes() / para_k).min(plane_offset);
-                let kernel_step = self.config.get_num_planes() / plane_step;
+                let plane_step =
+                    (self.config.get_num_planes(&self.arch) / para_k).min(plane_offset);
+                let kernel_step = self.config.get_num_planes(&self.arch) / plane_step;
 
                 for kernel_chunk in (0..self.config.k).step_by(kernel_step) {
                     for plane_chunk in (0..num_planes).step_by(plane_step) {
@@ -456,12 +465,12 @@ impl Kernel for Fft {
     fn create_drain(&mut self) {
         let is_nr = self.config.direction == FftDirection::NR;
 
-        let num_planes = self.config.get_num_planes();
-        let plane_length = self.config.get_plane_length();
-        let lg_plane_length = self.config.get_log_plane_length();
+        let num_planes = self.config.get_num_planes(&self.arch);
+        let plane_length = self.config.get_plane_length(&self.arch);
+        let lg_plane_length = self.config.get_log_plane_length(&self.arch);
         let num_plane_elems = plane_length * plane_length;
         let ntt_length = 1 << self.config.lg_n;
-        let num_pipes = unsafe { ARCH_CONFIG.array_length };
+        let num_pipes = self.arch.array_length;
 
         if ntt_length <= plane_length {
             let mut idx = Vec::new();
@@ -530,7 +539,9 @@ impl Kernel for Fft {
                     plane_length
                 };
                 let para_k = if self.config.transposed_input && round == 0 {
-                    self.config.k.min(self.config.get_num_planes().sqrt())
+                    self.config
+                        .k
+                        .min(self.config.get_num_planes(&self.arch).sqrt())
                 } else {
                     1
                 };
@@ -543,8 +554,9 @@ impl Kernel for Fft {
                     plane_length / residual_length
                 };
                 let plane_offset = self.plane_offset((num_dims - 2) as u32, factor);
-                let plane_step = (self.config.get_num_planes() / para_k).min(plane_offset);
-                let kernel_step = self.config.get_num_planes() / plane_step;
+                let plane_step =
+                    (self.config.get_num_planes(&self.arch) / para_k).min(plane_offset);
+                let kernel_step = self.config.get_num_planes(&self.arch) / plane_step;
 
                 for kernel_chunk in (0..self.config.k).step_by(kernel_step) {
                     let mut idx = Vec::new();
@@ -639,34 +651,103 @@ impl Kernel for Fft {
     fn get_kernel_type(&self) -> String {
         String::from("FFT")
     }
+    fn get_call(&self) -> KernelCall {
+        // undo the swap in new
+        let mut config = self.config;
+        if self.num_rounds() % 2 == 0 {
+            swap(&mut config.addr_output, &mut config.addr_tmp);
+        }
+        KernelCall::Fft(config, self.edits.clone())
+    }
+    fn execute(&self, mem: &mut MemAlloc) {
+        let n = 1 << self.config.lg_n;
+        let num_coeffs = n >> self.config.rate_bits;
+        let ex = self.config.extension;
+        let addr_output = if self.num_rounds() % 2 == 0 {
+            self.config.addr_tmp
+        } else {
+            self.config.addr_output
+        };
+        // the twiddles are in the base field, so each limb of an extension is transformed alone
+        for i in 0..self.config.k {
+            for e in 0..ex {
+                let mut values = vec![F::ZERO; n];
+                for (j, v) in values.iter_mut().enumerate().take(num_coeffs) {
+                    let idx = if self.config.transposed_input {
+                        j * self.config.k + i
+                    } else {
+                        i * num_coeffs + j
+                    };
+                    let addr = self.config.addr_input + (idx * ex + e) * SIZE_F;
+                    *v = mem.read_elem(FetchEdit::read_addr(&self.edits, addr));
+                }
+                if self.config.coset && !self.config.inverse {
+                    values
+                        .iter_mut()
+                        .zip(F::coset_shift().powers())
+                        .for_each(|(v, s)| *v *= s);
+                }
+                let mut values = fft(&values, self.config.inverse, false);
+                if self.config.coset && self.config.inverse {
+                    values
+                        .iter_mut()
+                        .zip(F::coset_shift().inverse().powers())
+                        .for_each(|(v, s)| *v *= s);
+                }
+                for (j, v) in values.into_iter().enumerate() {
+                    let j = match self.config.direction {
+                        FftDirection::NN => j,
+                        FftDirection::NR => bit_reverse(j, self.config.lg_n),
+                    };
+                    let addr = addr_output + ((i * n + j) * ex + e) * SIZE_F;
+                    mem.write_elem(FetchEdit::write_addr(&self.edits, addr), v);
+                }
+            }
+        }
+    }
 }
 
 impl Fft {
-    pub fn new(config: FftConfig) -> Fft {
+    pub fn new(config: FftConfig, hw: &HwConfig) -> Fft {
         assert!(config.extension > 0);
         let mut fft = Fft {
             config,
+            arch: hw.arch,
             prefetch: Fetch::new(FetchType::Read),
             read_request: Request::new(),
             write_request: Request::new(),
             drain: Fetch::new(FetchType::Write),
+            edits: Vec::new(),
         };
-        let lg_plane_length = fft.config.get_log_plane_length();
-        let num_dims = ceil_div_usize(fft.config.lg_n, lg_plane_length);
-        let num_rounds = ceil_div_usize(num_dims, 2);
         assert_ne!(
             fft.config.addr_input, fft.config.addr_tmp,
             "addr_input and addr_tmp should be different"
         );
-        if num_rounds % 2 == 0 {
+        if fft.num_rounds() % 2 == 0 {
             swap(&mut fft.config.addr_output, &mut fft.config.addr_tmp);
         }
         fft.log();
-        if unsafe { ENABLE_CONFIG.fft } {
+        if hw.enable.fft {
             fft.init()
         }
         fft
     }
+
+    pub(crate) fn num_rounds(&self) -> usize {
+        let lg_plane_length = self.config.get_log_plane_length(&self.arch);
+        let num_dims = ceil_div_usize(self.config.lg_n, lg_plane_length);
+        ceil_div_usize(num_dims, 2)
+    }
+
+    pub fn edit(&mut self, edit: FetchEdit) {
+        edit.apply(
+            &mut self.prefetch,
+            &mut self.read_request,
+            &mut self.write_request,
+            &mut self.drain,
+        );
+        self.edits.push(edit);
+    }
     fn idx_to_addri(&self, idx: Vec<Vec<usize>>, round: usize) -> Vec<Vec<usize>> {
         let n = 1 << self.config.lg_n;
         let addr_input = if round == 0 {
@@ -679,13 +760,16 @@ impl Fft {
                 x.iter()
                     .map(|y| {
                         let bound: usize = 1 << (self.config.lg_n - self.config.rate_bits);
-                        if (y % n) > bound && round == 0 {
+                        if (y % n) >= bound && round == 0 {
                             return 0;
                         };
                         let y = if round == 0 && self.config.transposed_input {
                             let row = y / (1 << self.config.lg_n);
                             let col = y % (1 << self.config.lg_n);
                             col * self.config.k + row
+                        } else if round == 0 {
+                            // the input polynomials have bound coefficients each
+                            y / n * bound + y % n
                         } else {
                             *y
                         };
@@ -719,13 +803,15 @@ impl Fft {
                 x.iter()
                     .map(|y| {
                         let bound: usize = 1 << (self.config.lg_n - self.config.rate_bits);
-                        if (y % n) > bound && round == 0 && !offset {
+                        if (y % n) >= bound && round == 0 && !offset {
                             return 0;
                         };
                         let y: usize = if round == 0 && !offset && self.config.transposed_input {
                             let row = y / (1 << self.config.lg_n);
                             let col = y % (1 << self.config.lg_n);
                             col * self.config.k + row
+                        } else if round == 0 && !offset {
+                            y / n * bound + y % n
                         } else {
                             *y
                         };
@@ -753,7 +839,7 @@ impl Fft {
     }
 
     fn plane_offset(&self, now_dim: u32, factor: usize) -> usize {
-        self.config.get_plane_length().pow(now_dim) / factor
+        self.config.get_plane_length(&self.arch).pow(now_dim) / factor
     }
 }
 
@@ -815,6 +901,7 @@ pub fn fft(input: &Vec<F>, inverse: bool, reverse: bool) -> Vec<F> {
 
 #[cfg(test)]
 mod tests {
+    use crate::config::HwConfig;
     use crate::kernel::fft::{fft, Fft, FftConfig, FftDirection};
     use plonky2::field::goldilocks_field::GoldilocksField as F;
     use plonky2::field::types::Field;
@@ -834,7 +921,7 @@ mod tests {
             extension: 1,
             transposed_input: false,
         };
-        let _fft = Fft::new(config);
+        let _fft = Fft::new(config, &HwConfig::default());
     }
 
     #[test]
@@ -864,4 +951,72 @@ mod tests {
         assert_eq!(output_fft, output_fft_expected, "fft result does not match");
         assert_eq!(output_ifft, input, "ifft result does not match");
     }
+
+    #[test]
+    fn test_fft_execute() {
+        use crate::kernel::kernel::Kernel;
+        use crate::memory::memory_allocator::MemAlloc;
+        use crate::util::SIZE_F;
+        use plonky2::field::polynomial::PolynomialValues;
+        use plonky2::field::types::Sample;
+        use plonky2::util::reverse_index_bits_in_place;
+
+        let (lg_n, k, rate_bits) = (5, 3, 2);
+        let n = 1 << lg_n;
+        let values = (0..k).map(|_| F::rand_vec(n)).collect::<Vec<_>>();
+        let mut mem = MemAlloc::new(1, 4096);
+        let addr_values = mem.alloc("values", n * k * SIZE_F).unwrap();
+        let addr_coeffs = mem.alloc("coeffs", n * k * SIZE_F).unwrap();
+        let addr_lde = mem.alloc("lde", (n << rate_bits) * k * SIZE_F).unwrap();
+        let addr_tmp = mem.alloc("tmp", (n << rate_bits) * k * SIZE_F).unwrap();
+        // transposed, one row after the other
+        for j in 0..n {
+            for i in 0..k {
+                mem.write_elem(addr_values + (j * k + i) * SIZE_F, values[i][j]);
+            }
+        }
+
+        let mut hw = HwConfig::default();
+        hw.enable.fft = false;
+        let config = FftConfig {
+            lg_n,
+            k,
+            direction: FftDirection::NN,
+            transposed_input: true,
+            addr_input: addr_values,
+            addr_tmp,
+            addr_output: addr_coeffs,
+            inverse: true,
+            rate_bits: 0,
+            coset: false,
+            extension: 1,
+        };
+        Fft::new(config, &hw).execute(&mut mem);
+        let config = FftConfig {
+            lg_n: lg_n + rate_bits,
+            direction: FftDirection::NR,
+            transposed_input: false,
+            addr_input: addr_coeffs,
+            addr_output: addr_lde,
+            inverse: false,
+            rate_bits,
+            coset: true,
+            ..config
+        };
+        Fft::new(config, &hw).execute(&mut mem);
+
+        for (i, v) in values.into_iter().enumerate() {
+            let coeffs = PolynomialValues::new(v).ifft();
+            assert_eq!(
+                mem.read_elems(addr_coeffs + i * n * SIZE_F, n),
+                coeffs.coeffs
+            );
+            let mut lde = coeffs.lde(rate_bits).coset_fft(F::coset_shift()).values;
+            reverse_index_bits_in_place(&mut lde);
+            assert_eq!(
+                mem.read_elems(addr_lde + i * (n << rate_bits) * SIZE_F, n << rate_bits),
+                lde
+            );
+        }
+    }
 }
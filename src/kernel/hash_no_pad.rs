@@ -1,15 +1,25 @@
 use log::debug;
+use plonky2::field::goldilocks_field::GoldilocksField as F;
+use plonky2::hash::hashing::{hash_n_to_m_no_pad, PlonkyPermutation};
+use plonky2::hash::poseidon::PoseidonPermutation;
+use serde::{Deserialize, Serialize};
 
 use crate::trace::trace::{Fetch, FetchType, Request};
-use crate::config::enable_config::ENABLE_CONFIG;
-use crate::kernel::kernel::Kernel;
-use crate::util::{HASH_COMPUTATION, SINGLE_HASH_DELAY, SIZE_F, SPONGE_RATE};
-#[derive(Debug, Clone)]
+use crate::config::HwConfig;
+use crate::kernel::kernel::{FetchEdit, Kernel, KernelCall};
+use crate::memory::memory_allocator::MemAlloc;
+use crate::util::{HASH_COMPUTATION, SINGLE_HASH_DELAY, SIZE_F, SPONGE_RATE, SPONGE_WIDTH};
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HashNoPadConfig {
     pub addr_input: Vec<usize>,
     pub addr_output: usize,
     pub input_length: Vec<usize>,
     pub output_length: usize,
+    // a duplexing of the challenger: the inputs overwrite the start of the sponge state kept
+    // here instead of a zero one, which is permuted once and kept for the next duplexing. The
+    // state stays in the hash unit, so it isn't fetched.
+    #[serde(default)]
+    pub addr_state: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
@@ -19,6 +29,7 @@ pub struct HashNoPad {
     pub drain: Fetch,
     pub write_request: Request,
     pub read_request: Request,
+    pub edits: Vec<FetchEdit>,
 }
 
 impl Kernel for HashNoPad {
@@ -87,20 +98,65 @@ impl Kernel for HashNoPad {
     fn get_computation(&self) -> usize {
         self.read_request.num_request_lines() * HASH_COMPUTATION
     }
+    fn get_call(&self) -> KernelCall {
+        KernelCall::HashNoPad(self.config.clone(), self.edits.clone())
+    }
+    fn execute(&self, mem: &mut MemAlloc) {
+        let mut inputs = Vec::new();
+        for (a, i) in self
+            .config
+            .addr_input
+            .iter()
+            .zip(self.config.input_length.iter())
+        {
+            for j in 0..*i {
+                inputs.push(mem.read_elem(FetchEdit::read_addr(&self.edits, a + j * SIZE_F)));
+            }
+        }
+        let outputs = match self.config.addr_state {
+            Some(addr_state) => {
+                assert!(inputs.len() <= SPONGE_RATE);
+                let mut state =
+                    PoseidonPermutation::<F>::new(mem.read_elems(addr_state, SPONGE_WIDTH));
+                state.set_from_slice(&inputs, 0);
+                state.permute();
+                mem.write_elems(addr_state, state.as_ref());
+                state.squeeze()[..self.config.output_length].to_vec()
+            }
+            None => {
+                hash_n_to_m_no_pad::<F, PoseidonPermutation<F>>(&inputs, self.config.output_length)
+            }
+        };
+        for (i, value) in outputs.into_iter().enumerate() {
+            let addr = FetchEdit::write_addr(&self.edits, self.config.addr_output + i * SIZE_F);
+            mem.write_elem(addr, value);
+        }
+    }
 }
 
 impl HashNoPad {
-    pub fn new(config: HashNoPadConfig) -> Self {
+    pub fn new(config: HashNoPadConfig, hw: &HwConfig) -> Self {
         let mut hash_no_pad = Self {
             config,
             prefetch: Fetch::new(FetchType::Read),
             drain: Fetch::new(FetchType::Write),
             write_request: Request::new(),
             read_request: Request::new(),
+            edits: Vec::new(),
         };
-        if unsafe { ENABLE_CONFIG.hash } {
+        if hw.enable.hash {
             hash_no_pad.init();
         }
         hash_no_pad
     }
+
+    pub fn edit(&mut self, edit: FetchEdit) {
+        edit.apply(
+            &mut self.prefetch,
+            &mut self.read_request,
+            &mut self.write_request,
+            &mut self.drain,
+        );
+        self.edits.push(edit);
+    }
 }
@@ -1,7 +1,20 @@
+use crate::config::HwConfig;
+use crate::kernel::fft::{Fft, FftConfig};
+use crate::kernel::hash_no_pad::{HashNoPad, HashNoPadConfig};
+use crate::kernel::memory_copy::{MemCpy, MemCpyConfig};
+use crate::kernel::transpose::{Transpose, TransposeConfig};
+use crate::kernel::tree::{Tree, TreeConfig};
+use crate::kernel::vec_cse::CseStats;
+use crate::kernel::vec_schedule::ScheduleStats;
+use crate::kernel::vector_chain::VectorChain;
+use crate::kernel::vector_operation::VecOpConfig;
+use crate::memory::memory_allocator::MemAlloc;
 use crate::trace::trace::{Fetch, FetchType, Request};
 use crate::util::SIZE_F;
+use std::collections::BTreeMap;
 use plonky2::field::goldilocks_field::GoldilocksField as F;
 use plonky2::field::types::{Field, PrimeField64};
+use serde::{Deserialize, Serialize};
 
 pub trait Kernel {
     fn init(&mut self) {
@@ -46,4 +59,132 @@ pub trait Kernel {
     fn get_computation(&self) -> usize;
     fn get_kernel_type(&self) -> String;
     fn log(&self);
+    // what KernelCall::build needs to create this kernel again, see system::replay
+    fn get_call(&self) -> KernelCall;
+    // compute the output values from the data in mem, see System::functional
+    fn execute(&self, mem: &mut MemAlloc);
+    // traffic before and after the ops were reordered, see HwConfig::opt
+    fn get_schedule_stats(&self) -> Option<ScheduleStats> {
+        None
+    }
+    // vector ops removed by tag, see HwConfig::opt
+    fn get_cse_stats(&self) -> BTreeMap<String, CseStats> {
+        BTreeMap::new()
+    }
+}
+
+/// A change made by the caller to the fetches of a kernel after it was created.
+/// Kernels record them so that a replay under another arch can redo them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FetchEdit {
+    ClearPrefetch,
+    ClearPrefetchAddr, // only prefetch.addr, the delays are kept
+    ClearReadRequest,
+    ClearWriteRequest,
+    ClearDrain,
+    PrefetchAddrTrans(Vec<MemCpyConfig>), // Fetch::addr_trans with each memcpy in turn
+    PrefetchAddrTransVec(Vec<MemCpyConfig>),
+    DrainAddrTransVec(Vec<MemCpyConfig>),
+    DrainInterval(f32),
+    WriteNumLines(Vec<usize>),
+}
+
+impl FetchEdit {
+    pub fn apply(
+        &self,
+        prefetch: &mut Fetch,
+        read_request: &mut Request,
+        write_request: &mut Request,
+        drain: &mut Fetch,
+    ) {
+        match self {
+            FetchEdit::ClearPrefetch => prefetch.clear(),
+            FetchEdit::ClearPrefetchAddr => prefetch.addr.clear(),
+            FetchEdit::ClearReadRequest => read_request.clear(),
+            FetchEdit::ClearWriteRequest => write_request.clear(),
+            FetchEdit::ClearDrain => drain.clear(),
+            FetchEdit::PrefetchAddrTrans(memcpys) => {
+                memcpys.iter().for_each(|m| prefetch.addr_trans(m))
+            }
+            FetchEdit::PrefetchAddrTransVec(memcpys) => prefetch.addr_trans_vec(memcpys),
+            FetchEdit::DrainAddrTransVec(memcpys) => drain.addr_trans_vec(memcpys),
+            FetchEdit::DrainInterval(interval) => drain.interval = *interval,
+            FetchEdit::WriteNumLines(num_lines) => write_request.num_lines = num_lines.clone(),
+        }
+    }
+
+    /// Where the element the kernel reads at addr actually is after the edits.
+    pub fn read_addr(edits: &[FetchEdit], addr: usize) -> usize {
+        edits.iter().fold(addr, |addr, edit| match edit {
+            FetchEdit::PrefetchAddrTrans(memcpys) => memcpys
+                .iter()
+                .fold(addr, |addr, m| m.translate(addr).unwrap_or(addr)),
+            FetchEdit::PrefetchAddrTransVec(memcpys) => first_translation(memcpys, addr),
+            _ => addr,
+        })
+    }
+
+    /// Where the element the kernel writes at addr actually goes after the edits.
+    pub fn write_addr(edits: &[FetchEdit], addr: usize) -> usize {
+        edits.iter().fold(addr, |addr, edit| match edit {
+            FetchEdit::DrainAddrTransVec(memcpys) => first_translation(memcpys, addr),
+            _ => addr,
+        })
+    }
+}
+
+// same as Fetch::addr_trans_vec, the first memcpy that covers addr translates it
+fn first_translation(memcpys: &[MemCpyConfig], addr: usize) -> usize {
+    memcpys
+        .iter()
+        .find_map(|m| m.translate(addr))
+        .unwrap_or(addr)
+}
+
+/// A kernel invocation without anything derived from the arch. VectorChain keeps
+/// the ops after the preloaded addresses are removed and the number of preloaded
+/// elements, since it may be run again after the preloads changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KernelCall {
+    Fft(FftConfig, Vec<FetchEdit>),
+    Tree(TreeConfig, Vec<FetchEdit>),
+    Transpose(TransposeConfig, Vec<FetchEdit>),
+    MemCpy(MemCpyConfig, bool, Vec<FetchEdit>), // config, enable
+    HashNoPad(HashNoPadConfig, Vec<FetchEdit>),
+    VectorChain(Vec<VecOpConfig>, usize), // vec ops, number of preloaded elements
+}
+
+impl KernelCall {
+    pub fn build(&self, hw: &HwConfig) -> Box<dyn Kernel> {
+        match self {
+            KernelCall::Fft(config, edits) => {
+                let mut k = Fft::new(*config, hw);
+                edits.iter().for_each(|e| k.edit(e.clone()));
+                Box::new(k)
+            }
+            KernelCall::Tree(config, edits) => {
+                let mut k = Tree::new(config.clone(), hw);
+                edits.iter().for_each(|e| k.edit(e.clone()));
+                Box::new(k)
+            }
+            KernelCall::Transpose(config, edits) => {
+                let mut k = Transpose::new(*config, hw);
+                edits.iter().for_each(|e| k.edit(e.clone()));
+                Box::new(k)
+            }
+            KernelCall::MemCpy(config, enable, edits) => {
+                let mut k = MemCpy::new(config.clone(), *enable, hw);
+                edits.iter().for_each(|e| k.edit(e.clone()));
+                Box::new(k)
+            }
+            KernelCall::HashNoPad(config, edits) => {
+                let mut k = HashNoPad::new(config.clone(), hw);
+                edits.iter().for_each(|e| k.edit(e.clone()));
+                Box::new(k)
+            }
+            KernelCall::VectorChain(vec_ops, num_preload_elems) => Box::new(
+                VectorChain::from_chained_ops(vec_ops.clone(), *num_preload_elems, hw),
+            ),
+        }
+    }
 }
@@ -1,100 +1,304 @@
-use std::collections::{HashMap, LinkedList};
+use std::collections::{BTreeSet, HashMap, VecDeque};
 
+use crate::config::arch_config::Replacement;
 use crate::util::SIZE_F;
 
 type K = usize;
 type V = usize;
+// (address, size) pairs, the loads and evictions of a put
+type Entries = Vec<(K, V)>;
 
-pub struct LRUcache {
+/// Decides which key of a BufferCache is evicted next.
+pub trait ReplacementPolicy {
+    // key was put into the buffer
+    fn insert(&mut self, key: K);
+    // key was put or got again while it is in the buffer
+    fn access(&mut self, key: K);
+    fn remove(&mut self, key: K);
+    // removes and returns the key to evict
+    fn evict(&mut self) -> Option<K>;
+}
+
+/// The policy for `replacement`. future is the sequence of keys that will be put or
+/// got, only Belady needs it.
+pub fn policy(replacement: Replacement, future: &[K]) -> Box<dyn ReplacementPolicy> {
+    match replacement {
+        Replacement::Lru => Box::new(Lru::new()),
+        Replacement::Fifo => Box::new(Fifo::default()),
+        Replacement::Belady => Box::new(Belady::new(future)),
+    }
+}
+
+const NIL: usize = usize::MAX;
+
+struct Node {
+    key: K,
+    prev: usize,
+    next: usize,
+}
+
+/// Least recently used, a doubly linked list of indices into nodes so that every
+/// operation is O(1).
+pub struct Lru {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    index: HashMap<K, usize>,
+    head: usize, // most recent
+    tail: usize,
+}
+
+impl Lru {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            index: HashMap::new(),
+            head: NIL,
+            tail: NIL,
+        }
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = (self.nodes[i].prev, self.nodes[i].next);
+        match prev {
+            NIL => self.head = next,
+            _ => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            _ => self.nodes[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, i: usize) {
+        self.nodes[i].prev = NIL;
+        self.nodes[i].next = self.head;
+        match self.head {
+            NIL => self.tail = i,
+            head => self.nodes[head].prev = i,
+        }
+        self.head = i;
+    }
+}
+
+impl Default for Lru {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplacementPolicy for Lru {
+    fn insert(&mut self, key: K) {
+        let node = Node {
+            key,
+            prev: NIL,
+            next: NIL,
+        };
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = node;
+                i
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        self.push_front(i);
+        self.index.insert(key, i);
+    }
+
+    fn access(&mut self, key: K) {
+        if let Some(&i) = self.index.get(&key) {
+            self.unlink(i);
+            self.push_front(i);
+        }
+    }
+
+    fn remove(&mut self, key: K) {
+        if let Some(i) = self.index.remove(&key) {
+            self.unlink(i);
+            self.free.push(i);
+        }
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        match self.tail {
+            NIL => None,
+            i => {
+                let key = self.nodes[i].key;
+                self.remove(key);
+                Some(key)
+            }
+        }
+    }
+}
+
+/// First in first out, accesses don't change the order.
+#[derive(Default)]
+pub struct Fifo {
+    queue: VecDeque<K>,
+}
+
+impl ReplacementPolicy for Fifo {
+    fn insert(&mut self, key: K) {
+        self.queue.push_back(key);
+    }
+
+    fn access(&mut self, _key: K) {}
+
+    fn remove(&mut self, key: K) {
+        self.queue.retain(|&k| k != key);
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        self.queue.pop_front()
+    }
+}
+
+/// Belady's MIN, evicts the key that is used again the farthest in the future. It is
+/// optimal if all values have the same size.
+pub struct Belady {
+    next_use: Vec<usize>, // for each access of future, when its key is accessed again
+    time: usize,
+    resident: BTreeSet<(usize, K)>, // (next use, key)
+    resident_next_use: HashMap<K, usize>,
+}
+
+impl Belady {
+    pub fn new(future: &[K]) -> Self {
+        let mut next_use = vec![NIL; future.len()];
+        let mut last = HashMap::new();
+        for (t, key) in future.iter().enumerate().rev() {
+            if let Some(next) = last.insert(*key, t) {
+                next_use[t] = next;
+            }
+        }
+        Self {
+            next_use,
+            time: 0,
+            resident: BTreeSet::new(),
+            resident_next_use: HashMap::new(),
+        }
+    }
+}
+
+impl ReplacementPolicy for Belady {
+    fn insert(&mut self, key: K) {
+        // past the known future, the key is not used again
+        let next_use = *self.next_use.get(self.time).unwrap_or(&NIL);
+        self.time += 1;
+        self.remove(key);
+        self.resident.insert((next_use, key));
+        self.resident_next_use.insert(key, next_use);
+    }
+
+    fn access(&mut self, key: K) {
+        self.insert(key);
+    }
+
+    fn remove(&mut self, key: K) {
+        if let Some(next_use) = self.resident_next_use.remove(&key) {
+            self.resident.remove(&(next_use, key));
+        }
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        let (_, key) = self.resident.pop_last()?;
+        self.resident_next_use.remove(&key);
+        Some(key)
+    }
+}
+
+/// The on-chip buffer of the vector kernels. A key is the address of a vector and its
+/// value the number of elements of it in the buffer.
+pub struct BufferCache {
     capacity: usize,
     map: HashMap<K, V>,
-    recent_keys: LinkedList<K>,
+    policy: Box<dyn ReplacementPolicy>,
     used: usize,
 }
 
-impl LRUcache {
-    pub fn new(capacity: usize) -> Self {
-        LRUcache {
+impl BufferCache {
+    pub fn new(capacity: usize, policy: Box<dyn ReplacementPolicy>) -> Self {
+        BufferCache {
             capacity,
             map: HashMap::new(),
-            recent_keys: LinkedList::new(),
+            policy,
             used: 0,
         }
     }
 
+    pub fn lru(capacity: usize) -> Self {
+        Self::new(capacity, Box::new(Lru::new()))
+    }
+
     pub fn get_capacity(&self) -> usize {
         self.capacity
     }
 
     pub fn get(&mut self, key: &K) -> Option<&V> {
         if self.map.contains_key(key) {
-            self.mark_as_recent(key);
+            self.policy.access(*key);
             self.map.get(key)
         } else {
             None
         }
     }
 
-    pub fn put(&mut self, key: K, value: V, wo_load: bool) -> (Vec<(K, V)>, Vec<(K, V)>) {
-        if self.map.contains_key(&key) {
-            let old_value = *self.map.get(&key).unwrap();
-            if old_value >= value {
-                self.mark_as_recent(&key);
-                (Vec::new(), Vec::new())
-            } else {
-                self.mark_as_recent(&key);
-                (
-                    vec![(key + old_value * SIZE_F, value - old_value)],
-                    Vec::new(),
-                )
+    /// Returns the (address, size) to load, only what is not in the buffer yet and
+    /// nothing if wo_load, and the evicted (key, value).
+    pub fn put(&mut self, key: K, value: V, wo_load: bool) -> (Entries, Entries) {
+        assert!(value <= self.capacity);
+        let old_value = match self.map.get(&key) {
+            Some(&old_value) if old_value >= value => {
+                self.policy.access(key);
+                return (Vec::new(), Vec::new());
             }
-        } else {
-            assert!(value <= self.capacity);
-            let mut evicted = Vec::new();
-            while self.used + value > self.capacity {
-                let tmp = self.recent_keys.pop_back();
-                if tmp.is_none() {
-                    println!("capacity: {}", self.capacity);
-                    println!("used: {}", self.used);
-                    println!("value: {}", value);
-                    println!("recent_keys: {:?}", self.recent_keys);
-                }
-                let oldest_key = tmp.unwrap(); //self.recent_keys.pop_back().unwrap();
-                self.used -= self.map.get(&oldest_key).unwrap();
-                let evicted_value = self.map.remove(&oldest_key).unwrap();
-                evicted.push((oldest_key, evicted_value));
+            // the vector grows, it is put again with the new size
+            Some(&old_value) => {
+                self.map.remove(&key);
+                self.policy.remove(key);
+                self.used -= old_value;
+                old_value
             }
-            self.map.insert(key.clone(), value);
-            self.recent_keys.push_front(key);
-            self.used += value;
+            None => 0,
+        };
 
-            let load = if wo_load { vec![] } else { vec![(key, value)] };
-            (load, evicted)
+        let mut evicted = Vec::new();
+        while self.used + value > self.capacity {
+            let oldest_key = self.policy.evict().unwrap_or_else(|| {
+                panic!(
+                    "buffer of {} elements is full with {} used, can't put {}",
+                    self.capacity, self.used, value
+                )
+            });
+            let evicted_value = self.map.remove(&oldest_key).unwrap();
+            self.used -= evicted_value;
+            evicted.push((oldest_key, evicted_value));
         }
-    }
+        self.map.insert(key, value);
+        self.policy.insert(key);
+        self.used += value;
 
-    fn mark_as_recent(&mut self, key: &K) {
-        let position = self.recent_keys.iter().position(|x| x == key).unwrap();
-        self.recent_keys.remove(position);
-        self.recent_keys.push_front(key.clone());
+        let load = if wo_load {
+            vec![]
+        } else {
+            vec![(key + old_value * SIZE_F, value - old_value)]
+        };
+        (load, evicted)
     }
 
+    // empties the buffer, in eviction order
     pub fn drain(&mut self) -> Vec<(K, V)> {
         let mut res = Vec::new();
-        while let Some(key) = self.recent_keys.pop_back() {
+        while let Some(key) = self.policy.evict() {
             let value = self.map.remove(&key).unwrap();
             res.push((key, value));
         }
+        self.used = 0;
         res
     }
-
-    fn print(&self) {
-        for key in self.recent_keys.iter() {
-            println!("{}: {}", key, self.map.get(key).unwrap());
-        }
-        println!("Used: {}", self.used);
-        println!();
-    }
 }
 
 #[cfg(test)]
@@ -103,14 +307,45 @@ mod tests {
 
     #[test]
     fn test_lru() {
-        let mut cache = LRUcache::new(30);
+        let mut cache = BufferCache::lru(30);
         cache.put(0, 10, false);
         cache.put(10, 10, false);
         cache.put(20, 10, false);
         cache.get(&0);
         cache.get(&10);
-        cache.put(30, 10, false);
+        let (load, evicted) = cache.put(30, 10, false);
+        assert_eq!(load, vec![(30, 10)]);
+        assert_eq!(evicted, vec![(20, 10)]);
+
+        // the part that is already in the buffer is not loaded again
+        let (load, evicted) = cache.put(0, 15, false);
+        assert_eq!(load, vec![(10 * SIZE_F, 5)]);
+        assert_eq!(evicted, vec![(10, 10)]);
+        assert_eq!(cache.put(0, 15, false), (vec![], vec![]));
+        assert_eq!(cache.drain(), vec![(30, 10), (0, 15)]);
+    }
+
+    #[test]
+    fn test_replacement_policies() {
+        // a buffer of two vectors
+        let future = [1, 2, 3, 1, 2, 4, 1, 2];
+        let evictions = |replacement: Replacement| {
+            let mut cache = BufferCache::new(2, policy(replacement, &future));
+            let evicted = future
+                .iter()
+                .flat_map(|&key| cache.put(key, 1, false).1)
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>();
+            evicted
+        };
+        assert_eq!(evictions(Replacement::Lru), vec![1, 2, 3, 1, 2, 4]);
+        assert_eq!(evictions(Replacement::Fifo), vec![1, 2, 3, 1, 2, 4]);
+        assert_eq!(evictions(Replacement::Belady), vec![2, 3, 2, 4]);
 
-        cache.print();
+        let mut fifo = BufferCache::new(2, policy(Replacement::Fifo, &[]));
+        fifo.put(1, 1, false);
+        fifo.put(2, 1, false);
+        fifo.get(&1);
+        assert_eq!(fifo.put(3, 1, false).1, vec![(1, 1)]);
     }
 }
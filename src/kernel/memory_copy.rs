@@ -1,23 +1,66 @@
 use log::debug;
 
+use serde::{Deserialize, Serialize};
+
 use crate::trace::trace::{Fetch, FetchType, Request};
-use crate::config::arch_config::ARCH_CONFIG;
-use crate::kernel::kernel::Kernel;
+use crate::config::arch_config::ArchConfig;
+use crate::config::HwConfig;
+use crate::kernel::kernel::{FetchEdit, Kernel, KernelCall};
+use crate::memory::memory_allocator::MemAlloc;
 use crate::util::SIZE_F;
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MemCpyConfig {
     pub addr_input: usize,
     pub addr_output: usize,
     pub input_length: usize, // number of elements
 }
 
+impl MemCpyConfig {
+    /// translate the address from output to input
+    pub fn addr_trans(&self, addr: &(u64, u64)) -> Vec<(u64, u64)> {
+        let mut res = Vec::new();
+        let (addr1, addr2) = addr.clone();
+        let addr_start = self.addr_output as u64;
+        let addr_end = (self.addr_output + self.input_length * SIZE_F) as u64;
+
+        let overlap_start = addr1.max(addr_start);
+        let overlap_end = addr2.min(addr_end);
+
+        let offset = |addr: u64| addr - self.addr_output as u64 + self.addr_input as u64;
+        if overlap_start < overlap_end {
+            res.push((offset(overlap_start), offset(overlap_end)));
+            if addr1 < overlap_start {
+                res.push((addr1, overlap_start - 1));
+            }
+            if addr2 > overlap_end {
+                res.push((overlap_end + 1, addr2));
+            }
+        } else {
+            res.push(addr.clone());
+        }
+        res
+    }
+
+    /// translate the address of an element from output to input, None if not copied
+    pub fn translate(&self, addr: usize) -> Option<usize> {
+        if addr >= self.addr_output && addr < self.addr_output + self.input_length * SIZE_F {
+            Some(addr - self.addr_output + self.addr_input)
+        } else {
+            None
+        }
+    }
+}
+
 // todo eliminate real fetch
 pub struct MemCpy {
     pub config: MemCpyConfig,
+    pub enable: bool,
+    pub arch: ArchConfig,
     pub prefetch: Fetch,
     pub drain: Fetch,
     pub write_request: Request,
     pub read_request: Request,
+    pub edits: Vec<FetchEdit>,
 }
 
 impl Kernel for MemCpy {
@@ -25,23 +68,21 @@ impl Kernel for MemCpy {
         let addrs = (0..self.config.input_length)
             .map(|i| self.config.addr_output + i * SIZE_F)
             .collect::<Vec<_>>();
-        unsafe {
-            let num_elems = ARCH_CONFIG.num_elems();
-            addrs.chunks(num_elems).for_each(|chunk| {
-                self.drain.push(
-                    chunk
-                        .chunks(ARCH_CONFIG.array_length)
-                        .map(|c| c.to_vec())
-                        .collect(),
-                );
-                self.write_request.push(
-                    chunk
-                        .chunks(ARCH_CONFIG.array_length)
-                        .map(|c| c.to_vec())
-                        .collect(),
-                );
-            });
-        }
+        let num_elems = self.arch.num_elems();
+        addrs.chunks(num_elems).for_each(|chunk| {
+            self.drain.push(
+                chunk
+                    .chunks(self.arch.array_length)
+                    .map(|c| c.to_vec())
+                    .collect(),
+            );
+            self.write_request.push(
+                chunk
+                    .chunks(self.arch.array_length)
+                    .map(|c| c.to_vec())
+                    .collect(),
+            );
+        });
 
         self.drain.mergable = true;
         self.drain.interval = 0.0;
@@ -53,23 +94,21 @@ impl Kernel for MemCpy {
         let addrs = (0..self.config.input_length)
             .map(|i| self.config.addr_input + i * SIZE_F)
             .collect::<Vec<_>>();
-        unsafe {
-            let num_elems = ARCH_CONFIG.num_elems();
-            addrs.chunks(num_elems).for_each(|chunk| {
-                self.prefetch.push(
-                    chunk
-                        .chunks(ARCH_CONFIG.array_length)
-                        .map(|c| c.to_vec())
-                        .collect(),
-                );
-                self.read_request.push(
-                    chunk
-                        .chunks(ARCH_CONFIG.array_length)
-                        .map(|c| c.to_vec())
-                        .collect(),
-                );
-            });
-        }
+        let num_elems = self.arch.num_elems();
+        addrs.chunks(num_elems).for_each(|chunk| {
+            self.prefetch.push(
+                chunk
+                    .chunks(self.arch.array_length)
+                    .map(|c| c.to_vec())
+                    .collect(),
+            );
+            self.read_request.push(
+                chunk
+                    .chunks(self.arch.array_length)
+                    .map(|c| c.to_vec())
+                    .collect(),
+            );
+        });
         self.prefetch.mergable = true;
         self.prefetch.interval = 0.0;
         self.prefetch.delay = vec![0; self.prefetch.len()];
@@ -96,16 +135,30 @@ impl Kernel for MemCpy {
     fn get_computation(&self) -> usize {
         0
     }
+    fn get_call(&self) -> KernelCall {
+        KernelCall::MemCpy(self.config.clone(), self.enable, self.edits.clone())
+    }
+    fn execute(&self, mem: &mut MemAlloc) {
+        for i in 0..self.config.input_length {
+            let addr = FetchEdit::read_addr(&self.edits, self.config.addr_input + i * SIZE_F);
+            let value = mem.read_elem(addr);
+            let addr = FetchEdit::write_addr(&self.edits, self.config.addr_output + i * SIZE_F);
+            mem.write_elem(addr, value);
+        }
+    }
 }
 
 impl MemCpy {
-    pub fn new(config: MemCpyConfig, enable: bool) -> Self {
+    pub fn new(config: MemCpyConfig, enable: bool, hw: &HwConfig) -> Self {
         let mut k = Self {
             config,
+            enable,
+            arch: hw.arch,
             prefetch: Fetch::new(FetchType::Read),
             drain: Fetch::new(FetchType::Write),
             write_request: Request::new(),
             read_request: Request::new(),
+            edits: Vec::new(),
         };
         if enable {
             k.init();
@@ -115,27 +168,16 @@ impl MemCpy {
 
     /// translate the address from output to input
     pub fn addr_trans(&self, addr: &(u64, u64)) -> Vec<(u64, u64)> {
-        let mut res = Vec::new();
-        let (addr1, addr2) = addr.clone();
-        let addr_start = self.config.addr_output as u64;
-        let addr_end = (self.config.addr_output + self.config.input_length * SIZE_F) as u64;
-
-        let overlap_start = addr1.max(addr_start);
-        let overlap_end = addr2.min(addr_end);
+        self.config.addr_trans(addr)
+    }
 
-        let offset =
-            |addr: u64| addr - self.config.addr_output as u64 + self.config.addr_input as u64;
-        if overlap_start < overlap_end {
-            res.push((offset(overlap_start), offset(overlap_end)));
-            if addr1 < overlap_start {
-                res.push((addr1, overlap_start - 1));
-            }
-            if addr2 > overlap_end {
-                res.push((overlap_end + 1, addr2));
-            }
-        } else {
-            res.push(addr.clone());
-        }
-        res
+    pub fn edit(&mut self, edit: FetchEdit) {
+        edit.apply(
+            &mut self.prefetch,
+            &mut self.read_request,
+            &mut self.write_request,
+            &mut self.drain,
+        );
+        self.edits.push(edit);
     }
 }
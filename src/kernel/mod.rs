@@ -6,5 +6,8 @@ pub mod lru;
 pub mod memory_copy;
 pub mod transpose;
 pub mod tree;
+pub mod vec_builder;
+pub mod vec_cse;
+pub mod vec_schedule;
 pub mod vector_chain;
 pub mod vector_operation;
@@ -1,12 +1,14 @@
 use log::debug;
 use plonky2::util::log2_strict;
+use serde::{Deserialize, Serialize};
 
 use crate::trace::trace::{Fetch, FetchType, Request};
-use crate::config::arch_config::ARCH_CONFIG;
-use crate::config::enable_config::ENABLE_CONFIG;
-use crate::kernel::kernel::Kernel;
+use crate::config::arch_config::ArchConfig;
+use crate::config::HwConfig;
+use crate::kernel::kernel::{FetchEdit, Kernel, KernelCall};
+use crate::memory::memory_allocator::MemAlloc;
 use crate::util::{bit_reverse, SIZE_F};
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct TransposeConfig {
     pub addr_input: usize,
     pub addr_output: usize,
@@ -21,25 +23,26 @@ pub struct TransposeConfig {
 // todo eliminate real fetch
 pub struct Transpose {
     pub config: TransposeConfig,
+    pub arch: ArchConfig,
     pub prefetch: Fetch,
     pub drain: Fetch,
     pub write_request: Request,
     pub read_request: Request,
+    pub edits: Vec<FetchEdit>,
 }
 
 impl Kernel for Transpose {
     fn create_drain(&mut self) {
-        let array_length = unsafe { 1 << (ARCH_CONFIG.array_length >> 1) };
-        let num_blocks_on_chip = unsafe {
-            ARCH_CONFIG.num_elems() / array_length / array_length / self.config.extension
-        };
+        let array_length = 1 << (self.arch.array_length >> 1);
+        let num_blocks_on_chip =
+            self.arch.num_elems() / array_length / array_length / self.config.extension;
         let mut addrs = Vec::new();
         let mut block_cnt = 0;
         let height = self.config.width;
         let width = self.config.height; // transpose
         for width_chunk in (0..width).step_by(array_length) {
             for height_chunk in (self.config.start..self.config.end).step_by(array_length) {
-                for i in height_chunk..(height_chunk + array_length).min(height) {
+                for i in height_chunk..(height_chunk + array_length).min(self.config.end) {
                     let block_length = array_length.min(width - width_chunk);
                     let i_res = if self.config.reverse {
                         bit_reverse(i, log2_strict(height))
@@ -76,16 +79,15 @@ impl Kernel for Transpose {
     fn create_read_request(&mut self) {}
     fn create_write_request(&mut self) {}
     fn create_prefetch(&mut self) {
-        let array_length = unsafe { 1 << (ARCH_CONFIG.array_length >> 1) };
-        let num_blocks_on_chip = unsafe {
-            ARCH_CONFIG.num_elems() / array_length / array_length / self.config.extension
-        };
+        let array_length = 1 << (self.arch.array_length >> 1);
+        let num_blocks_on_chip =
+            self.arch.num_elems() / array_length / array_length / self.config.extension;
         let mut addrs = Vec::new();
         let mut block_cnt = 0;
         for height_chunk in (0..self.config.height).step_by(array_length) {
             for width_chunk in (self.config.start..self.config.end).step_by(array_length) {
                 for i in height_chunk..(height_chunk + array_length).min(self.config.height) {
-                    let block_length = array_length.min(self.config.width - width_chunk);
+                    let block_length = array_length.min(self.config.end - width_chunk);
                     addrs.push(
                         (width_chunk..width_chunk + block_length)
                             .map(|j| {
@@ -136,10 +138,33 @@ impl Kernel for Transpose {
     fn get_computation(&self) -> usize {
         0
     }
+    fn get_call(&self) -> KernelCall {
+        KernelCall::Transpose(self.config, self.edits.clone())
+    }
+    fn execute(&self, mem: &mut MemAlloc) {
+        let ex = self.config.extension;
+        for i in 0..self.config.height {
+            for j in self.config.start..self.config.end {
+                let j_res = if self.config.reverse {
+                    bit_reverse(j, log2_strict(self.config.width))
+                } else {
+                    j
+                };
+                for e in 0..ex {
+                    let addr =
+                        self.config.addr_input + ((i * self.config.width + j) * ex + e) * SIZE_F;
+                    let value = mem.read_elem(FetchEdit::read_addr(&self.edits, addr));
+                    let addr = self.config.addr_output
+                        + ((j_res * self.config.height + i) * ex + e) * SIZE_F;
+                    mem.write_elem(FetchEdit::write_addr(&self.edits, addr), value);
+                }
+            }
+        }
+    }
 }
 
 impl Transpose {
-    pub fn new(config: TransposeConfig) -> Self {
+    pub fn new(config: TransposeConfig, hw: &HwConfig) -> Self {
         let input_left = config.addr_input;
         let input_right =
             config.addr_input + config.width * config.height * SIZE_F * config.extension;
@@ -155,14 +180,26 @@ impl Transpose {
         assert!(config.extension != 0);
         let mut k = Self {
             config,
+            arch: hw.arch,
             prefetch: Fetch::new(FetchType::Read),
             drain: Fetch::new(FetchType::Write),
             write_request: Request::new(),
             read_request: Request::new(),
+            edits: Vec::new(),
         };
-        if unsafe { ENABLE_CONFIG.other } {
+        if hw.enable.other {
             k.init();
         }
         k
     }
+
+    pub fn edit(&mut self, edit: FetchEdit) {
+        edit.apply(
+            &mut self.prefetch,
+            &mut self.read_request,
+            &mut self.write_request,
+            &mut self.drain,
+        );
+        self.edits.push(edit);
+    }
 }
@@ -2,14 +2,20 @@ use std::cmp::min;
 use std::vec;
 
 use log::debug;
+use plonky2::field::goldilocks_field::GoldilocksField as F;
+use plonky2::hash::hash_types::HashOut;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::plonk::config::Hasher;
+use serde::{Deserialize, Serialize};
 
 use crate::trace::trace::{Fetch, FetchType, Request};
-use crate::config::arch_config::ARCH_CONFIG;
-use crate::config::enable_config::ENABLE_CONFIG;
-use crate::kernel::kernel::Kernel;
+use crate::config::arch_config::ArchConfig;
+use crate::config::HwConfig;
+use crate::kernel::kernel::{FetchEdit, Kernel, KernelCall};
+use crate::memory::memory_allocator::MemAlloc;
 use crate::util::{ceil_div_usize, log2, HASH_COMPUTATION, SINGLE_HASH_DELAY, SIZE_F};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TreeConfig {
     pub leaf_length: usize, // length of the leaf
     pub cap_height: usize,
@@ -21,22 +27,24 @@ pub struct TreeConfig {
     pub transposed_leaves: bool,
 }
 
-pub fn get_buf_sz_elems() -> usize {
-    unsafe { ARCH_CONFIG.active_buf_size() * 1024 / SIZE_F }
+pub fn get_buf_sz_elems(arch: &ArchConfig) -> usize {
+    arch.active_buf_size() * 1024 / SIZE_F
 }
 
 pub struct Tree {
     pub config: TreeConfig,
+    pub arch: ArchConfig,
     pub prefetch: Fetch,
     pub read_request: Request,
     pub write_request: Request,
     pub drain: Fetch,
+    pub edits: Vec<FetchEdit>,
 }
 
 impl Kernel for Tree {
     fn create_prefetch(&mut self) {
         let log2_num_leaves = log2(self.config.num_leaves);
-        let num_elems = get_buf_sz_elems();
+        let num_elems = get_buf_sz_elems(&self.arch);
         let residual_level = log2_num_leaves + 1 - self.config.cap_height;
         let mut level = 0;
 
@@ -104,7 +112,7 @@ impl Kernel for Tree {
 
     fn create_read_request(&mut self) {
         let log2_num_leaves = log2(self.config.num_leaves);
-        let num_elems = get_buf_sz_elems();
+        let num_elems = get_buf_sz_elems(&self.arch);
         let residual_level = log2_num_leaves + 1 - self.config.cap_height;
 
         let mut level = 0;
@@ -181,7 +189,7 @@ impl Kernel for Tree {
 
     fn create_write_request(&mut self) {
         let log2_num_leaves = log2(self.config.num_leaves);
-        let num_elems = get_buf_sz_elems();
+        let num_elems = get_buf_sz_elems(&self.arch);
         let residual_level = log2_num_leaves + 1 - self.config.cap_height;
 
         let mut level = 0;
@@ -245,7 +253,7 @@ impl Kernel for Tree {
     fn create_drain(&mut self) {
         let num_digests = 2 * (self.config.num_leaves - (1 << self.config.cap_height));
         let log2_num_leaves = log2(self.config.num_leaves);
-        let num_elems = get_buf_sz_elems();
+        let num_elems = get_buf_sz_elems(&self.arch);
         let residual_level = log2_num_leaves + 1 - self.config.cap_height;
 
         let mut level = 0;
@@ -301,7 +309,8 @@ impl Kernel for Tree {
                 }
                 for level_idx in 0..num_levels {
                     debug_assert!(level_idx <= residual_level);
-                    if level_idx < residual_level - 1 {
+                    // the cap level goes to the cap buffer below
+                    if level + level_idx < residual_level - 1 {
                         let leaf_idx_left = leaf_idx >> level_idx;
                         let leaf_idx_right = (leaf_idx + num_leaves_reading) >> level_idx;
                         for i in leaf_idx_left..leaf_idx_right {
@@ -366,28 +375,104 @@ impl Kernel for Tree {
         }
         res * HASH_COMPUTATION
     }
+    fn get_call(&self) -> KernelCall {
+        KernelCall::Tree(self.config.clone(), self.edits.clone())
+    }
+    fn execute(&self, mem: &mut MemAlloc) {
+        let residual_level = log2(self.config.num_leaves) + 1 - self.config.cap_height;
+        let read = |mem: &MemAlloc, addrs: Vec<usize>| {
+            addrs
+                .into_iter()
+                .map(|a| mem.read_elem(FetchEdit::read_addr(&self.edits, a)))
+                .collect::<Vec<_>>()
+        };
+        let write = |mem: &mut MemAlloc, addrs: Vec<usize>, values: &[F]| {
+            for (a, v) in addrs.into_iter().zip(values) {
+                mem.write_elem(FetchEdit::write_addr(&self.edits, a), *v);
+            }
+        };
+
+        let mut digests = Vec::with_capacity(self.config.num_leaves);
+        for i in 0..self.config.num_leaves {
+            let idx = (0..self.config.leaf_length)
+                .map(|x| i * self.config.leaf_length + x)
+                .collect::<Vec<_>>();
+            let leaf = read(mem, self.idx_to_addr_leaves(&idx));
+            if self.config.transposed_leaves {
+                write(mem, self.idx_to_addr_transposed_leaves(&idx), &leaf);
+            }
+            digests.push(PoseidonHash::hash_or_noop(&leaf));
+        }
+        for level in 0..residual_level {
+            if level > 0 {
+                // the children are read back from the digest buffer
+                digests = (0..digests.len() / 2)
+                    .map(|i| {
+                        let child = |i: usize| {
+                            let idx = (0..Tree::DIGEST_LENGTH)
+                                .map(|x| {
+                                    self.tree_idx_transform(level - 1, i) * Tree::DIGEST_LENGTH + x
+                                })
+                                .collect();
+                            HashOut::from_vec(read(mem, self.idx_to_addr_digest(&idx)))
+                        };
+                        PoseidonHash::two_to_one(child(i << 1), child((i << 1) + 1))
+                    })
+                    .collect();
+            }
+            for (i, digest) in digests.iter().enumerate() {
+                let idx = (0..Tree::DIGEST_LENGTH)
+                    .map(|x| {
+                        if level == residual_level - 1 {
+                            i * Tree::DIGEST_LENGTH + x
+                        } else {
+                            self.tree_idx_transform(level, i) * Tree::DIGEST_LENGTH + x
+                        }
+                    })
+                    .collect();
+                let addrs = if level == residual_level - 1 {
+                    self.idx_to_addr_cap(&idx)
+                } else {
+                    self.idx_to_addr_digest(&idx)
+                };
+                write(mem, addrs, &digest.elements);
+            }
+        }
+    }
 }
 
 impl Tree {
     const SPONGE_RATE: usize = 8;
     pub const DIGEST_LENGTH: usize = 4;
 
-    pub fn new(config: TreeConfig) -> Tree {
+    pub fn new(config: TreeConfig, hw: &HwConfig) -> Tree {
         let mut k = Tree {
             config,
+            arch: hw.arch,
             prefetch: Fetch::new(FetchType::Read),
             read_request: Request::new(),
             write_request: Request::new(),
             drain: Fetch::new(FetchType::Write),
+            edits: Vec::new(),
         };
 
         k.log();
-        if unsafe { ENABLE_CONFIG.hash } {
+        if hw.enable.hash {
             k.init();
         }
         k
     }
 
+    pub fn edit(&mut self, edit: FetchEdit) {
+        edit.apply(
+            &mut self.prefetch,
+            &mut self.read_request,
+            &mut self.write_request,
+            &mut self.drain,
+        );
+        self.edits.push(edit);
+    }
+
     fn tree_idx_transform(&self, level: usize, idx: usize) -> usize {
         let height = log2(self.config.num_leaves) + 1;
         let subtree_height = height - self.config.cap_height;
@@ -474,9 +559,14 @@ impl Tree {
         1 << cap_height
     }
 
-    pub fn get_padding_length(num_leaves: usize, leaf_length: usize, cap_height: usize) -> usize {
+    pub fn get_padding_length(
+        num_leaves: usize,
+        leaf_length: usize,
+        cap_height: usize,
+        arch: &ArchConfig,
+    ) -> usize {
         let log2_num_leaves = log2(num_leaves);
-        let num_elems = get_buf_sz_elems();
+        let num_elems = get_buf_sz_elems(arch);
 
         let leaf_length = leaf_length;
         let log2_num_leaves_reading = log2(num_elems / leaf_length);
@@ -503,10 +593,12 @@ mod tests {
         };
         let tree = Tree {
             config,
+            arch: ArchConfig::default(),
             prefetch: Fetch::new(FetchType::Read),
             read_request: Request::new(),
             write_request: Request::new(),
             drain: Fetch::new(FetchType::Write),
+            edits: Vec::new(),
         };
 
         assert_eq!(tree.tree_idx_transform(0, 0), 0);
@@ -535,4 +627,43 @@ mod tests {
         assert_eq!(tree.tree_idx_transform(1, 6), 22);
         assert_eq!(tree.tree_idx_transform(1, 7), 23);
     }
+
+    #[test]
+    fn test_tree_execute() {
+        use plonky2::field::types::Sample;
+        use plonky2::hash::merkle_tree::MerkleTree;
+
+        let (num_leaves, leaf_length, cap_height) = (64, 7, 2);
+        let leaves = (0..num_leaves)
+            .map(|_| F::rand_vec(leaf_length))
+            .collect::<Vec<_>>();
+        let mut mem = MemAlloc::new(1, 4096);
+        let mut alloc = |id: &str, num_elems: usize| mem.alloc(id, num_elems * SIZE_F).unwrap();
+        let config = TreeConfig {
+            leaf_length,
+            cap_height,
+            num_leaves,
+            addr_leaves: alloc("leaves", num_leaves * leaf_length),
+            addr_transposed_leaves: alloc("transposed_leaves", num_leaves * leaf_length),
+            addr_digest_buf: alloc(
+                "digests",
+                Tree::num_digests(num_leaves, cap_height) * Tree::DIGEST_LENGTH,
+            ),
+            addr_cap_buf: alloc("cap", Tree::num_caps(cap_height) * Tree::DIGEST_LENGTH),
+            transposed_leaves: true,
+        };
+        // the leaves are the columns
+        for (i, leaf) in leaves.iter().enumerate() {
+            for (j, v) in leaf.iter().enumerate() {
+                mem.write_elem(config.addr_leaves + (j * num_leaves + i) * SIZE_F, *v);
+            }
+        }
+        Tree::new(config.clone(), &HwConfig::default()).execute(&mut mem);
+
+        let tree = MerkleTree::<F, PoseidonHash>::new(leaves.clone(), cap_height);
+        let cap = mem.read_elems(config.addr_cap_buf, Tree::num_caps(cap_height) * 4);
+        assert_eq!(cap, tree.cap.flatten());
+        let transposed = mem.read_elems(config.addr_transposed_leaves, num_leaves * leaf_length);
+        assert_eq!(transposed, leaves.concat());
+    }
 }
@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+
+use plonky2::field::goldilocks_field::GoldilocksField as F;
+
+use crate::kernel::vector_operation::{VecOpConfig, VecOpSrc, VecOpType};
+use crate::memory::memory_allocator::MemAlloc;
+use crate::util::SIZE_F;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Vector,
+    Scalar,   // one element for the whole vector, input 1 of a VS op
+    Constant, // a constant of the circuit, at address 0 and never loaded
+}
+
+// an operand of VecBuilder, checked against MemAlloc each time it is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VecHandle {
+    addr: usize,
+    kind: Operand,
+    block: Option<usize>, // key in VecBuilder::blocks if the builder allocated it
+}
+
+impl VecHandle {
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    pub fn kind(&self) -> Operand {
+        self.kind
+    }
+}
+
+// Builds a vector op program over named handles instead of raw addresses. The results of
+// add, sub and mul go to temporaries. A temporary freed early is only reused by the later
+// temporaries of the builder, and finish hands all the blocks the builder allocated to
+// MemAlloc::defer_free, so they stay allocated until the ops have run. A builder dropped
+// without finish frees them right away.
+pub struct VecBuilder<'a> {
+    mem: &'a mut MemAlloc,
+    vector_length: usize,
+    vec_ops: Vec<VecOpConfig>,
+    blocks: BTreeMap<usize, String>,
+    spare: Vec<(String, usize)>, // freed temporaries and their addresses
+    num_blocks: usize,
+    rich_isa: bool,
+}
+
+impl<'a> VecBuilder<'a> {
+    pub fn new(mem: &'a mut MemAlloc, vector_length: usize) -> Self {
+        Self {
+            mem,
+            vector_length,
+            vec_ops: Vec::new(),
+            blocks: BTreeMap::new(),
+            spare: Vec::new(),
+            num_blocks: 0,
+            rich_isa: false,
+        }
+    }
+
+    // emit the ops of ArchConfig::rich_vec_isa where they apply
+    pub fn with_rich_isa(mut self, rich_isa: bool) -> Self {
+        self.rich_isa = rich_isa;
+        self
+    }
+
+    pub fn vector(&self, addr: usize) -> VecHandle {
+        self.checked(VecHandle {
+            addr,
+            kind: Operand::Vector,
+            block: None,
+        })
+    }
+
+    // the idx-th of consecutive vectors, e.g. a column of the local values
+    pub fn column(&self, base: usize, idx: usize) -> VecHandle {
+        self.vector(base + idx * self.vector_length * SIZE_F)
+    }
+
+    pub fn scalar(&self, addr: usize) -> VecHandle {
+        self.checked(VecHandle {
+            addr,
+            kind: Operand::Scalar,
+            block: None,
+        })
+    }
+
+    // the idx-th of consecutive elements, e.g. a public input
+    pub fn scalar_at(&self, base: usize, idx: usize) -> VecHandle {
+        self.scalar(base + idx * SIZE_F)
+    }
+
+    pub fn constant(&self) -> VecHandle {
+        VecHandle {
+            addr: 0,
+            kind: Operand::Constant,
+            block: None,
+        }
+    }
+
+    pub fn alloc(&mut self, name: &str) -> VecHandle {
+        let addr = self.mem.alloc(name, self.vector_length * SIZE_F).unwrap();
+        self.insert(name.to_string(), addr)
+    }
+
+    // a block of scalars holding values, e.g. the constants of a lowered program
+    pub fn alloc_scalars(&mut self, values: &[F]) -> Vec<VecHandle> {
+        let name = self.mem.temp_name("vec_builder_scalars");
+        let addr = self.mem.alloc(&name, values.len() * SIZE_F).unwrap();
+        self.mem.write_elems(addr, values);
+        let block = self.insert(name, addr).block;
+        (0..values.len())
+            .map(|i| VecHandle {
+                addr: addr + i * SIZE_F,
+                kind: Operand::Scalar,
+                block,
+            })
+            .collect()
+    }
+
+    pub fn temp(&mut self) -> VecHandle {
+        match self.spare.pop() {
+            Some((name, addr)) => self.insert(name, addr),
+            None => {
+                let name = self.mem.temp_name("vec_builder");
+                self.alloc(&name)
+            }
+        }
+    }
+
+    // frees a vector of the builder early, so that later temporaries can reuse it
+    pub fn free(&mut self, vec: VecHandle) {
+        self.check(vec);
+        let name = vec
+            .block
+            .filter(|_| vec.kind == Operand::Vector)
+            .and_then(|block| self.blocks.remove(&block))
+            .expect("only the vectors allocated by the builder can be freed");
+        self.spare.push((name, vec.addr));
+    }
+
+    pub fn add(&mut self, a: VecHandle, b: VecHandle) -> VecHandle {
+        let out = self.temp();
+        self.add_into(out, a, b);
+        out
+    }
+
+    pub fn sub(&mut self, a: VecHandle, b: VecHandle) -> VecHandle {
+        let out = self.temp();
+        self.sub_into(out, a, b);
+        out
+    }
+
+    pub fn mul(&mut self, a: VecHandle, b: VecHandle) -> VecHandle {
+        let out = self.temp();
+        self.mul_into(out, a, b);
+        out
+    }
+
+    pub fn neg(&mut self, a: VecHandle) -> VecHandle {
+        let out = self.temp();
+        self.neg_into(out, a);
+        out
+    }
+
+    // -a, 0 - a without the rich ISA
+    pub fn neg_into(&mut self, out: VecHandle, a: VecHandle) {
+        if self.rich_isa {
+            self.push(VecOpType::NEG, out, a, self.constant());
+        } else {
+            self.push(VecOpType::SUB, out, self.constant(), a);
+        }
+    }
+
+    pub fn add_into(&mut self, out: VecHandle, a: VecHandle, b: VecHandle) {
+        self.push(VecOpType::ADD, out, a, b);
+    }
+
+    pub fn sub_into(&mut self, out: VecHandle, a: VecHandle, b: VecHandle) {
+        self.push(VecOpType::SUB, out, a, b);
+    }
+
+    pub fn mul_into(&mut self, out: VecHandle, a: VecHandle, b: VecHandle) {
+        self.push(VecOpType::MUL, out, a, b);
+    }
+
+    // ops generated elsewhere, e.g. by ConstraintConsumer
+    pub fn extend(&mut self, vec_ops: impl IntoIterator<Item = VecOpConfig>) {
+        self.vec_ops.extend(vec_ops);
+    }
+
+    // the last op writing out produces a final output
+    pub fn set_final(&mut self, out: VecHandle) {
+        let op = self
+            .vec_ops
+            .iter_mut()
+            .rev()
+            .find(|op| op.addr_output == out.addr)
+            .expect("no op writes the vector");
+        op.is_final_output = true;
+    }
+
+    // the caller runs the ops, then frees the temporaries with MemAlloc::free_deferred
+    pub fn finish(mut self) -> Vec<VecOpConfig> {
+        for name in self.take_blocks() {
+            self.mem.defer_free(&name);
+        }
+        std::mem::take(&mut self.vec_ops)
+    }
+
+    fn insert(&mut self, name: String, addr: usize) -> VecHandle {
+        self.blocks.insert(self.num_blocks, name);
+        self.num_blocks += 1;
+        VecHandle {
+            addr,
+            kind: Operand::Vector,
+            block: Some(self.num_blocks - 1),
+        }
+    }
+
+    fn take_blocks(&mut self) -> Vec<String> {
+        let spare = self.spare.drain(..).map(|(name, _)| name);
+        std::mem::take(&mut self.blocks)
+            .into_values()
+            .chain(spare)
+            .collect()
+    }
+
+    fn check(&self, vec: VecHandle) {
+        if let Some(block) = vec.block {
+            assert!(
+                self.blocks.contains_key(&block),
+                "vector {:#x} was already freed",
+                vec.addr
+            );
+        }
+        let size = match vec.kind {
+            Operand::Vector => self.vector_length * SIZE_F,
+            Operand::Scalar => SIZE_F,
+            Operand::Constant => return,
+        };
+        assert!(
+            self.mem.is_allocated(vec.addr, size),
+            "{:?} {:#x} is not in an allocated block",
+            vec.kind,
+            vec.addr
+        );
+    }
+
+    fn checked(&self, vec: VecHandle) -> VecHandle {
+        self.check(vec);
+        vec
+    }
+
+    fn push(&mut self, op_type: VecOpType, out: VecHandle, a: VecHandle, b: VecHandle) {
+        assert_eq!(out.kind, Operand::Vector, "the output must be a vector");
+        for vec in [out, a, b] {
+            self.check(vec);
+        }
+        // the scalar of a VS op is input 1
+        let (a, b) = match (a.kind, b.kind, op_type) {
+            (Operand::Scalar, Operand::Scalar, _) => {
+                // s + t = (0 + s) + t
+                self.push(VecOpType::ADD, out, self.constant(), a);
+                self.push(op_type, out, out, b);
+                return;
+            }
+            (Operand::Scalar, _, VecOpType::SUB) => {
+                // s - v = -v + s
+                self.neg_into(out, b);
+                self.push(VecOpType::ADD, out, out, a);
+                return;
+            }
+            (Operand::Scalar, _, _) => (b, a),
+            _ => (a, b),
+        };
+        self.vec_ops.push(VecOpConfig {
+            vector_length: self.vector_length,
+            addr_input_0: a.addr,
+            addr_input_1: b.addr,
+            addr_output: out.addr,
+            is_final_output: false,
+            op_type,
+            op_src: if b.kind == Operand::Scalar {
+                VecOpSrc::VS
+            } else {
+                VecOpSrc::VV
+            },
+        });
+    }
+}
+
+impl Drop for VecBuilder<'_> {
+    fn drop(&mut self) {
+        for name in self.take_blocks() {
+            self.mem.free(&name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::BATCH_SIZE;
+    use plonky2::field::types::Field;
+
+    #[test]
+    fn test_vec_builder() {
+        let mut mem = MemAlloc::new(1, 4096);
+        let lv = mem.alloc("local_values", 2 * BATCH_SIZE * SIZE_F).unwrap();
+        let pi = mem.alloc("public_inputs", 2 * SIZE_F).unwrap();
+        let constraint = mem.alloc("constraint", BATCH_SIZE * SIZE_F).unwrap();
+        let op = |a, b, out, op_type, op_src| VecOpConfig {
+            vector_length: BATCH_SIZE,
+            addr_input_0: a,
+            addr_input_1: b,
+            addr_output: out,
+            is_final_output: false,
+            op_type,
+            op_src,
+        };
+
+        let mut b = VecBuilder::new(&mut mem, BATCH_SIZE);
+        let c = b.vector(constraint);
+        let x1 = b.column(lv, 1);
+        // lv[0] * lv[1] - 1
+        let t = b.mul(b.column(lv, 0), x1);
+        b.sub_into(c, t, b.constant());
+        // pi[1] * lv[1], pi[0] - lv[1]
+        b.mul_into(c, b.scalar_at(pi, 1), x1);
+        b.sub_into(c, b.scalar_at(pi, 0), x1);
+        b.set_final(c);
+        let t_addr = t.addr();
+        let vec_ops = b.finish();
+
+        assert_eq!(
+            vec_ops,
+            vec![
+                op(lv, x1.addr(), t_addr, VecOpType::MUL, VecOpSrc::VV),
+                op(t_addr, 0, constraint, VecOpType::SUB, VecOpSrc::VV),
+                op(
+                    x1.addr(),
+                    pi + SIZE_F,
+                    constraint,
+                    VecOpType::MUL,
+                    VecOpSrc::VS
+                ),
+                op(0, x1.addr(), constraint, VecOpType::SUB, VecOpSrc::VV),
+                VecOpConfig {
+                    is_final_output: true,
+                    ..op(constraint, pi, constraint, VecOpType::ADD, VecOpSrc::VS)
+                },
+            ]
+        );
+        // the temporary is freed once the ops have run
+        assert!(mem.is_allocated(t_addr, BATCH_SIZE * SIZE_F));
+        mem.free_deferred(0);
+        assert!(!mem.is_allocated(t_addr, BATCH_SIZE * SIZE_F));
+        assert_eq!(mem.get_addr("vec_builder_0"), None);
+
+        // -lv[0] is a NEG only with the rich ISA
+        for (rich_isa, op_type) in [(false, VecOpType::SUB), (true, VecOpType::NEG)] {
+            let mut b = VecBuilder::new(&mut mem, BATCH_SIZE).with_rich_isa(rich_isa);
+            b.neg_into(b.vector(constraint), b.column(lv, 0));
+            assert_eq!(b.finish()[0].op_type, op_type);
+        }
+
+        // constants get their own slots, two scalars are broadcast first
+        let mut b = VecBuilder::new(&mut mem, BATCH_SIZE);
+        let [two, three] = b.alloc_scalars(&[F::TWO, F::from_canonical_u64(3)])[..] else {
+            unreachable!()
+        };
+        b.sub_into(b.vector(constraint), two, three);
+        let vec_ops = b.finish();
+        assert_eq!(
+            mem.read_elems(two.addr(), 2),
+            [F::TWO, F::from_canonical_u64(3)]
+        );
+        assert_eq!(
+            vec_ops,
+            vec![
+                op(0, two.addr(), constraint, VecOpType::ADD, VecOpSrc::VS),
+                op(
+                    constraint,
+                    three.addr(),
+                    constraint,
+                    VecOpType::SUB,
+                    VecOpSrc::VS
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "already freed")]
+    fn test_vec_builder_freed() {
+        let mut mem = MemAlloc::new(1, 4096);
+        let mut b = VecBuilder::new(&mut mem, BATCH_SIZE);
+        let t = b.temp();
+        b.free(t);
+        b.add(t, t);
+    }
+}
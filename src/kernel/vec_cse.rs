@@ -0,0 +1,405 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::AddAssign;
+
+use serde::{Deserialize, Serialize};
+
+use crate::kernel::vec_schedule::live_ops;
+use crate::kernel::vector_operation::{VecOpConfig, VecOpSrc, VecOpType};
+use crate::memory::memory_allocator::MemAlloc;
+use crate::util::SIZE_F;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpStatus {
+    Kept,
+    Duplicate, // computes a value that is still in memory, its readers read that
+    Dead,      // its output is a temporary nobody reads
+}
+
+/// Vector ops removed from a stream, per gate type or whatever the caller groups by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CseStats {
+    pub ops_before: usize,
+    pub duplicates: usize,
+    pub dead: usize,
+}
+
+impl CseStats {
+    pub fn count(status: &[OpStatus]) -> Self {
+        Self {
+            ops_before: status.len(),
+            duplicates: status.iter().filter(|&&s| s == OpStatus::Duplicate).count(),
+            dead: status.iter().filter(|&&s| s == OpStatus::Dead).count(),
+        }
+    }
+
+    pub fn ops_saved(&self) -> usize {
+        self.duplicates + self.dead
+    }
+}
+
+impl AddAssign for CseStats {
+    fn add_assign(&mut self, other: Self) {
+        self.ops_before += other.ops_before;
+        self.duplicates += other.duplicates;
+        self.dead += other.dead;
+    }
+}
+
+pub struct Eliminated {
+    pub ops: Vec<VecOpConfig>, // the kept ops, with the inputs of duplicates redirected
+    pub status: Vec<OpStatus>, // of each given op
+}
+
+// what a range of memory holds, interned into a value number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Input(usize, usize), // (addr, len) before the stream wrote to it
+    // op, length, value numbers of the inputs and of the output the op reads
+    Op(
+        VecOpType,
+        VecOpSrc,
+        usize,
+        usize,
+        Option<usize>,
+        Option<usize>,
+    ),
+    Part(usize, usize, usize), // (value, offset, len) of a longer value
+    Unknown(usize),            // partially overwritten, equal to nothing else
+}
+
+// a range of memory the stream wrote, holding value. If the op that wrote it was
+// dropped, the value is actually at `at` and dropped is that op.
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    end: usize,
+    value: usize,
+    at: usize,
+    dropped: Option<usize>,
+}
+
+#[derive(Default)]
+struct State {
+    values: HashMap<Key, usize>,
+    intervals: BTreeMap<usize, Interval>, // disjoint, by start address
+    homes: HashMap<usize, (usize, usize)>, // value -> last range an op wrote it to
+}
+
+// the dropped op whose output can't be redirected any more
+struct Conflict(usize);
+
+impl State {
+    fn intern(&mut self, key: Key) -> usize {
+        let n = self.values.len();
+        *self.values.entry(key).or_insert(n)
+    }
+
+    fn unknown(&mut self) -> usize {
+        let n = self.values.len();
+        self.intern(Key::Unknown(n))
+    }
+
+    fn overlapping(&self, start: usize, end: usize) -> Vec<(usize, Interval)> {
+        let mut res = self
+            .intervals
+            .range(..end)
+            .rev()
+            .take_while(|(_, iv)| iv.end > start)
+            .map(|(&s, &iv)| (s, iv))
+            .collect::<Vec<_>>();
+        res.reverse();
+        res
+    }
+
+    // the value of (addr, len) and where it actually is
+    fn read(&mut self, addr: usize, len: usize) -> Result<(usize, usize), Conflict> {
+        let end = addr + len * SIZE_F;
+        match self.overlapping(addr, end).as_slice() {
+            [] => Ok((self.intern(Key::Input(addr, len)), addr)),
+            [(start, iv)] if *start <= addr && iv.end >= end => {
+                let offset = addr - start;
+                let value = if offset == 0 && iv.end == end {
+                    iv.value
+                } else {
+                    self.intern(Key::Part(iv.value, offset, len))
+                };
+                if let Some(op) = iv.dropped {
+                    // the value has to be still where the dropped op found it
+                    let (at_value, _) = self.read(iv.at + offset, len)?;
+                    if at_value != value {
+                        return Err(Conflict(op));
+                    }
+                }
+                Ok((value, iv.at + offset))
+            }
+            ivs => match ivs.iter().find_map(|(_, iv)| iv.dropped) {
+                Some(op) => Err(Conflict(op)),
+                None => Ok((self.unknown(), addr)),
+            },
+        }
+    }
+
+    // the value of (addr, len) where it is, e.g. the output an FMA adds to
+    fn read_in_place(&mut self, addr: usize, len: usize) -> Result<usize, Conflict> {
+        let end = addr + len * SIZE_F;
+        if let Some(op) = self
+            .overlapping(addr, end)
+            .iter()
+            .find_map(|(_, iv)| iv.dropped)
+        {
+            return Err(Conflict(op));
+        }
+        Ok(self.read(addr, len)?.0)
+    }
+
+    fn write(
+        &mut self,
+        addr: usize,
+        len: usize,
+        value: usize,
+        at: usize,
+        dropped: Option<usize>,
+    ) -> Result<(), Conflict> {
+        let end = addr + len * SIZE_F;
+        let old = self.overlapping(addr, end);
+        for (start, iv) in old.iter() {
+            let partial = *start < addr || iv.end > end;
+            if let (true, Some(op)) = (partial, iv.dropped) {
+                return Err(Conflict(op));
+            }
+        }
+        for (start, iv) in old {
+            self.intervals.remove(&start);
+            // what is left of the old value isn't equal to anything
+            if start < addr {
+                let value = self.unknown();
+                self.intervals.insert(
+                    start,
+                    Interval {
+                        end: addr,
+                        value,
+                        at: start,
+                        dropped: None,
+                    },
+                );
+            }
+            if iv.end > end {
+                let value = self.unknown();
+                self.intervals.insert(
+                    end,
+                    Interval {
+                        end: iv.end,
+                        value,
+                        at: end,
+                        dropped: None,
+                    },
+                );
+            }
+        }
+        self.intervals.insert(
+            addr,
+            Interval {
+                end,
+                value,
+                at,
+                dropped,
+            },
+        );
+        if dropped.is_none() {
+            self.homes.insert(value, (addr, len));
+        }
+        Ok(())
+    }
+}
+
+fn value_key(op: &VecOpConfig, a: usize, b: Option<usize>, c: Option<usize>) -> Key {
+    let commutative = op.op_src == VecOpSrc::VV
+        && matches!(op.op_type, VecOpType::ADD | VecOpType::MUL | VecOpType::FMA);
+    let (a, b) = match b {
+        Some(b) if commutative && b < a => (b, Some(a)),
+        _ => (a, b),
+    };
+    Key::Op(op.op_type, op.op_src, op.vector_length, a, b, c)
+}
+
+// one pass of value numbering, the ops in keep are never dropped
+fn number(
+    vec_ops: &[VecOpConfig],
+    observable: &impl Fn(&VecOpConfig) -> bool,
+    keep: &HashSet<usize>,
+) -> Result<(Vec<VecOpConfig>, Vec<bool>), Conflict> {
+    let mut state = State::default();
+    let mut ops = Vec::new();
+    let mut duplicate = vec![false; vec_ops.len()];
+    for (i, op) in vec_ops.iter().enumerate() {
+        let (a, at_0) = state.read(op.addr_input_0, op.vector_length)?;
+        let len_1 = match op.op_src {
+            VecOpSrc::VV => op.vector_length,
+            VecOpSrc::VS => 1,
+        };
+        let (b, at_1) = match op.reads_input_1() {
+            true => state
+                .read(op.addr_input_1, len_1)
+                .map(|(b, at)| (Some(b), at))?,
+            false => (None, op.addr_input_1),
+        };
+        let c = match op.reads_output() && op.addr_output != 0 {
+            true => Some(state.read_in_place(op.addr_output, op.vector_length)?),
+            false => None,
+        };
+        let value = state.intern(value_key(op, a, b, c));
+        if op.addr_output == 0 {
+            ops.push(VecOpConfig {
+                addr_input_0: at_0,
+                addr_input_1: at_1,
+                ..*op
+            });
+            continue;
+        }
+
+        // the value is already somewhere, and it is not lost until read
+        let home = state
+            .homes
+            .get(&value)
+            .copied()
+            .filter(|&(addr, len)| state.read(addr, len).ok() == Some((value, addr)));
+        match home {
+            Some((addr, _)) if addr == op.addr_output => duplicate[i] = true,
+            Some((addr, _)) if !observable(op) && !keep.contains(&i) => {
+                state.write(op.addr_output, op.vector_length, value, addr, Some(i))?;
+                duplicate[i] = true;
+            }
+            _ => {
+                state.write(
+                    op.addr_output,
+                    op.vector_length,
+                    value,
+                    op.addr_output,
+                    None,
+                )?;
+                ops.push(VecOpConfig {
+                    addr_input_0: at_0,
+                    addr_input_1: at_1,
+                    ..*op
+                });
+            }
+        }
+    }
+    Ok((ops, duplicate))
+}
+
+/// Drop the ops that compute a value that is still in memory, reading it from there
+/// instead, then the ops whose outputs are temporaries nobody reads. An output is a
+/// temporary unless it is final or preloaded. Values are numbered by op, op source
+/// and the value numbers of the inputs, so a buffer that is reused for another value
+/// is not mistaken for the old one.
+pub fn eliminate(vec_ops: &[VecOpConfig], mem: &MemAlloc) -> Eliminated {
+    let observable = |op: &VecOpConfig| {
+        op.is_final_output || op.addr_output == 0 || mem.preloaded(op.addr_output)
+    };
+    let mut keep = HashSet::new();
+    // a dropped op whose value is overwritten before all its readers ran is kept
+    let (ops, duplicate) = loop {
+        match number(vec_ops, &observable, &keep) {
+            Ok(res) => break res,
+            Err(Conflict(op)) => assert!(keep.insert(op)),
+        }
+    };
+    let live = live_ops(&ops, observable);
+
+    let mut status = vec![OpStatus::Kept; vec_ops.len()];
+    let mut kept = (0..vec_ops.len()).filter(|&i| !duplicate[i]);
+    let mut live_iter = live.iter().peekable();
+    for (j, i) in kept.by_ref().enumerate() {
+        if live_iter.peek() == Some(&&j) {
+            live_iter.next();
+        } else {
+            status[i] = OpStatus::Dead;
+        }
+    }
+    for i in 0..vec_ops.len() {
+        if duplicate[i] {
+            status[i] = OpStatus::Duplicate;
+        }
+    }
+    Eliminated {
+        ops: live.iter().map(|&j| ops[j]).collect(),
+        status,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HwConfig;
+    use crate::kernel::kernel::Kernel;
+    use crate::kernel::vector_chain::VectorChain;
+    use plonky2::field::goldilocks_field::GoldilocksField as F;
+    use plonky2::field::types::{Field, Sample};
+
+    #[test]
+    fn test_eliminate() {
+        let n = 64;
+        let mut mem = MemAlloc::new(1, 4096);
+        let x = mem.alloc("x", 2 * n * SIZE_F).unwrap();
+        let y = x + n * SIZE_F;
+        let c = mem.alloc("c", SIZE_F).unwrap();
+        let t = mem.alloc("t", 3 * n * SIZE_F).unwrap();
+        let (t0, t1, t2) = (t, t + n * SIZE_F, t + 2 * n * SIZE_F);
+        let out = mem.alloc("out", 3 * n * SIZE_F).unwrap();
+        let (o0, o1, o2) = (out, out + n * SIZE_F, out + 2 * n * SIZE_F);
+        let acc = mem.alloc("acc", n * SIZE_F).unwrap();
+        mem.preload(acc, n);
+        let op = |a, b, c, op_type, op_src, is_final_output| VecOpConfig {
+            vector_length: n,
+            addr_input_0: a,
+            addr_input_1: b,
+            addr_output: c,
+            is_final_output,
+            op_type,
+            op_src,
+        };
+        use VecOpSrc::*;
+        use VecOpType::*;
+        let vec_ops = vec![
+            op(x, c, t0, SUB, VS, false),    // 0: x - c
+            op(t0, y, o0, MUL, VV, true),    // 1
+            op(x, c, t1, SUB, VS, false),    // 2: x - c again, read from t0
+            op(y, t1, o1, MUL, VV, true),    // 3: same as 1 but final, kept
+            op(x, y, t2, ADD, VV, false),    // 4
+            op(x, c, t0, SUB, VS, false),    // 5: already in t0
+            op(t0, t0, t0, MUL, VV, false),  // 6: t0 is another value now
+            op(x, c, t1, SUB, VS, false),    // 7: not in t0 any more
+            op(t1, t0, acc, ADD, VV, false), // 8: preloaded, kept
+            op(y, x, t2, ADD, VV, false),    // 9: same as 4, which is read now
+            op(t2, t2, o2, MUL, VV, true),   // 10
+            op(x, x, t1, MUL, VV, false),    // 11: nobody reads t1
+        ];
+        let res = eliminate(&vec_ops, &mem);
+        use OpStatus::*;
+        assert_eq!(
+            res.status,
+            vec![
+                Kept, Kept, Duplicate, Kept, Kept, Duplicate, Kept, Kept, Kept, Duplicate, Kept,
+                Dead
+            ]
+        );
+        assert_eq!(res.ops[2].addr_input_1, t0);
+        let stats = CseStats::count(&res.status);
+        assert_eq!((stats.duplicates, stats.dead, stats.ops_saved()), (3, 1, 4));
+
+        // the outputs that are not temporaries are the same
+        let mut hw = HwConfig::default();
+        hw.enable.other = false;
+        let (inputs, constant) = (F::rand_vec(2 * n), F::rand());
+        let mut outputs = Vec::new();
+        for ops in [vec_ops, res.ops] {
+            mem.write_elems(x, &inputs);
+            mem.write_elem(c, constant);
+            mem.write_elems(t, &vec![F::ZERO; 3 * n]);
+            mem.write_elems(acc, &vec![F::ZERO; n]);
+            VectorChain::new(ops, &mem, &hw).execute(&mut mem);
+            outputs.push((mem.read_elems(out, 3 * n), mem.read_elems(acc, n)));
+        }
+        assert_eq!(outputs[0], outputs[1]);
+    }
+}
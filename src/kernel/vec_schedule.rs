@@ -0,0 +1,264 @@
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::mem;
+use std::ops::AddAssign;
+
+use serde::{Deserialize, Serialize};
+
+use crate::kernel::vector_chain::{overlap, Convoy};
+use crate::kernel::vector_operation::{VecOpConfig, VecOpSrc};
+
+// number of ready ops, in program order, considered for the next slot
+const WINDOW: usize = 16;
+
+/// Traffic and size of the vector chains before and after scheduling, in total over
+/// the chains that were scheduled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleStats {
+    pub num_chains: usize,
+    pub ops_before: usize,
+    pub ops_after: usize,
+    pub convoys_before: usize,
+    pub convoys_after: usize,
+    pub bytes_read_before: usize,
+    pub bytes_read_after: usize,
+    pub bytes_written_before: usize,
+    pub bytes_written_after: usize,
+}
+
+impl AddAssign for ScheduleStats {
+    fn add_assign(&mut self, other: Self) {
+        self.num_chains += other.num_chains;
+        self.ops_before += other.ops_before;
+        self.ops_after += other.ops_after;
+        self.convoys_before += other.convoys_before;
+        self.convoys_after += other.convoys_after;
+        self.bytes_read_before += other.bytes_read_before;
+        self.bytes_read_after += other.bytes_read_after;
+        self.bytes_written_before += other.bytes_written_before;
+        self.bytes_written_after += other.bytes_written_after;
+    }
+}
+
+pub struct Schedule {
+    pub order: Vec<usize>, // indices of the kept ops, in the order they run
+    pub convoys: Vec<Convoy>,
+}
+
+// (address, length) of the vectors op reads, a VS op reads one element of input 1
+fn reads(op: &VecOpConfig) -> Vec<(usize, usize)> {
+    let input_1_length = match op.op_src {
+        VecOpSrc::VV => op.vector_length,
+        VecOpSrc::VS => 1,
+    };
+    let mut res = vec![(op.addr_input_0, op.vector_length)];
+    if op.reads_input_1() {
+        res.push((op.addr_input_1, input_1_length));
+    }
+    if op.reads_output() {
+        res.push((op.addr_output, op.vector_length));
+    }
+    res.retain(|x| x.0 != 0);
+    res
+}
+
+fn writes(op: &VecOpConfig) -> Option<(usize, usize)> {
+    Some((op.addr_output, op.vector_length)).filter(|x| x.0 != 0)
+}
+
+fn reads_from(op: &VecOpConfig, range: (usize, usize)) -> bool {
+    reads(op).into_iter().any(|r| overlap(r, range))
+}
+
+/// Whether b has to run after a, if b comes after a in program order: RAW, WAR, WAW.
+pub fn depends(a: &VecOpConfig, b: &VecOpConfig) -> bool {
+    let (output_a, output_b) = (writes(a), writes(b));
+    output_a.is_some_and(|w| reads_from(b, w))
+        || output_b.is_some_and(|w| reads_from(a, w) || output_a.is_some_and(|x| overlap(x, w)))
+}
+
+/// Indices of the ops that are kept when the temporaries nobody reads are dropped.
+/// An output that isn't observable, e.g. final, and not read by a later kept op of
+/// the chain is dead.
+pub fn live_ops(vec_ops: &[VecOpConfig], observable: impl Fn(&VecOpConfig) -> bool) -> Vec<usize> {
+    let mut live = vec![true; vec_ops.len()];
+    for i in (0..vec_ops.len()).rev() {
+        let op = &vec_ops[i];
+        if observable(op) {
+            continue;
+        }
+        let output = (op.addr_output, op.vector_length);
+        live[i] = (i + 1..vec_ops.len()).any(|j| live[j] && reads_from(&vec_ops[j], output));
+    }
+    (0..vec_ops.len()).filter(|&i| live[i]).collect()
+}
+
+// how much adding op after the ops in last helps: 2 if it consumes one of their
+// outputs, so that it is chained in the same convoy, 1 if it reads one of their
+// vectors again
+fn affinity(op: &VecOpConfig, last: &[VecOpConfig]) -> usize {
+    let consumes = last.iter().filter_map(writes).any(|w| reads_from(op, w));
+    let reuses = last.iter().flat_map(reads).any(|r| reads(op).contains(&r));
+    if consumes {
+        2
+    } else {
+        reuses as usize
+    }
+}
+
+/// List scheduling over the dependency DAG of the live ops. Each convoy is filled
+/// with the ready op that chains with or reuses the vectors of the ops already in
+/// it, ties go to program order. Dependent ops keep their relative order.
+pub fn schedule(vec_ops: &[VecOpConfig]) -> Schedule {
+    // outputs at address 0 are in the preloaded buffer
+    let kept = live_ops(vec_ops, |op| op.is_final_output || op.addr_output == 0);
+    let n = kept.len();
+    let mut num_preds = vec![0; n];
+    let mut succs = vec![Vec::new(); n];
+    for b in 0..n {
+        for a in 0..b {
+            if depends(&vec_ops[kept[a]], &vec_ops[kept[b]]) {
+                num_preds[b] += 1;
+                succs[a].push(b);
+            }
+        }
+    }
+    let mut ready = (0..n)
+        .filter(|&i| num_preds[i] == 0)
+        .collect::<BTreeSet<_>>();
+
+    let mut order = Vec::with_capacity(n);
+    let mut convoys: Vec<Convoy> = Vec::new();
+    let mut convoy = Convoy::new();
+    let mut in_convoy: Vec<VecOpConfig> = Vec::new();
+    while !ready.is_empty() {
+        let priority = |i: usize| (affinity(&vec_ops[kept[i]], &in_convoy), Reverse(i));
+        // the best ready op that still fits into the convoy
+        let fitting = ready
+            .iter()
+            .take(WINDOW)
+            .copied()
+            .filter(|&i| convoy.clone().add_vec_op(vec_ops[kept[i]]))
+            .max_by_key(|&i| priority(i));
+        let next = match fitting {
+            Some(i) => i,
+            None => {
+                // the one that follows up the full convoy best starts the next
+                let i = ready
+                    .iter()
+                    .take(WINDOW)
+                    .copied()
+                    .max_by_key(|&i| priority(i))
+                    .unwrap();
+                let following = convoy.following();
+                convoys.push(mem::replace(&mut convoy, following));
+                in_convoy.clear();
+                i
+            }
+        };
+        assert!(convoy.add_vec_op(vec_ops[kept[next]]));
+        in_convoy.push(vec_ops[kept[next]]);
+        order.push(kept[next]);
+        ready.remove(&next);
+        for &s in succs[next].iter() {
+            num_preds[s] -= 1;
+            if num_preds[s] == 0 {
+                ready.insert(s);
+            }
+        }
+    }
+    if !in_convoy.is_empty() {
+        convoys.push(convoy);
+    }
+    Schedule { order, convoys }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HwConfig;
+    use crate::kernel::kernel::Kernel;
+    use crate::kernel::vector_chain::VectorChain;
+    use crate::kernel::vector_operation::VecOpType;
+    use crate::memory::memory_allocator::MemAlloc;
+    use crate::util::SIZE_F;
+    use plonky2::field::goldilocks_field::GoldilocksField as F;
+    use plonky2::field::types::{Field, Sample};
+
+    #[test]
+    fn test_schedule() {
+        let n = 1024;
+        let mut mem = MemAlloc::new(1, 4096);
+        let mut alloc = |name: &str| mem.alloc(name, n * SIZE_F).unwrap();
+        let inputs = (0..4)
+            .map(|i| alloc(&format!("x{}", i)))
+            .collect::<Vec<_>>();
+        let tmps = (0..4)
+            .map(|i| alloc(&format!("t{}", i)))
+            .collect::<Vec<_>>();
+        let outputs = (0..2)
+            .map(|i| alloc(&format!("y{}", i)))
+            .collect::<Vec<_>>();
+        let op = |a, b, c, op_type, is_final_output| VecOpConfig {
+            vector_length: n,
+            addr_input_0: a,
+            addr_input_1: b,
+            addr_output: c,
+            is_final_output,
+            op_type,
+            op_src: VecOpSrc::VV,
+        };
+        // two independent products, interleaved, and a temporary nobody reads
+        let vec_ops = vec![
+            op(inputs[0], inputs[1], tmps[0], VecOpType::MUL, false),
+            op(inputs[2], inputs[3], tmps[1], VecOpType::MUL, false),
+            op(inputs[0], inputs[3], tmps[3], VecOpType::SUB, false),
+            op(tmps[0], inputs[0], tmps[2], VecOpType::ADD, false),
+            op(tmps[1], inputs[2], outputs[1], VecOpType::ADD, true),
+            op(tmps[2], inputs[1], outputs[0], VecOpType::SUB, true),
+        ];
+        assert_eq!(
+            live_ops(&vec_ops, |op| op.is_final_output),
+            vec![0, 1, 3, 4, 5]
+        );
+        let s = schedule(&vec_ops);
+        assert_eq!(s.order.len(), 5);
+        for (i, &a) in s.order.iter().enumerate() {
+            for &b in s.order.iter().skip(i + 1) {
+                assert!(b > a || !depends(&vec_ops[b], &vec_ops[a]));
+            }
+        }
+        // each product is chained with its consumers
+        assert_eq!(s.order, vec![0, 3, 5, 1, 4]);
+
+        let chain = |schedule_vec_ops: bool| {
+            let mut hw = HwConfig::default();
+            hw.opt.schedule_vec_ops = schedule_vec_ops;
+            VectorChain::new(vec_ops.clone(), &mem, &hw)
+        };
+        let (in_order, scheduled) = (chain(false), chain(true));
+        let stats = scheduled.get_schedule_stats().unwrap();
+        assert!(in_order.get_schedule_stats().is_none());
+        assert_eq!((stats.ops_before, stats.ops_after), (6, 5));
+        assert!(stats.convoys_after < stats.convoys_before);
+        assert!(stats.bytes_written_after < stats.bytes_written_before);
+        assert!(stats.bytes_read_after <= stats.bytes_read_before);
+
+        // the final outputs are the same as in program order
+        for &addr in inputs.iter() {
+            mem.write_elems(addr, &F::rand_vec(n));
+        }
+        in_order.execute(&mut mem);
+        let reference = outputs
+            .iter()
+            .map(|&addr| mem.read_elems(addr, n))
+            .collect::<Vec<_>>();
+        for &addr in outputs.iter() {
+            mem.write_elems(addr, &vec![F::ZERO; n]);
+        }
+        scheduled.execute(&mut mem);
+        for (&addr, values) in outputs.iter().zip(reference) {
+            assert_eq!(mem.read_elems(addr, n), values);
+        }
+    }
+}
@@ -1,16 +1,21 @@
 use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
 
-use crate::config::arch_config::ARCH_CONFIG;
-use crate::config::enable_config::ENABLE_CONFIG;
+use crate::config::arch_config::ArchConfig;
+use crate::config::HwConfig;
 use crate::kernel::vector_operation::VecOpConfig;
 use crate::memory::memory_allocator::MemAlloc;
 use crate::trace::trace::{Fetch, FetchType, Request};
 use crate::util::SIZE_F;
+use plonky2::field::goldilocks_field::GoldilocksField as F;
+use plonky2::field::types::Field;
 
-use super::kernel::Kernel;
-use super::lru::LRUcache;
-use super::vector_operation::VecOpSrc;
+use super::kernel::{Kernel, KernelCall};
+use super::lru::{policy, BufferCache};
+use super::vec_cse::{eliminate, CseStats};
+use super::vec_schedule::{schedule, ScheduleStats};
+use super::vector_operation::{VecOpSrc, VecOpType};
 
 const NUM_VEC_REGS: usize = 4;
 
@@ -39,20 +44,30 @@ impl Convoy {
         }
     }
 
+    // an empty convoy that starts with the register file this one leaves
+    pub fn following(&self) -> Convoy {
+        Convoy {
+            reg_file_state: self.reg_file_state.clone(),
+            ..Convoy::new()
+        }
+    }
+
+    // FMA takes the multiplier and an adder
     pub fn num_vec_mul(&self) -> usize {
-        self.vec_ops.iter().filter(|op| op.is_mul()).count()
+        self.vec_ops.iter().filter(|op| op.uses_mul()).count()
     }
 
     pub fn num_vec_add_or_sub(&self) -> usize {
-        self.vec_ops.iter().filter(|op| op.is_add_or_sub()).count()
+        self.vec_ops.iter().filter(|op| op.uses_add()).count()
     }
 
     pub fn need_ld(&self, vec_op: VecOpConfig) -> usize {
         let mut res_0 = true;
         let mut res_1 = match vec_op.op_src {
-            VecOpSrc::VV => true,
+            VecOpSrc::VV => vec_op.reads_input_1(),
             _ => false,
         };
+        let mut res_out = vec_op.reads_output() && vec_op.addr_output != 0;
         for i in 0..NUM_VEC_REGS {
             if cover(
                 (self.reg_file_state[i].0, self.reg_file_state[i].1),
@@ -68,6 +83,12 @@ impl Convoy {
             {
                 res_1 = false;
             }
+            if cover(
+                (self.reg_file_state[i].0, self.reg_file_state[i].1),
+                (vec_op.addr_output, vec_op.vector_length),
+            ) {
+                res_out = false;
+            }
         }
         if vec_op.addr_input_0 == 0 {
             res_0 = false;
@@ -76,7 +97,7 @@ impl Convoy {
             res_1 = false;
         }
 
-        return res_0 as usize + res_1 as usize;
+        return res_0 as usize + res_1 as usize + res_out as usize;
     }
 
     pub fn used(&self, reg: (usize, usize)) -> bool {
@@ -126,13 +147,14 @@ impl Convoy {
 
     pub fn add_vec_op(&mut self, vec_op: VecOpConfig) -> bool {
         if self.vec_ops.len() >= 3
-            || self.num_vec_mul() >= 1 && vec_op.is_mul()
-            || self.num_vec_add_or_sub() >= 2 && vec_op.is_add_or_sub()
+            || self.num_vec_mul() >= 1 && vec_op.uses_mul()
+            || self.num_vec_add_or_sub() >= 2 && vec_op.uses_add()
         {
             return false;
         }
 
-        if self.num_ld_ops + self.need_ld(vec_op) > 2 {
+        // an FMA or SELECT may load three vectors, then it is alone in the convoy
+        if !self.vec_ops.is_empty() && self.num_ld_ops + self.need_ld(vec_op) > 2 {
             return false;
         }
 
@@ -268,6 +290,12 @@ impl Convoy {
                     res.push((op.addr_input_1, 1));
                 }
             }
+            if op.reads_output()
+                && !state_cover(state, (op.addr_output, op.vector_length))
+                && op.addr_output != 0
+            {
+                res.push((op.addr_output, op.vector_length));
+            }
         }
         res
     }
@@ -291,59 +319,60 @@ impl Convoy {
 
 #[derive(Clone)]
 pub struct VectorChain {
-    vec_ops: Vec<VecOpConfig>,
+    vec_ops: Vec<VecOpConfig>, // without the preloaded addresses
+    ops: Vec<VecOpConfig>,     // as given, for execute
+    order: Vec<usize>,         // indices of the ops that run, in their order
     convoys: Vec<Convoy>,
+    schedule_stats: Option<ScheduleStats>,
+    pub cse_stats: BTreeMap<String, CseStats>,
     pub prefetch: Fetch,
     pub read_request: Request,
     pub write_request: Request,
     pub drain: Fetch,
 
     pub num_preload_elems: usize,
+    pub arch: ArchConfig,
 }
 
 impl Kernel for VectorChain {
     fn create_prefetch(&mut self) {
-        let elems_capacity = unsafe { ARCH_CONFIG.num_elems() * 2 - self.num_preload_elems };
-        let mut lru = LRUcache::new(elems_capacity);
+        let elems_capacity = self.arch.num_elems() * 2 - self.num_preload_elems;
+        let chunks = self.buffer_chunks(elems_capacity / 4);
+        let future = chunks
+            .iter()
+            .flatten()
+            .flat_map(|(addrs, _, _)| addrs.iter().copied().filter(|&x| x != 0))
+            .collect::<Vec<_>>();
+        let mut buffer = BufferCache::new(elems_capacity, policy(self.arch.replacement, &future));
 
         let mut out_tag = HashMap::<usize, bool>::new();
 
-        for (i, c) in self.convoys.iter().enumerate() {
+        for (i, convoy_chunks) in chunks.iter().enumerate() {
             let mut prefetch = Vec::new();
             let mut drain = Vec::new();
-            for vo in c.vec_ops.iter() {
-                for offset in (0..vo.vector_length).step_by(lru.get_capacity() / 4) {
-                    let vl = min(lru.get_capacity() / 4, vo.vector_length - offset);
-                    let addr_output = vo.addr_output + offset * SIZE_F;
-                    let addr_input_0 = vo.addr_input_0 + offset * SIZE_F;
-                    let addr_input_1 = vo.addr_input_1 + offset * SIZE_F;
-
-                    if addr_output != 0 {
-                        out_tag.insert(addr_output, true);
+            for &(addrs, vl, reads_output) in convoy_chunks.iter() {
+                let addr_output = addrs[2];
+                if addr_output != 0 {
+                    out_tag.insert(addr_output, true);
+                }
+                for (j, addr) in addrs.iter().enumerate().filter(|&(_, x)| *x != 0) {
+                    let (load, evicted) = buffer.put(*addr, vl, j == 2 && !reads_output);
+                    for (addr, size) in load {
+                        let elems = (0..size).map(|x| addr + x * SIZE_F).collect();
+                        prefetch.push(elems);
                     }
-                    for (j, addr) in [addr_input_0, addr_input_1, addr_output]
-                        .iter()
-                        .enumerate()
-                        .filter(|&(_, x)| *x != 0)
-                    {
-                        let (load, evicted) = lru.put(*addr, vl, j == 2);
-                        for (addr, size) in load {
+                    for (addr, size) in evicted {
+                        if let Some(true) = out_tag.get(&addr) {
                             let elems = (0..size).map(|x| addr + x * SIZE_F).collect();
-                            prefetch.push(elems);
-                        }
-                        for (addr, size) in evicted {
-                            if let Some(true) = out_tag.get(&addr) {
-                                let elems = (0..size).map(|x| addr + x * SIZE_F).collect();
-                                drain.push(elems);
-                                out_tag.remove(&addr);
-                            }
+                            drain.push(elems);
+                            out_tag.remove(&addr);
                         }
                     }
                 }
             }
 
             if i == self.convoys.len() - 1 {
-                for (addr, size) in lru.drain() {
+                for (addr, size) in buffer.drain() {
                     if let Some(true) = out_tag.get(&addr) {
                         let elems = (0..size).map(|x| addr + x * SIZE_F).collect();
                         drain.push(elems);
@@ -365,7 +394,7 @@ impl Kernel for VectorChain {
         self.drain.delay = self
             .convoys
             .iter()
-            .map(|c| c.num_vec_mul() * 2 + c.num_vec_add_or_sub())
+            .map(|c| c.vec_ops.iter().map(|op| op.delay()).sum())
             .collect();
         self.drain.interval = 1.0;
     }
@@ -399,15 +428,91 @@ impl Kernel for VectorChain {
 
     fn get_computation(&self) -> usize {
         let mut res = 0;
-        for c in self.vec_ops.iter() {
-            res += c.vector_length;
+        for &i in self.order.iter() {
+            res += self.vec_ops[i].vector_length;
         }
         res
     }
+
+    fn get_schedule_stats(&self) -> Option<ScheduleStats> {
+        self.schedule_stats
+    }
+
+    fn get_cse_stats(&self) -> BTreeMap<String, CseStats> {
+        self.cse_stats.clone()
+    }
+
+    fn get_call(&self) -> KernelCall {
+        KernelCall::VectorChain(self.vec_ops.clone(), self.num_preload_elems)
+    }
+    fn execute(&self, mem: &mut MemAlloc) {
+        // address 0 stands for a vector that is not in memory, all of it reads as 0
+        let read = |mem: &MemAlloc, addr: usize, i: usize| {
+            if addr == 0 {
+                F::ZERO
+            } else {
+                mem.read_elem(addr + i * SIZE_F)
+            }
+        };
+        for op in self.order.iter().map(|&i| &self.ops[i]) {
+            for i in 0..op.vector_length {
+                let a = read(mem, op.addr_input_0, i);
+                let b = match op.op_src {
+                    VecOpSrc::VV => read(mem, op.addr_input_1, i),
+                    VecOpSrc::VS => read(mem, op.addr_input_1, 0),
+                };
+                let c = if op.reads_output() {
+                    read(mem, op.addr_output, i)
+                } else {
+                    F::ZERO
+                };
+                let res = match op.op_type {
+                    VecOpType::ADD => a + b,
+                    VecOpType::SUB => a - b,
+                    VecOpType::MUL => a * b,
+                    VecOpType::FMA => a * b + c,
+                    VecOpType::SQUARE => a * a,
+                    VecOpType::NEG => -a,
+                    VecOpType::SELECT => {
+                        if b.is_zero() {
+                            c
+                        } else {
+                            a
+                        }
+                    }
+                };
+                if op.addr_output != 0 {
+                    mem.write_elem(op.addr_output + i * SIZE_F, res);
+                }
+            }
+        }
+    }
 }
 
 impl VectorChain {
-    pub fn new(vec_ops: Vec<VecOpConfig>, mem: &MemAlloc) -> VectorChain {
+    // the ops of each convoy in chunks of at most chunk_len elements, as the addresses
+    // of input 0, input 1 and the output, the length and whether the output is loaded
+    // too, in the order they are put into the buffer
+    fn buffer_chunks(&self, chunk_len: usize) -> Vec<Vec<([usize; 3], usize, bool)>> {
+        self.convoys
+            .iter()
+            .map(|c| {
+                c.vec_ops
+                    .iter()
+                    .flat_map(|vo| {
+                        (0..vo.vector_length).step_by(chunk_len).map(move |offset| {
+                            let addrs = [vo.addr_input_0, vo.addr_input_1, vo.addr_output]
+                                .map(|addr| addr + offset * SIZE_F);
+                            let len = min(chunk_len, vo.vector_length - offset);
+                            (addrs, len, vo.reads_output())
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn new(vec_ops: Vec<VecOpConfig>, mem: &MemAlloc, hw: &HwConfig) -> VectorChain {
         let vec_ops_addr = vec_ops
             .iter()
             .map(|x| {
@@ -424,34 +529,111 @@ impl VectorChain {
                 y
             })
             .collect::<Vec<_>>();
+        let mut k = Self::from_chained_ops(vec_ops_addr, mem.num_preload_elems, hw);
+        k.ops = vec_ops;
+        k
+    }
 
-        let convoys = vector_chaining(&vec_ops_addr);
+    // like new, without the duplicate and dead ops if hw.opt.cse_vec_ops. The ops
+    // saved are counted by the tag of the range they are in, e.g. the gate type.
+    pub fn new_eliminated(
+        vec_ops: Vec<VecOpConfig>,
+        tags: &[(String, Range<usize>)],
+        mem: &MemAlloc,
+        hw: &HwConfig,
+    ) -> VectorChain {
+        if !hw.opt.cse_vec_ops {
+            return Self::new(vec_ops, mem, hw);
+        }
+        let res = eliminate(&vec_ops, mem);
+        let mut k = Self::new(res.ops, mem, hw);
+        for (tag, range) in tags.iter() {
+            *k.cse_stats.entry(tag.clone()).or_default() +=
+                CseStats::count(&res.status[range.clone()]);
+        }
+        k
+    }
+
+    // vec_ops have 0 in place of the preloaded addresses, which execute reads as 0
+    pub fn from_chained_ops(
+        vec_ops: Vec<VecOpConfig>,
+        num_preload_elems: usize,
+        hw: &HwConfig,
+    ) -> VectorChain {
+        let (order, convoys) = if hw.opt.schedule_vec_ops {
+            let s = schedule(&vec_ops);
+            (s.order, s.convoys)
+        } else {
+            ((0..vec_ops.len()).collect(), vector_chaining(&vec_ops))
+        };
         let mut k = VectorChain {
-            vec_ops: vec_ops_addr,
+            ops: vec_ops.clone(),
+            vec_ops,
+            order,
             convoys,
+            schedule_stats: None,
+            cse_stats: BTreeMap::new(),
             prefetch: Fetch::new(FetchType::Read),
             read_request: Request::new(),
             write_request: Request::new(),
             drain: Fetch::new(FetchType::Write),
-            num_preload_elems: mem.num_preload_elems,
+            num_preload_elems,
+            arch: hw.arch,
         };
 
-        if unsafe { !ENABLE_CONFIG.other } {
+        if !hw.enable.other {
             return k;
         }
 
+        k.create_prefetch();
+
+        if hw.opt.schedule_vec_ops {
+            // the same chain in program order
+            let mut before = VectorChain {
+                order: (0..k.vec_ops.len()).collect(),
+                convoys: vector_chaining(&k.vec_ops),
+                prefetch: Fetch::new(FetchType::Read),
+                drain: Fetch::new(FetchType::Write),
+                ..k.clone()
+            };
+            before.create_prefetch();
+            let s = ScheduleStats {
+                num_chains: 1,
+                ops_before: before.order.len(),
+                ops_after: k.order.len(),
+                convoys_before: before.convoys.len(),
+                convoys_after: k.convoys.len(),
+                bytes_read_before: fetch_bytes(&before.prefetch),
+                bytes_read_after: fetch_bytes(&k.prefetch),
+                bytes_written_before: fetch_bytes(&before.drain),
+                bytes_written_after: fetch_bytes(&k.drain),
+            };
+            // the schedule is only kept if it moves fewer bytes
+            if s.bytes_read_after + s.bytes_written_after
+                > s.bytes_read_before + s.bytes_written_before
+            {
+                k = before;
+            }
+            k.schedule_stats = Some(ScheduleStats {
+                ops_after: k.order.len(),
+                convoys_after: k.convoys.len(),
+                bytes_read_after: fetch_bytes(&k.prefetch),
+                bytes_written_after: fetch_bytes(&k.drain),
+                ..s
+            });
+        }
+
         let read_segments = get_prefetch_segments(&k.convoys);
         let write_segments = get_drain_segments(&k.convoys);
 
-        k.create_prefetch();
-        k.vec_ops = vec_ops;
-
         for rl in read_segments.iter() {
-            k.read_request.push(get_request_lines(vec![rl.clone()]));
+            k.read_request
+                .push(get_request_lines(vec![rl.clone()], &hw.arch));
         }
 
         for wl in write_segments.iter() {
-            k.write_request.push(get_request_lines(vec![wl.clone()]));
+            k.write_request
+                .push(get_request_lines(vec![wl.clone()], &hw.arch));
         }
 
         k
@@ -468,12 +650,7 @@ fn vector_chaining(vec_ops: &Vec<VecOpConfig>) -> Vec<Convoy> {
     for op in vec_ops.iter() {
         let inserted = convoys.last_mut().map_or(false, |c| c.add_vec_op(*op));
         if !inserted {
-            let mut new_convoy = Convoy::new();
-            new_convoy.reg_file_state = convoys
-                .last_mut()
-                .map_or(vec![(0, 0, OutputType::Not); NUM_VEC_REGS], |c| {
-                    c.reg_file_state.clone()
-                });
+            let mut new_convoy = convoys.last().map_or(Convoy::new(), |c| c.following());
             new_convoy.add_vec_op(*op);
             convoys.push(new_convoy);
         }
@@ -498,7 +675,7 @@ fn state_cover(state: &Vec<(usize, usize, OutputType)>, reg: (usize, usize)) ->
     false
 }
 
-fn overlap(a: (usize, usize), b: (usize, usize)) -> bool {
+pub(crate) fn overlap(a: (usize, usize), b: (usize, usize)) -> bool {
     max(a.0, b.0) < min(a.0 + a.1 * SIZE_F, b.0 + b.1 * SIZE_F)
 }
 
@@ -540,8 +717,18 @@ fn get_drain_segments(convoys: &Vec<Convoy>) -> Vec<Vec<(usize, usize)>> {
     res
 }
 
-fn get_request_lines(segments: Vec<Vec<(usize, usize)>>) -> Vec<Vec<usize>> {
-    let al = unsafe { ARCH_CONFIG.array_length };
+// the segments are inclusive ranges of element addresses
+fn fetch_bytes(fetch: &Fetch) -> usize {
+    fetch
+        .addr
+        .iter()
+        .flatten()
+        .map(|(start, end)| (end - start) as usize + SIZE_F)
+        .sum()
+}
+
+fn get_request_lines(segments: Vec<Vec<(usize, usize)>>, arch: &ArchConfig) -> Vec<Vec<usize>> {
+    let al = arch.array_length;
     segments
         .iter()
         .map(|segs| {
@@ -560,3 +747,44 @@ fn get_request_lines(segments: Vec<Vec<(usize, usize)>>) -> Vec<Vec<usize>> {
         .flatten()
         .collect::<Vec<Vec<usize>>>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Replacement;
+
+    #[test]
+    fn test_replacement_traffic() {
+        let n = 2048;
+        let mem = MemAlloc::new(1, 4096);
+        let addr = |i: usize| (i + 1) * (1 << 20);
+        // c_i = a_i * a_(i+1) over more vectors than fit into the buffer
+        let vec_ops = (0..6)
+            .map(|i| VecOpConfig {
+                vector_length: n,
+                addr_input_0: addr(i % 5),
+                addr_input_1: addr((i + 1) % 5),
+                addr_output: addr(10 + i),
+                is_final_output: true,
+                op_type: VecOpType::MUL,
+                op_src: VecOpSrc::VV,
+            })
+            .collect::<Vec<_>>();
+
+        let bytes = |replacement: Replacement| {
+            let mut hw = HwConfig::default();
+            hw.arch.rdbuf_sz_kb = 16;
+            hw.arch.wrbuf_sz_kb = 16;
+            hw.arch.replacement = replacement;
+            let chain = VectorChain::new(vec_ops.clone(), &mem, &hw);
+            (fetch_bytes(&chain.prefetch), fetch_bytes(&chain.drain))
+        };
+        let (lru_read, lru_written) = bytes(Replacement::Lru);
+        let (belady_read, belady_written) = bytes(Replacement::Belady);
+        // every output is written exactly once whatever is evicted
+        assert_eq!(lru_written, 6 * n * SIZE_F);
+        assert_eq!(belady_written, lru_written);
+        assert!(belady_read < lru_read);
+        assert!(bytes(Replacement::Fifo).0 >= belady_read);
+    }
+}
@@ -1,19 +1,25 @@
 use crate::memory::memory_allocator::MemAlloc;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum VecOpType {
     ADD,
     SUB,
     MUL,
+    // only emitted with ArchConfig::rich_vec_isa
+    FMA,    // input 0 * input 1 + output
+    SQUARE, // input 0 * input 0, input 1 is unused
+    NEG,    // -input 0, input 1 is unused
+    SELECT, // input 0 where input 1 is not 0, else the output is kept
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum VecOpSrc {
     VV,
     VS,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct VecOpConfig {
     pub vector_length: usize,  // length of the vector
     pub addr_input_0: usize,   // base address of the input 0
@@ -51,23 +57,95 @@ impl VecOpConfig {
         self.is_add() || self.is_sub()
     }
 
+    // the op needs the multiplier of the PE
+    pub fn uses_mul(&self) -> bool {
+        matches!(
+            self.op_type,
+            VecOpType::MUL | VecOpType::FMA | VecOpType::SQUARE
+        )
+    }
+
+    // the op needs one of the adders of the PE, SELECT and NEG run there too
+    pub fn uses_add(&self) -> bool {
+        !matches!(self.op_type, VecOpType::MUL | VecOpType::SQUARE)
+    }
+
+    pub fn reads_input_1(&self) -> bool {
+        !matches!(self.op_type, VecOpType::SQUARE | VecOpType::NEG)
+    }
+
+    // the old value of the output is an operand too
+    pub fn reads_output(&self) -> bool {
+        matches!(self.op_type, VecOpType::FMA | VecOpType::SELECT)
+    }
+
     pub fn inv(
         mem: &mut MemAlloc,
         vector_length: usize, // length of the vector
         addr_input: usize,
         addr_output: usize,
         is_final_output: bool,
+        rich_isa: bool,
     ) -> Vec<VecOpConfig> {
-        let mut vec_ops = Vec::new();
-
         mem.alloc("t2_3_12_30_63", vector_length * SIZE_F);
-        let addr_t2_3_12_30_63 = mem.get_addr("t2_3_12_30_63").unwrap();
-
         mem.alloc("t6_31", vector_length * SIZE_F);
-        let addr_t6_31 = mem.get_addr("t6_31").unwrap();
-
         mem.alloc("t24", vector_length * SIZE_F);
-        let addr_t24 = mem.get_addr("t24").unwrap();
+        let addr_temps = ["t2_3_12_30_63", "t6_31", "t24"].map(|id| mem.get_addr(id).unwrap());
+
+        let vec_ops = Self::inv_with_temps(
+            vector_length,
+            addr_input,
+            addr_output,
+            addr_temps,
+            is_final_output,
+            rich_isa,
+        );
+
+        mem.free("t2_3_12_30_63");
+        mem.free("t6_31");
+        mem.free("t24");
+
+        vec_ops
+    }
+
+    // inv whose temporary vectors are only freed by MemAlloc::free_deferred, once the ops
+    // have run, for the functional simulation
+    pub fn inv_deferred(
+        mem: &mut MemAlloc,
+        vector_length: usize,
+        addr_input: usize,
+        addr_output: usize,
+        is_final_output: bool,
+        rich_isa: bool,
+    ) -> Vec<VecOpConfig> {
+        let addr_temps = [(); 3].map(|_| {
+            let id = mem.temp_name("inv");
+            let addr = mem.alloc(&id, vector_length * SIZE_F).unwrap();
+            mem.defer_free(&id);
+            addr
+        });
+        Self::inv_with_temps(
+            vector_length,
+            addr_input,
+            addr_output,
+            addr_temps,
+            is_final_output,
+            rich_isa,
+        )
+    }
+
+    // inv with its temporary vectors given, for callers that keep them allocated until
+    // the ops have run
+    pub fn inv_with_temps(
+        vector_length: usize,
+        addr_input: usize,
+        addr_output: usize,
+        addr_temps: [usize; 3],
+        is_final_output: bool,
+        rich_isa: bool,
+    ) -> Vec<VecOpConfig> {
+        let mut vec_ops = Vec::new();
+        let [addr_t2_3_12_30_63, addr_t6_31, addr_t24] = addr_temps;
 
         // t2
         vec_ops.extend(Self::exp_acc::<1>(
@@ -76,6 +154,7 @@ impl VecOpConfig {
             addr_input,
             addr_t2_3_12_30_63,
             false,
+            rich_isa,
         ));
 
         // t3
@@ -85,6 +164,7 @@ impl VecOpConfig {
             addr_input,
             addr_t2_3_12_30_63,
             false,
+            rich_isa,
         ));
 
         // t6
@@ -94,6 +174,7 @@ impl VecOpConfig {
             addr_t2_3_12_30_63,
             addr_t6_31,
             false,
+            rich_isa,
         ));
 
         // t12
@@ -103,6 +184,7 @@ impl VecOpConfig {
             addr_t6_31,
             addr_t2_3_12_30_63,
             false,
+            rich_isa,
         ));
 
         // t24
@@ -112,6 +194,7 @@ impl VecOpConfig {
             addr_t2_3_12_30_63,
             addr_t24,
             false,
+            rich_isa,
         ));
 
         // t30
@@ -121,6 +204,7 @@ impl VecOpConfig {
             addr_t6_31,
             addr_t2_3_12_30_63,
             false,
+            rich_isa,
         ));
 
         // t31
@@ -130,6 +214,7 @@ impl VecOpConfig {
             addr_input,
             addr_t6_31,
             false,
+            rich_isa,
         ));
 
         // t63 base^111111111111111111111111111111101111111111111111111111111111111
@@ -139,6 +224,7 @@ impl VecOpConfig {
             addr_t6_31,
             addr_t2_3_12_30_63,
             false,
+            rich_isa,
         ));
 
         //     base^1111111111111111111111111111111011111111111111111111111111111111
@@ -148,12 +234,9 @@ impl VecOpConfig {
             addr_input,
             addr_output,
             is_final_output,
+            rich_isa,
         ));
 
-        mem.free("t2_3_12_30_63");
-        mem.free("t6_31");
-        mem.free("t24");
-
         vec_ops
     }
 
@@ -163,12 +246,19 @@ impl VecOpConfig {
         addr_input_1: usize,
         addr_output: usize,
         is_final_output: bool,
+        rich_isa: bool,
     ) -> Vec<VecOpConfig> {
         let mut vec_ops = Vec::new();
 
         for i in 0..N {
             let ai = if i == 0 { addr_input_0 } else { addr_output };
-            vec_ops.push(Self::square(vector_length, ai, addr_output, false));
+            vec_ops.push(Self::square(
+                vector_length,
+                ai,
+                addr_output,
+                false,
+                rich_isa,
+            ));
         }
         vec_ops.push(VecOpConfig {
             vector_length: vector_length,
@@ -183,28 +273,59 @@ impl VecOpConfig {
         vec_ops
     }
 
-    fn square(
+    pub fn square(
         vector_length: usize,
         addr_input: usize,
         addr_output: usize,
         is_final_output: bool,
+        rich_isa: bool,
     ) -> VecOpConfig {
+        let (addr_input_1, op_type) = if rich_isa {
+            (0, VecOpType::SQUARE)
+        } else {
+            (addr_input, VecOpType::MUL)
+        };
         VecOpConfig {
-            vector_length: vector_length,
+            vector_length,
             addr_input_0: addr_input,
-            addr_input_1: addr_input,
-            addr_output: addr_output,
-            op_type: VecOpType::MUL,
+            addr_input_1,
+            addr_output,
+            op_type,
             op_src: VecOpSrc::VV,
-            is_final_output: is_final_output,
+            is_final_output,
+        }
+    }
+
+    // output = input 0 * input 1 + output
+    pub fn fma(
+        vector_length: usize,
+        addr_input_0: usize,
+        addr_input_1: usize,
+        addr_output: usize,
+        op_src: VecOpSrc,
+        is_final_output: bool,
+    ) -> VecOpConfig {
+        VecOpConfig {
+            vector_length,
+            addr_input_0,
+            addr_input_1,
+            addr_output,
+            op_type: VecOpType::FMA,
+            op_src,
+            is_final_output,
         }
     }
 
+    // the addition of FMA is done before the product is reduced
     pub fn delay(&self) -> usize {
         match self.op_type {
             VecOpType::ADD => 1,
             VecOpType::SUB => 1,
             VecOpType::MUL => 2,
+            VecOpType::FMA => 2,
+            VecOpType::SQUARE => 2,
+            VecOpType::NEG => 1,
+            VecOpType::SELECT => 1,
         }
     }
 }
@@ -259,6 +380,7 @@ impl<const D: usize> VecOpExtension<D> {
         addr_output: usize,
         op_src: VecOpSrc,
         is_final_output: bool,
+        rich_isa: bool,
     ) -> Vec<VecOpConfig> {
         match D {
             2 => VecOpExtension2::mul(
@@ -269,6 +391,7 @@ impl<const D: usize> VecOpExtension<D> {
                 addr_output,
                 op_src,
                 is_final_output,
+                rich_isa,
             ),
             _ => panic!("Unsupported D"),
         }
@@ -316,11 +439,35 @@ impl<const D: usize> VecOpExtension<D> {
         vec_ops
     }
 
+    // output += input 0 * input 1, input 1 is in the base field
+    pub fn scalar_fma(
+        vector_length: usize,
+        addr_input_0: usize,
+        addr_input_1: usize,
+        addr_output: usize,
+        op_src: VecOpSrc,
+        is_final_output: bool,
+    ) -> Vec<VecOpConfig> {
+        (0..D)
+            .map(|i| {
+                VecOpConfig::fma(
+                    vector_length,
+                    addr_input_0 + i * vector_length * SIZE_F,
+                    addr_input_1,
+                    addr_output + i * vector_length * SIZE_F,
+                    op_src,
+                    is_final_output,
+                )
+            })
+            .collect()
+    }
+
     pub fn exp_u64(
         mem: &mut MemAlloc,
         _addr_input: usize,
         addr_res: usize,
         is_final_output: bool,
+        rich_isa: bool,
     ) -> Vec<VecOpConfig> {
         let mut vec_ops = Vec::new();
         let addr_current = mem.alloc("current", D * SIZE_F).unwrap();
@@ -333,6 +480,7 @@ impl<const D: usize> VecOpExtension<D> {
                 addr_res,
                 VecOpSrc::VS,
                 is_final_output,
+                rich_isa,
             ));
             vec_ops.extend(Self::mul(
                 mem,
@@ -342,6 +490,7 @@ impl<const D: usize> VecOpExtension<D> {
                 addr_current,
                 VecOpSrc::VS,
                 false,
+                rich_isa,
             ));
         }
         mem.free("current");
@@ -355,6 +504,83 @@ impl VecOpExtension2 {
     const SUB_DELAY: usize = 2;
     const MUL_DELAY: usize = 5;
 
+    // output = input 0 * input 1 with a scalar input 1, exact for the functional mode: the
+    // components of input 0 and output are addressed apart, addr_w_input_1 holds W times the
+    // component 1 of input 1 and addr_tmp 2 vectors of temporaries. Output may be input 0
+    pub fn mul_scalar_exact(
+        vector_length: usize,
+        addr_input_0: [usize; 2],
+        addr_input_1: usize,
+        addr_w_input_1: usize,
+        addr_output: [usize; 2],
+        addr_tmp: usize,
+        is_final_output: bool,
+    ) -> Vec<VecOpConfig> {
+        let addr_tmp = [addr_tmp, addr_tmp + vector_length * SIZE_F];
+        let op = |addr_input_0, addr_input_1, addr_output, op_type, op_src, is_final_output| {
+            VecOpConfig {
+                vector_length,
+                addr_input_0,
+                addr_input_1,
+                addr_output,
+                op_type,
+                op_src,
+                is_final_output,
+            }
+        };
+        vec![
+            // (a0 + a1 X)(z0 + z1 X) = a0 z0 + W a1 z1 + (a0 z1 + a1 z0) X
+            op(
+                addr_input_0[1],
+                addr_w_input_1,
+                addr_tmp[0],
+                VecOpType::MUL,
+                VecOpSrc::VS,
+                false,
+            ),
+            op(
+                addr_input_0[0],
+                addr_input_1 + SIZE_F,
+                addr_tmp[1],
+                VecOpType::MUL,
+                VecOpSrc::VS,
+                false,
+            ),
+            op(
+                addr_input_0[0],
+                addr_input_1,
+                addr_output[0],
+                VecOpType::MUL,
+                VecOpSrc::VS,
+                false,
+            ),
+            op(
+                addr_output[0],
+                addr_tmp[0],
+                addr_output[0],
+                VecOpType::ADD,
+                VecOpSrc::VV,
+                is_final_output,
+            ),
+            op(
+                addr_input_0[1],
+                addr_input_1,
+                addr_output[1],
+                VecOpType::MUL,
+                VecOpSrc::VS,
+                false,
+            ),
+            op(
+                addr_output[1],
+                addr_tmp[1],
+                addr_output[1],
+                VecOpType::ADD,
+                VecOpSrc::VV,
+                is_final_output,
+            ),
+        ]
+    }
+
     fn add(
         vector_length: usize,
         addr_input_0: usize, //  2 * vector_length *SIZE_F
@@ -443,6 +669,7 @@ impl VecOpExtension2 {
         addr_output: usize,
         op_src: VecOpSrc,
         is_final_output: bool,
+        rich_isa: bool,
     ) -> Vec<VecOpConfig> {
         let mut vec_ops = Vec::new();
         let is_vs = op_src == VecOpSrc::VS;
@@ -471,6 +698,48 @@ impl VecOpExtension2 {
             is_final_output: is_final_output,
         });
         let addr_exmul_0 = mem.alloc("exmul_0", vector_length * SIZE_F).unwrap();
+        if rich_isa {
+            // W * a1
+            vec_ops.push(VecOpConfig {
+                vector_length,
+                addr_input_0: a1,
+                addr_input_1: 0,
+                addr_output: addr_exmul_0,
+                op_type: VecOpType::MUL,
+                op_src: VecOpSrc::VS,
+                is_final_output: false,
+            });
+            // a0 * b0 + _ * b1
+            vec_ops.push(VecOpConfig::fma(
+                vector_length,
+                addr_exmul_0,
+                b1,
+                addr_output,
+                op_src,
+                is_final_output,
+            ));
+            // a1 * b0
+            vec_ops.push(VecOpConfig {
+                vector_length,
+                addr_input_0: a1,
+                addr_input_1: b0,
+                addr_output: addr_output + vector_length * SIZE_F,
+                op_type: VecOpType::MUL,
+                op_src,
+                is_final_output: false,
+            });
+            // a0 * b1 + a1 * b0
+            vec_ops.push(VecOpConfig::fma(
+                vector_length,
+                a0,
+                b1,
+                addr_output + vector_length * SIZE_F,
+                op_src,
+                is_final_output,
+            ));
+            mem.free("exmul_0");
+            return vec_ops;
+        }
         // W * a1
         vec_ops.push(VecOpConfig {
             vector_length: vector_length,
@@ -538,3 +807,69 @@ impl VecOpExtension2 {
         vec_ops
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HwConfig;
+    use crate::kernel::kernel::Kernel;
+    use crate::kernel::vector_chain::VectorChain;
+    use plonky2::field::goldilocks_field::GoldilocksField as F;
+    use plonky2::field::types::{Field, Sample};
+
+    #[test]
+    fn test_rich_isa() {
+        let n = 64;
+        let mut mem = MemAlloc::new(1, 4096);
+        let x = mem.alloc("x", 2 * n * SIZE_F).unwrap();
+        let y = mem.alloc("y", 2 * n * SIZE_F).unwrap();
+        let out = mem.alloc("out", 2 * n * SIZE_F).unwrap();
+        let x_inv = mem.alloc("x_inv", n * SIZE_F).unwrap();
+        let inputs = F::rand_vec(4 * n);
+        let hw = HwConfig::default();
+
+        // the same values with fewer ops
+        let mut run = |rich_isa: bool| {
+            let mut vec_ops =
+                VecOpExtension::<2>::mul(&mut mem, n, x, y, out, VecOpSrc::VV, true, rich_isa);
+            vec_ops.extend(VecOpConfig::inv(&mut mem, n, x, x_inv, true, rich_isa));
+            // the temporaries are freed once the ops are generated, keep them writable
+            mem.alloc("scratch", 64 * n * SIZE_F).unwrap();
+            mem.write_elems(x, &inputs[..2 * n]);
+            mem.write_elems(y, &inputs[2 * n..]);
+            let chain = VectorChain::new(vec_ops, &mem, &hw);
+            chain.execute(&mut mem);
+            mem.free("scratch");
+            (mem.read_elems(out, 2 * n), mem.read_elems(x_inv, n), chain)
+        };
+        let (base_out, base_inv, base) = run(false);
+        let (rich_out, rich_inv, rich) = run(true);
+        assert_eq!(rich_out, base_out);
+        assert_eq!(rich_inv, base_inv);
+        assert_eq!(rich_inv[0], inputs[0].inverse());
+        // W is a constant at address 0, which is not loaded, so only check the second limb
+        let (a, b) = ((inputs[0], inputs[n]), (inputs[2 * n], inputs[3 * n]));
+        assert_eq!(rich_out[n], a.0 * b.1 + a.1 * b.0);
+        assert!(rich.get_computation() < base.get_computation());
+
+        let op = |op_type, addr_input_1| VecOpConfig {
+            vector_length: n,
+            addr_input_0: x,
+            addr_input_1,
+            addr_output: out,
+            is_final_output: true,
+            op_type,
+            op_src: VecOpSrc::VV,
+        };
+        let mut selector = vec![F::ZERO; n];
+        selector[1] = F::ONE;
+        mem.write_elems(y, &selector);
+        mem.write_elems(out, &vec![F::TWO; n]);
+        let vec_ops = vec![op(VecOpType::SELECT, y), op(VecOpType::NEG, 0)];
+        VectorChain::new(vec_ops[..1].to_vec(), &mem, &hw).execute(&mut mem);
+        let selected = mem.read_elems(out, 2);
+        assert_eq!(selected, vec![F::TWO, inputs[1]]);
+        VectorChain::new(vec_ops[1..].to_vec(), &mem, &hw).execute(&mut mem);
+        assert_eq!(mem.read_elems(out, 1)[0], -inputs[0]);
+    }
+}
@@ -1,5 +1,4 @@
 // #![allow(unused)]
-#![feature(linked_list_remove)]
 pub mod trace;
 pub mod config;
 pub mod kernel;
@@ -7,4 +6,5 @@ pub mod memory;
 pub mod plonk;
 pub mod system;
 pub mod util;
-pub mod starky;
\ No newline at end of file
+pub mod starky;
+pub mod sweep;
\ No newline at end of file
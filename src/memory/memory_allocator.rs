@@ -1,3 +1,12 @@
+use std::cell::Cell;
+use std::cmp::min;
+
+use anyhow::{ensure, Result};
+use log::warn;
+use plonky2::field::goldilocks_field::GoldilocksField as F;
+use plonky2::field::types::{Field, PrimeField64};
+use serde::{Deserialize, Serialize};
+
 use crate::util::SIZE_F;
 
 #[derive(Debug)]
@@ -7,7 +16,28 @@ pub struct MemBlock {
     pub end: usize,
     pub size: usize,
     pub free: bool,
-    pub data: Vec<u8>,
+    pub data: Vec<u8>, // written by the kernels in functional mode
+}
+
+// the calls that change a MemAlloc, recorded for system::replay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MemEvent {
+    Alloc {
+        id: String,
+        size: usize,
+        addr: usize,
+    },
+    Free {
+        id: String,
+    },
+    Preload {
+        addr: usize,
+        size: usize,
+    },
+    Unpreload {
+        addr: usize,
+    },
+    ClearPreload,
 }
 
 #[derive(Debug)]
@@ -18,6 +48,15 @@ pub struct MemAlloc {
 
     pub preload_vecs: Vec<(usize, usize)>, // preload vecs with start addr
     pub num_preload_elems: usize,
+
+    // Some while the events are recorded
+    pub events: Option<Vec<MemEvent>>,
+
+    pub num_stray_accesses: Cell<usize>, // see stray_access
+
+    // blocks that are only freed once the vector ops reading them have run, see defer_free
+    pub deferred: Vec<String>,
+    num_temps: usize,
 }
 
 impl MemAlloc {
@@ -38,6 +77,10 @@ impl MemAlloc {
             blocks: blocks,
             preload_vecs: Vec::new(),
             num_preload_elems: 0,
+            events: None,
+            num_stray_accesses: Cell::new(0),
+            deferred: Vec::new(),
+            num_temps: 0,
         };
         mem.alloc("occupy", 8);
         mem
@@ -77,7 +120,7 @@ impl MemAlloc {
             end: self.blocks[idx].start + size,
             size: size,
             free: false,
-            data: Vec::with_capacity(size as usize),
+            data: Vec::new(),
         };
 
         if remain_size > 0 {
@@ -94,7 +137,13 @@ impl MemAlloc {
 
         self.blocks[idx] = new_block;
 
-        return Some(self.blocks[idx].start);
+        let addr = self.blocks[idx].start;
+        self.record(MemEvent::Alloc {
+            id: self.blocks[idx].id.clone(),
+            size,
+            addr,
+        });
+        return Some(addr);
     }
 
     fn merge(&mut self, mut idx: usize) {
@@ -138,10 +187,35 @@ impl MemAlloc {
         self.blocks[idx].id = String::from("0");
         self.blocks[idx].free = true;
         self.blocks[idx].data.clear();
+        self.record(MemEvent::Free { id });
 
         self.merge(idx);
     }
 
+    // a name that no other block gets, for temporaries that outlive their generator
+    pub fn temp_name(&mut self, prefix: &str) -> String {
+        self.num_temps += 1;
+        format!("{}_{}", prefix, self.num_temps - 1)
+    }
+
+    // Ops are generated before they run, so the temporaries they use are freed by
+    // free_deferred after that, and can't be reused by the blocks allocated in between.
+    pub fn defer_free(&mut self, id: &str) {
+        self.deferred.push(id.to_string());
+    }
+
+    // what free_deferred frees back to
+    pub fn deferred_mark(&self) -> usize {
+        self.deferred.len()
+    }
+
+    // frees the blocks deferred since mark
+    pub fn free_deferred(&mut self, mark: usize) {
+        for id in self.deferred.split_off(mark) {
+            self.free(&id);
+        }
+    }
+
     pub fn get_addr(&self, id: &str) -> Option<usize> {
         let id = String::from(id);
         for block in self.blocks.iter() {
@@ -162,49 +236,119 @@ impl MemAlloc {
         return None;
     }
 
-    pub fn read(&self, addr: usize, mut size: usize) -> Vec<u8> {
-        let start = addr;
-        let end = addr + size;
-
-        let mut data = Vec::new();
-
-        for block in self.blocks.iter() {
-            if block.free {
+    // the data of a block is only as long as what was written to it, the rest reads as 0
+    pub fn read(&self, addr: usize, size: usize) -> Vec<u8> {
+        let mut data = vec![0u8; size];
+        let mut covered = 0;
+        for block in self.blocks.iter().filter(|b| !b.free) {
+            let max_start = std::cmp::max(addr, block.start);
+            let min_end = std::cmp::min(addr + size, block.end);
+            if max_start >= min_end {
                 continue;
             }
-            let max_start = std::cmp::max(start, block.start);
-            let min_end = std::cmp::min(end, block.end);
-            data.extend(&block.data[(max_start - block.start)..(min_end - block.start)]);
-            size -= min_end - max_start;
-            if size == 0 {
-                break;
+            covered += min_end - max_start;
+            let written = std::cmp::min(min_end, block.start + block.data.len());
+            if max_start < written {
+                data[(max_start - addr)..(written - addr)].copy_from_slice(
+                    &block.data[(max_start - block.start)..(written - block.start)],
+                );
             }
         }
 
-        if size > 0 {
-            panic!("read from unallocated memory");
+        if covered < size {
+            panic!("read from unallocated memory {:#x}", addr);
         }
-        return data;
+        data
     }
 
     pub fn write(&mut self, addr: usize, data: Vec<u8>) {
-        let start = addr;
         let end = addr + data.len();
-        let mut size = data.len();
-
-        for block in self.blocks.iter_mut() {
-            if block.free {
+        let mut covered = 0;
+        for block in self.blocks.iter_mut().filter(|b| !b.free) {
+            let max_start = std::cmp::max(addr, block.start);
+            let min_end = std::cmp::min(end, block.end);
+            if max_start >= min_end {
                 continue;
             }
-            let max_start = std::cmp::max(start, block.start);
-            let min_end = std::cmp::min(end, block.end);
+            if block.data.len() < min_end - block.start {
+                block.data.resize(min_end - block.start, 0);
+            }
             block.data[(max_start - block.start)..(min_end - block.start)]
-                .copy_from_slice(&data[(max_start - start)..(min_end - start)]);
-            size -= min_end - max_start;
+                .copy_from_slice(&data[(max_start - addr)..(min_end - addr)]);
+            covered += min_end - max_start;
         }
 
-        if size > 0 {
-            panic!("write to unallocated memory");
+        if covered < data.len() {
+            panic!("write to unallocated memory {:#x}", addr);
+        }
+    }
+
+    // the allocated block that holds the element at addr, the blocks are sorted by start
+    fn elem_block(&self, addr: usize) -> Option<usize> {
+        let idx = self.blocks.partition_point(|b| b.end <= addr);
+        self.blocks
+            .get(idx)
+            .filter(|b| !b.free && addr + SIZE_F <= b.end)
+            .map(|_| idx)
+    }
+
+    // [addr, addr + size) lies in one allocated block
+    pub fn is_allocated(&self, addr: usize, size: usize) -> bool {
+        let idx = self.blocks.partition_point(|b| b.end <= addr);
+        self.blocks
+            .get(idx)
+            .is_some_and(|b| !b.free && addr + size <= b.end)
+    }
+
+    // the element at addr, 0 if it was never written
+    pub fn read_elem(&self, addr: usize) -> F {
+        let Some(idx) = self.elem_block(addr) else {
+            self.stray_access(addr);
+            return F::ZERO;
+        };
+        let block = &self.blocks[idx];
+        let offset = addr - block.start;
+        let mut bytes = [0u8; SIZE_F];
+        if offset < block.data.len() {
+            let end = min(offset + SIZE_F, block.data.len());
+            bytes[..end - offset].copy_from_slice(&block.data[offset..end]);
+        }
+        F::from_canonical_u64(u64::from_le_bytes(bytes))
+    }
+
+    pub fn write_elem(&mut self, addr: usize, value: F) {
+        let Some(idx) = self.elem_block(addr) else {
+            self.stray_access(addr);
+            return;
+        };
+        let block = &mut self.blocks[idx];
+        let offset = addr - block.start;
+        if block.data.len() < offset + SIZE_F {
+            block.data.resize(offset + SIZE_F, 0);
+        }
+        block.data[offset..offset + SIZE_F]
+            .copy_from_slice(&value.to_canonical_u64().to_le_bytes());
+    }
+
+    // element accesses outside of the allocated blocks are dropped, a write is lost and a
+    // read returns 0
+    fn stray_access(&self, addr: usize) {
+        if self.num_stray_accesses.get() == 0 {
+            warn!("functional access to unallocated memory {:#x}", addr);
+        }
+        self.num_stray_accesses
+            .set(self.num_stray_accesses.get() + 1);
+    }
+
+    pub fn read_elems(&self, addr: usize, num_elems: usize) -> Vec<F> {
+        (0..num_elems)
+            .map(|i| self.read_elem(addr + i * SIZE_F))
+            .collect()
+    }
+
+    pub fn write_elems(&mut self, addr: usize, values: &[F]) {
+        for (i, v) in values.iter().enumerate() {
+            self.write_elem(addr + i * SIZE_F, *v);
         }
     }
 
@@ -232,6 +376,7 @@ impl MemAlloc {
         }
         self.preload_vecs.insert(left, (addr, size * SIZE_F));
         self.num_preload_elems += size;
+        self.record(MemEvent::Preload { addr, size });
     }
     pub fn preloaded(&self, addr: usize) -> bool {
         if self.preload_vecs.len() == 0 {
@@ -266,6 +411,7 @@ impl MemAlloc {
         return false;
     }
     pub fn unpreload(&mut self, addr: usize) {
+        self.record(MemEvent::Unpreload { addr });
         let mut left = 0;
         let mut right = self.preload_vecs.len();
         while left < right {
@@ -288,6 +434,34 @@ impl MemAlloc {
     pub fn clear_preload(&mut self) {
         self.preload_vecs.clear();
         self.num_preload_elems = 0;
+        self.record(MemEvent::ClearPreload);
+    }
+
+    fn record(&mut self, event: MemEvent) {
+        if let Some(events) = self.events.as_mut() {
+            events.push(event);
+        }
+    }
+
+    // redo a recorded event, alloc has to return the recorded address
+    pub fn apply(&mut self, event: &MemEvent) -> Result<()> {
+        match event {
+            MemEvent::Alloc { id, size, addr } => {
+                let new_addr = self.alloc(id, *size);
+                ensure!(
+                    new_addr == Some(*addr),
+                    "block {} allocated at {:?} instead of {}",
+                    id,
+                    new_addr,
+                    addr
+                );
+            }
+            MemEvent::Free { id } => self.free(id),
+            MemEvent::Preload { addr, size } => self.preload(*addr, *size),
+            MemEvent::Unpreload { addr } => self.unpreload(*addr),
+            MemEvent::ClearPreload => self.clear_preload(),
+        }
+        Ok(())
     }
 }
 
@@ -1,14 +1,20 @@
-use crate::config::enable_config::ENABLE_CONFIG;
+use crate::config::HwConfig;
 use crate::starky::proof::StarkOpeningSet;
 use crate::system::system::System;
 
 use crate::kernel::hash_no_pad::{HashNoPad, HashNoPadConfig};
+use crate::kernel::kernel::FetchEdit;
 use crate::kernel::memory_copy::{MemCpy, MemCpyConfig};
 use crate::kernel::tree::Tree;
-use crate::util::{FRI_PROOF_OF_WORK_ROUND, NUM_HASH_OUT_ELTS, SIZE_F, SPONGE_RATE as RATE};
+use crate::util::{
+    FRI_PROOF_OF_WORK_ROUND, NUM_HASH_OUT_ELTS, SIZE_F, SPONGE_RATE as RATE, SPONGE_WIDTH,
+};
 
 use crate::plonk::proofs::OpeningSet;
 
+/// A duplex sponge like plonky2's Challenger. In functional mode output_addr holds the
+/// SPONGE_WIDTH elements of the sponge state, whose first RATE elements are the outputs, taken
+/// from the end; otherwise only the traffic of the sponge is modeled.
 pub struct Challenger {
     pub input_addrs: Vec<usize>,
     pub output_addr: usize,
@@ -24,31 +30,74 @@ impl Challenger {
         }
     }
 
-    pub fn pow(&mut self) -> HashNoPad {
-        let mut hk = HashNoPad::new(HashNoPadConfig {
-            addr_input: self.input_addrs.clone(),
-            addr_output: self.output_addr,
-            input_length: vec![1; RATE],
-            output_length: RATE,
-        });
-        unsafe {
-            if ENABLE_CONFIG.hash {
-                hk.drain.interval = 1.0;
-                hk.write_request.num_lines = vec![FRI_PROOF_OF_WORK_ROUND];
-            }
+    pub fn pow(&mut self, hw: &HwConfig) -> HashNoPad {
+        let mut hk = HashNoPad::new(
+            HashNoPadConfig {
+                addr_input: self.input_addrs.clone(),
+                addr_output: self.output_addr,
+                input_length: vec![1; RATE],
+                output_length: RATE,
+                addr_state: None,
+            },
+            hw,
+        );
+        if hw.enable.hash {
+            hk.edit(FetchEdit::DrainInterval(1.0));
+            hk.edit(FetchEdit::WriteNumLines(vec![FRI_PROOF_OF_WORK_ROUND]));
         }
         hk
     }
 
+    // the sponge state buffer that output_addr points to
+    pub fn buffer_size(functional: bool) -> usize {
+        if functional {
+            SPONGE_WIDTH * SIZE_F
+        } else {
+            RATE * SIZE_F
+        }
+    }
+
+    fn duplexing(&mut self, sys: &mut System) {
+        // without inputs the state is permuted as it is, by absorbing its own outputs
+        if self.input_addrs.is_empty() {
+            self.input_addrs
+                .extend((0..RATE).map(|i| self.output_addr + i * SIZE_F));
+        }
+        let kernel = HashNoPad::new(
+            HashNoPadConfig {
+                input_length: vec![1; self.input_addrs.len()],
+                addr_input: std::mem::take(&mut self.input_addrs),
+                addr_output: self.output_addr,
+                output_length: RATE,
+                addr_state: Some(self.output_addr),
+            },
+            &sys.hw,
+        );
+        sys.run_once(&kernel);
+        self.output_buffer_length = RATE;
+    }
+
     pub fn observe_element(&mut self, sys: &mut System, addr: usize) {
+        if sys.functional {
+            // the outputs don't reflect this input
+            self.output_buffer_length = 0;
+        }
         self.input_addrs.push(addr);
         if self.input_addrs.len() == RATE {
-            let kernel = HashNoPad::new(HashNoPadConfig {
-                addr_input: self.input_addrs.clone(),
-                addr_output: self.output_addr,
-                input_length: vec![1; RATE],
-                output_length: RATE,
-            });
+            if sys.functional {
+                self.duplexing(sys);
+                return;
+            }
+            let kernel = HashNoPad::new(
+                HashNoPadConfig {
+                    addr_input: self.input_addrs.clone(),
+                    addr_output: self.output_addr,
+                    input_length: vec![1; RATE],
+                    output_length: RATE,
+                    addr_state: None,
+                },
+                &sys.hw,
+            );
             sys.run_once(&kernel);
             self.input_addrs.clear();
             self.output_buffer_length = RATE;
@@ -72,16 +121,24 @@ impl Challenger {
     }
 
     pub fn get_challenge(&mut self, sys: &mut System, addr: usize) {
-        if self.output_buffer_length == 0 {
+        if sys.functional {
+            if !self.input_addrs.is_empty() || self.output_buffer_length == 0 {
+                self.duplexing(sys);
+            }
+        } else if self.output_buffer_length == 0 {
             if self.input_addrs.is_empty() {
                 self.input_addrs.extend(vec![0; RATE]);
             }
-            let kernel = HashNoPad::new(HashNoPadConfig {
-                addr_input: self.input_addrs.clone(),
-                addr_output: self.output_addr,
-                input_length: vec![1; RATE],
-                output_length: RATE,
-            });
+            let kernel = HashNoPad::new(
+                HashNoPadConfig {
+                    addr_input: self.input_addrs.clone(),
+                    addr_output: self.output_addr,
+                    input_length: vec![1; RATE],
+                    output_length: RATE,
+                    addr_state: None,
+                },
+                &sys.hw,
+            );
             sys.run_once(&kernel);
             self.input_addrs.clear();
             self.output_buffer_length = RATE;
@@ -93,14 +150,18 @@ impl Challenger {
                 addr_output: addr,
                 input_length: 1,
             },
-            unsafe { ENABLE_CONFIG.hash },
+            sys.hw.enable.hash,
+            &sys.hw,
         );
-        kernel.prefetch.addr.clear();
+        kernel.edit(FetchEdit::ClearPrefetchAddr);
         self.output_buffer_length -= 1;
         sys.run_once(&kernel);
     }
 
     pub fn get_n_challenges(&mut self, sys: &mut System, addr: usize, n: usize) {
+        if sys.functional {
+            return self.get_n_challenges_functional(sys, addr, n);
+        }
         if self.output_buffer_length >= n {
             let mut kernel = MemCpy::new(
                 MemCpyConfig {
@@ -108,10 +169,11 @@ impl Challenger {
                     addr_output: addr,
                     input_length: n,
                 },
-                unsafe { ENABLE_CONFIG.hash },
+                sys.hw.enable.hash,
+                &sys.hw,
             );
-            kernel.prefetch.clear();
-            kernel.read_request.clear();
+            kernel.edit(FetchEdit::ClearPrefetch);
+            kernel.edit(FetchEdit::ClearReadRequest);
             self.output_buffer_length -= n;
             sys.run_once(&kernel);
         } else {
@@ -121,6 +183,38 @@ impl Challenger {
         }
     }
 
+    fn get_n_challenges_functional(&mut self, sys: &mut System, addr: usize, n: usize) {
+        if !self.input_addrs.is_empty() || self.output_buffer_length == 0 {
+            self.duplexing(sys);
+        }
+        if self.output_buffer_length >= n {
+            // popped one by one, so in the reverse order of the outputs
+            let kernels = (0..n)
+                .map(|i| {
+                    let mut kernel = MemCpy::new(
+                        MemCpyConfig {
+                            addr_input: self.output_addr
+                                + (self.output_buffer_length - 1 - i) * SIZE_F,
+                            addr_output: addr + i * SIZE_F,
+                            input_length: 1,
+                        },
+                        sys.hw.enable.hash,
+                        &sys.hw,
+                    );
+                    kernel.edit(FetchEdit::ClearPrefetch);
+                    kernel.edit(FetchEdit::ClearReadRequest);
+                    kernel
+                })
+                .collect();
+            self.output_buffer_length -= n;
+            sys.run_vec(kernels);
+        } else {
+            for i in 0..n {
+                self.get_challenge(sys, addr + i * SIZE_F);
+            }
+        }
+    }
+
     pub fn observe_extension_element<const D: usize>(&mut self, sys: &mut System, addr: usize) {
         for i in 0..D {
             self.observe_element(sys, addr + i * SIZE_F);
@@ -163,11 +257,26 @@ impl Challenger {
             openings.addr_quotient_polys,
             openings.quotient_polys_length,
         );
+        // plonky2 observes the lookup openings at the end of each batch
+        if sys.functional {
+            self.observe_extension_elements::<D>(
+                sys,
+                openings.addr_lookup_zs,
+                openings.lookup_zs_length,
+            );
+        }
         self.observe_extension_elements::<D>(
             sys,
             openings.addr_plonk_zs_next,
             openings.plonk_zs_next_length,
         );
+        if sys.functional {
+            self.observe_extension_elements::<D>(
+                sys,
+                openings.addr_lookup_zs_next,
+                openings.lookup_zs_next_length,
+            );
+        }
     }
     pub fn observe_stark_openings<const D: usize>(
         &mut self,
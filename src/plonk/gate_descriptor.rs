@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use plonky2::field::extension::Extendable;
+use plonky2::gates::arithmetic_base::ArithmeticGate;
+use plonky2::gates::arithmetic_extension::ArithmeticExtensionGate;
+use plonky2::gates::base_sum::BaseSumGate;
+use plonky2::gates::constant::ConstantGate;
+use plonky2::gates::coset_interpolation::CosetInterpolationGate;
+use plonky2::gates::exponentiation::ExponentiationGate;
+use plonky2::gates::gate::GateRef;
+use plonky2::gates::multiplication_extension::MulExtensionGate;
+use plonky2::gates::noop::NoopGate;
+use plonky2::gates::poseidon::PoseidonGate;
+use plonky2::gates::poseidon_mds::PoseidonMdsGate;
+use plonky2::gates::public_input::PublicInputGate;
+use plonky2::gates::random_access::RandomAccessGate;
+use plonky2::gates::reducing::ReducingGate;
+use plonky2::gates::reducing_extension::ReducingExtensionGate;
+use plonky2::hash::hash_types::RichField;
+
+use crate::util::B;
+
+// The gates with a hand-written port in plonk::gates, with the parameters the port needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GateDescriptor {
+    Arithmetic {
+        num_ops: usize,
+    },
+    BaseSum {
+        num_limbs: usize,
+    },
+    Constant {
+        num_consts: usize,
+    },
+    Exponentiation {
+        num_power_bits: usize,
+    },
+    PublicInput,
+    RandomAccess {
+        bits: usize,
+        num_copies: usize,
+        num_extra_constants: usize,
+    },
+    Poseidon,
+    Noop,
+    U32AddMany {
+        num_addends: usize,
+        num_ops: usize,
+    },
+    U32RangeCheck {
+        num_input_limbs: usize,
+    },
+    Comparison {
+        num_bits: usize,
+        num_chunks: usize,
+    },
+    U32Arithmetic {
+        num_ops: usize,
+    },
+    U32Subtraction {
+        num_ops: usize,
+    },
+    PoseidonMds,
+    LowDegreeInterpolation {
+        subgroup_bits: usize,
+    },
+    MulExtension {
+        num_ops: usize,
+    },
+    ArithmeticExtension {
+        num_ops: usize,
+    },
+    ReducingExtension {
+        num_coeffs: usize,
+    },
+    Reducing {
+        num_coeffs: usize,
+    },
+    CosetInterpolation {
+        subgroup_bits: usize,
+        degree: usize,
+    },
+}
+
+impl GateDescriptor {
+    // The gates of plonky2 are downcast. The gates of other crates, e.g. the u32 gates, are
+    // not types of this crate's plonky2, so their fields are read from their Debug id.
+    pub fn of<F: RichField + Extendable<D>, const D: usize>(
+        gate_ref: &GateRef<F, D>,
+    ) -> Result<Self> {
+        let gate = gate_ref.0.as_any();
+        let descriptor = if let Some(gate) = gate.downcast_ref::<ArithmeticGate>() {
+            Self::Arithmetic {
+                num_ops: gate.num_ops,
+            }
+        } else if let Some(gate) = gate.downcast_ref::<BaseSumGate<B>>() {
+            Self::BaseSum {
+                num_limbs: gate.num_limbs,
+            }
+        } else if gate.is::<ConstantGate>() {
+            Self::Constant {
+                num_consts: gate_ref.0.num_constants(),
+            }
+        } else if let Some(gate) = gate.downcast_ref::<ExponentiationGate<F, D>>() {
+            Self::Exponentiation {
+                num_power_bits: gate.num_power_bits,
+            }
+        } else if gate.is::<PublicInputGate>() {
+            Self::PublicInput
+        } else if let Some(gate) = gate.downcast_ref::<RandomAccessGate<F, D>>() {
+            Self::RandomAccess {
+                bits: gate.bits,
+                num_copies: gate.num_copies,
+                num_extra_constants: gate.num_extra_constants,
+            }
+        } else if gate.is::<PoseidonGate<F, D>>() {
+            Self::Poseidon
+        } else if gate.is::<NoopGate>() {
+            Self::Noop
+        } else if gate.is::<PoseidonMdsGate<F, D>>() {
+            Self::PoseidonMds
+        } else if let Some(gate) = gate.downcast_ref::<MulExtensionGate<D>>() {
+            Self::MulExtension {
+                num_ops: gate.num_ops,
+            }
+        } else if let Some(gate) = gate.downcast_ref::<ArithmeticExtensionGate<D>>() {
+            Self::ArithmeticExtension {
+                num_ops: gate.num_ops,
+            }
+        } else if let Some(gate) = gate.downcast_ref::<ReducingExtensionGate<D>>() {
+            Self::ReducingExtension {
+                num_coeffs: gate.num_coeffs,
+            }
+        } else if let Some(gate) = gate.downcast_ref::<ReducingGate<D>>() {
+            Self::Reducing {
+                num_coeffs: gate.num_coeffs,
+            }
+        } else if let Some(gate) = gate.downcast_ref::<CosetInterpolationGate<F, D>>() {
+            Self::CosetInterpolation {
+                subgroup_bits: gate.subgroup_bits,
+                degree: gate.degree,
+            }
+        } else {
+            return Self::from_id(&gate_ref.0.id());
+        };
+        Ok(descriptor)
+    }
+
+    // the name of the gate type, without its parameters
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Arithmetic { .. } => "ArithmeticGate",
+            Self::BaseSum { .. } => "BaseSumGate",
+            Self::Constant { .. } => "ConstantGate",
+            Self::Exponentiation { .. } => "ExponentiationGate",
+            Self::PublicInput => "PublicInputGate",
+            Self::RandomAccess { .. } => "RandomAccessGate",
+            Self::Poseidon => "PoseidonGate",
+            Self::Noop => "NoopGate",
+            Self::U32AddMany { .. } => "U32AddManyGate",
+            Self::U32RangeCheck { .. } => "U32RangeCheckGate",
+            Self::Comparison { .. } => "ComparisonGate",
+            Self::U32Arithmetic { .. } => "U32ArithmeticGate",
+            Self::U32Subtraction { .. } => "U32SubtractionGate",
+            Self::PoseidonMds => "PoseidonMdsGate",
+            Self::LowDegreeInterpolation { .. } => "LowDegreeInterpolationGate",
+            Self::MulExtension { .. } => "MulExtensionGate",
+            Self::ArithmeticExtension { .. } => "ArithmeticExtensionGate",
+            Self::ReducingExtension { .. } => "ReducingExtensionGate",
+            Self::Reducing { .. } => "ReducingGate",
+            Self::CosetInterpolation { .. } => "CosetInterpolationGate",
+        }
+    }
+
+    fn from_id(id: &str) -> Result<Self> {
+        let (name, fields) = id_fields(id);
+        let param = |param: &str| -> Result<usize> {
+            let value = fields
+                .get(param)
+                .ok_or_else(|| anyhow!("gate {} has no parameter {}", id, param))?;
+            value
+                .parse()
+                .with_context(|| format!("parameter {} of gate {} is {}", param, id, value))
+        };
+        Ok(match name {
+            "U32AddManyGate" => Self::U32AddMany {
+                num_addends: param("num_addends")?,
+                num_ops: param("num_ops")?,
+            },
+            "U32RangeCheckGate" => Self::U32RangeCheck {
+                num_input_limbs: param("num_input_limbs")?,
+            },
+            "ComparisonGate" => Self::Comparison {
+                num_bits: param("num_bits")?,
+                num_chunks: param("num_chunks")?,
+            },
+            "U32ArithmeticGate" => Self::U32Arithmetic {
+                num_ops: param("num_ops")?,
+            },
+            "U32SubtractionGate" => Self::U32Subtraction {
+                num_ops: param("num_ops")?,
+            },
+            "LowDegreeInterpolationGate" => Self::LowDegreeInterpolation {
+                subgroup_bits: param("subgroup_bits")?,
+            },
+            _ => return Err(anyhow!("gate {} has no hand-written port", id)),
+        })
+    }
+}
+
+// "Name { a: 1, b: [2, 3] }" is "Name" with the fields a and b
+fn id_fields(id: &str) -> (&str, HashMap<&str, &str>) {
+    let Some((name, body)) = id.split_once('{') else {
+        return (id.trim(), HashMap::new());
+    };
+    let body = body.trim_end();
+    let body = body.strip_suffix('}').unwrap_or(body);
+    let mut fields = HashMap::new();
+    let (mut depth, mut start) = (0i32, 0);
+    for (i, c) in body.char_indices().chain([(body.len(), ',')]) {
+        match c {
+            '(' | '[' | '{' | '<' => depth += 1,
+            ')' | ']' | '}' | '>' => depth -= 1,
+            ',' if depth == 0 => {
+                if let Some((key, value)) = body[start..i].split_once(':') {
+                    fields.insert(key.trim(), value.trim());
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    (name.trim(), fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plonky2::field::goldilocks_field::GoldilocksField as F;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+
+    #[test]
+    fn test_gate_descriptor() {
+        let config = CircuitConfig::standard_recursion_config();
+        let random_access = RandomAccessGate::<F, 2>::new_from_config(&config, 4);
+        let gate = GateRef::new(random_access.clone());
+        assert_eq!(
+            GateDescriptor::of(&gate).unwrap(),
+            GateDescriptor::RandomAccess {
+                bits: 4,
+                num_copies: random_access.num_copies,
+                num_extra_constants: random_access.num_extra_constants,
+            }
+        );
+        assert_eq!(
+            GateDescriptor::of(&gate).unwrap().name(),
+            "RandomAccessGate"
+        );
+        let gate = GateRef::<F, 2>::new(ConstantGate::new(2));
+        assert_eq!(
+            GateDescriptor::of(&gate).unwrap(),
+            GateDescriptor::Constant { num_consts: 2 }
+        );
+
+        // num_ops is not a prefix match of num_opsx, nor of a nested field
+        let id = "U32AddManyGate { num_addends: 3, num_opsx: [1, 2], num_ops: 5, \
+                  _phantom: PhantomData<(F, G)> }";
+        assert_eq!(
+            GateDescriptor::from_id(id).unwrap(),
+            GateDescriptor::U32AddMany {
+                num_addends: 3,
+                num_ops: 5
+            }
+        );
+        let err = GateDescriptor::from_id("U32ArithmeticGate { num_op: 3 }").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "gate U32ArithmeticGate { num_op: 3 } has no parameter num_ops"
+        );
+        assert!(GateDescriptor::from_id("CustomGate").is_err());
+    }
+}
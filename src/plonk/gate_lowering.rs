@@ -0,0 +1,467 @@
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::iter::{Product, Sum};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use num::BigUint;
+use plonky2::field::extension::{Extendable, Frobenius};
+use plonky2::field::goldilocks_field::GoldilocksField as F;
+use plonky2::field::packed::PackedField;
+use plonky2::field::types::{Field, Field64, PrimeField, PrimeField64, Sample};
+use plonky2::gates::arithmetic_base::ArithmeticGate;
+use plonky2::gates::base_sum::BaseSumGate;
+use plonky2::gates::constant::ConstantGate;
+use plonky2::gates::exponentiation::ExponentiationGate;
+use plonky2::gates::gate::{Gate, GateRef};
+use plonky2::gates::lookup::LookupGate;
+use plonky2::gates::lookup_table::LookupTableGate;
+use plonky2::gates::packed_util::PackedEvaluableBase;
+use plonky2::gates::poseidon::PoseidonGate;
+use plonky2::gates::public_input::PublicInputGate;
+use plonky2::gates::random_access::RandomAccessGate;
+use plonky2::gates::util::StridedConstraintConsumer;
+use plonky2::hash::hash_types::{HashOut, RichField};
+use plonky2::hash::poseidon::{Poseidon, N_PARTIAL_ROUNDS, SPONGE_WIDTH};
+use plonky2::plonk::vars::{EvaluationVarsBase, EvaluationVarsBasePacked};
+use plonky2::util::strided_view::PackedStridedView;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::kernel::vec_builder::VecBuilder;
+use crate::kernel::vector_operation::VecOpConfig;
+use crate::memory::memory_allocator::MemAlloc;
+use crate::plonk::vars::EvaluationVarsBaseBatch;
+use crate::starky::lowering::{
+    as_scalars, as_scalars_mut, input, reset, scalar_input, Input, Program, Sym,
+};
+use crate::util::SIZE_F;
+
+// Runs the packed evaluation of a gate once on symbolic values and lowers the recorded
+// constraints to the vector ops of each batch, like a hand-written gate in plonk::gates.
+pub struct LoweredGate {
+    program: Program,
+}
+
+impl LoweredGate {
+    pub fn new<G, const D: usize>(gate: &G) -> Self
+    where
+        F: Extendable<D>,
+        G: PackedEvaluableBase<F, D>,
+    {
+        reset();
+        let local_constants = (0..gate.num_constants())
+            .map(|i| input(Input::GateConstant(i)))
+            .collect::<Vec<_>>();
+        let local_wires = (0..gate.num_wires())
+            .map(|i| input(Input::Wire(i)))
+            .collect::<Vec<_>>();
+        let public_inputs_hash = HashOut {
+            elements: [0, 1, 2, 3].map(|i| scalar_input(Input::PublicInputsHash(i))),
+        };
+        let mut constraints = vec![Sym::ZEROS; gate.num_constraints()];
+
+        let vars = EvaluationVarsBasePacked::<Sym> {
+            local_constants: PackedStridedView::new(as_scalars(&local_constants), 1, 0),
+            local_wires: PackedStridedView::new(as_scalars(&local_wires), 1, 0),
+            public_inputs_hash: &public_inputs_hash,
+        };
+        let consumer =
+            StridedConstraintConsumer::<Sym>::new(as_scalars_mut(&mut constraints), 1, 0);
+        gate.eval_unfiltered_base_packed(vars, consumer);
+
+        Self {
+            program: Program::new(constraints),
+        }
+    }
+
+    // Runs the scalar evaluation of a gate once on symbolic values instead, for a gate
+    // without a packed evaluation whose evaluation is generic in the field, like PoseidonGate
+    fn new_scalar<G: Gate<SymField, 1>>(gate: &G) -> Self {
+        reset();
+        let local_constants = (0..gate.num_constants())
+            .map(|i| SymField(input(Input::GateConstant(i))))
+            .collect::<Vec<_>>();
+        let local_wires = (0..gate.num_wires())
+            .map(|i| SymField(input(Input::Wire(i))))
+            .collect::<Vec<_>>();
+        let public_inputs_hash = HashOut {
+            elements: [0, 1, 2, 3].map(|i| SymField(input(Input::PublicInputsHash(i)))),
+        };
+        let mut constraints = vec![SymField::ZERO; gate.num_constraints()];
+
+        let vars = EvaluationVarsBase {
+            local_constants: PackedStridedView::new(&local_constants, 1, 0),
+            local_wires: PackedStridedView::new(&local_wires, 1, 0),
+            public_inputs_hash: &public_inputs_hash,
+        };
+        let consumer = StridedConstraintConsumer::new(&mut constraints, 1, 0);
+        gate.eval_unfiltered_base_one(vars, consumer);
+
+        Self {
+            program: Program::new(constraints.into_iter().map(|c| c.0).collect()),
+        }
+    }
+
+    pub fn num_ops(&self) -> usize {
+        self.program.num_ops()
+    }
+
+    // constraint j of the batch goes to addr_res_batch + j * batch_size
+    pub fn eval(
+        &self,
+        mem: &mut MemAlloc,
+        vars_batch: EvaluationVarsBaseBatch,
+        addr_res_batch: usize,
+        rich_isa: bool,
+    ) -> Vec<VecOpConfig> {
+        let len = vars_batch.len();
+        let addr_constraints = (0..self.program.num_roots())
+            .map(|j| addr_res_batch + j * len * SIZE_F)
+            .collect::<Vec<_>>();
+        let mut b = VecBuilder::new(mem, len).with_rich_isa(rich_isa);
+        let input = |b: &VecBuilder, input| match input {
+            Input::Wire(j) => b.column(vars_batch.addr_local_wires, j),
+            Input::GateConstant(j) => b.column(vars_batch.addr_local_constants, j),
+            Input::PublicInputsHash(j) => b.scalar_at(vars_batch.addr_public_inputs_hash, j),
+            _ => unreachable!("{:?} is not an input of a gate", input),
+        };
+        self.program.eval(&mut b, input, &addr_constraints);
+        for addr in addr_constraints {
+            b.set_final(b.vector(addr));
+        }
+        b.finish()
+    }
+}
+
+// A Sym as a field of its own, for the scalar evaluation of the gates in
+// LoweredGate::new_scalar. The ring operations and the constants are recorded; inverses,
+// canonical values and random values have no symbolic value and panic.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct SymField(Sym);
+
+macro_rules! impl_op {
+    ($op:ident, $op_fn:ident, $op_assign:ident, $op_assign_fn:ident) => {
+        impl $op for SymField {
+            type Output = SymField;
+            fn $op_fn(self, rhs: SymField) -> SymField {
+                SymField(self.0.$op_fn(rhs.0))
+            }
+        }
+
+        impl $op_assign for SymField {
+            fn $op_assign_fn(&mut self, rhs: SymField) {
+                self.0.$op_assign_fn(rhs.0);
+            }
+        }
+    };
+}
+
+impl_op!(Add, add, AddAssign, add_assign);
+impl_op!(Sub, sub, SubAssign, sub_assign);
+impl_op!(Mul, mul, MulAssign, mul_assign);
+
+impl Div for SymField {
+    type Output = SymField;
+    fn div(self, _rhs: SymField) -> SymField {
+        unimplemented!("a division by a symbolic value is not recorded")
+    }
+}
+
+impl DivAssign for SymField {
+    fn div_assign(&mut self, rhs: SymField) {
+        *self = *self / rhs;
+    }
+}
+
+impl Neg for SymField {
+    type Output = SymField;
+    fn neg(self) -> SymField {
+        SymField(-self.0)
+    }
+}
+
+impl Sum for SymField {
+    fn sum<I: Iterator<Item = SymField>>(iter: I) -> SymField {
+        SymField(iter.map(|x| x.0).sum())
+    }
+}
+
+impl Product for SymField {
+    fn product<I: Iterator<Item = SymField>>(iter: I) -> SymField {
+        SymField(iter.map(|x| x.0).product())
+    }
+}
+
+impl Display for SymField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl Sample for SymField {
+    fn sample<R>(_rng: &mut R) -> Self
+    where
+        R: rand::RngCore + ?Sized,
+    {
+        unimplemented!("a symbolic value is not sampled")
+    }
+}
+
+impl Serialize for SymField {
+    fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom(
+            "a symbolic value is not serialized",
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for SymField {
+    fn deserialize<De: Deserializer<'de>>(_deserializer: De) -> Result<Self, De::Error> {
+        Err(serde::de::Error::custom(
+            "a symbolic value is not deserialized",
+        ))
+    }
+}
+
+impl From<F> for SymField {
+    fn from(value: F) -> Self {
+        SymField(Sym::from(value))
+    }
+}
+
+impl Field for SymField {
+    const ZERO: Self = SymField(Sym::ZEROS);
+    const ONE: Self = SymField(Sym::ONES);
+    const TWO: Self = SymField(Sym::TWO);
+    const NEG_ONE: Self = SymField(Sym::NEG_ONE);
+    const TWO_ADICITY: usize = F::TWO_ADICITY;
+    const CHARACTERISTIC_TWO_ADICITY: usize = F::CHARACTERISTIC_TWO_ADICITY;
+    const MULTIPLICATIVE_GROUP_GENERATOR: Self = SymField(Sym::MULTIPLICATIVE_GROUP_GENERATOR);
+    const POWER_OF_TWO_GENERATOR: Self = SymField(Sym::POWER_OF_TWO_GENERATOR);
+    const BITS: usize = F::BITS;
+
+    fn order() -> BigUint {
+        F::order()
+    }
+    fn characteristic() -> BigUint {
+        F::characteristic()
+    }
+
+    fn try_inverse(&self) -> Option<Self> {
+        unimplemented!("the inverse of a symbolic value is not recorded")
+    }
+
+    fn from_noncanonical_biguint(n: BigUint) -> Self {
+        F::from_noncanonical_biguint(n).into()
+    }
+    fn from_canonical_u64(n: u64) -> Self {
+        F::from_canonical_u64(n).into()
+    }
+    fn from_noncanonical_u128(n: u128) -> Self {
+        F::from_noncanonical_u128(n).into()
+    }
+    fn from_noncanonical_u64(n: u64) -> Self {
+        F::from_noncanonical_u64(n).into()
+    }
+    fn from_noncanonical_i64(n: i64) -> Self {
+        F::from_noncanonical_i64(n).into()
+    }
+}
+
+impl Field64 for SymField {
+    const ORDER: u64 = F::ORDER;
+}
+
+impl PrimeField for SymField {
+    fn to_canonical_biguint(&self) -> BigUint {
+        unimplemented!("a symbolic value has no canonical value")
+    }
+}
+
+impl PrimeField64 for SymField {
+    fn to_canonical_u64(&self) -> u64 {
+        unimplemented!("a symbolic value has no canonical value")
+    }
+    fn to_noncanonical_u64(&self) -> u64 {
+        unimplemented!("a symbolic value has no canonical value")
+    }
+}
+
+impl Frobenius<1> for SymField {}
+
+// The constants of GoldilocksField. The layers that reduce the canonical values by default
+// take the ones for field extensions, which only need the ring operations.
+impl Poseidon for SymField {
+    const MDS_MATRIX_CIRC: [u64; SPONGE_WIDTH] = <F as Poseidon>::MDS_MATRIX_CIRC;
+    const MDS_MATRIX_DIAG: [u64; SPONGE_WIDTH] = <F as Poseidon>::MDS_MATRIX_DIAG;
+    const FAST_PARTIAL_FIRST_ROUND_CONSTANT: [u64; SPONGE_WIDTH] =
+        <F as Poseidon>::FAST_PARTIAL_FIRST_ROUND_CONSTANT;
+    const FAST_PARTIAL_ROUND_CONSTANTS: [u64; N_PARTIAL_ROUNDS] =
+        <F as Poseidon>::FAST_PARTIAL_ROUND_CONSTANTS;
+    const FAST_PARTIAL_ROUND_VS: [[u64; SPONGE_WIDTH - 1]; N_PARTIAL_ROUNDS] =
+        <F as Poseidon>::FAST_PARTIAL_ROUND_VS;
+    const FAST_PARTIAL_ROUND_W_HATS: [[u64; SPONGE_WIDTH - 1]; N_PARTIAL_ROUNDS] =
+        <F as Poseidon>::FAST_PARTIAL_ROUND_W_HATS;
+    const FAST_PARTIAL_ROUND_INITIAL_MATRIX: [[u64; SPONGE_WIDTH - 1]; SPONGE_WIDTH - 1] =
+        <F as Poseidon>::FAST_PARTIAL_ROUND_INITIAL_MATRIX;
+
+    fn mds_layer(state: &[Self; SPONGE_WIDTH]) -> [Self; SPONGE_WIDTH] {
+        Self::mds_layer_field::<Self, 1>(state)
+    }
+
+    fn mds_partial_layer_fast(state: &[Self; SPONGE_WIDTH], r: usize) -> [Self; SPONGE_WIDTH] {
+        Self::mds_partial_layer_fast_field::<Self, 1>(state, r)
+    }
+
+    fn constant_layer(state: &mut [Self; SPONGE_WIDTH], round_ctr: usize) {
+        Self::constant_layer_field::<Self, 1>(state, round_ctr)
+    }
+}
+
+impl RichField for SymField {}
+
+type Recorder = fn(&dyn Any) -> LoweredGate;
+
+// The gates are recorded over GoldilocksField, for the extension degree they are registered
+// with
+#[derive(Default)]
+struct Registry {
+    recorders: HashMap<(TypeId, usize), Recorder>, // by gate type and D
+    lowered: HashMap<(String, usize), Arc<LoweredGate>>, // by gate id and D
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut registry = Registry::default();
+        registry.add::<ArithmeticGate, 2>();
+        registry.add::<BaseSumGate<2>, 2>();
+        registry.add::<ConstantGate, 2>();
+        registry.add::<ExponentiationGate<F, 2>, 2>();
+        registry.add::<LookupGate, 2>();
+        registry.add::<LookupTableGate, 2>();
+        registry.add::<PublicInputGate, 2>();
+        registry.add::<RandomAccessGate<F, 2>, 2>();
+        registry
+            .recorders
+            .insert((TypeId::of::<PoseidonGate<F, 2>>(), 2), |_| {
+                LoweredGate::new_scalar(&PoseidonGate::<SymField, 1>::new())
+            });
+        Mutex::new(registry)
+    })
+}
+
+impl Registry {
+    fn add<G, const D: usize>(&mut self)
+    where
+        F: Extendable<D>,
+        G: PackedEvaluableBase<F, D>,
+    {
+        self.recorders.insert((TypeId::of::<G>(), D), |gate| {
+            LoweredGate::new(gate.downcast_ref::<G>().unwrap())
+        });
+    }
+}
+
+// Makes a gate type with a packed evaluation, e.g. a custom gate of another crate, lowerable
+// without hand-written vector ops. The gates of plonky2 that have one are registered for D = 2.
+pub fn register<G, const D: usize>()
+where
+    F: Extendable<D>,
+    G: PackedEvaluableBase<F, D>,
+{
+    registry().lock().unwrap().add::<G, D>();
+}
+
+// the lowering of a registered gate type, recorded once for each gate id. A gate type that is
+// registered for D but used over another field than GoldilocksField panics.
+pub fn lowered<T: RichField + Extendable<D>, const D: usize>(
+    gate_ref: &GateRef<T, D>,
+) -> Option<Arc<LoweredGate>> {
+    let gate = gate_ref.0.as_any();
+    let mut registry = registry().lock().unwrap();
+    let recorder = *registry.recorders.get(&(gate.type_id(), D))?;
+    assert_eq!(
+        TypeId::of::<T>(),
+        TypeId::of::<F>(),
+        "{} is lowered over GoldilocksField, not {}",
+        gate_ref.0.id(),
+        type_name::<T>()
+    );
+    let lowered = registry
+        .lowered
+        .entry((gate_ref.0.id(), D))
+        .or_insert_with(|| Arc::new(recorder(gate)));
+    Some(lowered.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HwConfig;
+    use crate::kernel::kernel::Kernel;
+    use crate::kernel::vector_chain::VectorChain;
+    use crate::util::BATCH_SIZE;
+    use plonky2::field::types::Sample;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::vars::EvaluationVarsBaseBatch as Plonky2VarsBatch;
+
+    #[test]
+    fn test_lowered_gate() {
+        let config = CircuitConfig::standard_recursion_config();
+        let gates: [GateRef<F, 2>; 7] = [
+            GateRef::new(ArithmeticGate::new_from_config(&config)),
+            GateRef::new(BaseSumGate::<2>::new(8)),
+            GateRef::new(ConstantGate::new(2)),
+            GateRef::new(ExponentiationGate::new_from_config(&config)),
+            GateRef::new(PoseidonGate::new()),
+            GateRef::new(PublicInputGate),
+            GateRef::new(RandomAccessGate::new_from_config(&config, 4)),
+        ];
+        for gate in gates {
+            let lowered = lowered(&gate).unwrap();
+            let gate = &gate.0;
+            assert!(lowered.num_ops() > 0);
+
+            let n = BATCH_SIZE;
+            let constants = F::rand_vec(gate.num_constants() * n);
+            let wires = F::rand_vec(gate.num_wires() * n);
+            let hash = HashOut::<F>::rand();
+            let mut mem = MemAlloc::new(1, 4096);
+            let mut alloc = |name: &str, len: usize, values: &[F]| {
+                let addr = mem.alloc(name, len * SIZE_F).unwrap();
+                mem.write_elems(addr, values);
+                addr
+            };
+            let addr_constants = alloc("constants", constants.len(), &constants);
+            let addr_wires = alloc("wires", wires.len(), &wires);
+            let addr_hash = alloc("public_inputs_hash", 4, &hash.elements);
+            let addr_res = alloc("res_batch", gate.num_constraints() * n, &[]);
+            let vars = EvaluationVarsBaseBatch::new(n, addr_constants, addr_wires, addr_hash);
+
+            let vec_ops = lowered.eval(&mut mem, vars, addr_res, false);
+            VectorChain::new(vec_ops, &mem, &HwConfig::default()).execute(&mut mem);
+            mem.free_deferred(0);
+
+            let expected = gate
+                .eval_unfiltered_base_batch(Plonky2VarsBatch::new(n, &constants, &wires, &hash));
+            assert_eq!(
+                mem.read_elems(addr_res, gate.num_constraints() * n),
+                expected,
+                "{}",
+                gate.id()
+            );
+        }
+    }
+
+    // a gate type is lowered for the degrees it is registered with
+    #[test]
+    fn test_lowered_degree() {
+        let config = CircuitConfig::standard_recursion_config();
+        let gate = GateRef::<F, 4>::new(ArithmeticGate::new_from_config(&config));
+        assert!(lowered(&gate).is_none());
+        register::<ArithmeticGate, 4>();
+        assert!(lowered(&gate).unwrap().num_ops() > 0);
+    }
+}
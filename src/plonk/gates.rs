@@ -1,8 +1,11 @@
-use log::debug;
+use log::{debug, warn};
 use plonky2::gates::gate::GateRef;
 
+use crate::kernel::vec_builder::VecBuilder;
 use crate::kernel::vector_operation::{VecOpConfig, VecOpExtension, VecOpSrc, VecOpType};
 use crate::memory::memory_allocator::MemAlloc;
+use crate::plonk::gate_descriptor::GateDescriptor;
+use crate::plonk::gate_lowering::LoweredGate;
 use crate::plonk::vars::EvaluationVarsBaseBatch;
 use crate::system::system::System;
 use crate::util::{ceil_div_usize, B, SIZE_F, SPONGE_WIDTH};
@@ -21,218 +24,270 @@ pub fn eval_filtered_base_batch<F: RichField + Extendable<D>, const D: usize>(
     num_selectors: usize,
     num_lookup_selectors: usize,
     addr_res_batch: usize,
+    lowered: Option<&LoweredGate>, // from gate_lowering::lowered, looked up once per gate
 ) -> Vec<VecOpConfig> {
     let mut _start = Instant::now();
-    let addr_sub = sys.mem.alloc("sub", vars_batch.len() * SIZE_F).unwrap();
-    let addr_filters = sys.mem.alloc("filters", vars_batch.len() * SIZE_F).unwrap();
-    let mut vec_ops = Vec::new();
+    let n = vars_batch.len();
 
     let gate = &gate_ref.0;
-
-    for i in 0..group_range.len() {
-        if i != row {
-            vec_ops.push(VecOpConfig {
-                vector_length: vars_batch.len(),
-                addr_input_0: vars_batch.addr_local_constants
-                    + selector_index * vars_batch.len() * SIZE_F,
-                addr_input_1: addr_group_range + i * SIZE_F,
-                addr_output: addr_sub,
-                op_type: VecOpType::SUB,
-                op_src: VecOpSrc::VS,
-                is_final_output: false,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: vars_batch.len(),
-                addr_input_0: addr_sub,
-                addr_input_1: addr_filters,
-                addr_output: addr_filters,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-                is_final_output: i == group_range.len() - 1,
+    let rich_isa = sys.hw.arch.rich_vec_isa;
+
+    let functional = sys.functional;
+    let (mut vec_ops, addr_filter) = if functional {
+        // compute_filter: the product of i - s over the other rows i of the group, and
+        // UNUSED_SELECTOR - s with several selectors, the last value of addr_group_range
+        let mut b = VecBuilder::new(&mut sys.mem, n).with_rich_isa(rich_isa);
+        let s = b.column(vars_batch.addr_local_constants, selector_index);
+        let mut filter = None;
+        for k in group_range
+            .clone()
+            .enumerate()
+            .filter(|&(_, i)| i != row)
+            .map(|(k, _)| k)
+            .chain((num_selectors > 1).then_some(group_range.len()))
+        {
+            let factor = b.sub(b.scalar_at(addr_group_range, k), s);
+            filter = Some(match filter {
+                None => factor,
+                Some(filter) => {
+                    b.mul_into(filter, filter, factor);
+                    b.free(factor);
+                    filter
+                }
             });
         }
-    }
-    vars_batch.addr_local_constants +=
-        (num_selectors + num_lookup_selectors) * vars_batch.len() * SIZE_F;
+        let addr_filter = filter.map(|filter| filter.addr());
+        (b.finish(), addr_filter)
+    } else {
+        let addr_sub = sys.mem.alloc("sub", n * SIZE_F).unwrap();
+        let addr_filters = sys.mem.alloc("filters", n * SIZE_F).unwrap();
+        let mut vec_ops = Vec::new();
+        for i in 0..group_range.len() {
+            if i != row {
+                vec_ops.push(VecOpConfig {
+                    vector_length: n,
+                    addr_input_0: vars_batch.addr_local_constants + selector_index * n * SIZE_F,
+                    addr_input_1: addr_group_range + i * SIZE_F,
+                    addr_output: addr_sub,
+                    op_type: VecOpType::SUB,
+                    op_src: VecOpSrc::VS,
+                    is_final_output: false,
+                });
+                vec_ops.push(VecOpConfig {
+                    vector_length: n,
+                    addr_input_0: addr_sub,
+                    addr_input_1: addr_filters,
+                    addr_output: addr_filters,
+                    op_type: VecOpType::MUL,
+                    op_src: VecOpSrc::VV,
+                    is_final_output: i == group_range.len() - 1,
+                });
+            }
+        }
+        (vec_ops, Some(addr_filters))
+    };
+    vars_batch.addr_local_constants += (num_selectors + num_lookup_selectors) * n * SIZE_F;
 
     debug!("Gate: {:?}", gate.id());
-    if let Some(index) = gate.id().find("Gate") {
-        let substring = &gate.id()[..index + 4];
-        match substring {
-            "ArithmeticGate" => vec_ops.extend(eval_unfiltered_base_packed_arithmetic(
-                &mut sys.mem,
+    // gates without hand-written ops are lowered from their packed evaluation
+    let descriptor = match (lowered, GateDescriptor::of(gate_ref)) {
+        (Some(_), _) if sys.hw.opt.symbolic_gates => None,
+        (Some(_), Err(_)) => None,
+        (_, Ok(descriptor)) => Some(descriptor),
+        (None, Err(e)) => {
+            if sys.estimated_gates.insert(gate.id()) {
+                warn!("{:#}, and it has no lowering, its ops are estimated", e);
+            }
+            None
+        }
+    };
+    let mem = &mut sys.mem;
+    match descriptor {
+        None => vec_ops.extend(match lowered {
+            Some(lowered) => lowered.eval(mem, vars_batch, addr_res_batch, rich_isa),
+            None => eval_unfiltered_base_estimated(
                 vars_batch,
                 addr_res_batch,
-                get_params(gate.id(), "num_ops"),
-            )),
-            "BaseSumGate" => vec_ops.extend(eval_unfiltered_base_packed_base_sum::<B>(
-                &mut sys.mem,
+                gate.num_wires(),
+                gate.num_constants(),
+                gate.num_constraints(),
+                gate.degree(),
+            ),
+        }),
+        Some(GateDescriptor::Arithmetic { num_ops }) => {
+            vec_ops.extend(eval_unfiltered_base_packed_arithmetic(
+                mem,
                 vars_batch,
                 addr_res_batch,
-                get_params(gate.id(), "num_limbs"),
-            )),
-            "ConstantGate" => vec_ops.extend(eval_unfiltered_base_packed_constant(
+                num_ops,
+                rich_isa,
+            ))
+        }
+        Some(GateDescriptor::BaseSum { num_limbs }) => vec_ops.extend(
+            eval_unfiltered_base_packed_base_sum::<B>(mem, vars_batch, addr_res_batch, num_limbs),
+        ),
+        Some(GateDescriptor::Constant { num_consts }) => vec_ops.extend(
+            eval_unfiltered_base_packed_constant(vars_batch, addr_res_batch, num_consts),
+        ),
+        Some(GateDescriptor::Exponentiation { num_power_bits }) => {
+            vec_ops.extend(eval_unfiltered_base_packed_exponentiation(
+                mem,
                 vars_batch,
                 addr_res_batch,
-                get_params(gate.id(), "num_consts"),
-            )),
-            "ExponentiationGate" => vec_ops.extend(eval_unfiltered_base_packed_exponentiation(
-                &mut sys.mem,
+                num_power_bits,
+                rich_isa,
+            ))
+        }
+        Some(GateDescriptor::PublicInput) => vec_ops.extend(
+            eval_unfiltered_base_packed_public_input(vars_batch, addr_res_batch),
+        ),
+        Some(GateDescriptor::RandomAccess {
+            bits,
+            num_copies,
+            num_extra_constants,
+        }) => vec_ops.extend(eval_unfiltered_base_packed_random_access(
+            mem,
+            vars_batch,
+            addr_res_batch,
+            bits,
+            num_copies,
+            num_extra_constants,
+            rich_isa,
+        )),
+        Some(GateDescriptor::Poseidon) => vec_ops.extend(eval_unfiltered_base_poseidon(
+            mem,
+            vars_batch,
+            addr_res_batch,
+            rich_isa,
+        )),
+        Some(GateDescriptor::Noop) => {
+            // Do nothing
+        }
+        Some(GateDescriptor::U32AddMany {
+            num_addends,
+            num_ops,
+        }) => vec_ops.extend(eval_unfiltered_base_packed_u32_add_many(
+            mem,
+            vars_batch,
+            addr_res_batch,
+            num_addends,
+            num_ops,
+        )),
+        Some(GateDescriptor::U32RangeCheck { num_input_limbs }) => {
+            vec_ops.extend(eval_unfiltered_base_packed_u32_range_check(
+                mem,
                 vars_batch,
                 addr_res_batch,
-                get_params(gate.id(), "num_power_bits"),
-            )),
-            "PublicInputGate" => vec_ops.extend(eval_unfiltered_base_packed_public_input(
+                num_input_limbs,
+            ))
+        }
+        Some(GateDescriptor::Comparison {
+            num_bits,
+            num_chunks,
+        }) => vec_ops.extend(eval_unfiltered_base_packed_comparison(
+            mem,
+            vars_batch,
+            addr_res_batch,
+            num_bits,
+            num_chunks,
+        )),
+        Some(GateDescriptor::U32Arithmetic { num_ops }) => vec_ops.extend(
+            eval_unfiltered_base_packed_u32_arithmetic(mem, vars_batch, addr_res_batch, num_ops),
+        ),
+        Some(GateDescriptor::U32Subtraction { num_ops }) => vec_ops.extend(
+            eval_unfiltered_base_packed_u32_subtraction(mem, vars_batch, addr_res_batch, num_ops),
+        ),
+        Some(GateDescriptor::PoseidonMds) => {
+            vec_ops.extend(eval_unfiltered_base_packed_poseidon_mds::<D>(
+                mem,
                 vars_batch,
                 addr_res_batch,
-            )),
-            "RandomAccessGate" => vec_ops.extend(eval_unfiltered_base_packed_random_access(
-                &mut sys.mem,
+                rich_isa,
+            ))
+        }
+        Some(GateDescriptor::LowDegreeInterpolation { subgroup_bits }) => {
+            vec_ops.extend(eval_unfiltered_base_packed_low_degree_interpolation::<D>(
+                mem,
                 vars_batch,
                 addr_res_batch,
-                get_params(gate.id(), "bits"),
-                get_params(gate.id(), "num_copies"),
-                get_params(gate.id(), "num_extra_constants"),
-            )),
-            "PoseidonGate" => vec_ops.extend(eval_unfiltered_base_poseidon(
-                &mut sys.mem,
+                subgroup_bits,
+                rich_isa,
+            ))
+        }
+        Some(GateDescriptor::MulExtension { num_ops }) => {
+            vec_ops.extend(eval_unfiltered_base_packed_mul_extension::<D>(
+                mem,
                 vars_batch,
                 addr_res_batch,
-            )),
-            "NoopGate" => {
-                // Do nothing
-            }
-            "U32AddManyGate" => {
-                let num_addends = get_params(gate.id(), "num_addends");
-                let num_ops = get_params(gate.id(), "num_ops");
-                vec_ops.extend(eval_unfiltered_base_packed_u32_add_many(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    num_addends,
-                    num_ops,
-                ));
-            }
-            "U32RangeCheckGate" => {
-                let num_input_limbs = get_params(gate.id(), "num_input_limbs");
-                vec_ops.extend(eval_unfiltered_base_packed_u32_range_check(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    num_input_limbs,
-                ));
-            }
-            "ComparisonGate" => {
-                let num_bits = get_params(gate.id(), "num_bits");
-                let num_chunks = get_params(gate.id(), "num_chunks");
-                vec_ops.extend(eval_unfiltered_base_packed_comparison(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    num_bits,
-                    num_chunks,
-                ));
-            }
-            "U32ArithmeticGate" => {
-                let num_ops = get_params(gate.id(), "num_ops");
-                vec_ops.extend(eval_unfiltered_base_packed_u32_arithmetic(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    num_ops,
-                ));
-            }
-            "U32SubtractionGate" => {
-                let num_ops = get_params(gate.id(), "num_ops");
-                vec_ops.extend(eval_unfiltered_base_packed_u32_subtraction(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    num_ops,
-                ));
-            }
-            "PoseidonMdsGate" => {
-                vec_ops.extend(eval_unfiltered_base_packed_poseidon_mds::<D>(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                ));
-            }
-            "LowDegreeInterpolationGate" => {
-                let subgroup_bits = get_params(gate.id(), "subgroup_bits");
-                vec_ops.extend(eval_unfiltered_base_packed_low_degree_interpolation::<D>(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    subgroup_bits,
-                ));
-            }
-            "MulExtensionGate" => {
-                let num_ops = get_params(gate.id(), "num_ops");
-                vec_ops.extend(eval_unfiltered_base_packed_mul_extension::<D>(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    num_ops,
-                ));
-            }
-            "ArithmeticExtensionGate" => {
-                let num_ops = get_params(gate.id(), "num_ops");
-                vec_ops.extend(eval_unfiltered_base_packed_arithmetic_extension::<D>(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    num_ops,
-                ));
-            }
-            "ReducingExtensionGate" => {
-                let num_coeffs = get_params(gate.id(), "num_coeffs");
-                vec_ops.extend(eval_unfiltered_base_packed_reducing_extension::<D>(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    num_coeffs,
-                ));
-            }
-            "ReducingGate" => {
-                let num_coeffs = get_params(gate.id(), "num_coeffs");
-                vec_ops.extend(eval_unfiltered_base_packed_reducing::<D>(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    num_coeffs,
-                ));
-            }
-            "CosetInterpolationGate" => {
-                let subgroup_bits = get_params(gate.id(), "subgroup_bits");
-                let degree = get_params(gate.id(), "degree");
-                vec_ops.extend(eval_unfiltered_base_packed_coset_interpolation::<D>(
-                    &mut sys.mem,
-                    vars_batch,
-                    addr_res_batch,
-                    subgroup_bits,
-                    degree,
-                ));
-            }
-            _ => {
-                panic!("Gate type {:?} not found", substring);
-            }
+                num_ops,
+                rich_isa,
+            ))
+        }
+        Some(GateDescriptor::ArithmeticExtension { num_ops }) => {
+            vec_ops.extend(eval_unfiltered_base_packed_arithmetic_extension::<D>(
+                mem,
+                vars_batch,
+                addr_res_batch,
+                num_ops,
+                rich_isa,
+            ))
+        }
+        Some(GateDescriptor::ReducingExtension { num_coeffs }) => {
+            vec_ops.extend(eval_unfiltered_base_packed_reducing_extension::<D>(
+                mem,
+                vars_batch,
+                addr_res_batch,
+                num_coeffs,
+                rich_isa,
+            ))
+        }
+        Some(GateDescriptor::Reducing { num_coeffs }) => {
+            vec_ops.extend(eval_unfiltered_base_packed_reducing::<D>(
+                mem,
+                vars_batch,
+                addr_res_batch,
+                num_coeffs,
+                rich_isa,
+            ))
+        }
+        Some(GateDescriptor::CosetInterpolation {
+            subgroup_bits,
+            degree,
+        }) => vec_ops.extend(eval_unfiltered_base_packed_coset_interpolation::<D>(
+            mem,
+            vars_batch,
+            addr_res_batch,
+            subgroup_bits,
+            degree,
+            rich_isa,
+        )),
+    }
+    if !functional {
+        // the model applies the filter once, to the first constraint
+        vec_ops.push(VecOpConfig {
+            vector_length: n,
+            addr_input_0: addr_filter.unwrap(),
+            addr_input_1: addr_res_batch,
+            addr_output: addr_res_batch,
+            op_type: VecOpType::SUB,
+            op_src: VecOpSrc::VV,
+            is_final_output: true,
+        });
+        sys.mem.free("sub");
+        sys.mem.free("filters");
+    } else if let Some(addr_filter) = addr_filter {
+        for c in 0..gate.num_constraints() {
+            vec_ops.push(VecOpConfig {
+                vector_length: n,
+                addr_input_0: addr_res_batch + c * n * SIZE_F,
+                addr_input_1: addr_filter,
+                addr_output: addr_res_batch + c * n * SIZE_F,
+                op_type: VecOpType::MUL,
+                op_src: VecOpSrc::VV,
+                is_final_output: true,
+            });
         }
-    } else {
-        panic!("Gate type {:?} not found", gate.id());
     }
-    vec_ops.push(VecOpConfig {
-        vector_length: vars_batch.len(),
-        addr_input_0: addr_filters,
-        addr_input_1: addr_res_batch,
-        addr_output: addr_res_batch,
-        op_type: VecOpType::SUB,
-        op_src: VecOpSrc::VV,
-        is_final_output: true,
-    });
-
-    sys.mem.free("sub");
-    sys.mem.free("filters");
 
     let _duration = _start.elapsed();
     // debug!(
@@ -242,11 +297,70 @@ pub fn eval_filtered_base_batch<F: RichField + Extendable<D>, const D: usize>(
     vec_ops
 }
 
+// A stand-in for gates with neither a port nor a lowering: each constraint is a product of
+// degree inputs plus a sum of the rest of its share, so that every wire and constant is read
+// and every constraint written, with about as many ops as a gate of that shape
+pub(crate) fn eval_unfiltered_base_estimated(
+    vars_base: EvaluationVarsBaseBatch,
+    addr_res_batch: usize,
+    num_wires: usize,
+    num_constants: usize,
+    num_constraints: usize,
+    degree: usize,
+) -> Vec<VecOpConfig> {
+    let n = vars_base.len();
+    let mut inputs = (0..num_wires)
+        .map(|i| vars_base.addr_local_wires + i * n * SIZE_F)
+        .chain((0..num_constants).map(|i| vars_base.addr_local_constants + i * n * SIZE_F))
+        .collect::<Vec<_>>();
+    if inputs.is_empty() {
+        inputs.push(0);
+    }
+    let reads = degree.max(ceil_div_usize(inputs.len(), num_constraints.max(1)));
+    let mut inputs = inputs.into_iter().cycle();
+
+    let mut vec_ops = Vec::new();
+    for c in 0..num_constraints {
+        let addr_output = addr_res_batch + c * n * SIZE_F;
+        let first = inputs.next().unwrap();
+        if reads == 1 {
+            // a copy, as the constraint of a degree 1 gate reading a single input
+            vec_ops.push(VecOpConfig {
+                vector_length: n,
+                addr_input_0: first,
+                addr_input_1: 0,
+                addr_output,
+                op_type: VecOpType::ADD,
+                op_src: VecOpSrc::VS,
+                is_final_output: true,
+            });
+            continue;
+        }
+        for j in 1..reads {
+            vec_ops.push(VecOpConfig {
+                vector_length: n,
+                addr_input_0: if j == 1 { first } else { addr_output },
+                addr_input_1: inputs.next().unwrap(),
+                addr_output,
+                op_type: if j < degree {
+                    VecOpType::MUL
+                } else {
+                    VecOpType::ADD
+                },
+                op_src: VecOpSrc::VV,
+                is_final_output: j == reads - 1,
+            });
+        }
+    }
+    vec_ops
+}
+
 fn eval_unfiltered_base_packed_arithmetic(
     mem: &mut MemAlloc,
     vars_base: EvaluationVarsBaseBatch,
     addr_res_batch: usize,
     num_ops: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     let mut vec_ops = Vec::new();
 
@@ -274,26 +388,38 @@ fn eval_unfiltered_base_packed_arithmetic(
             op_src: VecOpSrc::VV,
             is_final_output: false,
         });
-        // addend * const_1
-        vec_ops.push(VecOpConfig {
-            vector_length: vars_base.len(),
-            addr_input_0: vars_base.addr_local_wires + (4 * i + 2) * vars_base.len() * SIZE_F,
-            addr_input_1: vars_base.addr_local_constants + vars_base.len() * SIZE_F,
-            addr_output: addr_1,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-            is_final_output: false,
-        });
-        // _ + _
-        vec_ops.push(VecOpConfig {
-            vector_length: vars_base.len(),
-            addr_input_0: addr_0,
-            addr_input_1: addr_1,
-            addr_output: addr_0,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-            is_final_output: false,
-        });
+        if rich_isa {
+            // _ + addend * const_1
+            vec_ops.push(VecOpConfig::fma(
+                vars_base.len(),
+                vars_base.addr_local_wires + (4 * i + 2) * vars_base.len() * SIZE_F,
+                vars_base.addr_local_constants + vars_base.len() * SIZE_F,
+                addr_0,
+                VecOpSrc::VV,
+                false,
+            ));
+        } else {
+            // addend * const_1
+            vec_ops.push(VecOpConfig {
+                vector_length: vars_base.len(),
+                addr_input_0: vars_base.addr_local_wires + (4 * i + 2) * vars_base.len() * SIZE_F,
+                addr_input_1: vars_base.addr_local_constants + vars_base.len() * SIZE_F,
+                addr_output: addr_1,
+                op_type: VecOpType::MUL,
+                op_src: VecOpSrc::VV,
+                is_final_output: false,
+            });
+            // _ + _
+            vec_ops.push(VecOpConfig {
+                vector_length: vars_base.len(),
+                addr_input_0: addr_0,
+                addr_input_1: addr_1,
+                addr_output: addr_0,
+                op_type: VecOpType::ADD,
+                op_src: VecOpSrc::VV,
+                is_final_output: false,
+            });
+        }
         // output - computed_output
         vec_ops.push(VecOpConfig {
             vector_length: vars_base.len(),
@@ -393,28 +519,26 @@ fn sbox_ops(
     addr_state: usize,
     length: usize,
     batch_size: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     let mut vec_ops = Vec::new();
     let addr_sbox_tmp0 = mem.alloc("sbox_0", length * batch_size * SIZE_F).unwrap();
     let addr_sbox_tmp1 = mem.alloc("sbox_1", length * batch_size * SIZE_F).unwrap();
-    vec_ops.push(VecOpConfig {
-        vector_length: length * batch_size,
-        addr_input_0: addr_state,
-        addr_input_1: addr_state,
-        addr_output: addr_sbox_tmp0,
-        op_type: VecOpType::MUL,
-        op_src: VecOpSrc::VV,
-        is_final_output: false,
-    });
-    vec_ops.push(VecOpConfig {
-        vector_length: length * batch_size,
-        addr_input_0: addr_sbox_tmp0,
-        addr_input_1: addr_sbox_tmp0,
-        addr_output: addr_sbox_tmp1,
-        op_type: VecOpType::MUL,
-        op_src: VecOpSrc::VV,
-        is_final_output: false,
-    });
+    // x^2, x^4
+    vec_ops.push(VecOpConfig::square(
+        length * batch_size,
+        addr_state,
+        addr_sbox_tmp0,
+        false,
+        rich_isa,
+    ));
+    vec_ops.push(VecOpConfig::square(
+        length * batch_size,
+        addr_sbox_tmp0,
+        addr_sbox_tmp1,
+        false,
+        rich_isa,
+    ));
     vec_ops.push(VecOpConfig {
         vector_length: length * batch_size,
         addr_input_0: addr_state,
@@ -483,6 +607,7 @@ fn eval_unfiltered_base_poseidon(
     mem: &mut MemAlloc,
     vars_base: EvaluationVarsBaseBatch,
     addr_res_batch: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     const WIRE_SWAP: usize = 2 * SPONGE_WIDTH;
     const START_DELTA: usize = 2 * SPONGE_WIDTH + 1;
@@ -598,7 +723,13 @@ fn eval_unfiltered_base_poseidon(
                 addr_res += vars_base.len() * SIZE_F;
             }
         }
-        vec_ops.extend(sbox_ops(mem, addr_state, SPONGE_WIDTH, vars_base.len()));
+        vec_ops.extend(sbox_ops(
+            mem,
+            addr_state,
+            SPONGE_WIDTH,
+            vars_base.len(),
+            rich_isa,
+        ));
         vec_ops.extend(mds_ops(mem, addr_state, vars_base.len()));
     }
 
@@ -616,7 +747,7 @@ fn eval_unfiltered_base_poseidon(
             is_final_output: true,
         });
         addr_res += vars_base.len() * SIZE_F;
-        vec_ops.extend(sbox_ops(mem, addr_state, 1, vars_base.len()));
+        vec_ops.extend(sbox_ops(mem, addr_state, 1, vars_base.len(), rich_isa));
         vec_ops.push(VecOpConfig {
             vector_length: vars_base.len(),
             addr_input_0: addr_state,
@@ -641,7 +772,7 @@ fn eval_unfiltered_base_poseidon(
         is_final_output: true,
     });
     addr_res += vars_base.len() * SIZE_F;
-    vec_ops.extend(sbox_ops(mem, addr_state, 1, vars_base.len()));
+    vec_ops.extend(sbox_ops(mem, addr_state, 1, vars_base.len(), rich_isa));
     vec_ops.extend(mds_ops(mem, addr_state, vars_base.len()));
 
     for r in 0..HALF_N_FULL_ROUNDS {
@@ -662,7 +793,13 @@ fn eval_unfiltered_base_poseidon(
                 addr_res += vars_base.len() * SIZE_F;
             }
         }
-        vec_ops.extend(sbox_ops(mem, addr_state, SPONGE_WIDTH, vars_base.len()));
+        vec_ops.extend(sbox_ops(
+            mem,
+            addr_state,
+            SPONGE_WIDTH,
+            vars_base.len(),
+            rich_isa,
+        ));
         vec_ops.extend(mds_ops(mem, addr_state, vars_base.len()));
     }
     for i in 0..SPONGE_WIDTH {
@@ -705,6 +842,7 @@ fn eval_unfiltered_base_packed_exponentiation(
     vars_base: EvaluationVarsBaseBatch,
     addr_res_batch: usize,
     num_power_bits: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     let mut vec_ops = Vec::new();
 
@@ -723,15 +861,13 @@ fn eval_unfiltered_base_packed_exponentiation(
         .unwrap();
     for i in 0..num_power_bits {
         if i > 0 {
-            vec_ops.push(VecOpConfig {
-                vector_length: vars_base.len(),
-                addr_input_0: addr_intermediate_values + (i - 1) * vars_base.len() * SIZE_F,
-                addr_input_1: addr_intermediate_values + (i - 1) * vars_base.len() * SIZE_F,
-                addr_output: addr_prev_intermediate_value,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
-                is_final_output: false,
-            });
+            vec_ops.push(VecOpConfig::square(
+                vars_base.len(),
+                addr_intermediate_values + (i - 1) * vars_base.len() * SIZE_F,
+                addr_prev_intermediate_value,
+                false,
+                rich_isa,
+            ));
         }
 
         let addr_cur_bit = addr_power_bits + (num_power_bits - i - 1) * vars_base.len() * SIZE_F;
@@ -745,31 +881,47 @@ fn eval_unfiltered_base_packed_exponentiation(
             is_final_output: false,
         });
 
-        // cur_bit * base
-        vec_ops.push(VecOpConfig {
-            vector_length: vars_base.len(),
-            addr_input_0: addr_cur_bit,
-            addr_input_1: addr_base,
-            addr_output: addr_computed_intermediate_value,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-            is_final_output: false,
-        });
-        // _ + not_cur_bit
-        vec_ops.push(VecOpConfig {
-            vector_length: vars_base.len(),
-            addr_input_0: addr_computed_intermediate_value,
-            addr_input_1: addr_not_cur_bit,
-            addr_output: addr_computed_intermediate_value,
-            op_type: VecOpType::ADD,
-            op_src: VecOpSrc::VV,
-            is_final_output: false,
-        });
+        if rich_isa {
+            // not_cur_bit + cur_bit * base
+            vec_ops.push(VecOpConfig::fma(
+                vars_base.len(),
+                addr_cur_bit,
+                addr_base,
+                addr_not_cur_bit,
+                VecOpSrc::VV,
+                false,
+            ));
+        } else {
+            // cur_bit * base
+            vec_ops.push(VecOpConfig {
+                vector_length: vars_base.len(),
+                addr_input_0: addr_cur_bit,
+                addr_input_1: addr_base,
+                addr_output: addr_computed_intermediate_value,
+                op_type: VecOpType::MUL,
+                op_src: VecOpSrc::VV,
+                is_final_output: false,
+            });
+            // _ + not_cur_bit
+            vec_ops.push(VecOpConfig {
+                vector_length: vars_base.len(),
+                addr_input_0: addr_computed_intermediate_value,
+                addr_input_1: addr_not_cur_bit,
+                addr_output: addr_computed_intermediate_value,
+                op_type: VecOpType::ADD,
+                op_src: VecOpSrc::VV,
+                is_final_output: false,
+            });
+        }
+        let addr_multiplier = match rich_isa {
+            true => addr_not_cur_bit,
+            false => addr_computed_intermediate_value,
+        };
         // prev_intermediate_value * _
         vec_ops.push(VecOpConfig {
             vector_length: vars_base.len(),
             addr_input_0: addr_prev_intermediate_value,
-            addr_input_1: addr_computed_intermediate_value,
+            addr_input_1: addr_multiplier,
             addr_output: addr_computed_intermediate_value,
             op_type: VecOpType::MUL,
             op_src: VecOpSrc::VV,
@@ -832,6 +984,7 @@ fn eval_unfiltered_base_packed_random_access(
     bits: usize,
     num_copies: usize,
     num_extra_constants: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     let mut vec_ops = Vec::new();
 
@@ -898,26 +1051,45 @@ fn eval_unfiltered_base_packed_random_access(
         arb_offset += 1;
         mem.free("acc");
 
+        // fold in place, the items of round b are 2^b apart and the chosen one ends up in
+        // list_items[0]. x + b * (y - x) is not a SELECT, b is only a bit on the rows of the
+        // trace and not on the LDE coset
         let addr_list_items_tmp = mem
             .alloc("list_items_tmp", vars_base.len() * SIZE_F)
             .unwrap();
         for b in 0..bits {
+            let addr_bit = addr_bits + b * vars_base.len() * SIZE_F;
             for i in 0..(1 << (bits - b - 1)) {
+                let x = addr_list_items + (i << (b + 1)) * vars_base.len() * SIZE_F;
+                let y = x + (1 << b) * vars_base.len() * SIZE_F;
                 // y - x
                 vec_ops.push(VecOpConfig {
                     vector_length: vars_base.len(),
-                    addr_input_0: addr_list_items + (i * 2 + 1) * vars_base.len() * SIZE_F,
-                    addr_input_1: addr_list_items + i * 2 * vars_base.len() * SIZE_F,
+                    addr_input_0: y,
+                    addr_input_1: x,
                     addr_output: addr_list_items_tmp,
                     op_type: VecOpType::SUB,
                     op_src: VecOpSrc::VV,
                     is_final_output: false,
                 });
+                if rich_isa {
+                    // b * _ + x
+                    vec_ops.push(VecOpConfig {
+                        vector_length: vars_base.len(),
+                        addr_input_0: addr_list_items_tmp,
+                        addr_input_1: addr_bit,
+                        addr_output: x,
+                        op_type: VecOpType::FMA,
+                        op_src: VecOpSrc::VV,
+                        is_final_output: false,
+                    });
+                    continue;
+                }
                 // b * _
                 vec_ops.push(VecOpConfig {
                     vector_length: vars_base.len(),
                     addr_input_0: addr_list_items_tmp,
-                    addr_input_1: 0, // const
+                    addr_input_1: addr_bit,
                     addr_output: addr_list_items_tmp,
                     op_type: VecOpType::MUL,
                     op_src: VecOpSrc::VV,
@@ -926,9 +1098,9 @@ fn eval_unfiltered_base_packed_random_access(
                 // x + _
                 vec_ops.push(VecOpConfig {
                     vector_length: vars_base.len(),
-                    addr_input_0: addr_list_items + i * 2 * vars_base.len() * SIZE_F,
+                    addr_input_0: x,
                     addr_input_1: addr_list_items_tmp,
-                    addr_output: addr_list_items + i * vars_base.len() * SIZE_F,
+                    addr_output: x,
                     op_type: VecOpType::ADD,
                     op_src: VecOpSrc::VV,
                     is_final_output: false,
@@ -2010,6 +2182,7 @@ fn eval_unfiltered_base_packed_poseidon_mds<const D: usize>(
     mem: &mut MemAlloc,
     vars_base: EvaluationVarsBaseBatch,
     addr_res_batch: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     let wires_input = |i: usize| {
         assert!(i < SPONGE_WIDTH);
@@ -2035,6 +2208,7 @@ fn eval_unfiltered_base_packed_poseidon_mds<const D: usize>(
             addr_computed_outputs,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
         vec_ops.extend(VecOpExtension::<D>::add(
             SPONGE_WIDTH * vars_base.len(),
@@ -2062,6 +2236,7 @@ fn eval_unfiltered_base_packed_low_degree_interpolation<const D: usize>(
     vars_base: EvaluationVarsBaseBatch,
     mut addr_res_batch: usize,
     subgroup_bits: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     let num_points = 1 << subgroup_bits;
     let start_values = || 1;
@@ -2179,6 +2354,7 @@ fn eval_unfiltered_base_packed_low_degree_interpolation<const D: usize>(
             addr_res_batch,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
         vec_ops.extend(VecOpExtension::<D>::sub(
             vars_base.len(),
@@ -2203,6 +2379,7 @@ fn eval_unfiltered_base_packed_low_degree_interpolation<const D: usize>(
             addr_eval_with_powers,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
         vec_ops.extend(VecOpExtension::<D>::add(
             vars_base.len(),
@@ -2231,6 +2408,7 @@ fn eval_unfiltered_base_packed_reducing_extension<const D: usize>(
     vars_base: EvaluationVarsBaseBatch,
     mut addr_res_batch: usize,
     num_coeffs: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     let wires_output = || vars_base.addr_local_wires;
     let wires_alpha = || vars_base.addr_local_wires + D * vars_base.len() * SIZE_F;
@@ -2261,6 +2439,7 @@ fn eval_unfiltered_base_packed_reducing_extension<const D: usize>(
             addr_res_batch,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
         vec_ops.extend(VecOpExtension::<D>::add(
             vars_base.len(),
@@ -2289,8 +2468,15 @@ fn eval_unfiltered_base_packed_reducing<const D: usize>(
     vars_base: EvaluationVarsBaseBatch,
     addr_res_batch: usize,
     num_coeffs: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
-    eval_unfiltered_base_packed_reducing_extension::<D>(mem, vars_base, addr_res_batch, num_coeffs)
+    eval_unfiltered_base_packed_reducing_extension::<D>(
+        mem,
+        vars_base,
+        addr_res_batch,
+        num_coeffs,
+        rich_isa,
+    )
 }
 
 fn eval_unfiltered_base_packed_mul_extension<const D: usize>(
@@ -2298,6 +2484,7 @@ fn eval_unfiltered_base_packed_mul_extension<const D: usize>(
     vars_base: EvaluationVarsBaseBatch,
     mut addr_res_batch: usize,
     num_ops: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     let wires_ith_multiplicand_0 =
         |i: usize| vars_base.addr_local_wires + (3 * D * i) * vars_base.len() * SIZE_F;
@@ -2316,6 +2503,7 @@ fn eval_unfiltered_base_packed_mul_extension<const D: usize>(
             addr_res_batch,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
         vec_ops.extend(VecOpExtension::<D>::scalar_mul(
             vars_base.len(),
@@ -2344,6 +2532,7 @@ fn eval_unfiltered_base_packed_arithmetic_extension<const D: usize>(
     vars_base: EvaluationVarsBaseBatch,
     mut addr_res_batch: usize,
     num_ops: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     let wires_ith_multiplicand_0 =
         |i: usize| vars_base.addr_local_wires + (4 * D * i) * vars_base.len() * SIZE_F;
@@ -2367,6 +2556,7 @@ fn eval_unfiltered_base_packed_arithmetic_extension<const D: usize>(
             addr_res_batch,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
         vec_ops.extend(VecOpExtension::<D>::scalar_mul(
             vars_base.len(),
@@ -2376,22 +2566,34 @@ fn eval_unfiltered_base_packed_arithmetic_extension<const D: usize>(
             VecOpSrc::VV,
             false,
         ));
-        vec_ops.extend(VecOpExtension::<D>::scalar_mul(
-            vars_base.len(),
-            wires_ith_addend(i), // addend
-            const_1,             // const_1
-            addr_addend,
-            VecOpSrc::VV,
-            false,
-        ));
-        vec_ops.extend(VecOpExtension::<D>::add(
-            vars_base.len(),
-            addr_res_batch,
-            addr_addend,
-            addr_res_batch,
-            VecOpSrc::VV,
-            false,
-        ));
+        if rich_isa {
+            // _ + addend * const_1
+            vec_ops.extend(VecOpExtension::<D>::scalar_fma(
+                vars_base.len(),
+                wires_ith_addend(i), // addend
+                const_1,             // const_1
+                addr_res_batch,
+                VecOpSrc::VV,
+                false,
+            ));
+        } else {
+            vec_ops.extend(VecOpExtension::<D>::scalar_mul(
+                vars_base.len(),
+                wires_ith_addend(i), // addend
+                const_1,             // const_1
+                addr_addend,
+                VecOpSrc::VV,
+                false,
+            ));
+            vec_ops.extend(VecOpExtension::<D>::add(
+                vars_base.len(),
+                addr_res_batch,
+                addr_addend,
+                addr_res_batch,
+                VecOpSrc::VV,
+                false,
+            ));
+        }
         mem.free("addend");
 
         vec_ops.extend(VecOpExtension::<D>::sub(
@@ -2413,6 +2615,7 @@ fn eval_unfiltered_base_packed_coset_interpolation<const D: usize>(
     mut addr_res_batch: usize,
     subgroup_bits: usize,
     degree: usize,
+    rich_isa: bool,
 ) -> Vec<VecOpConfig> {
     let mut vec_ops = Vec::new();
 
@@ -2522,6 +2725,7 @@ fn eval_unfiltered_base_packed_coset_interpolation<const D: usize>(
             addr_eval,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
         // eval * term
         vec_ops.extend(VecOpExtension::<D>::mul(
@@ -2532,6 +2736,7 @@ fn eval_unfiltered_base_packed_coset_interpolation<const D: usize>(
             addr_computed_eval,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
         vec_ops.extend(VecOpExtension::<D>::add(
             vars_base.len(),
@@ -2549,6 +2754,7 @@ fn eval_unfiltered_base_packed_coset_interpolation<const D: usize>(
             addr_computed_prod,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
     }
 
@@ -2565,6 +2771,7 @@ fn eval_unfiltered_base_packed_coset_interpolation<const D: usize>(
             addr_res_batch,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
         addr_res_batch += vars_base.len() * SIZE_F * D;
         vec_ops.extend(VecOpExtension::<D>::mul(
@@ -2575,6 +2782,7 @@ fn eval_unfiltered_base_packed_coset_interpolation<const D: usize>(
             addr_res_batch,
             VecOpSrc::VV,
             false,
+            rich_isa,
         ));
         addr_res_batch += vars_base.len() * SIZE_F * D;
 
@@ -2616,6 +2824,7 @@ fn eval_unfiltered_base_packed_coset_interpolation<const D: usize>(
                 addr_eval,
                 VecOpSrc::VV,
                 false,
+                rich_isa,
             ));
             // eval * term
             vec_ops.extend(VecOpExtension::<D>::mul(
@@ -2626,6 +2835,7 @@ fn eval_unfiltered_base_packed_coset_interpolation<const D: usize>(
                 addr_computed_eval,
                 VecOpSrc::VV,
                 false,
+                rich_isa,
             ));
             vec_ops.extend(VecOpExtension::<D>::add(
                 vars_base.len(),
@@ -2643,6 +2853,7 @@ fn eval_unfiltered_base_packed_coset_interpolation<const D: usize>(
                 addr_computed_prod,
                 VecOpSrc::VV,
                 false,
+                rich_isa,
             ));
         }
     }
@@ -2668,14 +2879,3 @@ fn eval_unfiltered_base_packed_coset_interpolation<const D: usize>(
 
     vec_ops
 }
-
-fn get_params(id: String, param: &str) -> usize {
-    if let Some(index) = id.find(param) {
-        let substring = &id[index + param.len() + 2..];
-        let end = substring.find(",").unwrap_or(substring.find(" ").unwrap());
-        let value = &substring[..end];
-        value.parse::<usize>().unwrap()
-    } else {
-        panic!("Gate {:?} doesn't have parameter {:?} !", id, param);
-    }
-}
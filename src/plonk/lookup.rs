@@ -0,0 +1,683 @@
+use plonky2::field::extension::Extendable;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::Field;
+use plonky2::gates::lookup::LookupGate;
+use plonky2::gates::lookup_table::LookupTableGate;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_builder::{LookupChallenges, LookupWire, NUM_COINS_LOOKUP};
+use plonky2::plonk::circuit_data::{CommonCircuitData, ProverOnlyCircuitData};
+use plonky2::plonk::config::GenericConfig;
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::kernel::transpose::{Transpose, TransposeConfig};
+use crate::kernel::vec_builder::{VecBuilder, VecHandle};
+use crate::kernel::vector_chain::VectorChain;
+use crate::kernel::vector_operation::{VecOpConfig, VecOpSrc, VecOpType};
+use crate::memory::memory_allocator::MemAlloc;
+use crate::plonk::vars::EvaluationVarsBaseBatch;
+use crate::system::system::System;
+use crate::util::{ceil_div_usize, SIZE_F};
+
+// The lookup argument of plonky2, after the Tip5 paper: per challenge, RE checks the lookup
+// tables and the partial SLDC polynomials hold the running sums of the logarithmic derivatives.
+
+fn vec_op(
+    vector_length: usize,
+    addr_input_0: usize,
+    addr_input_1: usize,
+    addr_output: usize,
+    op_type: VecOpType,
+    op_src: VecOpSrc,
+) -> VecOpConfig {
+    VecOpConfig {
+        vector_length,
+        addr_input_0,
+        addr_input_1,
+        addr_output,
+        op_type,
+        op_src,
+        is_final_output: false,
+    }
+}
+
+// The slots of the lookup gates of the circuit, read from its gates
+fn num_lu_slots<F: RichField + Extendable<D>, const D: usize>(
+    common_data: &CommonCircuitData<F, D>,
+) -> usize {
+    common_data
+        .gates
+        .iter()
+        .find_map(|gate| gate.0.as_any().downcast_ref::<LookupGate>())
+        .expect("a circuit with lookups has a LookupGate")
+        .num_slots
+}
+
+fn num_lut_slots<F: RichField + Extendable<D>, const D: usize>(
+    common_data: &CommonCircuitData<F, D>,
+) -> usize {
+    common_data
+        .gates
+        .iter()
+        .find_map(|gate| gate.0.as_any().downcast_ref::<LookupTableGate>())
+        .expect("a circuit with lookup tables has a LookupTableGate")
+        .num_slots
+}
+
+// The lookup selectors follow the gate selectors: these, in the order of plonky2's
+// gates::selectors::LookupSelectors, then one end selector per LUT
+#[derive(Debug, Clone, Copy)]
+enum LookupSelectors {
+    TransSre = 0,
+    TransLdc,
+    InitSre,
+    LastLdc,
+}
+
+// the first end selector
+fn start_end<F: RichField + Extendable<D>, const D: usize>(
+    common_data: &CommonCircuitData<F, D>,
+) -> usize {
+    let start_end = common_data.num_lookup_selectors - common_data.luts.len();
+    assert_eq!(start_end, LookupSelectors::LastLdc as usize + 1);
+    start_end
+}
+
+// the number of SLDC polynomials of a challenge, and the slots of the LUT and LU rows in each
+fn sldc_chunks<F: RichField + Extendable<D>, const D: usize>(
+    common_data: &CommonCircuitData<F, D>,
+    lu_degree: usize,
+) -> (usize, Vec<Range<usize>>, Vec<Range<usize>>) {
+    let num_lu_slots = num_lu_slots(common_data);
+    let num_lut_slots = num_lut_slots(common_data);
+    let num_partial_lookups = ceil_div_usize(num_lu_slots, lu_degree);
+    let lut_degree = ceil_div_usize(num_lut_slots, num_partial_lookups);
+    let chunks = |slots: usize, degree: usize| {
+        (0..num_partial_lookups)
+            .map(|j| (j * degree).min(slots)..((j + 1) * degree).min(slots))
+            .collect::<Vec<_>>()
+    };
+    (
+        num_partial_lookups,
+        chunks(num_lut_slots, lut_degree),
+        chunks(num_lu_slots, lu_degree),
+    )
+}
+
+// Computes the lookup polynomials of one challenge on the rows of one LUT, like plonky2's
+// compute_lookup_polys. addr_rows holds these rows of the witness transposed, wire j of the
+// rows last_lu_gate..=first_lut_gate at addr_rows + j * len. Poly q of the challenge is at
+// addr_polys + q * degree, the other rows are not written.
+//
+// The rows are scanned from the last to the first as log(len) shifted vector ops, and the
+// inverses are of combo - alpha, so that no op needs a constant.
+pub fn lookup_polys<F: RichField + Extendable<D>, const D: usize>(
+    mem: &mut MemAlloc,
+    common_data: &CommonCircuitData<F, D>,
+    lookup_wire: &LookupWire,
+    addr_rows: usize,
+    addr_deltas: usize,
+    addr_polys: usize,
+    rich_isa: bool,
+) -> Vec<VecOpConfig> {
+    let degree = common_data.degree();
+    let num_lu_slots = num_lu_slots(common_data);
+    let num_lut_slots = num_lut_slots(common_data);
+    let (num_partial_lookups, lut_chunks, lu_chunks) = sldc_chunks(
+        common_data,
+        common_data.config.max_quotient_degree_factor - 1,
+    );
+
+    let first_row = lookup_wire.last_lu_gate;
+    let len = lookup_wire.first_lut_gate + 1 - first_row;
+    let lu_len = lookup_wire.last_lut_gate - first_row;
+    let lut_len = len - lu_len;
+    let row =
+        |poly: usize, offset: usize| addr_polys + (poly * degree + first_row + offset) * SIZE_F;
+    let wire = |wire: usize, offset: usize| addr_rows + (wire * len + offset) * SIZE_F;
+    let delta = |challenge: LookupChallenges| addr_deltas + challenge as usize * SIZE_F;
+    let addr_a = delta(LookupChallenges::ChallengeA);
+    let addr_b = delta(LookupChallenges::ChallengeB);
+    let addr_alpha = delta(LookupChallenges::ChallengeAlpha);
+    let addr_delta = delta(LookupChallenges::ChallengeDelta);
+
+    let size = (num_lut_slots * lut_len).max(num_lu_slots * lu_len) * SIZE_F;
+    let addr_differences = mem.alloc("lookup_differences", size).unwrap();
+    let addr_inverses = mem.alloc("lookup_inverses", size).unwrap();
+    let addr_combos = mem.alloc("lookup_combos", lut_len * SIZE_F).unwrap();
+    let addr_power = mem.alloc("lookup_delta_power", SIZE_F).unwrap();
+    let addr_inv_temps =
+        ["lookup_inv_t0", "lookup_inv_t1", "lookup_inv_t2"].map(|id| mem.alloc(id, size).unwrap());
+    let mut vec_ops = Vec::new();
+
+    // 1 / (combo - alpha) of each slot, with combo = input + a * output
+    let inverses =
+        |vec_ops: &mut Vec<VecOpConfig>, slots: &[(usize, usize)], offset: usize, length: usize| {
+            for (s, &(input, output)) in slots.iter().enumerate() {
+                let addr_difference = addr_differences + s * length * SIZE_F;
+                vec_ops.extend([
+                    vec_op(
+                        length,
+                        wire(output, offset),
+                        addr_a,
+                        addr_difference,
+                        VecOpType::MUL,
+                        VecOpSrc::VS,
+                    ),
+                    vec_op(
+                        length,
+                        wire(input, offset),
+                        addr_difference,
+                        addr_difference,
+                        VecOpType::ADD,
+                        VecOpSrc::VV,
+                    ),
+                    vec_op(
+                        length,
+                        addr_difference,
+                        addr_alpha,
+                        addr_difference,
+                        VecOpType::SUB,
+                        VecOpSrc::VS,
+                    ),
+                ]);
+            }
+            // inv reads its input after writing its output
+            vec_ops.extend(VecOpConfig::inv_with_temps(
+                slots.len() * length,
+                addr_differences,
+                addr_inverses,
+                addr_inv_temps,
+                false,
+                rich_isa,
+            ));
+        };
+
+    // partial Sums of the LUT rows, from the previous poly or 0, each m / (alpha - combo)
+    let lut_slots = (0..num_lut_slots)
+        .map(|s| {
+            (
+                LookupTableGate::wire_ith_looked_inp(s),
+                LookupTableGate::wire_ith_looked_out(s),
+            )
+        })
+        .collect::<Vec<_>>();
+    inverses(&mut vec_ops, &lut_slots, lu_len, lut_len);
+    for s in 0..num_lut_slots {
+        let addr_inverse = addr_inverses + s * lut_len * SIZE_F;
+        let addr_multiplicity = wire(LookupTableGate::wire_ith_multiplicity(s), lu_len);
+        vec_ops.push(vec_op(
+            lut_len,
+            addr_multiplicity,
+            addr_inverse,
+            addr_inverse,
+            VecOpType::MUL,
+            VecOpSrc::VV,
+        ));
+    }
+    for (j, chunk) in lut_chunks.iter().enumerate() {
+        let mut addr_prev = if j == 0 { 0 } else { row(j, lu_len) };
+        if chunk.is_empty() {
+            vec_ops.push(vec_op(
+                lut_len,
+                addr_prev,
+                0,
+                row(j + 1, lu_len),
+                VecOpType::ADD,
+                VecOpSrc::VS,
+            ));
+        }
+        for s in chunk.clone() {
+            let addr_inverse = addr_inverses + s * lut_len * SIZE_F;
+            vec_ops.push(vec_op(
+                lut_len,
+                addr_prev,
+                addr_inverse,
+                row(j + 1, lu_len),
+                VecOpType::SUB,
+                VecOpSrc::VV,
+            ));
+            addr_prev = row(j + 1, lu_len);
+        }
+    }
+
+    // RE of each LUT row on its own, then RE(row) = RE(row + 1) * delta^num_lut_slots + _
+    let addr_re = row(0, lu_len);
+    for (s, &(input, output)) in lut_slots.iter().enumerate() {
+        let addr_combo = if s == 0 { addr_re } else { addr_combos };
+        if s > 0 {
+            vec_ops.push(vec_op(
+                lut_len,
+                addr_re,
+                addr_delta,
+                addr_re,
+                VecOpType::MUL,
+                VecOpSrc::VS,
+            ));
+        }
+        vec_ops.extend([
+            vec_op(
+                lut_len,
+                wire(output, lu_len),
+                addr_b,
+                addr_combo,
+                VecOpType::MUL,
+                VecOpSrc::VS,
+            ),
+            vec_op(
+                lut_len,
+                wire(input, lu_len),
+                addr_combo,
+                addr_combo,
+                VecOpType::ADD,
+                VecOpSrc::VV,
+            ),
+        ]);
+        if s > 0 {
+            vec_ops.push(vec_op(
+                lut_len,
+                addr_re,
+                addr_combos,
+                addr_re,
+                VecOpType::ADD,
+                VecOpSrc::VV,
+            ));
+        }
+    }
+    let mut addr_multiplier = addr_delta;
+    for _ in 1..num_lut_slots {
+        vec_ops.push(vec_op(
+            1,
+            addr_multiplier,
+            addr_delta,
+            addr_power,
+            VecOpType::MUL,
+            VecOpSrc::VV,
+        ));
+        addr_multiplier = addr_power;
+    }
+    let mut shift = 1;
+    while shift < lut_len {
+        if shift > 1 {
+            vec_ops.push(vec_op(
+                1,
+                addr_multiplier,
+                addr_multiplier,
+                addr_power,
+                VecOpType::MUL,
+                VecOpSrc::VV,
+            ));
+            addr_multiplier = addr_power;
+        }
+        vec_ops.extend([
+            vec_op(
+                lut_len - shift,
+                row(0, lu_len + shift),
+                addr_multiplier,
+                addr_combos,
+                VecOpType::MUL,
+                VecOpSrc::VS,
+            ),
+            vec_op(
+                lut_len - shift,
+                addr_re,
+                addr_combos,
+                addr_re,
+                VecOpType::ADD,
+                VecOpSrc::VV,
+            ),
+        ]);
+        shift *= 2;
+    }
+
+    // partial LDCs of the LU rows, each - 1 / (alpha - combo)
+    if lu_len > 0 {
+        let lu_slots = (0..num_lu_slots)
+            .map(|s| {
+                (
+                    LookupGate::wire_ith_looking_inp(s),
+                    LookupGate::wire_ith_looking_out(s),
+                )
+            })
+            .collect::<Vec<_>>();
+        inverses(&mut vec_ops, &lu_slots, 0, lu_len);
+        for (j, chunk) in lu_chunks.iter().enumerate() {
+            let mut addr_prev = if j == 0 { 0 } else { row(j, 0) };
+            for s in chunk.clone() {
+                let addr_inverse = addr_inverses + s * lu_len * SIZE_F;
+                vec_ops.push(vec_op(
+                    lu_len,
+                    addr_prev,
+                    addr_inverse,
+                    row(j + 1, 0),
+                    VecOpType::ADD,
+                    VecOpSrc::VV,
+                ));
+                addr_prev = row(j + 1, 0);
+            }
+        }
+    }
+
+    // the last poly sums the rows up to the first LUT row, every poly adds that of the next row
+    let mut shift = 1;
+    while shift < len {
+        let addr_last = row(num_partial_lookups, 0);
+        vec_ops.push(vec_op(
+            len - shift,
+            addr_last,
+            row(num_partial_lookups, shift),
+            addr_last,
+            VecOpType::ADD,
+            VecOpSrc::VV,
+        ));
+        shift *= 2;
+    }
+    for j in 1..num_partial_lookups {
+        vec_ops.push(vec_op(
+            len - 1,
+            row(j, 0),
+            row(num_partial_lookups, 1),
+            row(j, 0),
+            VecOpType::ADD,
+            VecOpSrc::VV,
+        ));
+    }
+
+    // the last write of each part of the polys is final, the LUT rows of a partial Sum are
+    // not all overwritten when the next row is added
+    let polys = row(0, 0)..row(num_partial_lookups, len);
+    let mut written = HashSet::new();
+    for op in vec_ops.iter_mut().rev() {
+        if polys.contains(&op.addr_output) && written.insert(op.addr_output) {
+            op.is_final_output = true;
+        }
+    }
+
+    // freed by the caller once the ops have run
+    mem.defer_free("lookup_differences");
+    mem.defer_free("lookup_inverses");
+    mem.defer_free("lookup_combos");
+    mem.defer_free("lookup_delta_power");
+    for id in ["lookup_inv_t0", "lookup_inv_t1", "lookup_inv_t2"] {
+        mem.defer_free(id);
+    }
+    vec_ops
+}
+
+// The lookup polynomials of all challenges, after the Z's and partial products in addr_output
+pub fn compute_all_lookup_polys<F, C, const D: usize>(
+    sys: &mut System,
+    common_data: &CommonCircuitData<F, D>,
+    prover_data: &ProverOnlyCircuitData<F, C, D>,
+    addr_witness: usize,
+    num_wires: usize,
+    addr_deltas: usize,
+    addr_output: usize,
+) where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    let degree = common_data.degree();
+    let num_wires_used = (3 * num_lut_slots(common_data)).max(2 * num_lu_slots(common_data));
+    for lookup_wire in &prover_data.lookup_rows {
+        let first_row = lookup_wire.last_lu_gate;
+        let len = lookup_wire.first_lut_gate + 1 - first_row;
+        let addr_rows = sys
+            .mem
+            .alloc("lookup_rows", num_wires_used * len * SIZE_F)
+            .unwrap();
+        let transpose = Transpose::new(
+            TransposeConfig {
+                addr_input: addr_witness + first_row * num_wires * SIZE_F,
+                addr_output: addr_rows,
+                width: num_wires,
+                height: len,
+                start: 0,
+                end: num_wires_used,
+                reverse: false,
+                extension: 1,
+            },
+            &sys.hw,
+        );
+        sys.run_once(&transpose);
+
+        for i in 0..common_data.config.num_challenges {
+            let deferred_mark = sys.mem.deferred_mark();
+            let vec_ops = lookup_polys(
+                &mut sys.mem,
+                common_data,
+                lookup_wire,
+                addr_rows,
+                addr_deltas + i * NUM_COINS_LOOKUP * SIZE_F,
+                addr_output + i * common_data.num_lookup_polys * degree * SIZE_F,
+                sys.hw.arch.rich_vec_isa,
+            );
+            sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+            sys.mem.free_deferred(deferred_mark);
+        }
+        sys.mem.free("lookup_rows");
+    }
+}
+
+// The polynomial of each LUT at the delta of each challenge, for the RE constraints, like
+// plonky2's get_lut_poly: the combos input + b * output of the LUT padded with zeros to its
+// rows, as the coefficients from the highest degree. Computed on the CPU, as in plonky2.
+pub fn lut_re_poly_evals<F: RichField + Extendable<D>, const D: usize>(
+    common_data: &CommonCircuitData<F, D>,
+    deltas: &[F],
+) -> Vec<F> {
+    let num_lut_slots = num_lut_slots(common_data);
+    deltas
+        .chunks(NUM_COINS_LOOKUP)
+        .flat_map(|deltas| {
+            let b = deltas[LookupChallenges::ChallengeB as usize];
+            let delta = deltas[LookupChallenges::ChallengeDelta as usize];
+            common_data.luts.iter().map(move |lut| {
+                let degree = num_lut_slots * ceil_div_usize(lut.len(), num_lut_slots);
+                let eval = lut.iter().fold(F::ZERO, |acc, &(input, output)| {
+                    acc * delta + F::from_canonical_u16(input) + b * F::from_canonical_u16(output)
+                });
+                eval * delta.exp_u64((degree - lut.len()) as u64)
+            })
+        })
+        .collect()
+}
+
+// the number of lookup constraints of a challenge in the vanishing polynomial
+pub fn num_lookup_constraints<F: RichField + Extendable<D>, const D: usize>(
+    common_data: &CommonCircuitData<F, D>,
+) -> usize {
+    if common_data.num_lookup_polys == 0 {
+        return 0;
+    }
+    4 + common_data.luts.len() + 2 * (common_data.num_lookup_polys - 1)
+}
+
+// Where check_lookup_constraints_batch finds the lookup polynomials of a batch, at the points
+// and the next points, and the lookup challenges and LUT evaluations of its challenge
+#[derive(Debug, Copy, Clone)]
+pub struct LookupVarsBatch {
+    pub addr_local_lookup_zs: usize,
+    pub addr_next_lookup_zs: usize,
+    pub addr_deltas: usize,
+    pub addr_lut_re_poly_evals: usize,
+}
+
+// The lookup constraints of one challenge on a batch, like plonky2's
+// check_lookup_constraints_batch. Lookup poly q of the challenge is the column q of
+// addr_local_lookup_zs and addr_next_lookup_zs, and constraint j goes to column j of addr_res.
+pub fn check_lookup_constraints_batch<F: RichField + Extendable<D>, const D: usize>(
+    mem: &mut MemAlloc,
+    common_data: &CommonCircuitData<F, D>,
+    vars_batch: EvaluationVarsBaseBatch,
+    lookup_vars: LookupVarsBatch,
+    addr_res: usize,
+) -> Vec<VecOpConfig> {
+    let LookupVarsBatch {
+        addr_local_lookup_zs,
+        addr_next_lookup_zs,
+        addr_deltas,
+        addr_lut_re_poly_evals,
+    } = lookup_vars;
+    let num_lut_slots = num_lut_slots(common_data);
+    let (num_sldc_polys, lut_chunks, lu_chunks) =
+        sldc_chunks(common_data, common_data.quotient_degree_factor - 1);
+    let num_selectors = common_data.selectors_info.num_selectors();
+
+    let mut b = VecBuilder::new(mem, vars_batch.len());
+    let wire = |b: &VecBuilder, j| b.column(vars_batch.addr_local_wires, j);
+    let selector =
+        |b: &VecBuilder, r: usize| b.column(vars_batch.addr_local_constants, num_selectors + r);
+    let delta =
+        |b: &VecBuilder, challenge: LookupChallenges| b.scalar_at(addr_deltas, challenge as usize);
+    let z_re = b.column(addr_local_lookup_zs, 0);
+    let next_z_re = b.column(addr_next_lookup_zs, 0);
+    let z_x = |b: &VecBuilder, poly: usize| b.column(addr_local_lookup_zs, 1 + poly);
+    let z_gx = |b: &VecBuilder, poly: usize| b.column(addr_next_lookup_zs, 1 + poly);
+
+    let mut constraints = Vec::new();
+    let filtered = |b: &mut VecBuilder, constraints: &mut Vec<VecHandle>, r: usize, term| {
+        let out = b.column(addr_res, constraints.len());
+        let filter = selector(b, r);
+        b.mul_into(out, filter, term);
+        constraints.push(out);
+    };
+
+    let (last_ldc, init_sre) = (z_x(&b, num_sldc_polys - 1), z_x(&b, 0));
+    filtered(
+        &mut b,
+        &mut constraints,
+        LookupSelectors::LastLdc as usize,
+        last_ldc,
+    );
+    filtered(
+        &mut b,
+        &mut constraints,
+        LookupSelectors::InitSre as usize,
+        init_sre,
+    );
+    filtered(
+        &mut b,
+        &mut constraints,
+        LookupSelectors::InitSre as usize,
+        z_re,
+    );
+    for r in 0..common_data.luts.len() {
+        let eval = b.scalar_at(addr_lut_re_poly_evals, r);
+        let term = b.sub(z_re, eval);
+        let r = start_end(common_data) + r;
+        filtered(&mut b, &mut constraints, r, term);
+    }
+
+    // the combos of the lookup table for RE, and alpha - combo for the Sums and LDCs
+    let combo = |b: &mut VecBuilder, input: usize, output: usize, challenge| {
+        let output = wire(b, output);
+        let scaled = b.mul(output, delta(b, challenge));
+        b.add(wire(b, input), scaled)
+    };
+    let mut sum = next_z_re;
+    for s in 0..num_lut_slots {
+        let combo = combo(
+            &mut b,
+            LookupTableGate::wire_ith_looked_inp(s),
+            LookupTableGate::wire_ith_looked_out(s),
+            LookupChallenges::ChallengeB,
+        );
+        let scaled = b.mul(sum, delta(&b, LookupChallenges::ChallengeDelta));
+        sum = b.add(scaled, combo);
+    }
+    let term = b.sub(z_re, sum);
+    filtered(
+        &mut b,
+        &mut constraints,
+        LookupSelectors::TransSre as usize,
+        term,
+    );
+
+    // prod(alpha - combo) and sum_i(m_i * prod_{j != i}(alpha - combo_j)) of a chunk of slots,
+    // accumulated one slot at a time, with m_i = 1 for the LU slots
+    let [one] = b.alloc_scalars(&[GoldilocksField::ONE])[..] else {
+        unreachable!()
+    };
+    let products = |b: &mut VecBuilder, slots: &[(usize, usize, Option<usize>)]| {
+        let mut acc: Option<(VecHandle, VecHandle)> = None;
+        for &(input, output, multiplicity) in slots {
+            let combo = combo(b, input, output, LookupChallenges::ChallengeA);
+            let factor = b.sub(delta(b, LookupChallenges::ChallengeAlpha), combo);
+            let weight = multiplicity.map(|m| wire(b, m));
+            acc = Some(match acc {
+                None => (factor, weight.unwrap_or(one)),
+                Some((prod, sum)) => {
+                    let sum = b.mul(sum, factor);
+                    let weighted = match weight {
+                        Some(weight) => b.mul(weight, prod),
+                        None => prod,
+                    };
+                    (b.mul(prod, factor), b.add(sum, weighted))
+                }
+            });
+        }
+        acc
+    };
+    for poly in 0..num_sldc_polys {
+        let prev = if poly == 0 {
+            z_gx(&b, num_sldc_polys - 1)
+        } else {
+            z_x(&b, poly - 1)
+        };
+        let diff = b.sub(z_x(&b, poly), prev);
+
+        let lut_slots = lut_chunks[poly]
+            .clone()
+            .map(|s| {
+                (
+                    LookupTableGate::wire_ith_looked_inp(s),
+                    LookupTableGate::wire_ith_looked_out(s),
+                    Some(LookupTableGate::wire_ith_multiplicity(s)),
+                )
+            })
+            .collect::<Vec<_>>();
+        let term = match products(&mut b, &lut_slots) {
+            Some((prod, sum)) => {
+                let term = b.mul(prod, diff);
+                b.sub(term, sum)
+            }
+            None => diff,
+        };
+        filtered(
+            &mut b,
+            &mut constraints,
+            LookupSelectors::TransSre as usize,
+            term,
+        );
+
+        let lu_slots = lu_chunks[poly]
+            .clone()
+            .map(|s| {
+                (
+                    LookupGate::wire_ith_looking_inp(s),
+                    LookupGate::wire_ith_looking_out(s),
+                    None,
+                )
+            })
+            .collect::<Vec<_>>();
+        let term = match products(&mut b, &lu_slots) {
+            Some((prod, sum)) => {
+                let term = b.mul(prod, diff);
+                b.add(term, sum)
+            }
+            None => diff,
+        };
+        filtered(
+            &mut b,
+            &mut constraints,
+            LookupSelectors::TransLdc as usize,
+            term,
+        );
+    }
+
+    for out in constraints {
+        b.set_final(out);
+    }
+    b.finish()
+}
@@ -5,4 +5,8 @@ pub mod zero_poly_coset;
 pub mod vars;
 pub mod vanishing_poly;
 pub mod gates;
+pub mod gate_lowering;
+pub mod gate_descriptor;
+pub mod preflight;
+pub mod lookup;
 pub mod proofs;
\ No newline at end of file
@@ -5,12 +5,16 @@ use plonky2::fri::FriParams;
 use plonky2::hash::hash_types::RichField;
 use plonky2::util::log2_strict;
 
-use crate::config::enable_config::ENABLE_CONFIG;
+use crate::config::HwConfig;
 use crate::kernel::fft::{Fft, FftConfig, FftDirection};
+use crate::kernel::kernel::FetchEdit;
 use crate::kernel::memory_copy::{MemCpy, MemCpyConfig};
+use crate::kernel::transpose::{Transpose, TransposeConfig};
 use crate::kernel::tree::{Tree, TreeConfig};
 use crate::kernel::vector_chain::VectorChain;
-use crate::kernel::vector_operation::{VecOpConfig, VecOpExtension, VecOpSrc, VecOpType};
+use crate::kernel::vector_operation::{
+    VecOpConfig, VecOpExtension, VecOpExtension2, VecOpSrc, VecOpType,
+};
 use crate::plonk::challenger::Challenger;
 use crate::system::system::System;
 use crate::util::{bit_reverse, BATCH_SIZE, SALT_SIZE, SIZE_F};
@@ -45,6 +49,41 @@ impl PolynomialBatch {
         merged_input: Vec<MemCpy>,
     ) -> Self {
         debug!("commitment: {:?}", name);
+        let pb = Self::alloc(
+            name,
+            sys,
+            degree,
+            num_kernels,
+            rate_bits,
+            blinding,
+            cap_height,
+        );
+        pb.from_values(
+            sys,
+            addr_values,
+            degree,
+            num_kernels,
+            rate_bits,
+            blinding,
+            cap_height,
+            transposed_input,
+            merged_input,
+        );
+        pb.free_salt(sys);
+        pb
+    }
+
+    // the buffers of a commitment whose polynomials are written by the caller, then
+    // committed with from_coeffs
+    pub fn alloc(
+        name: &str,
+        sys: &mut System,
+        degree: usize,
+        num_kernels: usize,
+        rate_bits: usize,
+        blinding: bool,
+        cap_height: usize,
+    ) -> Self {
         let salt_size = if blinding { SALT_SIZE } else { 0 };
 
         let salt_id = format!("{}{}", "salt_poly_cpu", name);
@@ -54,8 +93,12 @@ impl PolynomialBatch {
         let transposed_leaves_id = format!("{}{}", "transposed_leaves", name);
         let polynomials_id = format!("{}{}", "polynomials", name);
 
-        let padding_length =
-            Tree::get_padding_length(degree << rate_bits, num_kernels + salt_size, cap_height);
+        let padding_length = Tree::get_padding_length(
+            degree << rate_bits,
+            num_kernels + salt_size,
+            cap_height,
+            &sys.hw.arch,
+        );
 
         let addr_salt = if blinding {
             sys.mem
@@ -99,7 +142,7 @@ impl PolynomialBatch {
             .mem
             .alloc(&polynomials_id, degree * num_kernels * SIZE_F)
             .unwrap();
-        let pb = Self {
+        Self {
             name: name.to_string(),
             addr_leaves: addr_leaves,
             addr_transposed_leaves: addr_transposed_leaves,
@@ -112,23 +155,14 @@ impl PolynomialBatch {
             rate_bits,
             blinding,
             padding_length,
-        };
+        }
+    }
 
-        pb.from_values(
-            sys,
-            addr_values,
-            degree,
-            num_kernels,
-            rate_bits,
-            blinding,
-            cap_height,
-            transposed_input,
-            merged_input,
-        );
-        if blinding {
-            sys.mem.free(&salt_id);
+    // the salt is only read by from_coeffs
+    pub fn free_salt(&self, sys: &mut System) {
+        if self.blinding {
+            sys.mem.free(&format!("{}{}", "salt_poly_cpu", self.name));
         }
-        pb
     }
 
     // only for commitment addr, indicates CPU to ASIC copy
@@ -232,27 +266,33 @@ impl PolynomialBatch {
     ) {
         let addr_coeffs = self.addr_polynomials;
 
-        let mut ifft_kernel = Fft::new(FftConfig {
-            lg_n: log2_strict(degree),
-            k: num_kernels,
-            direction: FftDirection::NN,
-            addr_input: addr_values,
-            addr_tmp: 1 << 60,
-            addr_output: addr_coeffs,
-            inverse: true,
-            rate_bits: 0,
-            coset: false,
-            extension: 1,
-            transposed_input: transposed_input,
-        });
-        for memcpy in &merged_input {
-            ifft_kernel.prefetch.addr_trans(memcpy);
-        }
+        let mut ifft_kernel = Fft::new(
+            FftConfig {
+                lg_n: log2_strict(degree),
+                k: num_kernels,
+                direction: FftDirection::NN,
+                addr_input: addr_values,
+                addr_tmp: 1 << 60,
+                addr_output: addr_coeffs,
+                inverse: true,
+                rate_bits: 0,
+                coset: false,
+                extension: 1,
+                transposed_input: transposed_input,
+            },
+            &sys.hw,
+        );
+        ifft_kernel.edit(FetchEdit::PrefetchAddrTrans(
+            merged_input
+                .iter()
+                .map(|memcpy| memcpy.config.clone())
+                .collect(),
+        ));
         sys.run_once(&ifft_kernel);
         self.from_coeffs(sys, rate_bits, blinding, cap_height, degree, num_kernels);
     }
 
-    fn from_coeffs(
+    pub fn from_coeffs(
         &self,
         sys: &mut System,
         rate_bits: usize,
@@ -279,6 +319,7 @@ impl PolynomialBatch {
                         input_length: degree,
                     },
                     false,
+                    &sys.hw,
                 )
             })
             .collect::<Vec<_>>();
@@ -287,19 +328,23 @@ impl PolynomialBatch {
             .mem
             .alloc("coeffs_tmp", degree * num_kernels * SIZE_F)
             .unwrap();
-        let mut coset_fft_kernel = Fft::new(FftConfig {
-            lg_n: log2_strict(degree),
-            k: num_kernels,
-            direction: FftDirection::NN,
-            addr_input: addr_coeffs,
-            addr_output: addr_leaves,
-            addr_tmp: addr_addr_coeffs_tmp,
-            inverse: false,
-            rate_bits,
-            coset: true,
-            extension: 1,
-            transposed_input: false,
-        });
+        // the leaves are in bit-reversed order, like in plonky2
+        let mut coset_fft_kernel = Fft::new(
+            FftConfig {
+                lg_n: log2_strict(degree),
+                k: num_kernels,
+                direction: FftDirection::NR,
+                addr_input: addr_coeffs,
+                addr_output: addr_leaves,
+                addr_tmp: addr_addr_coeffs_tmp,
+                inverse: false,
+                rate_bits,
+                coset: true,
+                extension: 1,
+                transposed_input: false,
+            },
+            &sys.hw,
+        );
         sys.mem.free("coeffs_tmp");
 
         if blinding {
@@ -311,28 +356,34 @@ impl PolynomialBatch {
                         input_length: degree,
                     },
                     false,
+                    &sys.hw,
                 ));
             }
         }
 
-        coset_fft_kernel.prefetch.addr_trans_vec(&padding_kernel);
-        coset_fft_kernel.drain.addr_trans_vec(&padding_kernel);
+        let padding_configs = padding_kernel
+            .iter()
+            .map(|memcpy| memcpy.config.clone())
+            .collect::<Vec<_>>();
+        coset_fft_kernel.edit(FetchEdit::PrefetchAddrTransVec(padding_configs.clone()));
+        coset_fft_kernel.edit(FetchEdit::DrainAddrTransVec(padding_configs.clone()));
 
         sys.run_once(&coset_fft_kernel);
 
-        let mut tree_kernel = Tree::new(TreeConfig {
-            leaf_length: num_kernels + salt_size,
-            cap_height,
-            num_leaves: degree,
-            addr_leaves: addr_leaves,
-            addr_transposed_leaves: self.addr_transposed_leaves,
-            addr_digest_buf: addr_digest_buf,
-            addr_cap_buf,
-            transposed_leaves: true,
-        });
-        padding_kernel
-            .iter()
-            .for_each(|memcpy| tree_kernel.prefetch.addr_trans(memcpy));
+        let mut tree_kernel = Tree::new(
+            TreeConfig {
+                leaf_length: num_kernels + salt_size,
+                cap_height,
+                num_leaves: degree,
+                addr_leaves: addr_leaves,
+                addr_transposed_leaves: self.addr_transposed_leaves,
+                addr_digest_buf: addr_digest_buf,
+                addr_cap_buf,
+                transposed_leaves: true,
+            },
+            &sys.hw,
+        );
+        tree_kernel.edit(FetchEdit::PrefetchAddrTransVec(padding_configs));
         sys.run_once(&tree_kernel);
     }
 
@@ -346,6 +397,17 @@ impl PolynomialBatch {
         addr_zeta_g: usize,
     ) {
         debug!("prove_openings");
+        if sys.functional {
+            return Self::prove_openings_functional(
+                sys,
+                instance,
+                oracles,
+                challenger,
+                fri_params,
+                addr_zeta,
+                addr_zeta_g,
+            );
+        }
         let addr_alpha = sys.mem.alloc("alpha", D * SIZE_F).unwrap();
         challenger.get_extension_challenge::<D>(sys, addr_alpha);
         let mut alpha = ReducingFactor::new(addr_alpha);
@@ -428,7 +490,7 @@ impl PolynomialBatch {
             addr_point = addr_zeta_g;
         }
 
-        sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
+        sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
 
         let addr_final_coeff = sys
             .mem
@@ -448,19 +510,22 @@ impl PolynomialBatch {
             .unwrap();
 
         final_poly_degree = final_poly_degree << fri_params.config.rate_bits;
-        sys.run_once(&Fft::new(FftConfig {
-            lg_n: log2_strict(final_poly_degree),
-            k: 1,
-            direction: FftDirection::NR,
-            addr_input: addr_final_poly,
-            addr_tmp: 1 << 60,
-            addr_output: addr_final_values,
-            inverse: false,
-            rate_bits: fri_params.config.rate_bits,
-            coset: true,
-            extension: D,
-            transposed_input: false,
-        }));
+        sys.run_once(&Fft::new(
+            FftConfig {
+                lg_n: log2_strict(final_poly_degree),
+                k: 1,
+                direction: FftDirection::NR,
+                addr_input: addr_final_poly,
+                addr_tmp: 1 << 60,
+                addr_output: addr_final_values,
+                inverse: false,
+                rate_bits: fri_params.config.rate_bits,
+                coset: true,
+                extension: D,
+                transposed_input: false,
+            },
+            &sys.hw,
+        ));
 
         fri_proof::<D>(
             sys,
@@ -474,11 +539,224 @@ impl PolynomialBatch {
         sys.mem.free("final_poly");
     }
 
+    // prove_openings as plonky2 computes it, for D = 2: the batches are reduced by the powers
+    // of alpha, divided by (X - point) one coefficient after the other, and summed into the
+    // final polynomial shifted by alpha^count. The components of a value are kept apart
+    fn prove_openings_functional<F: RichField + Extendable<D>, const D: usize>(
+        sys: &mut System,
+        instance: &FriInstanceInfo<F, D>,
+        oracles: &Vec<&Self>,
+        challenger: &mut Challenger,
+        fri_params: &FriParams,
+        addr_zeta: usize,
+        addr_zeta_g: usize,
+    ) {
+        if D != 2 {
+            panic!("Unsupported D");
+        }
+        let rate_bits = fri_params.config.rate_bits;
+        let addr_alpha = sys.mem.alloc("alpha", D * SIZE_F).unwrap();
+        challenger.get_extension_challenge::<D>(sys, addr_alpha);
+
+        let degree = instance
+            .batches
+            .iter()
+            .flat_map(|batch| batch.polynomials.iter())
+            .map(|fri_poly| 1 << oracles[fri_poly.oracle_index].degree_log)
+            .max()
+            .unwrap();
+        let components = |addr: usize, length: usize| [addr, addr + length * SIZE_F];
+        let addr_final_poly = sys.mem.alloc("final_poly", degree * D * SIZE_F).unwrap();
+        let addr_tmp = sys.mem.alloc("fri_tmp", degree * D * SIZE_F).unwrap();
+        // W, W * alpha1, alpha^count, W * (alpha^count)1 and W * point1
+        let addr_scalars = sys.mem.alloc("fri_scalars_cpu", 6 * SIZE_F).unwrap();
+        sys.write_cpu(addr_scalars, &[<F as Extendable<D>>::W]);
+        let addr_w_alpha1 = addr_scalars + SIZE_F;
+        let addr_pow = addr_scalars + 2 * SIZE_F;
+        let addr_w_pow1 = addr_scalars + 4 * SIZE_F;
+        let addr_w_point1 = addr_scalars + 5 * SIZE_F;
+        let w_mul = |addr_input: usize, addr_output: usize| VecOpConfig {
+            vector_length: 1,
+            addr_input_0: addr_scalars,
+            addr_input_1: addr_input + SIZE_F,
+            addr_output,
+            op_type: VecOpType::MUL,
+            op_src: VecOpSrc::VS,
+            is_final_output: false,
+        };
+        let add = |vector_length, addr_input_0, addr_input_1, addr_output| VecOpConfig {
+            vector_length,
+            addr_input_0,
+            addr_input_1,
+            addr_output,
+            op_type: VecOpType::ADD,
+            op_src: VecOpSrc::VV,
+            is_final_output: true,
+        };
+        sys.run_once(&VectorChain::new(
+            vec![w_mul(addr_alpha, addr_w_alpha1)],
+            &sys.mem,
+            &sys.hw,
+        ));
+
+        for (batch, addr_point) in instance.batches.iter().zip([addr_zeta, addr_zeta_g]) {
+            let addr_composition_poly = sys
+                .mem
+                .alloc("composition_poly", degree * D * SIZE_F)
+                .unwrap();
+            let addr_quotient = sys.mem.alloc("quotient", degree * D * SIZE_F).unwrap();
+            let composition_poly = components(addr_composition_poly, degree);
+            let quotient = components(addr_quotient, degree);
+
+            // composition = sum alpha^k p_k, by Horner from the last polynomial
+            let mut vec_ops = Vec::new();
+            for fri_poly in batch.polynomials.iter().rev() {
+                let oracle = oracles[fri_poly.oracle_index];
+                let poly_degree = 1 << oracle.degree_log;
+                vec_ops.extend(VecOpExtension2::mul_scalar_exact(
+                    degree,
+                    composition_poly,
+                    addr_alpha,
+                    addr_w_alpha1,
+                    composition_poly,
+                    addr_tmp,
+                    false,
+                ));
+                vec_ops.push(add(
+                    poly_degree,
+                    composition_poly[0],
+                    oracle.addr_polynomials + fri_poly.polynomial_index * poly_degree * SIZE_F,
+                    composition_poly[0],
+                ));
+            }
+            // alpha^count, count being the number of polynomials of the batch
+            vec_ops.extend([
+                add(1, addr_alpha, 0, addr_pow),
+                add(1, addr_alpha + SIZE_F, 0, addr_pow + SIZE_F),
+            ]);
+            for _ in 1..batch.polynomials.len() {
+                vec_ops.extend(VecOpExtension2::mul_scalar_exact(
+                    1,
+                    components(addr_pow, 1),
+                    addr_alpha,
+                    addr_w_alpha1,
+                    components(addr_pow, 1),
+                    addr_tmp,
+                    false,
+                ));
+            }
+            vec_ops.extend([
+                w_mul(addr_pow, addr_w_pow1),
+                w_mul(addr_point, addr_w_point1),
+            ]);
+            sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+
+            // quotient = composition / (X - point), from the leading coefficient down
+            let at = |addrs: [usize; 2], i: usize| addrs.map(|addr| addr + i * SIZE_F);
+            for chunk in (0..degree - 1).rev().collect::<Vec<_>>().chunks(BATCH_SIZE) {
+                let mut vec_ops = Vec::new();
+                for &i in chunk {
+                    vec_ops.extend(VecOpExtension2::mul_scalar_exact(
+                        1,
+                        at(quotient, i + 1),
+                        addr_point,
+                        addr_w_point1,
+                        at(quotient, i),
+                        addr_tmp,
+                        false,
+                    ));
+                    for e in 0..D {
+                        let addr = at(quotient, i)[e];
+                        vec_ops.push(add(1, addr, at(composition_poly, i + 1)[e], addr));
+                    }
+                }
+                sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+            }
+
+            // final_poly = final_poly * alpha^count + quotient
+            let final_poly = components(addr_final_poly, degree);
+            let mut vec_ops = VecOpExtension2::mul_scalar_exact(
+                degree,
+                final_poly,
+                addr_pow,
+                addr_w_pow1,
+                final_poly,
+                addr_tmp,
+                false,
+            );
+            for e in 0..D {
+                vec_ops.push(add(degree, final_poly[e], quotient[e], final_poly[e]));
+            }
+            sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+            sys.mem.free("composition_poly");
+            sys.mem.free("quotient");
+        }
+
+        // the LDE of the final polynomial, its coefficients padded with zeros
+        let lde_degree = degree << rate_bits;
+        let addr_final_coeff = sys
+            .mem
+            .alloc("final_coeff", lde_degree * D * SIZE_F)
+            .unwrap();
+        let addr_final_values = sys
+            .mem
+            .alloc("final_values", lde_degree * D * SIZE_F)
+            .unwrap();
+        sys.run_once(&Transpose::new(
+            TransposeConfig {
+                addr_input: addr_final_poly,
+                addr_output: addr_final_coeff,
+                width: degree,
+                height: D,
+                reverse: false,
+                extension: 1,
+                start: 0,
+                end: degree,
+            },
+            &sys.hw,
+        ));
+        sys.run_once(&Fft::new(
+            FftConfig {
+                lg_n: log2_strict(lde_degree),
+                k: 1,
+                direction: FftDirection::NR,
+                addr_input: addr_final_coeff,
+                addr_tmp: 1 << 60,
+                addr_output: addr_final_values,
+                inverse: false,
+                rate_bits,
+                coset: true,
+                extension: D,
+                transposed_input: false,
+            },
+            &sys.hw,
+        ));
+        sys.mem.free("fri_scalars_cpu");
+        sys.mem.free("fri_tmp");
+
+        fri_proof::<D>(
+            sys,
+            oracles,
+            addr_final_coeff,
+            addr_final_values,
+            lde_degree,
+            challenger,
+            fri_params,
+        );
+        sys.mem.free("final_poly");
+    }
+
     pub fn num_layers(&self, cap_height: usize) -> usize {
         self.degree_log + self.rate_bits - cap_height
     }
 
-    pub fn prove(&self, index: usize, cap_height: usize, addr_proof: usize) -> Vec<MemCpy> {
+    pub fn prove(
+        &self,
+        index: usize,
+        cap_height: usize,
+        addr_proof: usize,
+        hw: &HwConfig,
+    ) -> Vec<MemCpy> {
         let mut res = Vec::new();
         let num_layers = self.num_layers(cap_height);
 
@@ -505,7 +783,8 @@ impl PolynomialBatch {
                     addr_output: addr_proof + i * Tree::DIGEST_LENGTH * SIZE_F,
                     input_length: Tree::DIGEST_LENGTH,
                 },
-                unsafe { ENABLE_CONFIG.hash },
+                hw.enable.hash,
+                hw,
             ));
         }
         res
@@ -535,6 +814,7 @@ fn divide_by_linear<const D: usize>(
             addr_res + (degree - i - 1) * SIZE_F,
             VecOpSrc::VS,
             false,
+            sys.hw.arch.rich_vec_isa,
         ));
         vec_ops.extend(VecOpExtension::<D>::add(
             1,
@@ -620,6 +900,7 @@ impl ReducingFactor {
             self.addr_base,
             addr_base_exp,
             false,
+            sys.hw.arch.rich_vec_isa,
         ));
         vec_ops.extend(VecOpExtension::<D>::mul(
             &mut sys.mem,
@@ -629,6 +910,7 @@ impl ReducingFactor {
             addr_p,
             VecOpSrc::VS,
             true,
+            sys.hw.arch.rich_vec_isa,
         ));
         sys.mem.free("base_exp");
         self.count = 0;
@@ -0,0 +1,310 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_data::{CommonCircuitData, ProverOnlyCircuitData};
+use plonky2::plonk::config::GenericConfig;
+use serde::Serialize;
+
+use crate::plonk::gate_descriptor::GateDescriptor;
+use crate::plonk::gate_lowering;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum GateSupport {
+    HandWritten,       // a port in plonk::gates
+    Symbolic,          // registered in plonk::gate_lowering
+    Estimated(String), // neither, costed from its shape by gates::eval_unfiltered_base_estimated
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GateReport {
+    pub id: String,
+    pub instances: usize, // rows using the gate
+    pub support: GateSupport,
+}
+
+// What the simulator makes of a circuit, available right after it is built, so that a circuit
+// it can only approximate is known before its witness is generated
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitReport {
+    pub gates: Vec<GateReport>,
+    pub num_lookup_tables: usize,
+    pub num_lookup_polys: usize,
+    pub zero_knowledge: bool,
+    pub num_challenges: usize,
+    pub extension_degree: usize,
+    pub degree_bits: usize,
+    pub rate_bits: usize,
+    pub cap_height: usize,
+    pub num_query_rounds: usize,
+    pub proof_of_work_bits: u32,
+    pub reduction_arity_bits: Vec<usize>,
+}
+
+impl CircuitReport {
+    pub fn new<F, C, const D: usize>(
+        common_data: &CommonCircuitData<F, D>,
+        prover_data: &ProverOnlyCircuitData<F, C, D>,
+    ) -> Self
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+    {
+        let instances = Self::count_instances(common_data, prover_data);
+        let gates = common_data
+            .gates
+            .iter()
+            .zip(instances)
+            .map(|(gate, instances)| GateReport {
+                id: gate.0.id(),
+                instances,
+                support: match (GateDescriptor::of(gate), gate_lowering::lowered(gate)) {
+                    (Ok(_), _) => GateSupport::HandWritten,
+                    (Err(_), Some(_)) => GateSupport::Symbolic,
+                    (Err(e), None) => GateSupport::Estimated(format!("{:#}", e)),
+                },
+            })
+            .collect();
+        let fri_params = &common_data.fri_params;
+        Self {
+            gates,
+            num_lookup_tables: common_data.luts.len(),
+            num_lookup_polys: common_data.num_lookup_polys,
+            zero_knowledge: common_data.config.zero_knowledge,
+            num_challenges: common_data.config.num_challenges,
+            extension_degree: D,
+            degree_bits: fri_params.degree_bits,
+            rate_bits: fri_params.config.rate_bits,
+            cap_height: fri_params.config.cap_height,
+            num_query_rounds: fri_params.config.num_query_rounds,
+            proof_of_work_bits: fri_params.config.proof_of_work_bits,
+            reduction_arity_bits: fri_params.reduction_arity_bits.clone(),
+        }
+    }
+
+    // the rows of each gate, from the selector polynomials that lead the constants: on the
+    // rows of its group, selector g holds the index of the gate, elsewhere a value past them
+    fn count_instances<F, C, const D: usize>(
+        common_data: &CommonCircuitData<F, D>,
+        prover_data: &ProverOnlyCircuitData<F, C, D>,
+    ) -> Vec<usize>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+    {
+        let num_gates = common_data.gates.len();
+        let num_selectors = common_data.selectors_info.num_selectors();
+        let mut instances = vec![0; num_gates];
+        for poly in &prover_data.constants_sigmas_commitment.polynomials[..num_selectors] {
+            for value in poly.clone().fft().values {
+                let gate = value.to_canonical_u64() as usize;
+                if gate < num_gates {
+                    instances[gate] += 1;
+                }
+            }
+        }
+        instances
+    }
+
+    pub fn has_lookups(&self) -> bool {
+        self.num_lookup_tables > 0 || self.num_lookup_polys > 0
+    }
+
+    // everything the simulator only approximates, empty if the circuit is modelled exactly
+    pub fn problems(&self) -> Vec<String> {
+        self.gates
+            .iter()
+            .filter_map(|gate| match &gate.support {
+                GateSupport::Estimated(reason) => Some(format!(
+                    "{}, its cost is estimated from its degree and number of constraints",
+                    reason
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn check(&self) -> Result<()> {
+        let problems = self.problems();
+        if !problems.is_empty() {
+            bail!("approximated circuit: {}", problems.join("; "));
+        }
+        Ok(())
+    }
+
+    pub fn log(&self) {
+        for gate in &self.gates {
+            info!("{:>8} x {} ({:?})", gate.instances, gate.id, gate.support);
+        }
+        info!(
+            "degree_bits {}, D = {}, {} challenges, zero_knowledge {}, {} lookup tables",
+            self.degree_bits,
+            self.extension_degree,
+            self.num_challenges,
+            self.zero_knowledge,
+            self.num_lookup_tables
+        );
+        info!(
+            "FRI: rate_bits {}, cap_height {}, {} queries, {} proof of work bits, arities {:?}",
+            self.rate_bits,
+            self.cap_height,
+            self.num_query_rounds,
+            self.proof_of_work_bits,
+            self.reduction_arity_bits
+        );
+        for problem in self.problems() {
+            warn!("{}", problem);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plonky2::field::extension::Extendable;
+    use plonky2::field::types::Field;
+    use plonky2::gates::gate::Gate;
+    use plonky2::gates::lookup_table::LookupTable;
+    use plonky2::iop::ext_target::ExtensionTarget;
+    use plonky2::iop::generator::{generate_partial_witness, WitnessGeneratorRef};
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use plonky2::plonk::vars::{EvaluationTargets, EvaluationVars};
+    use plonky2::util::serialization::{Buffer, IoResult};
+    use std::sync::Arc;
+
+    use crate::config::{Backend, HwConfig, RamConfig};
+    use crate::memory::memory_allocator::MemAlloc;
+    use crate::plonk::prover::prove_with_partition_witness;
+    use crate::system::system::System;
+
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<2>>::F;
+
+    #[test]
+    fn test_circuit_report() {
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let x = builder.add_virtual_target();
+        let mut y = x;
+        for _ in 0..100 {
+            y = builder.mul_add(y, y, x);
+        }
+        builder.register_public_input(y);
+        let data = builder.build::<C>();
+
+        let report = CircuitReport::new(&data.common, &data.prover_only);
+        let arithmetic = report
+            .gates
+            .iter()
+            .find(|gate| gate.id.starts_with("ArithmeticGate"))
+            .unwrap();
+        // 100 ops, 20 to a gate
+        assert_eq!(arithmetic.instances, 5);
+        assert_eq!(
+            report
+                .gates
+                .iter()
+                .map(|gate| gate.instances)
+                .sum::<usize>(),
+            1 << report.degree_bits
+        );
+        assert!(report.check().is_ok());
+
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let table: LookupTable = Arc::new((0..4).map(|i| (i, i * i)).collect());
+        let index = builder.add_lookup_table_from_pairs(table);
+        let x = builder.constant(F::TWO);
+        let y = builder.add_lookup_from_index(x, index);
+        builder.register_public_input(y);
+        let data = builder.build::<C>();
+
+        let report = CircuitReport::new(&data.common, &data.prover_only);
+        assert!(report.has_lookups());
+        assert!(report.check().is_ok());
+    }
+    // a gate that neither plonk::gates nor gate_lowering knows: wire 1 is the cube of wire 0
+    struct CubeGate;
+
+    impl<F: RichField + Extendable<D>, const D: usize> Gate<F, D> for CubeGate {
+        fn id(&self) -> String {
+            "CubeGate".into()
+        }
+
+        fn serialize(&self, _: &mut Vec<u8>, _: &CommonCircuitData<F, D>) -> IoResult<()> {
+            Ok(())
+        }
+
+        fn deserialize(_: &mut Buffer, _: &CommonCircuitData<F, D>) -> IoResult<Self> {
+            Ok(Self)
+        }
+
+        fn eval_unfiltered(&self, vars: EvaluationVars<F, D>) -> Vec<F::Extension> {
+            let x = vars.local_wires[0];
+            vec![vars.local_wires[1] - x * x * x]
+        }
+
+        fn eval_unfiltered_circuit(
+            &self,
+            builder: &mut CircuitBuilder<F, D>,
+            vars: EvaluationTargets<D>,
+        ) -> Vec<ExtensionTarget<D>> {
+            let x = vars.local_wires[0];
+            let cube = builder.cube_extension(x);
+            vec![builder.sub_extension(vars.local_wires[1], cube)]
+        }
+
+        fn generators(&self, _: usize, _: &[F]) -> Vec<WitnessGeneratorRef<F, D>> {
+            Vec::new()
+        }
+
+        fn num_wires(&self) -> usize {
+            2
+        }
+
+        fn num_constants(&self) -> usize {
+            0
+        }
+
+        fn degree(&self) -> usize {
+            3
+        }
+
+        fn num_constraints(&self) -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn test_estimated_gate() {
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        for _ in 0..10 {
+            builder.add_gate(CubeGate, vec![]);
+        }
+        let data = builder.build::<C>();
+
+        let report = CircuitReport::new(&data.common, &data.prover_only);
+        let cube = report
+            .gates
+            .iter()
+            .find(|gate| gate.id == "CubeGate")
+            .unwrap();
+        assert!(matches!(cube.support, GateSupport::Estimated(_)));
+        assert_eq!(cube.instances, 10);
+        assert!(report.check().is_err());
+
+        // the prover still goes through
+        let mut hw = HwConfig::default();
+        hw.timing.backend = Backend::Analytical;
+        let mut sys = System::new(
+            MemAlloc::new(1, 4096),
+            RamConfig::new("test_estimated_gate"),
+            hw,
+        );
+        let partition_witness =
+            generate_partial_witness(PartialWitness::new(), &data.prover_only, &data.common);
+        prove_with_partition_witness(&mut sys, &data.prover_only, &data.common, partition_witness);
+        assert_eq!(sys.report().estimated_gates, vec!["CubeGate".to_string()]);
+    }
+}
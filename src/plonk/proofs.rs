@@ -2,14 +2,16 @@ use plonky2::field::extension::Extendable;
 use plonky2::hash::hash_types::RichField;
 use plonky2::plonk::circuit_data::CommonCircuitData;
 
-use crate::config::arch_config::ARCH_CONFIG;
+use crate::kernel::kernel::FetchEdit;
 use crate::kernel::transpose::{Transpose, TransposeConfig};
 use crate::kernel::vector_chain::VectorChain;
-use crate::kernel::vector_operation::{VecOpExtension, VecOpSrc};
+use crate::kernel::vector_operation::{
+    VecOpConfig, VecOpExtension, VecOpExtension2, VecOpSrc, VecOpType,
+};
 use crate::memory::memory_allocator::MemAlloc;
 use crate::plonk::oracle::PolynomialBatch;
 use crate::system::system::System;
-use crate::util::SIZE_F;
+use crate::util::{SALT_SIZE, SIZE_F};
 
 pub struct OpeningSet<const D: usize> {
     pub addr_constants: usize,
@@ -68,7 +70,7 @@ impl<const D: usize> OpeningSet<D> {
         let addr_quotient_polys = sys
             .mem
             .alloc(
-                "quotient_polys",
+                "quotient_polys_eval",
                 quotient_polys_commitment.leaf_length * Self::SIZE_FE,
             )
             .unwrap();
@@ -77,31 +79,31 @@ impl<const D: usize> OpeningSet<D> {
             .alloc("wires_eval", wires_commitment.leaf_length * Self::SIZE_FE)
             .unwrap();
 
-        Self::eval_commitment(
+        Self::eval_commitment::<F>(
             sys,
             addr_zeta,
             constants_sigmas_commitment,
             addr_constants_sigmas_eval,
         );
-        Self::eval_commitment(
+        Self::eval_commitment::<F>(
             sys,
             addr_zeta,
             zs_partial_products_lookup_commitment,
             addr_zs_partial_products_lookup_eval,
         );
-        Self::eval_commitment(
+        Self::eval_commitment::<F>(
             sys,
             addr_zeta_g,
             zs_partial_products_lookup_commitment,
             addr_zs_partial_products_lookup_next_eval,
         );
-        Self::eval_commitment(
+        Self::eval_commitment::<F>(
             sys,
             addr_zeta,
             quotient_polys_commitment,
             addr_quotient_polys,
         );
-        Self::eval_commitment(sys, addr_zeta, wires_commitment, addr_wires_eval);
+        Self::eval_commitment::<F>(sys, addr_zeta, wires_commitment, addr_wires_eval);
 
         Self {
             addr_constants: addr_constants_sigmas_eval,
@@ -139,41 +141,173 @@ impl<const D: usize> OpeningSet<D> {
         mem.free("constants_sigmas_eval");
         mem.free("zs_partial_products_lookup_eval");
         mem.free("zs_partial_products_lookup_next_eval");
-        mem.free("quotient_polys");
+        mem.free("quotient_polys_eval");
         mem.free("wires_eval");
     }
 
-    fn eval_commitment(
+    // acc = acc * z + c over the coefficients, with the extension components of acc
+    // (component-major) updated by hand: (a0 + a1 X)(z0 + z1 X) = a0 z0 + W a1 z1 + (a0 z1 + a1 z0) X
+    fn eval_commitment<F: RichField + Extendable<D>>(
         sys: &mut System,
         addr_zeta: usize,
         commitment: &PolynomialBatch,
         addr_res: usize,
     ) {
+        if !sys.functional {
+            return Self::eval_commitment_model(sys, addr_zeta, commitment, addr_res);
+        }
+        if D != 2 {
+            panic!("Unsupported D");
+        }
         let addr_poly = commitment.addr_polynomials;
         let degree = 1 << commitment.degree_log;
-        let num_polys = commitment.leaf_length;
+        let num_polys = commitment.leaf_length - if commitment.blinding { SALT_SIZE } else { 0 };
+        let chunk_length = (sys.hw.arch.num_elems() / num_polys).max(1);
 
         let addr_eval_commitment_tmp = sys
             .mem
-            .alloc("eval_commitment_tmp", unsafe {
-                ARCH_CONFIG.array_length * num_polys * SIZE_F
-            })
+            .alloc("eval_commitment_tmp", degree * num_polys * SIZE_F)
             .unwrap();
-        let chunk_length = unsafe { ARCH_CONFIG.num_elems() / num_polys };
+        let addr_acc = sys
+            .mem
+            .alloc("eval_commitment_acc", D * num_polys * SIZE_F)
+            .unwrap();
+        let addr_t = sys
+            .mem
+            .alloc("eval_commitment_t", 2 * num_polys * SIZE_F)
+            .unwrap();
+        let addr_w_z1 = sys.mem.alloc("eval_commitment_w_cpu", SIZE_F).unwrap();
+        sys.write_cpu(addr_w_z1, &[<F as Extendable<D>>::W]);
+        let addr_z1 = addr_zeta + SIZE_F;
+        let (addr_acc0, addr_acc1) = (addr_acc, addr_acc + num_polys * SIZE_F);
+        let op = |addr_input_0, addr_input_1, addr_output, op_type, op_src, is_final_output| {
+            VecOpConfig {
+                vector_length: num_polys,
+                addr_input_0,
+                addr_input_1,
+                addr_output,
+                op_type,
+                op_src,
+                is_final_output,
+            }
+        };
+
+        // W * z1
+        sys.run_once(&VectorChain::new(
+            vec![VecOpConfig {
+                vector_length: 1,
+                addr_input_0: addr_w_z1,
+                addr_input_1: addr_z1,
+                addr_output: addr_w_z1,
+                op_type: VecOpType::MUL,
+                op_src: VecOpSrc::VS,
+                is_final_output: false,
+            }],
+            &sys.mem,
+            &sys.hw,
+        ));
+
         for i in (0..degree).rev().step_by(chunk_length) {
+            let start = i + 1 - chunk_length.min(i + 1);
+            let mut eval_commitment_trans_kernel = Transpose::new(
+                TransposeConfig {
+                    addr_input: addr_poly,
+                    addr_output: addr_eval_commitment_tmp,
+                    width: degree,
+                    height: num_polys,
+                    reverse: false,
+                    extension: 1,
+                    start,
+                    end: i + 1,
+                },
+                &sys.hw,
+            );
+            eval_commitment_trans_kernel.edit(FetchEdit::ClearDrain);
+            eval_commitment_trans_kernel.edit(FetchEdit::ClearWriteRequest);
+            sys.run_once(&eval_commitment_trans_kernel);
+
             let mut vec_ops = Vec::new();
-            let mut eval_commitment_trans_kernel = Transpose::new(TransposeConfig {
-                addr_input: addr_poly,
-                addr_output: addr_eval_commitment_tmp,
-                width: degree,
-                height: num_polys,
+            for j in (start..=i).rev() {
+                // the transpose writes the coefficients j at row j, whatever the chunk
+                let addr_c = addr_eval_commitment_tmp + j * num_polys * SIZE_F;
+                vec_ops.extend(VecOpExtension2::mul_scalar_exact(
+                    num_polys,
+                    [addr_acc0, addr_acc1],
+                    addr_zeta,
+                    addr_w_z1,
+                    [addr_acc0, addr_acc1],
+                    addr_t,
+                    true,
+                ));
+                vec_ops.push(op(
+                    addr_acc0,
+                    addr_c,
+                    addr_acc0,
+                    VecOpType::ADD,
+                    VecOpSrc::VV,
+                    true,
+                ));
+            }
+            sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+        }
+
+        // the opening set keeps the D components of a value together
+        sys.run_once(&Transpose::new(
+            TransposeConfig {
+                addr_input: addr_acc,
+                addr_output: addr_res,
+                width: num_polys,
+                height: D,
                 reverse: false,
                 extension: 1,
-                start: i + 1 - chunk_length.min(i + 1),
-                end: i + 1,
-            });
-            eval_commitment_trans_kernel.drain.clear();
-            eval_commitment_trans_kernel.write_request.clear();
+                start: 0,
+                end: num_polys,
+            },
+            &sys.hw,
+        ));
+
+        sys.mem.free("eval_commitment_tmp");
+        sys.mem.free("eval_commitment_acc");
+        sys.mem.free("eval_commitment_t");
+        sys.mem.free("eval_commitment_w_cpu");
+    }
+
+    // the traffic of eval_commitment, without the values
+    fn eval_commitment_model(
+        sys: &mut System,
+        addr_zeta: usize,
+        commitment: &PolynomialBatch,
+        addr_res: usize,
+    ) {
+        let addr_poly = commitment.addr_polynomials;
+        let degree = 1 << commitment.degree_log;
+        let num_polys = commitment.leaf_length;
+
+        let addr_eval_commitment_tmp = sys
+            .mem
+            .alloc(
+                "eval_commitment_tmp",
+                sys.hw.arch.array_length * num_polys * SIZE_F,
+            )
+            .unwrap();
+        let chunk_length = sys.hw.arch.num_elems() / num_polys;
+        for i in (0..degree).rev().step_by(chunk_length) {
+            let mut vec_ops = Vec::new();
+            let mut eval_commitment_trans_kernel = Transpose::new(
+                TransposeConfig {
+                    addr_input: addr_poly,
+                    addr_output: addr_eval_commitment_tmp,
+                    width: degree,
+                    height: num_polys,
+                    reverse: false,
+                    extension: 1,
+                    start: i + 1 - chunk_length.min(i + 1),
+                    end: i + 1,
+                },
+                &sys.hw,
+            );
+            eval_commitment_trans_kernel.edit(FetchEdit::ClearDrain);
+            eval_commitment_trans_kernel.edit(FetchEdit::ClearWriteRequest);
             sys.run_once(&eval_commitment_trans_kernel);
             for _j in i + 1 - chunk_length.min(i + 1)..=i {
                 // acc * x
@@ -185,6 +319,7 @@ impl<const D: usize> OpeningSet<D> {
                     addr_res,
                     VecOpSrc::VS,
                     false,
+                    sys.hw.arch.rich_vec_isa,
                 ));
                 // _ + c
                 vec_ops.extend(VecOpExtension::<D>::add(
@@ -196,7 +331,7 @@ impl<const D: usize> OpeningSet<D> {
                     true,
                 ));
             }
-            sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
+            sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
         }
 
         sys.mem.free("eval_commitment_tmp");
@@ -1,31 +1,41 @@
 use log::debug;
-use plonky2::field::extension::Extendable;
-use plonky2::field::types::Field;
+use plonky2::field::extension::{Extendable, FieldExtension};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::{Field, PrimeField64};
 use plonky2::fri::FriParams;
 use plonky2::hash::hash_types::RichField;
-use plonky2::iop::witness::PartitionWitness;
+use plonky2::iop::witness::{PartitionWitness, Witness};
+use plonky2::plonk::circuit_builder::NUM_COINS_LOOKUP;
 use plonky2::plonk::circuit_data::{CommonCircuitData, ProverOnlyCircuitData};
-use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::config::{GenericConfig, GenericHashOut};
+use plonky2::plonk::prover::set_lookup_wires;
 use plonky2::util::{log2_ceil, log2_strict};
 use rand::Rng;
+use std::collections::HashSet;
 
-use crate::config::arch_config::ARCH_CONFIG;
-use crate::config::enable_config::ENABLE_CONFIG;
+use crate::config::HwConfig;
 use crate::kernel::fft::{Fft, FftConfig, FftDirection};
 use crate::kernel::hash_no_pad::{HashNoPad, HashNoPadConfig};
+use crate::kernel::kernel::FetchEdit;
 use crate::kernel::memory_copy::{MemCpy, MemCpyConfig};
 use crate::kernel::transpose::{Transpose, TransposeConfig};
 use crate::kernel::tree::{Tree, TreeConfig};
 use crate::kernel::vector_chain::VectorChain;
-use crate::kernel::vector_operation::{VecOpConfig, VecOpExtension, VecOpSrc, VecOpType};
+use crate::kernel::vector_operation::{
+    VecOpConfig, VecOpExtension, VecOpExtension2, VecOpSrc, VecOpType,
+};
 use crate::plonk::challenger::Challenger;
+use crate::plonk::lookup::{compute_all_lookup_polys, lut_re_poly_evals};
 use crate::plonk::oracle::PolynomialBatch;
+use crate::plonk::preflight::CircuitReport;
 use crate::plonk::proofs::OpeningSet;
-use crate::plonk::vanishing_poly::eval_vanishing_poly_base_batch;
+use crate::plonk::vanishing_poly::{
+    eval_vanishing_poly_base_batch, VanishingChallenges, VanishingVarsBatch,
+};
 use crate::plonk::vars::EvaluationVarsBaseBatch;
 use crate::plonk::zero_poly_coset::ZeroPolyOnCoset;
 use crate::system::system::System;
-use crate::util::{ceil_div_usize, log2, BATCH_SIZE, NUM_HASH_OUT_ELTS, SIZE_F, SPONGE_RATE};
+use crate::util::{ceil_div_usize, log2, BATCH_SIZE, NUM_HASH_OUT_ELTS, SIZE_F};
 
 /// "*_cpu" indicates that the data is computed on the CPU
 pub fn prove_with_partition_witness<
@@ -36,16 +46,27 @@ pub fn prove_with_partition_witness<
     sys: &mut System,
     prover_data: &ProverOnlyCircuitData<F, C, D>,
     common_data: &CommonCircuitData<F, D>,
-    partition_witness: PartitionWitness<F>,
+    mut partition_witness: PartitionWitness<F>,
 ) {
-    unsafe {
-        debug!("ArchConfig is {:?}", ARCH_CONFIG);
-    }
+    debug!("ArchConfig is {:?}", sys.hw.arch);
     debug!("Proving with partition witness");
+    // the hand-written gate ports only model the traffic, their constants are not in memory
+    assert!(
+        !sys.functional || sys.hw.opt.symbolic_gates,
+        "the functional simulation needs opt.symbolic_gates"
+    );
+
+    // estimated gates are warned about before simulating anything
+    let report = CircuitReport::new(common_data, prover_data);
+    report.log();
 
     let config = &common_data.config;
     let num_challenges = config.num_challenges;
     let degree = common_data.degree();
+    let has_lookup = !common_data.luts.is_empty();
+
+    // the multiplicities of the lookup tables are counted on the CPU, as in plonky2
+    set_lookup_wires(prover_data, common_data, &mut partition_witness);
 
     let addr_public_inputs = sys
         .mem
@@ -54,18 +75,25 @@ pub fn prove_with_partition_witness<
             prover_data.public_inputs.len() * SIZE_F,
         )
         .unwrap(); // we don't support get_targets operation, should be done in the CPU
+    let public_inputs = partition_witness.get_targets(&prover_data.public_inputs);
+    sys.write_cpu(addr_public_inputs, &public_inputs);
     let addr_public_inputs_hash = sys
         .mem
         .alloc("public_inputs_hash", NUM_HASH_OUT_ELTS * SIZE_F)
         .unwrap();
-    let public_inputs_hash_kernel = HashNoPad::new(HashNoPadConfig {
-        addr_input: vec![addr_public_inputs],
-        addr_output: addr_public_inputs_hash,
-        input_length: vec![prover_data.public_inputs.len()],
-        output_length: NUM_HASH_OUT_ELTS,
-    });
+    let public_inputs_hash_kernel = HashNoPad::new(
+        HashNoPadConfig {
+            addr_input: vec![addr_public_inputs],
+            addr_output: addr_public_inputs_hash,
+            input_length: vec![prover_data.public_inputs.len()],
+            output_length: NUM_HASH_OUT_ELTS,
+            addr_state: None,
+        },
+        &sys.hw,
+    );
     sys.run_once(&public_inputs_hash_kernel);
 
+    sys.push_phase("wires commitment");
     let addr_witness = sys
         .mem
         .alloc(
@@ -73,6 +101,7 @@ pub fn prove_with_partition_witness<
             partition_witness.num_wires * partition_witness.degree * SIZE_F,
         )
         .unwrap();
+    write_witness(sys, addr_witness, &partition_witness);
 
     let wires_commitment = PolynomialBatch::new(
         "wires_commitment",
@@ -86,15 +115,23 @@ pub fn prove_with_partition_witness<
         true,
         Vec::new(),
     );
+    sys.pop_phase();
 
     let addr_chanllenger_output_buffer = sys
         .mem
-        .alloc("Challenger.output_buffer", SPONGE_RATE * SIZE_F)
+        .alloc(
+            "Challenger.output_buffer",
+            Challenger::buffer_size(sys.functional),
+        )
         .unwrap();
     let addr_prover_data_circuit_digest = sys
         .mem
         .alloc("prover_data.circuit_digest_cpu", NUM_HASH_OUT_ELTS * SIZE_F)
         .unwrap();
+    sys.write_cpu(
+        addr_prover_data_circuit_digest,
+        &prover_data.circuit_digest.to_vec(),
+    );
 
     let mut challenger = Challenger::new(addr_chanllenger_output_buffer);
     challenger.observe_hash(sys, addr_prover_data_circuit_digest);
@@ -107,7 +144,43 @@ pub fn prove_with_partition_witness<
     challenger.get_n_challenges(sys, addr_betas, num_challenges);
     challenger.get_n_challenges(sys, addr_gammas, num_challenges);
 
+    // NUM_COINS_LOOKUP challenges for each challenge of the lookup argument: the betas, the
+    // gammas and as many more, taken in this order in chunks of NUM_COINS_LOOKUP
+    let addr_deltas = if has_lookup {
+        let num_lookup_challenges = NUM_COINS_LOOKUP * num_challenges;
+        let addr_deltas = sys
+            .mem
+            .alloc("deltas", num_lookup_challenges * SIZE_F)
+            .unwrap();
+        let mks = [addr_betas, addr_gammas]
+            .iter()
+            .enumerate()
+            .map(|(i, &addr_input)| {
+                MemCpy::new(
+                    MemCpyConfig {
+                        addr_input,
+                        addr_output: addr_deltas + i * num_challenges * SIZE_F,
+                        input_length: num_challenges,
+                    },
+                    sys.hw.enable.other,
+                    &sys.hw,
+                )
+            })
+            .collect();
+        sys.run_vec(mks);
+        challenger.get_n_challenges(
+            sys,
+            addr_deltas + 2 * num_challenges * SIZE_F,
+            num_lookup_challenges - 2 * num_challenges,
+        );
+        addr_deltas
+    } else {
+        0
+    };
+
+    sys.push_phase("partial products");
     let num_routed_wires_quotient = common_data.num_partial_products + 1;
+    let num_all_lookup_polys = num_challenges * common_data.num_lookup_polys;
     let addr_sigmas = sys
         .mem
         .alloc(
@@ -123,6 +196,16 @@ pub fn prove_with_partition_witness<
         .mem
         .alloc("k_is_cpu", common_data.config.num_routed_wires * SIZE_F)
         .unwrap();
+    // the sigmas by wire
+    let sigmas = (0..common_data.config.num_routed_wires)
+        .flat_map(|j| prover_data.sigmas.iter().map(move |row| row[j]))
+        .collect::<Vec<_>>();
+    sys.write_cpu(addr_sigmas, &sigmas);
+    sys.write_cpu(addr_subgroup, &prover_data.subgroup);
+    sys.write_cpu(
+        addr_k_is,
+        &common_data.k_is[..common_data.config.num_routed_wires],
+    );
     let addr_partial_products_and_zs = sys
         .mem
         .alloc(
@@ -151,9 +234,11 @@ pub fn prove_with_partition_witness<
         .mem
         .alloc(
             "zs_partial_products",
-            num_challenges * num_routed_wires_quotient * prover_data.subgroup.len() * SIZE_F,
+            (num_challenges * num_routed_wires_quotient + num_all_lookup_polys)
+                * prover_data.subgroup.len()
+                * SIZE_F,
         )
-        .unwrap(); // num_challenges * num_routed_wires_quotient polynomials of degree prover_data.subgroup.len()
+        .unwrap(); // num_challenges * num_routed_wires_quotient polynomials of degree prover_data.subgroup.len(), then the lookup polynomials
 
     let mut mks = Vec::new();
     for i in 0..num_challenges {
@@ -162,11 +247,12 @@ pub fn prove_with_partition_witness<
                 addr_input: addr_partial_products_and_zs
                     + i * num_routed_wires_quotient * prover_data.subgroup.len() * SIZE_F,
                 addr_output: addr_zs_partial_products
-                    + num_challenges * prover_data.subgroup.len()
-                    + i * (num_routed_wires_quotient - 1) * prover_data.subgroup.len(),
+                    + num_challenges * prover_data.subgroup.len() * SIZE_F
+                    + i * (num_routed_wires_quotient - 1) * prover_data.subgroup.len() * SIZE_F,
                 input_length: (num_routed_wires_quotient - 1) * prover_data.subgroup.len(),
             },
             false,
+            &sys.hw,
         );
         mks.push(mk);
 
@@ -179,17 +265,33 @@ pub fn prove_with_partition_witness<
                 input_length: prover_data.subgroup.len(),
             },
             false,
+            &sys.hw,
         );
         mks.push(mk);
     }
 
-    sys.mem.free("partial_products_and_zs");
+    // the copies by the commitment only read it in functional mode
+    if !sys.functional {
+        sys.mem.free("partial_products_and_zs");
+    }
+    if has_lookup {
+        compute_all_lookup_polys(
+            sys,
+            common_data,
+            prover_data,
+            addr_witness,
+            partition_witness.num_wires,
+            addr_deltas,
+            addr_zs_partial_products
+                + num_challenges * num_routed_wires_quotient * prover_data.subgroup.len() * SIZE_F,
+        );
+    }
     let partial_products_zs_and_lookup_commitment = PolynomialBatch::new(
         "partial_products_zs_and_lookup_commitment",
         sys,
         addr_zs_partial_products,
         prover_data.subgroup.len(),
-        num_challenges * num_routed_wires_quotient,
+        num_challenges * num_routed_wires_quotient + num_all_lookup_polys,
         config.fri_config.rate_bits,
         config.zero_knowledge,
         config.fri_config.cap_height,
@@ -197,7 +299,11 @@ pub fn prove_with_partition_witness<
         mks,
     );
 
+    if sys.functional {
+        sys.mem.free("partial_products_and_zs");
+    }
     sys.mem.free("zs_partial_products");
+    sys.pop_phase();
 
     challenger.observe_cap(
         sys,
@@ -208,14 +314,18 @@ pub fn prove_with_partition_witness<
     let addr_alphas = sys.mem.alloc("alphas", num_challenges * SIZE_F).unwrap();
     challenger.get_n_challenges(sys, addr_alphas, num_challenges);
 
+    sys.push_phase("quotient");
     let quotient_degree_bits = log2_ceil(common_data.quotient_degree_factor);
-    let addr_points = sys
-        .mem
-        .alloc(
-            "points_cpu",
-            (1 << (common_data.degree_bits() + quotient_degree_bits)) * SIZE_F,
-        )
-        .unwrap();
+    let lde_size = 1 << (common_data.degree_bits() + quotient_degree_bits);
+    // already shifted to the coset
+    let addr_points = sys.mem.alloc("points_cpu", lde_size * SIZE_F).unwrap();
+    if sys.functional {
+        let points = F::two_adic_subgroup(common_data.degree_bits() + quotient_degree_bits)
+            .into_iter()
+            .map(|x| F::coset_shift() * x)
+            .collect::<Vec<_>>();
+        sys.write_cpu(addr_points, &points);
+    }
 
     let constants_sigmas_commitment = PolynomialBatch::new_alloc(
         "constants_sigmas_commitment",
@@ -232,18 +342,80 @@ pub fn prove_with_partition_witness<
                 .len(),
         ),
     ); // copy from cpu
+    if sys.functional {
+        let constants_sigmas = &prover_data.constants_sigmas_commitment;
+        let leaves = constants_sigmas.merkle_tree.leaves.concat();
+        sys.write_cpu(constants_sigmas_commitment.addr_leaves, &leaves);
+        let coeffs = constants_sigmas
+            .polynomials
+            .iter()
+            .flat_map(|p| p.coeffs.iter().copied())
+            .collect::<Vec<_>>();
+        sys.write_cpu(constants_sigmas_commitment.addr_polynomials, &coeffs);
+    }
 
+    // the rows of each group, then the unused selector in functional mode, the factors of
+    // compute_filter
+    let unused_selector = sys
+        .functional
+        .then(|| unused_selector(common_data, prover_data));
     for (i, group) in common_data.selectors_info.groups.iter().enumerate() {
         let group_id = format!("group_{}_cpu", i);
-        let group_length = group.len();
-        sys.mem.alloc(&group_id, group_length * SIZE_F);
+        let group_length = group.len() + sys.functional as usize;
+        let addr_group = sys.mem.alloc(&group_id, group_length * SIZE_F).unwrap();
+        let values = group
+            .clone()
+            .map(F::from_canonical_usize)
+            .chain(unused_selector)
+            .collect::<Vec<_>>();
+        sys.write_cpu(addr_group, &values);
     }
-    let lde_size = 1 << (common_data.degree_bits() + quotient_degree_bits);
 
-    let addr_quotient_polys = sys
-        .mem
-        .alloc("quotient_polys", num_challenges * lde_size * SIZE_F)
-        .unwrap();
+    // in functional mode the quotient polynomials are committed as plonky2 does: each is
+    // trimmed to quotient_degree and split in chunks of degree, which are in place when
+    // nothing is trimmed
+    let num_quotient_polys = num_challenges * common_data.quotient_degree_factor;
+    let quotient_degree = common_data.quotient_degree_factor * degree;
+    let functional_commitment = sys.functional.then(|| {
+        PolynomialBatch::alloc(
+            "quotient_polys_commitment",
+            sys,
+            degree,
+            num_quotient_polys,
+            config.fri_config.rate_bits,
+            config.zero_knowledge,
+            config.fri_config.cap_height,
+        )
+    });
+    let addr_quotient_polys = match &functional_commitment {
+        Some(commitment) if quotient_degree == lde_size => commitment.addr_polynomials,
+        _ => sys
+            .mem
+            .alloc("quotient_polys", num_challenges * lde_size * SIZE_F)
+            .unwrap(),
+    };
+    // the polynomial of each LUT evaluated at its delta, on the CPU
+    let addr_lut_re_poly_evals = if has_lookup {
+        let addr = sys
+            .mem
+            .alloc(
+                "lut_re_poly_evals_cpu",
+                num_challenges * common_data.luts.len() * SIZE_F,
+            )
+            .unwrap();
+        if sys.functional {
+            let deltas = sys
+                .mem
+                .read_elems(addr_deltas, NUM_COINS_LOOKUP * num_challenges)
+                .iter()
+                .map(|delta| F::from_canonical_u64(delta.to_canonical_u64()))
+                .collect::<Vec<_>>();
+            sys.write_cpu(addr, &lut_re_poly_evals(common_data, &deltas));
+        }
+        addr
+    } else {
+        0
+    };
 
     compute_quotient_polys(
         sys,
@@ -253,24 +425,64 @@ pub fn prove_with_partition_witness<
         &constants_sigmas_commitment,
         &wires_commitment,
         &partial_products_zs_and_lookup_commitment,
-        addr_betas,
-        addr_gammas,
-        addr_alphas,
-        addr_quotient_polys,
-    );
-    let quotient_polys_commitment = PolynomialBatch::new(
-        "quotient_polys_commitment",
-        sys,
+        VanishingChallenges {
+            addr_betas,
+            addr_gammas,
+            addr_alphas,
+            addr_deltas,
+            addr_lut_re_poly_evals,
+        },
         addr_quotient_polys,
-        degree,
-        num_challenges * common_data.quotient_degree_factor,
-        config.fri_config.rate_bits,
-        config.zero_knowledge,
-        config.fri_config.cap_height,
-        false,
-        Vec::new(),
     );
-    sys.mem.free("quotient_polys");
+    let quotient_polys_commitment = match functional_commitment {
+        Some(commitment) => {
+            if quotient_degree != lde_size {
+                let mks = (0..num_challenges)
+                    .map(|i| {
+                        MemCpy::new(
+                            MemCpyConfig {
+                                addr_input: addr_quotient_polys + i * lde_size * SIZE_F,
+                                addr_output: commitment.addr_polynomials
+                                    + i * quotient_degree * SIZE_F,
+                                input_length: quotient_degree,
+                            },
+                            sys.hw.enable.other,
+                            &sys.hw,
+                        )
+                    })
+                    .collect();
+                sys.run_vec(mks);
+                sys.mem.free("quotient_polys");
+            }
+            commitment.from_coeffs(
+                sys,
+                config.fri_config.rate_bits,
+                config.zero_knowledge,
+                config.fri_config.cap_height,
+                degree,
+                num_quotient_polys,
+            );
+            commitment.free_salt(sys);
+            commitment
+        }
+        None => {
+            let commitment = PolynomialBatch::new(
+                "quotient_polys_commitment",
+                sys,
+                addr_quotient_polys,
+                degree,
+                num_quotient_polys,
+                config.fri_config.rate_bits,
+                config.zero_knowledge,
+                config.fri_config.cap_height,
+                false,
+                Vec::new(),
+            );
+            sys.mem.free("quotient_polys");
+            commitment
+        }
+    };
+    sys.pop_phase();
 
     challenger.observe_cap(
         sys,
@@ -280,19 +492,44 @@ pub fn prove_with_partition_witness<
     let addr_zeta = sys.mem.alloc("zeta", D * SIZE_F).unwrap();
     let addr_zeta_g = sys.mem.alloc("zeta_g", D * SIZE_F).unwrap();
     challenger.get_n_challenges(sys, addr_zeta, D);
-    let zeta_g_kernel = VectorChain::new(
-        VecOpExtension::<D>::mul(
-            &mut sys.mem,
-            1,
-            addr_zeta,
-            0,
-            addr_zeta_g,
-            VecOpSrc::VS,
-            true,
-        ),
-        &sys.mem,
-    );
-    sys.run_once(&zeta_g_kernel);
+    sys.push_phase("openings");
+    if sys.functional {
+        // the 2^degree_bits-th root of unity of the extension is in the base field
+        let g =
+            F::Extension::primitive_root_of_unity(common_data.degree_bits()).to_basefield_array();
+        assert!(g[1..].iter().all(|c| c.is_zero()));
+        let addr_g = sys.mem.alloc("g_cpu", SIZE_F).unwrap();
+        sys.write_cpu(addr_g, &g[..1]);
+        let vec_ops = (0..D)
+            .map(|i| VecOpConfig {
+                vector_length: 1,
+                addr_input_0: addr_zeta + i * SIZE_F,
+                addr_input_1: addr_g,
+                addr_output: addr_zeta_g + i * SIZE_F,
+                op_type: VecOpType::MUL,
+                op_src: VecOpSrc::VS,
+                is_final_output: true,
+            })
+            .collect();
+        sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+        sys.mem.free("g_cpu");
+    } else {
+        let zeta_g_kernel = VectorChain::new(
+            VecOpExtension::<D>::mul(
+                &mut sys.mem,
+                1,
+                addr_zeta,
+                0,
+                addr_zeta_g,
+                VecOpSrc::VS,
+                true,
+                sys.hw.arch.rich_vec_isa,
+            ),
+            &sys.mem,
+            &sys.hw,
+        );
+        sys.run_once(&zeta_g_kernel);
+    }
 
     let openings = OpeningSet::new(
         sys,
@@ -306,7 +543,9 @@ pub fn prove_with_partition_witness<
     );
 
     challenger.observe_openings(sys, &openings);
+    sys.pop_phase();
 
+    sys.push_phase("fri");
     let instance = common_data.get_fri_instance(F::Extension::ONE);
     PolynomialBatch::prove_openings(
         sys,
@@ -322,8 +561,48 @@ pub fn prove_with_partition_witness<
         addr_zeta,
         addr_zeta_g,
     );
+    sys.pop_phase();
+}
+
+// The value of the selector polynomials on the rows of the other groups, which plonky2
+// keeps private: the one past the gate indices. It only enters the filters with several
+// groups, where each row is out of all groups but its own
+fn unused_selector<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    common_data: &CommonCircuitData<F, D>,
+    prover_data: &ProverOnlyCircuitData<F, C, D>,
+) -> F {
+    let num_selectors = common_data.selectors_info.num_selectors();
+    if num_selectors == 1 {
+        return F::ZERO;
+    }
+    let num_gates = common_data.gates.len() as u64;
+    prover_data.constants_sigmas_commitment.polynomials[..num_selectors]
+        .iter()
+        .find_map(|poly| {
+            poly.clone()
+                .fft()
+                .values
+                .into_iter()
+                .find(|v| v.to_canonical_u64() >= num_gates)
+        })
+        .unwrap()
 }
 
+// row-major, like the transposed_input of the wires commitment expects
+fn write_witness<F: RichField>(sys: &mut System, addr: usize, witness: &PartitionWitness<F>) {
+    let (degree, num_wires) = (witness.degree, witness.num_wires);
+    let witness = witness.clone().full_witness();
+    let values = (0..degree)
+        .flat_map(|row| (0..num_wires).map(move |col| (row, col)))
+        .map(|(row, col)| witness.get_wire(row, col))
+        .collect::<Vec<_>>();
+    sys.write_cpu(addr, &values);
+}
+
+// The partial products and Z of each challenge. In functional mode they are computed like
+// plonky2's wires_permutation_partial_products_and_zs: poly m of challenge i is at
+// addr_output + (i * num_polys + m) * subgroup_length, the Z last. The routed wires of the
+// witness are transposed first, so that the ops run over all the rows at once.
 fn all_wires_permutation_partial_products(
     sys: &mut System,
     addr_output: usize,
@@ -340,11 +619,53 @@ fn all_wires_permutation_partial_products(
     num_challenges: usize,
 ) {
     debug!("all_wires_permutation_partial_products");
+    if !sys.functional {
+        (0..num_challenges).for_each(|i| {
+            wires_permutation_partial_products_and_zs(
+                sys,
+                addr_output + i * num_routed_wires * subgroup_length * SIZE_F,
+                addr_witness,
+                addr_betas + i * SIZE_F,
+                addr_gammas + i * SIZE_F,
+                addr_sigmas,
+                addr_subgroup,
+                subgroup_length,
+                addr_k_is,
+                num_routed_wires,
+                num_wires,
+                degree,
+            )
+        });
+        return;
+    }
+    let num_polys = ceil_div_usize(num_routed_wires, degree);
+    let addr_rows = sys
+        .mem
+        .alloc(
+            "permutation_rows",
+            num_routed_wires * subgroup_length * SIZE_F,
+        )
+        .unwrap();
+    let transpose = Transpose::new(
+        TransposeConfig {
+            addr_input: addr_witness,
+            addr_output: addr_rows,
+            width: num_wires,
+            height: subgroup_length,
+            start: 0,
+            end: num_routed_wires,
+            reverse: false,
+            extension: 1,
+        },
+        &sys.hw,
+    );
+    sys.run_once(&transpose);
+
     (0..num_challenges).for_each(|i| {
-        wires_permutation_partial_products_and_zs(
+        wires_permutation_partial_products_and_zs_functional(
             sys,
-            addr_output + i * num_routed_wires * subgroup_length * SIZE_F,
-            addr_witness,
+            addr_output + i * num_polys * subgroup_length * SIZE_F,
+            addr_rows,
             addr_betas + i * SIZE_F,
             addr_gammas + i * SIZE_F,
             addr_sigmas,
@@ -352,10 +673,10 @@ fn all_wires_permutation_partial_products(
             subgroup_length,
             addr_k_is,
             num_routed_wires,
-            num_wires,
             degree,
         )
     });
+    sys.mem.free("permutation_rows");
 }
 
 pub fn wires_permutation_partial_products_and_zs(
@@ -486,6 +807,7 @@ pub fn wires_permutation_partial_products_and_zs(
             addr_denominators + i * num_routed_wires * SIZE_F,
             addr_denominators + i * num_routed_wires * SIZE_F,
             false,
+            sys.hw.arch.rich_vec_isa,
         ));
 
         // quotient_values
@@ -535,7 +857,7 @@ pub fn wires_permutation_partial_products_and_zs(
             });
         }
     }
-    sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
+    sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
     sys.mem.clear_preload();
 
     sys.mem.free("numerators");
@@ -545,6 +867,225 @@ pub fn wires_permutation_partial_products_and_zs(
     sys.mem.free("all_quotient_chunk_products");
 }
 
+// The partial products and Z of one challenge in functional mode. Wire j of addr_rows and
+// addr_sigmas is at j * subgroup_length.
+fn wires_permutation_partial_products_and_zs_functional(
+    sys: &mut System,
+    addr_output: usize,
+    addr_rows: usize,
+    addr_beta: usize,
+    addr_gamma: usize,
+    addr_sigmas: usize,
+    addr_subgroup: usize,
+    subgroup_length: usize,
+    addr_k_is: usize,
+    num_routed_wires: usize,
+    degree: usize,
+) {
+    debug!("wires_permutation_partial_products_and_zs_functional");
+
+    let n = subgroup_length;
+    let num_polys = ceil_div_usize(num_routed_wires, degree);
+    let size = num_routed_wires * n * SIZE_F;
+    let addr_numerators = sys.mem.alloc("numerators", size).unwrap();
+    let addr_denominators = sys.mem.alloc("denominators", size).unwrap();
+    let addr_denominator_invs = sys.mem.alloc("denominator_invs", size).unwrap();
+    let addr_inv_temps = [
+        "permutation_inv_t0",
+        "permutation_inv_t1",
+        "permutation_inv_t2",
+    ]
+    .map(|id| sys.mem.alloc(id, size).unwrap());
+    let addr_scan = sys.mem.alloc("permutation_scan", n * SIZE_F).unwrap();
+    let addr_one = sys.mem.alloc("permutation_one", SIZE_F).unwrap();
+    sys.write_cpu(addr_one, &[GoldilocksField::ONE]);
+
+    sys.mem.preload(addr_k_is, num_routed_wires);
+    sys.mem.preload(addr_beta, 1);
+    sys.mem.preload(addr_gamma, 1);
+    sys.mem.preload(addr_one, 1);
+
+    let op =
+        |vector_length, addr_input_0, addr_input_1, addr_output, op_type, op_src| VecOpConfig {
+            vector_length,
+            addr_input_0,
+            addr_input_1,
+            addr_output,
+            op_type,
+            op_src,
+            is_final_output: false,
+        };
+    let wire = |addr: usize, j: usize| addr + j * n * SIZE_F;
+    let poly = |m: usize| addr_output + m * n * SIZE_F;
+
+    let mut vec_ops = Vec::new();
+    for j in 0..num_routed_wires {
+        let (numerator, denominator) = (wire(addr_numerators, j), wire(addr_denominators, j));
+        vec_ops.extend([
+            // wire_value + beta * k_i * x + gamma
+            op(
+                n,
+                addr_subgroup,
+                addr_k_is + j * SIZE_F,
+                numerator,
+                VecOpType::MUL,
+                VecOpSrc::VS,
+            ),
+            op(
+                n,
+                numerator,
+                addr_beta,
+                numerator,
+                VecOpType::MUL,
+                VecOpSrc::VS,
+            ),
+            op(
+                n,
+                wire(addr_rows, j),
+                numerator,
+                numerator,
+                VecOpType::ADD,
+                VecOpSrc::VV,
+            ),
+            op(
+                n,
+                numerator,
+                addr_gamma,
+                numerator,
+                VecOpType::ADD,
+                VecOpSrc::VS,
+            ),
+            // wire_value + beta * s_sigma + gamma
+            op(
+                n,
+                wire(addr_sigmas, j),
+                addr_beta,
+                denominator,
+                VecOpType::MUL,
+                VecOpSrc::VS,
+            ),
+            op(
+                n,
+                wire(addr_rows, j),
+                denominator,
+                denominator,
+                VecOpType::ADD,
+                VecOpSrc::VV,
+            ),
+            op(
+                n,
+                denominator,
+                addr_gamma,
+                denominator,
+                VecOpType::ADD,
+                VecOpSrc::VS,
+            ),
+        ]);
+    }
+    vec_ops.extend(VecOpConfig::inv_with_temps(
+        num_routed_wires * n,
+        addr_denominators,
+        addr_denominator_invs,
+        addr_inv_temps,
+        false,
+        sys.hw.arch.rich_vec_isa,
+    ));
+
+    // the quotient values multiplied up over the wires, poly m up to the end of chunk m
+    for j in 0..num_routed_wires {
+        let out = poly(j / degree);
+        let prev = if j == 0 {
+            wire(addr_numerators, 0)
+        } else {
+            vec_ops.push(op(
+                n,
+                poly((j - 1) / degree),
+                wire(addr_numerators, j),
+                out,
+                VecOpType::MUL,
+                VecOpSrc::VV,
+            ));
+            out
+        };
+        vec_ops.push(op(
+            n,
+            prev,
+            wire(addr_denominator_invs, j),
+            out,
+            VecOpType::MUL,
+            VecOpSrc::VV,
+        ));
+    }
+
+    // Z of a row is the product of the last poly over the rows before it, an exclusive scan
+    // in log(n) shifted ops alternating between a temporary and the last poly
+    let addr_z = poly(num_polys - 1);
+    vec_ops.push(op(1, addr_one, 0, addr_scan, VecOpType::ADD, VecOpSrc::VS));
+    vec_ops.push(op(
+        n - 1,
+        addr_z,
+        0,
+        addr_scan + SIZE_F,
+        VecOpType::ADD,
+        VecOpSrc::VS,
+    ));
+    let (mut src, mut dst) = (addr_scan, addr_z);
+    let mut shift = 1;
+    while shift < n {
+        vec_ops.extend([
+            op(shift, src, 0, dst, VecOpType::ADD, VecOpSrc::VS),
+            op(
+                n - shift,
+                src + shift * SIZE_F,
+                src,
+                dst + shift * SIZE_F,
+                VecOpType::MUL,
+                VecOpSrc::VV,
+            ),
+        ]);
+        std::mem::swap(&mut src, &mut dst);
+        shift *= 2;
+    }
+    if src != addr_z {
+        vec_ops.push(op(n, src, 0, addr_z, VecOpType::ADD, VecOpSrc::VS));
+    }
+    // the partial products start from Z of their row
+    for m in 0..num_polys - 1 {
+        vec_ops.push(op(
+            n,
+            poly(m),
+            addr_z,
+            poly(m),
+            VecOpType::MUL,
+            VecOpSrc::VV,
+        ));
+    }
+
+    let polys = poly(0)..poly(num_polys);
+    let mut written = HashSet::new();
+    for op in vec_ops.iter_mut().rev() {
+        if polys.contains(&op.addr_output) && written.insert(op.addr_output) {
+            op.is_final_output = true;
+        }
+    }
+
+    sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+    sys.mem.clear_preload();
+
+    for id in [
+        "numerators",
+        "denominators",
+        "denominator_invs",
+        "permutation_inv_t0",
+        "permutation_inv_t1",
+        "permutation_inv_t2",
+        "permutation_scan",
+        "permutation_one",
+    ] {
+        sys.mem.free(id);
+    }
+}
+
 fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
     sys: &mut System,
 
@@ -555,38 +1096,55 @@ fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
     constants_sigmas_commitment: &PolynomialBatch,
     wires_commitment: &PolynomialBatch,
     zs_partial_products_and_lookup_commitment: &PolynomialBatch,
-    addr_betas: usize,
-    addr_gammas: usize,
-    addr_alphas: usize,
+    challenges: VanishingChallenges,
     addr_res: usize,
 ) {
     debug!("compute_quotient_polys");
+    let VanishingChallenges {
+        addr_betas,
+        addr_gammas,
+        addr_alphas,
+        addr_deltas,
+        addr_lut_re_poly_evals,
+    } = challenges;
     let num_challenges = common_data.config.num_challenges;
     let rate_bits = common_data.config.fri_config.rate_bits;
     let degree_bits = common_data.degree_bits();
     let quotient_degree_bits = log2_ceil(common_data.quotient_degree_factor);
     let num_routed_wires = common_data.config.num_routed_wires;
     let num_wires = common_data.config.num_wires;
-    let num_constants = common_data.config.num_constants;
+    // the model keeps the constant wires of the config, the circuit may use fewer of them
+    let num_constants = if sys.functional {
+        common_data.num_constants
+    } else {
+        common_data.config.num_constants
+    };
     let num_partial_products = common_data.num_partial_products;
+    let num_zs_partial_products = (num_partial_products + 1) * num_challenges;
+    let num_all_lookup_polys = num_challenges * common_data.num_lookup_polys;
 
     let step = 1 << (rate_bits - quotient_degree_bits);
     let next_step = 1 << quotient_degree_bits;
     let lde_size = 1 << (degree_bits + quotient_degree_bits);
-    println!("lde_size: {}", lde_size);
     let num_batches = ceil_div_usize(lde_size, BATCH_SIZE);
 
-    let z_h_on_coset = ZeroPolyOnCoset::new(sys, rate_bits);
+    // Z_H takes 2^quotient_degree_bits values on the coset, the model computes 2^rate_bits
+    let z_h_on_coset = if sys.functional {
+        ZeroPolyOnCoset::new(sys, degree_bits, quotient_degree_bits)
+    } else {
+        ZeroPolyOnCoset::new(sys, degree_bits, rate_bits)
+    };
 
     let addr_res_tmp = sys
         .mem
         .alloc("quotient_values_tmp", num_challenges * lde_size * SIZE_F)
         .unwrap();
 
+    // the gate ops are generated in the first batch and run in every batch, so are their
+    // temporaries
+    let deferred_mark = sys.mem.deferred_mark();
     let mut mks = Vec::new();
-
-    let mut eval_gate_ops = VectorChain::new(vec![], &sys.mem);
-    let mut eval_gate_flag = false;
+    let mut eval_gate_ops = None;
 
     let addr_k_is = sys.mem.get_addr("k_is_cpu").unwrap();
     let mut k_is_cp = MemCpy::new(
@@ -595,10 +1153,11 @@ fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
             addr_output: 0,
             input_length: num_routed_wires,
         },
-        unsafe { ENABLE_CONFIG.other },
+        sys.hw.enable.other,
+        &sys.hw,
     );
-    k_is_cp.drain.clear();
-    k_is_cp.write_request.clear();
+    k_is_cp.edit(FetchEdit::ClearDrain);
+    k_is_cp.edit(FetchEdit::ClearWriteRequest);
     sys.run_once(&k_is_cp);
 
     debug!("num_batches: {}", num_batches);
@@ -625,9 +1184,10 @@ fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
                 addr_output: addr_shifted_xs_batch,
                 input_length: xs_batch_len,
             },
-            unsafe { ENABLE_CONFIG.other },
+            sys.hw.enable.other,
+            &sys.hw,
         );
-        xs_batch_cp.drain.clear();
+        xs_batch_cp.edit(FetchEdit::ClearDrain);
         mcks.push(xs_batch_cp);
         sys.mem.preload(addr_shifted_xs_batch, xs_batch_len);
 
@@ -639,6 +1199,7 @@ fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
         let mut addr_local_constants_batch_refs = Vec::with_capacity(xs_batch_len);
         let mut addr_local_wires_batch_refs = Vec::with_capacity(xs_batch_len);
         let mut addr_local_zs_partial_and_lookup = Vec::with_capacity(xs_batch_len);
+        let mut addr_next_lookup_zs_batch = Vec::with_capacity(xs_batch_len);
 
         for i in indices_batch {
             let i_next = (i + next_step) % lde_size;
@@ -653,7 +1214,7 @@ fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
             let local_wires = wires_commitment.get_lde_values_addr(i, step);
             let mut local_zs_partial_and_lookup =
                 zs_partial_products_and_lookup_commitment.get_lde_values_addr(i, step);
-            local_zs_partial_and_lookup.1 = (num_partial_products + 1) * num_challenges;
+            local_zs_partial_and_lookup.1 = num_zs_partial_products + num_all_lookup_polys;
 
             let next_zs_partial_and_lookup =
                 zs_partial_products_and_lookup_commitment.get_lde_values_addr(i_next, step);
@@ -679,16 +1240,29 @@ fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
             addr_next_zs_batch.push(next_zs);
             addr_partial_products_batch.push(partial_products);
             addr_s_sigmas_batch.push(s_sigmas);
+            addr_next_lookup_zs_batch.push((
+                next_zs_partial_and_lookup.0 + num_zs_partial_products * SIZE_F,
+                num_all_lookup_polys,
+            ));
         }
         // println!("addr_local_constants_sigmas_batch[0]: {:?}", addr_local_constants_sigmas_batch[0]);
         // println!("addr_local_wires_batch_refs[0]: {:?}", addr_local_wires_batch_refs[0]);
         // println!("addr_local_zs_partial_and_lookup[0]: {:?}", addr_local_zs_partial_and_lookup[0]);
         // println!("addr_next_zs_batch[0]: {:?}", addr_next_zs_batch[0]);
 
-        mcks.extend(batch_copy_transpose(&addr_local_constants_sigmas_batch));
-        mcks.extend(batch_copy_transpose(&addr_local_wires_batch_refs));
-        mcks.extend(batch_copy_transpose(&addr_local_zs_partial_and_lookup));
-        mcks.extend(batch_copy_transpose(&addr_next_zs_batch));
+        mcks.extend(batch_copy_transpose(
+            &addr_local_constants_sigmas_batch,
+            &sys.hw,
+        ));
+        mcks.extend(batch_copy_transpose(&addr_local_wires_batch_refs, &sys.hw));
+        mcks.extend(batch_copy_transpose(
+            &addr_local_zs_partial_and_lookup,
+            &sys.hw,
+        ));
+        mcks.extend(batch_copy_transpose(&addr_next_zs_batch, &sys.hw));
+        if num_all_lookup_polys > 0 {
+            mcks.extend(batch_copy_transpose(&addr_next_lookup_zs_batch, &sys.hw));
+        }
 
         let addr_local_constants_batch = sys
             .mem
@@ -743,6 +1317,19 @@ fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
             addr_partial_products,
             xs_batch_len * addr_partial_products_batch[0].1,
         );
+        let (addr_local_lookup_zs, addr_next_lookup_zs) = if num_all_lookup_polys > 0 {
+            let mut alloc = |name| {
+                let addr = sys
+                    .mem
+                    .alloc(name, xs_batch_len * num_all_lookup_polys * SIZE_F)
+                    .unwrap();
+                sys.mem.preload(addr, xs_batch_len * num_all_lookup_polys);
+                addr
+            };
+            (alloc("local_lookup_zs"), alloc("next_lookup_zs"))
+        } else {
+            (0, 0)
+        };
         let addr_s_sigmas = sys
             .mem
             .alloc("s_sigmas", xs_batch_len * addr_s_sigmas_batch[0].1 * SIZE_F)
@@ -750,6 +1337,27 @@ fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
         sys.run_vec(mcks);
         sys.mem
             .preload(addr_s_sigmas, xs_batch_len * addr_s_sigmas_batch[0].1);
+        let addr_local_lookup_zs_batch = addr_local_zs_partial_and_lookup
+            .iter()
+            .map(|&(addr, _)| {
+                (
+                    addr + num_zs_partial_products * SIZE_F,
+                    num_all_lookup_polys,
+                )
+            })
+            .collect::<Vec<_>>();
+        for (batch_addr, addr_output) in [
+            (&addr_local_constants_batch_refs, addr_local_constants_batch),
+            (&addr_s_sigmas_batch, addr_s_sigmas),
+            (&addr_local_wires_batch_refs, addr_local_wires_batch),
+            (&addr_local_zs_batch, addr_local_zs),
+            (&addr_next_zs_batch, addr_next_zs),
+            (&addr_partial_products_batch, addr_partial_products),
+            (&addr_local_lookup_zs_batch, addr_local_lookup_zs),
+            (&addr_next_lookup_zs_batch, addr_next_lookup_zs),
+        ] {
+            gather_transpose(sys, batch_addr, addr_output);
+        }
 
         let addr_quotient_values_batch = sys
             .mem
@@ -765,51 +1373,78 @@ fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
         sys.mem.preload(addr_betas, num_challenges);
         sys.mem.preload(addr_gammas, num_challenges);
         sys.mem.preload(addr_alphas, num_challenges);
+        if num_all_lookup_polys > 0 {
+            sys.mem
+                .preload(addr_deltas, NUM_COINS_LOOKUP * num_challenges);
+            sys.mem.preload(
+                addr_lut_re_poly_evals,
+                num_challenges * common_data.luts.len(),
+            );
+        }
 
         eval_vanishing_poly_base_batch(
             sys,
             common_data,
-            addr_shifted_xs_batch,
             vars_batch,
-            addr_local_zs,
-            addr_next_zs,
-            addr_partial_products,
-            addr_s_sigmas,
-            addr_betas,
-            addr_gammas,
-            addr_alphas,
+            VanishingVarsBatch {
+                addr_xs: addr_shifted_xs_batch,
+                index_start: batch_i * BATCH_SIZE,
+                addr_local_zs,
+                addr_next_zs,
+                addr_partial_products,
+                addr_s_sigmas,
+                addr_local_lookup_zs,
+                addr_next_lookup_zs,
+                addr_res: addr_quotient_values_batch,
+            },
+            challenges,
             &z_h_on_coset,
-            addr_quotient_values_batch,
             &mut eval_gate_ops,
-            &mut eval_gate_flag,
         );
 
-        let mut vec_ops = Vec::new();
-        for i in 0..num_challenges {
-            let addr_denominator_inv = z_h_on_coset.eval_inverse(0);
-            vec_ops.push(VecOpConfig {
-                vector_length: xs_batch_len,
-                addr_input_0: addr_quotient_values_batch + i * xs_batch_len * SIZE_F,
-                addr_input_1: addr_denominator_inv,
-                addr_output: addr_quotient_values_batch + i * xs_batch_len * SIZE_F,
-                op_src: VecOpSrc::VV,
-                op_type: VecOpType::MUL,
-                is_final_output: true,
-            });
-        }
-        sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
-        for i in 0..num_challenges {
-            let mk = MemCpy::new(
-                MemCpyConfig {
-                    addr_input: addr_quotient_values_batch + i * xs_batch_len * SIZE_F,
-                    addr_output: addr_res_tmp
-                        + i * xs_batch_len * SIZE_F * num_batches
-                        + batch_i * xs_batch_len * SIZE_F,
-                    input_length: xs_batch_len,
-                },
-                false,
-            );
-            mks.push(mk);
+        if sys.functional {
+            // _ / Z_H(x), into the values of each challenge
+            let vec_ops = (0..num_challenges)
+                .flat_map(|i| {
+                    z_h_on_coset.mul_inverses(
+                        xs_batch_len,
+                        batch_i * BATCH_SIZE,
+                        addr_quotient_values_batch + i * xs_batch_len * SIZE_F,
+                        addr_res_tmp + (i * lde_size + batch_i * BATCH_SIZE) * SIZE_F,
+                        true,
+                    )
+                })
+                .collect();
+            sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+        } else {
+            let mut vec_ops = Vec::new();
+            for i in 0..num_challenges {
+                let addr_denominator_inv = z_h_on_coset.eval_inverse(0);
+                vec_ops.push(VecOpConfig {
+                    vector_length: xs_batch_len,
+                    addr_input_0: addr_quotient_values_batch + i * xs_batch_len * SIZE_F,
+                    addr_input_1: addr_denominator_inv,
+                    addr_output: addr_quotient_values_batch + i * xs_batch_len * SIZE_F,
+                    op_src: VecOpSrc::VV,
+                    op_type: VecOpType::MUL,
+                    is_final_output: true,
+                });
+            }
+            sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+            for i in 0..num_challenges {
+                let mk = MemCpy::new(
+                    MemCpyConfig {
+                        addr_input: addr_quotient_values_batch + i * xs_batch_len * SIZE_F,
+                        addr_output: addr_res_tmp
+                            + i * xs_batch_len * SIZE_F * num_batches
+                            + batch_i * xs_batch_len * SIZE_F,
+                        input_length: xs_batch_len,
+                    },
+                    false,
+                    &sys.hw,
+                );
+                mks.push(mk);
+            }
         }
 
         sys.mem.clear_preload();
@@ -819,33 +1454,58 @@ fn compute_quotient_polys<F: RichField + Extendable<D>, const D: usize>(
         sys.mem.free("next_zs");
         sys.mem.free("partial_products");
         sys.mem.free("s_sigmas");
+        if num_all_lookup_polys > 0 {
+            sys.mem.free("local_lookup_zs");
+            sys.mem.free("next_lookup_zs");
+        }
         sys.mem.free("quotient_values_batch");
 
         sys.mem.free("shifted_xs_batch");
     }
-
-    let mut fft_k = Fft::new(FftConfig {
-        lg_n: log2_ceil(lde_size),
-        k: num_challenges,
-        direction: FftDirection::NN,
-        addr_input: addr_res_tmp,
-        addr_tmp: 1 << 60,
-        addr_output: addr_res,
-        inverse: true,
-        rate_bits: 0,
-        coset: true,
-        extension: 1,
-        transposed_input: false,
-    });
-    for memcpy in &mks {
-        fft_k.prefetch.addr_trans(memcpy);
+    sys.mem.free_deferred(deferred_mark);
+
+    let mut fft_k = Fft::new(
+        FftConfig {
+            lg_n: log2_ceil(lde_size),
+            k: num_challenges,
+            direction: FftDirection::NN,
+            addr_input: addr_res_tmp,
+            addr_tmp: 1 << 60,
+            addr_output: addr_res,
+            inverse: true,
+            rate_bits: 0,
+            coset: true,
+            extension: 1,
+            transposed_input: false,
+        },
+        &sys.hw,
+    );
+    // the copies of the values are fused into the fetches of the FFT
+    if !mks.is_empty() {
+        fft_k.edit(FetchEdit::PrefetchAddrTrans(
+            mks.iter().map(|memcpy| memcpy.config.clone()).collect(),
+        ));
     }
     sys.run_once(&fft_k);
     sys.mem.free("quotient_values_tmp");
 }
 
-fn batch_copy_transpose(batch_addr: &[(usize, usize)]) -> Vec<MemCpy> {
-    let al = unsafe { ARCH_CONFIG.array_length };
+// the MemCpys of batch_copy_transpose only model the traffic, in functional mode the values
+// are gathered here, element i of point j at i * n + j
+fn gather_transpose(sys: &mut System, batch_addr: &[(usize, usize)], addr_output: usize) {
+    if !sys.functional {
+        return;
+    }
+    let n = batch_addr.len();
+    for (j, &(addr, width)) in batch_addr.iter().enumerate() {
+        for (i, v) in sys.mem.read_elems(addr, width).into_iter().enumerate() {
+            sys.mem.write_elem(addr_output + (i * n + j) * SIZE_F, v);
+        }
+    }
+}
+
+fn batch_copy_transpose(batch_addr: &[(usize, usize)], hw: &HwConfig) -> Vec<MemCpy> {
+    let al = hw.arch.array_length;
     let width = batch_addr[0].1;
     let height = batch_addr.len();
 
@@ -858,10 +1518,11 @@ fn batch_copy_transpose(batch_addr: &[(usize, usize)]) -> Vec<MemCpy> {
                     addr_output: 0,
                     input_length: width,
                 },
-                unsafe { ENABLE_CONFIG.other },
+                hw.enable.other,
+                hw,
             );
-            mk.drain.clear();
-            mk.write_request.clear();
+            mk.edit(FetchEdit::ClearDrain);
+            mk.edit(FetchEdit::ClearWriteRequest);
             mcs.push(mk);
         }
     }
@@ -879,17 +1540,29 @@ pub fn fri_proof<const D: usize>(
     fri_params: &FriParams,
 ) {
     let mut addr_trees: Vec<PolynomialBatch> = Vec::new();
-    fri_committed_trees::<D>(
-        sys,
-        addr_lde_polynomial_coeffs,
-        addr_lde_coset_values,
-        degree,
-        challenger,
-        fri_params,
-        &mut addr_trees,
-    );
+    if sys.functional {
+        fri_committed_trees_functional::<D>(
+            sys,
+            addr_lde_polynomial_coeffs,
+            addr_lde_coset_values,
+            degree,
+            challenger,
+            fri_params,
+            &mut addr_trees,
+        );
+    } else {
+        fri_committed_trees::<D>(
+            sys,
+            addr_lde_polynomial_coeffs,
+            addr_lde_coset_values,
+            degree,
+            challenger,
+            fri_params,
+            &mut addr_trees,
+        );
+    }
 
-    sys.run_once(&challenger.pow());
+    sys.run_once(&challenger.pow(&sys.hw));
 
     fri_prover_query_rounds(
         sys,
@@ -960,22 +1633,26 @@ pub fn fri_committed_trees<const D: usize>(
                 addr_output: addr_leaves,
                 input_length: num_leaves * leaf_length,
             },
-            unsafe { ENABLE_CONFIG.hash },
+            sys.hw.enable.hash,
+            &sys.hw,
         );
-        mk.prefetch.clear();
-        mk.read_request.clear();
+        mk.edit(FetchEdit::ClearPrefetch);
+        mk.edit(FetchEdit::ClearReadRequest);
         sys.run_once(&mk);
 
-        let tree = Tree::new(TreeConfig {
-            leaf_length: leaf_length,
-            cap_height: cap_height,
-            num_leaves: num_leaves,
-            addr_cap_buf: addr_cap,
-            addr_leaves: addr_leaves,
-            addr_transposed_leaves: 0,
-            addr_digest_buf: addr_digests,
-            transposed_leaves: false,
-        });
+        let tree = Tree::new(
+            TreeConfig {
+                leaf_length: leaf_length,
+                cap_height: cap_height,
+                num_leaves: num_leaves,
+                addr_cap_buf: addr_cap,
+                addr_leaves: addr_leaves,
+                addr_transposed_leaves: 0,
+                addr_digest_buf: addr_digests,
+                transposed_leaves: false,
+            },
+            &sys.hw,
+        );
         sys.run_once(&tree);
 
         challenger.observe_cap(sys, addr_cap, cap_height);
@@ -985,21 +1662,24 @@ pub fn fri_committed_trees<const D: usize>(
 
         let addr_coeffs_trans = sys.mem.alloc("coeffs_trans", degree * D * SIZE_F).unwrap();
 
-        let chunk_length = unsafe { ARCH_CONFIG.num_elems() / arity / D };
+        let chunk_length = sys.hw.arch.num_elems() / arity / D;
         for chunk in (0..degree / arity).step_by(chunk_length) {
             let mut vec_ops = Vec::new();
-            let mut coeffs_trans = Transpose::new(TransposeConfig {
-                addr_input: addr_coeffs + chunk * arity * D * SIZE_F,
-                addr_output: addr_coeffs_trans,
-                width: arity,
-                height: chunk_length.min(degree / arity - chunk),
-                reverse: false,
-                extension: D,
-                start: 0,
-                end: arity,
-            });
-            coeffs_trans.drain.clear();
-            coeffs_trans.write_request.clear();
+            let mut coeffs_trans = Transpose::new(
+                TransposeConfig {
+                    addr_input: addr_coeffs + chunk * arity * D * SIZE_F,
+                    addr_output: addr_coeffs_trans,
+                    width: arity,
+                    height: chunk_length.min(degree / arity - chunk),
+                    reverse: false,
+                    extension: D,
+                    start: 0,
+                    end: arity,
+                },
+                &sys.hw,
+            );
+            coeffs_trans.edit(FetchEdit::ClearDrain);
+            coeffs_trans.edit(FetchEdit::ClearWriteRequest);
             sys.run_once(&coeffs_trans);
 
             for i in 0..arity {
@@ -1011,6 +1691,7 @@ pub fn fri_committed_trees<const D: usize>(
                     addr_coeffs,
                     VecOpSrc::VS,
                     false,
+                    sys.hw.arch.rich_vec_isa,
                 ));
                 vec_ops.extend(VecOpExtension::<D>::add(
                     degree / arity,
@@ -1021,7 +1702,7 @@ pub fn fri_committed_trees<const D: usize>(
                     true,
                 ));
             }
-            sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
+            sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
         }
 
         let mut vec_ops = Vec::new();
@@ -1030,22 +1711,26 @@ pub fn fri_committed_trees<const D: usize>(
             addr_shift,
             addr_shift,
             true,
+            sys.hw.arch.rich_vec_isa,
+        ));
+        sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+
+        sys.run_once(&Fft::new(
+            FftConfig {
+                lg_n: log2(degree / arity),
+                k: 1,
+                direction: FftDirection::NR,
+                addr_input: addr_coeffs,
+                addr_tmp: addr_coeffs_trans,
+                addr_output: addr_values,
+                inverse: false,
+                rate_bits: 0,
+                coset: true,
+                extension: D,
+                transposed_input: false,
+            },
+            &sys.hw,
         ));
-        sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
-
-        sys.run_once(&Fft::new(FftConfig {
-            lg_n: log2(degree / arity),
-            k: 1,
-            direction: FftDirection::NR,
-            addr_input: addr_coeffs,
-            addr_tmp: addr_coeffs_trans,
-            addr_output: addr_values,
-            inverse: false,
-            rate_bits: 0,
-            coset: true,
-            extension: D,
-            transposed_input: false,
-        }));
         sys.mem.free("coeffs_trans");
         sys.mem.free("beta");
         degree /= arity;
@@ -1059,6 +1744,217 @@ pub fn fri_committed_trees<const D: usize>(
     );
 }
 
+// fri_committed_trees as plonky2 computes it, for D = 2: the leaves are the values of
+// the previous round, which the NR FFT leaves in bit-reversed order, the coefficients are
+// folded by the powers of beta and evaluated on the coset of shift^arity
+fn fri_committed_trees_functional<const D: usize>(
+    sys: &mut System,
+    addr_coeffs: usize,
+    addr_values: usize,
+    mut degree: usize,
+    challenger: &mut Challenger,
+    fri_params: &FriParams,
+    addr_trees: &mut Vec<PolynomialBatch>,
+) {
+    if D != 2 {
+        panic!("Unsupported D");
+    }
+    let cap_height = fri_params.config.cap_height;
+
+    let mut shift = GoldilocksField::MULTIPLICATIVE_GROUP_GENERATOR;
+    for (arity_i, arity_bits) in fri_params.reduction_arity_bits.iter().enumerate() {
+        let arity = 1 << arity_bits;
+        let num_leaves = degree / arity;
+        let leaf_length = arity * D;
+
+        let digests_id = format!("{}{}", "digests", arity_i);
+        let cap_id = format!("{}{}", "cap", arity_i);
+        let leaves_id = format!("{}{}", "leaves", arity_i);
+        let addr_digests = sys
+            .mem
+            .alloc(
+                &digests_id,
+                Tree::num_digests(num_leaves, cap_height) * Tree::DIGEST_LENGTH * SIZE_F,
+            )
+            .unwrap();
+        let addr_cap = sys
+            .mem
+            .alloc(
+                &cap_id,
+                Tree::num_caps(cap_height) * Tree::DIGEST_LENGTH * SIZE_F,
+            )
+            .unwrap();
+        let addr_leaves = sys
+            .mem
+            .alloc(&leaves_id, num_leaves * leaf_length * SIZE_F)
+            .unwrap();
+        addr_trees.push(PolynomialBatch {
+            name: format!("Tree_{}", arity_i),
+            addr_cap,
+            addr_digests,
+            addr_leaves,
+            addr_polynomials: 0,
+            addr_salt: 0,
+            leaf_length,
+            degree_log: log2(num_leaves),
+            rate_bits: fri_params.config.rate_bits,
+            blinding: false,
+            addr_transposed_leaves: 0,
+            padding_length: 0,
+        });
+
+        sys.run_once(&MemCpy::new(
+            MemCpyConfig {
+                addr_input: addr_values,
+                addr_output: addr_leaves,
+                input_length: num_leaves * leaf_length,
+            },
+            sys.hw.enable.hash,
+            &sys.hw,
+        ));
+        sys.run_once(&Tree::new(
+            TreeConfig {
+                leaf_length,
+                cap_height,
+                num_leaves,
+                addr_cap_buf: addr_cap,
+                addr_leaves,
+                addr_transposed_leaves: 0,
+                addr_digest_buf: addr_digests,
+                transposed_leaves: false,
+            },
+            &sys.hw,
+        ));
+        challenger.observe_cap(sys, addr_cap, cap_height);
+
+        // beta, W * beta1 and the powers of the shift
+        let addr_beta = sys
+            .mem
+            .alloc("beta", (D + 2 + num_leaves) * SIZE_F)
+            .unwrap();
+        challenger.get_extension_challenge::<D>(sys, addr_beta);
+        let addr_w_beta1 = addr_beta + D * SIZE_F;
+        let addr_shift_powers = addr_beta + (D + 2) * SIZE_F;
+        sys.write_cpu(
+            addr_w_beta1 + SIZE_F,
+            &[<GoldilocksField as Extendable<2>>::W],
+        );
+        shift = shift.exp_u64(arity as u64);
+        sys.write_cpu(
+            addr_shift_powers,
+            &shift.powers().take(num_leaves).collect::<Vec<_>>(),
+        );
+
+        // the row i * D + e holds the component e of the coefficients i of the chunks
+        let addr_coeffs_trans = sys.mem.alloc("coeffs_trans", degree * D * SIZE_F).unwrap();
+        sys.run_once(&Transpose::new(
+            TransposeConfig {
+                addr_input: addr_coeffs,
+                addr_output: addr_coeffs_trans,
+                width: leaf_length,
+                height: num_leaves,
+                reverse: false,
+                extension: 1,
+                start: 0,
+                end: leaf_length,
+            },
+            &sys.hw,
+        ));
+        let addr_acc = sys
+            .mem
+            .alloc("fold_acc", 2 * num_leaves * D * SIZE_F)
+            .unwrap();
+        let addr_tmp = addr_acc + num_leaves * D * SIZE_F;
+        let acc = [addr_acc, addr_acc + num_leaves * SIZE_F];
+        let op = |addr_input_0, addr_input_1, addr_output, op_type, op_src| VecOpConfig {
+            vector_length: num_leaves,
+            addr_input_0,
+            addr_input_1,
+            addr_output,
+            op_type,
+            op_src,
+            is_final_output: true,
+        };
+        let mut vec_ops = vec![VecOpConfig {
+            vector_length: 1,
+            addr_input_0: addr_w_beta1 + SIZE_F,
+            addr_input_1: addr_beta + SIZE_F,
+            addr_output: addr_w_beta1,
+            op_type: VecOpType::MUL,
+            op_src: VecOpSrc::VS,
+            is_final_output: false,
+        }];
+        // reduce_with_powers of each chunk, by Horner from its last coefficient
+        for i in (0..arity).rev() {
+            vec_ops.extend(VecOpExtension2::mul_scalar_exact(
+                num_leaves,
+                acc,
+                addr_beta,
+                addr_w_beta1,
+                acc,
+                addr_tmp,
+                false,
+            ));
+            for (e, &addr) in acc.iter().enumerate() {
+                let addr_c = addr_coeffs_trans + (i * D + e) * num_leaves * SIZE_F;
+                vec_ops.push(op(addr, addr_c, addr, VecOpType::ADD, VecOpSrc::VV));
+            }
+        }
+        // the coset FFT of shift is the FFT of the coefficients times the powers of shift
+        for (e, &addr) in acc.iter().enumerate() {
+            vec_ops.push(op(
+                addr,
+                addr_shift_powers,
+                addr_tmp + e * num_leaves * SIZE_F,
+                VecOpType::MUL,
+                VecOpSrc::VV,
+            ));
+        }
+        sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+        for (addr_input, addr_output) in [(addr_acc, addr_coeffs), (addr_tmp, addr_coeffs_trans)] {
+            sys.run_once(&Transpose::new(
+                TransposeConfig {
+                    addr_input,
+                    addr_output,
+                    width: num_leaves,
+                    height: D,
+                    reverse: false,
+                    extension: 1,
+                    start: 0,
+                    end: num_leaves,
+                },
+                &sys.hw,
+            ));
+        }
+        sys.run_once(&Fft::new(
+            FftConfig {
+                lg_n: log2(num_leaves),
+                k: 1,
+                direction: FftDirection::NR,
+                addr_input: addr_coeffs_trans,
+                addr_tmp: addr_acc,
+                addr_output: addr_values,
+                inverse: false,
+                rate_bits: 0,
+                coset: false,
+                extension: D,
+                transposed_input: false,
+            },
+            &sys.hw,
+        ));
+        sys.mem.free("fold_acc");
+        sys.mem.free("coeffs_trans");
+        sys.mem.free("beta");
+        degree = num_leaves;
+    }
+
+    challenger.observe_extension_elements::<D>(
+        sys,
+        addr_coeffs,
+        degree >> fri_params.config.rate_bits,
+    );
+}
+
 pub fn fri_prover_query_rounds(
     sys: &mut System,
     initial_merkle_trees: &Vec<&PolynomialBatch>,
@@ -1110,6 +2006,7 @@ pub fn fri_prover_query_round(
             x_index,
             cap_height,
             addr_initial_proof + initial_proof_length * NUM_HASH_OUT_ELTS * SIZE_F,
+            &sys.hw,
         ));
         initial_proof_length += tree.num_layers(cap_height);
     }
@@ -1133,9 +2030,234 @@ pub fn fri_prover_query_round(
             x_index >> arity_bits,
             cap_height,
             addr_merkle_proof + merkle_proof_length * NUM_HASH_OUT_ELTS * SIZE_F,
+            &sys.hw,
         ));
         merkle_proof_length += tree.num_layers(cap_height);
         x_index >>= arity_bits;
     }
     sys.run_vec(mks);
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use std::sync::Arc;
+
+    use plonky2::gates::lookup_table::LookupTable;
+    use plonky2::hash::merkle_tree::MerkleCap;
+    use plonky2::iop::generator::generate_partial_witness;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use plonky2::util::timing::TimingTree;
+
+    use super::*;
+    use crate::config::{Backend, RamConfig};
+    use crate::memory::memory_allocator::MemAlloc;
+
+    // proves the witness with plonky2 and in functional mode, and compares the commitments,
+    // the challenges, the openings and the FRI commit phase of both
+    fn check_functional_proof(
+        data: &CircuitData<GoldilocksField, PoseidonGoldilocksConfig, 2>,
+        pw: PartialWitness<GoldilocksField>,
+    ) -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut hw = HwConfig::default();
+        hw.timing.backend = Backend::Analytical;
+        // the lowerings of the gates compute their constraints
+        hw.opt.symbolic_gates = true;
+        let mut sys = System::new(
+            MemAlloc::new(1, 4096),
+            RamConfig::new("test_functional"),
+            hw,
+        );
+        sys.functional = true;
+        let partition_witness = generate_partial_witness(pw, &data.prover_only, &data.common);
+        let proof = plonky2::plonk::prover::prove_with_partition_witness(
+            &data.prover_only,
+            &data.common,
+            partition_witness.clone(),
+            &mut TimingTree::default(),
+        )?;
+        data.verify(proof.clone())?;
+        prove_with_partition_witness(&mut sys, &data.prover_only, &data.common, partition_witness);
+
+        let cap = |name: &str, cap: &MerkleCap<F, <C as GenericConfig<D>>::Hasher>| {
+            let addr = sys.mem.get_addr(name).unwrap();
+            let expected = cap.0.iter().flat_map(|h| h.elements).collect::<Vec<_>>();
+            assert_eq!(
+                sys.mem.read_elems(addr, expected.len()),
+                expected,
+                "{}",
+                name
+            );
+        };
+        cap("capwires_commitment", &proof.proof.wires_cap);
+        cap(
+            "cappartial_products_zs_and_lookup_commitment",
+            &proof.proof.plonk_zs_partial_products_cap,
+        );
+
+        let challenges = proof.get_challenges(
+            proof.get_public_inputs_hash(),
+            &data.prover_only.circuit_digest,
+            &data.common,
+        )?;
+        let read = |name: &str, n: usize| sys.mem.read_elems(sys.mem.get_addr(name).unwrap(), n);
+        let num_challenges = data.common.config.num_challenges;
+        assert_eq!(read("betas", num_challenges), challenges.plonk_betas);
+        assert_eq!(read("gammas", num_challenges), challenges.plonk_gammas);
+        if !challenges.plonk_deltas.is_empty() {
+            assert_eq!(
+                read("deltas", challenges.plonk_deltas.len()),
+                challenges.plonk_deltas
+            );
+        }
+        assert_eq!(read("alphas", num_challenges), challenges.plonk_alphas);
+        cap(
+            "capquotient_polys_commitment",
+            &proof.proof.quotient_polys_cap,
+        );
+        assert_eq!(
+            read("zeta", D),
+            FieldExtension::<D>::to_basefield_array(&challenges.plonk_zeta)
+        );
+
+        let opening = |name: &str, offset: usize, expected: &[<F as Extendable<D>>::Extension]| {
+            let addr = sys.mem.get_addr(name).unwrap() + offset * D * SIZE_F;
+            let expected = expected
+                .iter()
+                .flat_map(FieldExtension::<D>::to_basefield_array)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                sys.mem.read_elems(addr, expected.len()),
+                expected,
+                "{}",
+                name
+            );
+        };
+        let openings = &proof.proof.openings;
+        opening("constants_sigmas_eval", 0, &openings.constants);
+        opening(
+            "constants_sigmas_eval",
+            data.common.num_constants,
+            &openings.plonk_sigmas,
+        );
+        opening("wires_eval", 0, &openings.wires);
+        opening("zs_partial_products_lookup_eval", 0, &openings.plonk_zs);
+        opening(
+            "zs_partial_products_lookup_next_eval",
+            0,
+            &openings.plonk_zs_next,
+        );
+        opening(
+            "zs_partial_products_lookup_eval",
+            num_challenges,
+            &openings.partial_products,
+        );
+        let num_zs_partial_products = num_challenges * (1 + data.common.num_partial_products);
+        opening(
+            "zs_partial_products_lookup_eval",
+            num_zs_partial_products,
+            &openings.lookup_zs,
+        );
+        opening(
+            "zs_partial_products_lookup_next_eval",
+            num_zs_partial_products,
+            &openings.lookup_zs_next,
+        );
+        opening("quotient_polys_eval", 0, &openings.quotient_polys);
+
+        let opening_proof = &proof.proof.opening_proof;
+        assert!(!opening_proof.commit_phase_merkle_caps.is_empty());
+        for (i, commit_phase_cap) in opening_proof.commit_phase_merkle_caps.iter().enumerate() {
+            cap(&format!("cap{}", i), commit_phase_cap);
+        }
+        opening("final_coeff", 0, &opening_proof.final_poly.coeffs);
+        Ok(())
+    }
+
+    #[test]
+    fn test_functional_wires_cap() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let initial_a = builder.add_virtual_target();
+        let initial_b = builder.add_virtual_target();
+        let mut prev_target = initial_a;
+        let mut cur_target = initial_b;
+        for _ in 0..1999 {
+            let temp = builder.add(prev_target, cur_target);
+            prev_target = cur_target;
+            cur_target = temp;
+        }
+        builder.register_public_input(initial_a);
+        builder.register_public_input(initial_b);
+        builder.register_public_input(cur_target);
+        let mut pw = PartialWitness::new();
+        pw.set_target(initial_a, F::ZERO);
+        pw.set_target(initial_b, F::ONE);
+        let data = builder.build::<C>();
+        check_functional_proof(&data, pw)
+    }
+
+    // the lookup polynomials are in the Zs and partial products commitment, and their
+    // constraints in the quotient
+    #[test]
+    fn test_functional_lookups() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let mut pw = PartialWitness::new();
+        // several rows of each lookup gate and two tables, for the scans over the rows
+        for t in 0..2 {
+            let table: LookupTable = Arc::new((0..64).map(|i| (i, i * i + t)).collect());
+            let index = builder.add_lookup_table_from_pairs(table);
+            for i in 0..200 {
+                let x = builder.add_virtual_target();
+                pw.set_target(x, F::from_canonical_usize(i * 7 % 64));
+                let y = builder.add_lookup_from_index(x, index);
+                builder.register_public_input(y);
+            }
+        }
+        let data = builder.build::<C>();
+        check_functional_proof(&data, pw)
+    }
+
+    #[test]
+    fn test_prove_lookups() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let table: LookupTable = Arc::new((0..16).map(|i| (i, i * i)).collect());
+        let index = builder.add_lookup_table_from_pairs(table);
+        for i in 0..50 {
+            let x = builder.constant(F::from_canonical_usize(i % 16));
+            let y = builder.add_lookup_from_index(x, index);
+            builder.register_public_input(y);
+        }
+        let data = builder.build::<C>();
+
+        let mut hw = HwConfig::default();
+        hw.timing.backend = Backend::Analytical;
+        let mut sys = System::new(
+            MemAlloc::new(1, 4096),
+            RamConfig::new("test_prove_lookups"),
+            hw,
+        );
+        let partition_witness =
+            generate_partial_witness(PartialWitness::new(), &data.prover_only, &data.common);
+        prove_with_partition_witness(&mut sys, &data.prover_only, &data.common, partition_witness);
+        assert!(sys.mem.get_addr("deltas").is_some());
+    }
+}
@@ -1,8 +1,9 @@
-use crate::config::enable_config::ENABLE_CONFIG;
+use crate::kernel::kernel::FetchEdit;
 use crate::kernel::memory_copy::{MemCpy, MemCpyConfig};
 use crate::kernel::vector_chain::VectorChain;
 use crate::kernel::vector_operation::{VecOpConfig, VecOpSrc, VecOpType};
 use crate::memory::memory_allocator::MemAlloc;
+use crate::plonk::gate_descriptor::GateDescriptor;
 use crate::plonk::vars::EvaluationVarsBaseBatch;
 use crate::plonk::zero_poly_coset::ZeroPolyOnCoset;
 use crate::system::system::System;
@@ -10,30 +11,70 @@ use crate::util::{ceil_div_usize, SIZE_F};
 use log::debug;
 use plonky2::field::extension::Extendable;
 use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_builder::NUM_COINS_LOOKUP;
 use plonky2::plonk::circuit_data::CommonCircuitData;
+use std::ops::Range;
 
+use crate::plonk::gate_lowering;
 use crate::plonk::gates::eval_filtered_base_batch;
+use crate::plonk::lookup::{check_lookup_constraints_batch, num_lookup_constraints, LookupVarsBatch};
+// Where eval_vanishing_poly_base_batch finds the polynomials of a batch, loaded by the previous
+// transpose kernels, and where it writes the n values of each challenge
+#[derive(Debug, Copy, Clone)]
+pub struct VanishingVarsBatch {
+    pub addr_xs: usize,
+    pub index_start: usize, // the index of the first point of the batch
+    pub addr_local_zs: usize,
+    pub addr_next_zs: usize,
+    pub addr_partial_products: usize,
+    pub addr_s_sigmas: usize,
+    pub addr_local_lookup_zs: usize, // unused without lookups
+    pub addr_next_lookup_zs: usize,  // unused without lookups
+    pub addr_res: usize,             // n * num_challenges * SIZE_F
+}
+
+// The challenges of the quotient, num_challenges of each, and the LUT evaluations at the
+// deltas
+#[derive(Debug, Copy, Clone)]
+pub struct VanishingChallenges {
+    pub addr_betas: usize,
+    pub addr_gammas: usize,
+    pub addr_alphas: usize,
+    pub addr_deltas: usize,
+    pub addr_lut_re_poly_evals: usize,
+}
+
+// eval_gate_ops holds the ops of the gates, generated in the first batch and run in every batch
 pub fn eval_vanishing_poly_base_batch<F: RichField + Extendable<D>, const D: usize>(
     sys: &mut System,
     common_data: &CommonCircuitData<F, D>,
-    addr_xs_batch: usize, // load in previous transpose kernel
     vars_batch: EvaluationVarsBaseBatch,
-    addr_local_zs: usize,         // load in previous transpose kernel
-    addr_next_zs: usize,          // load in previous transpose kernel
-    addr_partial_products: usize, // load in previous transpose kernel
-    addr_s_sigmas: usize,         // load in previous transpose kernel
-    addr_betas: usize,
-    addr_gammas: usize,
-    addr_alphas: usize,
+    batch: VanishingVarsBatch,
+    challenges: VanishingChallenges,
     z_h_on_coset: &ZeroPolyOnCoset,
-    addr_res_batch: usize, // n * num_chanllengers * SIZE_F
-
-    eval_gate_ops: &mut VectorChain,
-    eval_gate_ops_flag: &mut bool,
+    eval_gate_ops: &mut Option<VectorChain>,
 ) {
     // debug!("eval_vanishing_poly_base_batch");
 
     let n = vars_batch.len();
+    let VanishingVarsBatch {
+        addr_xs: addr_xs_batch,
+        index_start,
+        addr_local_zs,
+        addr_next_zs,
+        addr_partial_products,
+        addr_s_sigmas,
+        addr_local_lookup_zs,
+        addr_next_lookup_zs,
+        addr_res: addr_res_batch,
+    } = batch;
+    let VanishingChallenges {
+        addr_betas,
+        addr_gammas,
+        addr_alphas,
+        addr_deltas,
+        addr_lut_re_poly_evals,
+    } = challenges;
 
     let max_degree = common_data.quotient_degree_factor;
     let num_prods = common_data.num_partial_products;
@@ -46,19 +87,23 @@ pub fn eval_vanishing_poly_base_batch<F: RichField + Extendable<D>, const D: usi
         .unwrap();
     sys.mem
         .preload(addr_constraint_terms_batch, n * num_gate_constraints);
-    if *eval_gate_ops_flag == false {
+    if eval_gate_ops.is_none() {
         debug!("get vec chain evaluate_gate_constraints_base_batch");
-        let vec_ops = evaluate_gate_constraints_base_batch::<F, D>(
+        let (vec_ops, gate_ranges) = evaluate_gate_constraints_base_batch::<F, D>(
             sys,
             common_data,
             vars_batch,
             addr_constraint_terms_batch,
         );
-        *eval_gate_ops = VectorChain::new(vec_ops, &sys.mem);
-        *eval_gate_ops_flag = true;
+        *eval_gate_ops = Some(VectorChain::new_eliminated(
+            vec_ops,
+            &gate_ranges,
+            &sys.mem,
+            &sys.hw,
+        ));
         debug!("end vec chain evaluate_gate_constraints_base_batch");
     }
-    sys.run_once(eval_gate_ops);
+    sys.run_once(eval_gate_ops.as_ref().unwrap());
     sys.mem.unpreload(vars_batch.addr_local_constants);
 
     let num_challenges = common_data.config.num_challenges;
@@ -81,9 +126,22 @@ pub fn eval_vanishing_poly_base_batch<F: RichField + Extendable<D>, const D: usi
         addr_vanishing_partial_products_terms,
         num_challenges * n * (num_prods + 1),
     );
+    let num_lookup_constraints = num_lookup_constraints(common_data);
+    let addr_vanishing_lookup_terms = sys
+        .mem
+        .alloc(
+            "vanishing_lookup_terms",
+            num_challenges * n * num_lookup_constraints * SIZE_F,
+        )
+        .unwrap();
+    sys.mem.preload(
+        addr_vanishing_lookup_terms,
+        num_challenges * n * num_lookup_constraints,
+    );
     let addr_l_0_x = sys.mem.alloc("l_0_x", n * SIZE_F).unwrap();
-    z_h_on_coset.eval_l_0(sys, n, addr_xs_batch, addr_l_0_x);
+    z_h_on_coset.eval_l_0(sys, n, index_start, addr_xs_batch, addr_l_0_x);
 
+    let deferred_mark = sys.mem.deferred_mark();
     let mut vec_ops = Vec::new();
 
     let addr_k_is = sys.mem.get_addr("k_is_cpu").unwrap();
@@ -98,28 +156,75 @@ pub fn eval_vanishing_poly_base_batch<F: RichField + Extendable<D>, const D: usi
         .unwrap();
     sys.mem
         .preload(addr_denominator_values, num_routed_wires * n);
+    // the model doesn't write the sums of the denominators back
+    let functional = sys.functional;
+    let addr_denominator_sums = |j: usize| {
+        if functional {
+            addr_denominator_values + j * n * SIZE_F
+        } else {
+            0
+        }
+    };
 
     for i in 0..num_challenges {
-        // z_x.sub_one()
-        vec_ops.push(VecOpConfig {
-            vector_length: n,
-            addr_input_0: addr_local_zs + i * n * SIZE_F,
-            addr_input_1: 0,
-            addr_output: addr_vanishing_z_1_terms + i * n * SIZE_F,
-            op_type: VecOpType::SUB,
-            op_src: VecOpSrc::VS,
-            is_final_output: false,
-        });
-        // l_0_x * _
-        vec_ops.push(VecOpConfig {
-            vector_length: n,
-            addr_input_0: addr_l_0_x,
-            addr_input_1: addr_vanishing_z_1_terms + i * n * SIZE_F,
-            addr_output: addr_vanishing_z_1_terms + i * n * SIZE_F,
-            op_type: VecOpType::MUL,
-            op_src: VecOpSrc::VV,
-            is_final_output: false,
-        });
+        if sys.functional {
+            // l_0_x * z_x.sub_one(), as l_0_x * z_x - l_0_x
+            vec_ops.push(VecOpConfig {
+                vector_length: n,
+                addr_input_0: addr_l_0_x,
+                addr_input_1: addr_local_zs + i * n * SIZE_F,
+                addr_output: addr_vanishing_z_1_terms + i * n * SIZE_F,
+                op_type: VecOpType::MUL,
+                op_src: VecOpSrc::VV,
+                is_final_output: false,
+            });
+            vec_ops.push(VecOpConfig {
+                vector_length: n,
+                addr_input_0: addr_vanishing_z_1_terms + i * n * SIZE_F,
+                addr_input_1: addr_l_0_x,
+                addr_output: addr_vanishing_z_1_terms + i * n * SIZE_F,
+                op_type: VecOpType::SUB,
+                op_src: VecOpSrc::VV,
+                is_final_output: false,
+            });
+        } else {
+            // z_x.sub_one()
+            vec_ops.push(VecOpConfig {
+                vector_length: n,
+                addr_input_0: addr_local_zs + i * n * SIZE_F,
+                addr_input_1: 0,
+                addr_output: addr_vanishing_z_1_terms + i * n * SIZE_F,
+                op_type: VecOpType::SUB,
+                op_src: VecOpSrc::VS,
+                is_final_output: false,
+            });
+            // l_0_x * _
+            vec_ops.push(VecOpConfig {
+                vector_length: n,
+                addr_input_0: addr_l_0_x,
+                addr_input_1: addr_vanishing_z_1_terms + i * n * SIZE_F,
+                addr_output: addr_vanishing_z_1_terms + i * n * SIZE_F,
+                op_type: VecOpType::MUL,
+                op_src: VecOpSrc::VV,
+                is_final_output: false,
+            });
+        }
+        if num_lookup_constraints > 0 {
+            let num_lookup_polys = common_data.num_lookup_polys;
+            vec_ops.extend(check_lookup_constraints_batch(
+                &mut sys.mem,
+                common_data,
+                vars_batch,
+                LookupVarsBatch {
+                    addr_local_lookup_zs: addr_local_lookup_zs + i * num_lookup_polys * n * SIZE_F,
+                    addr_next_lookup_zs: addr_next_lookup_zs + i * num_lookup_polys * n * SIZE_F,
+                    addr_deltas: addr_deltas + i * NUM_COINS_LOOKUP * SIZE_F,
+                    addr_lut_re_poly_evals: addr_lut_re_poly_evals
+                        + i * common_data.luts.len() * SIZE_F,
+                },
+                addr_vanishing_lookup_terms + i * num_lookup_constraints * n * SIZE_F,
+            ));
+        }
         for j in 0..num_routed_wires {
             // k_i * x
             vec_ops.push(VecOpConfig {
@@ -177,7 +282,7 @@ pub fn eval_vanishing_poly_base_batch<F: RichField + Extendable<D>, const D: usi
                 vector_length: n,
                 addr_input_0: vars_batch.addr_local_wires + j * n * SIZE_F,
                 addr_input_1: addr_denominator_values + j * n * SIZE_F,
-                addr_output: 0, // addr_denominator_values + j * n * SIZE_F,
+                addr_output: addr_denominator_sums(j),
                 op_type: VecOpType::ADD,
                 op_src: VecOpSrc::VV,
                 is_final_output: false,
@@ -187,7 +292,7 @@ pub fn eval_vanishing_poly_base_batch<F: RichField + Extendable<D>, const D: usi
                 vector_length: n,
                 addr_input_0: addr_denominator_values + j * n * SIZE_F,
                 addr_input_1: addr_gammas + i * SIZE_F,
-                addr_output: 0, //addr_denominator_values + j * n * SIZE_F,
+                addr_output: addr_denominator_sums(j),
                 op_type: VecOpType::ADD,
                 op_src: VecOpSrc::VS,
                 is_final_output: false,
@@ -196,6 +301,7 @@ pub fn eval_vanishing_poly_base_batch<F: RichField + Extendable<D>, const D: usi
         let addr_current_partial_products = addr_partial_products + i * num_prods * n * SIZE_F;
         vec_ops.extend(check_partial_products(
             &mut sys.mem,
+            sys.functional,
             n,
             num_routed_wires,
             addr_numerator_values,
@@ -215,49 +321,93 @@ pub fn eval_vanishing_poly_base_batch<F: RichField + Extendable<D>, const D: usi
             .map(|i| addr_vanishing_partial_products_terms + i * n * SIZE_F)
             .collect::<Vec<_>>(),
     );
+    addr_vanishing_terms.extend(
+        (0..num_challenges * num_lookup_constraints)
+            .map(|i| addr_vanishing_lookup_terms + i * n * SIZE_F)
+            .collect::<Vec<_>>(),
+    );
     addr_vanishing_terms.extend(
         (0..num_gate_constraints)
             .map(|i| addr_constraint_terms_batch + i * n * SIZE_F)
             .collect::<Vec<_>>(),
     );
-    let terms_len = addr_vanishing_terms.len();
-    for i in 0..num_challenges {
-        for j in 0..terms_len {
+    if sys.functional {
+        // reduce_with_powers, Horner from the last term: the last term + 0, then
+        // _ * alpha + term
+        let (&addr_last_term, addr_terms) = addr_vanishing_terms.split_last().unwrap();
+        for i in 0..num_challenges {
             vec_ops.push(VecOpConfig {
                 vector_length: n,
-                addr_input_0: if j != 0 {
-                    addr_res_batch + i * n * SIZE_F
-                } else {
-                    0
-                },
-                addr_input_1: addr_alphas + i * SIZE_F,
-                addr_output: addr_res_batch + i * n * SIZE_F,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VS,
-                is_final_output: false,
-            });
-            vec_ops.push(VecOpConfig {
-                vector_length: n,
-                addr_input_0: addr_vanishing_terms[j],
-                addr_input_1: addr_res_batch + i * n * SIZE_F,
+                addr_input_0: addr_last_term,
+                addr_input_1: 0,
                 addr_output: addr_res_batch + i * n * SIZE_F,
                 op_type: VecOpType::ADD,
-                op_src: VecOpSrc::VV,
-                is_final_output: true,
+                op_src: VecOpSrc::VS,
+                is_final_output: addr_terms.is_empty(),
             });
+            for (j, &addr_term) in addr_terms.iter().enumerate().rev() {
+                vec_ops.push(VecOpConfig {
+                    vector_length: n,
+                    addr_input_0: addr_res_batch + i * n * SIZE_F,
+                    addr_input_1: addr_alphas + i * SIZE_F,
+                    addr_output: addr_res_batch + i * n * SIZE_F,
+                    op_type: VecOpType::MUL,
+                    op_src: VecOpSrc::VS,
+                    is_final_output: false,
+                });
+                vec_ops.push(VecOpConfig {
+                    vector_length: n,
+                    addr_input_0: addr_term,
+                    addr_input_1: addr_res_batch + i * n * SIZE_F,
+                    addr_output: addr_res_batch + i * n * SIZE_F,
+                    op_type: VecOpType::ADD,
+                    op_src: VecOpSrc::VV,
+                    is_final_output: j == 0,
+                });
+            }
+        }
+    } else {
+        for i in 0..num_challenges {
+            for (j, &addr_term) in addr_vanishing_terms.iter().enumerate() {
+                vec_ops.push(VecOpConfig {
+                    vector_length: n,
+                    addr_input_0: if j != 0 {
+                        addr_res_batch + i * n * SIZE_F
+                    } else {
+                        0
+                    },
+                    addr_input_1: addr_alphas + i * SIZE_F,
+                    addr_output: addr_res_batch + i * n * SIZE_F,
+                    op_type: VecOpType::MUL,
+                    op_src: VecOpSrc::VS,
+                    is_final_output: false,
+                });
+                vec_ops.push(VecOpConfig {
+                    vector_length: n,
+                    addr_input_0: addr_term,
+                    addr_input_1: addr_res_batch + i * n * SIZE_F,
+                    addr_output: addr_res_batch + i * n * SIZE_F,
+                    op_type: VecOpType::ADD,
+                    op_src: VecOpSrc::VV,
+                    is_final_output: true,
+                });
+            }
         }
     }
-    sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
+    sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+    sys.mem.free_deferred(deferred_mark);
     sys.mem.free("denominator_values");
     sys.mem.free("numerator_values");
     sys.mem.free("vanishing_partial_products_terms");
     sys.mem.free("vanishing_z_1_terms");
     sys.mem.free("l_0_x");
+    sys.mem.free("vanishing_lookup_terms");
     sys.mem.free("constraint_terms_batch");
 }
 
 pub fn check_partial_products(
     mem: &mut MemAlloc,
+    functional: bool,
     n: usize,
     num_routed_wires: usize,
     addr_numerators: usize,   // n * num_routed_wires * SIZE_F
@@ -278,36 +428,44 @@ pub fn check_partial_products(
     addr_partials_split.push(addr_z_gx);
 
     for chunk_idx in 0..=num_prods {
-        let addr_num_chunk_product = mem.alloc("num_chunk_product", n * SIZE_F).unwrap();
-        let addr_den_chunk_product = mem.alloc("den_chunk_product", n * SIZE_F).unwrap();
+        // in functional mode the caller frees the chunk products once the ops have run
+        let [addr_num_chunk_product, addr_den_chunk_product] =
+            ["num_chunk_product", "den_chunk_product"].map(|prefix| {
+                if !functional {
+                    return mem.alloc(prefix, n * SIZE_F).unwrap();
+                }
+                let id = mem.temp_name(prefix);
+                let addr = mem.alloc(&id, n * SIZE_F).unwrap();
+                mem.defer_free(&id);
+                addr
+            });
         for i in chunk_idx * max_degree..num_routed_wires.min((chunk_idx + 1) * max_degree) {
             let addr_numerator = addr_numerators + i * n * SIZE_F;
             let addr_denominator = addr_denominators + i * n * SIZE_F;
 
+            // the first factor of a chunk is copied, as _ + 0, the model multiplies it by 0
+            let first = i == chunk_idx * max_degree;
+            let (op_type, op_src) = if first && functional {
+                (VecOpType::ADD, VecOpSrc::VS)
+            } else {
+                (VecOpType::MUL, VecOpSrc::VV)
+            };
             vec_ops.push(VecOpConfig {
                 vector_length: n,
                 addr_input_0: addr_numerator,
-                addr_input_1: if i != chunk_idx * max_degree {
-                    addr_num_chunk_product
-                } else {
-                    0
-                },
+                addr_input_1: if first { 0 } else { addr_num_chunk_product },
                 addr_output: addr_num_chunk_product,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
+                op_type,
+                op_src,
                 is_final_output: false,
             });
             vec_ops.push(VecOpConfig {
                 vector_length: n,
                 addr_input_0: addr_denominator,
-                addr_input_1: if i != chunk_idx * max_degree {
-                    addr_den_chunk_product
-                } else {
-                    0
-                },
+                addr_input_1: if first { 0 } else { addr_den_chunk_product },
                 addr_output: addr_den_chunk_product,
-                op_type: VecOpType::MUL,
-                op_src: VecOpSrc::VV,
+                op_type,
+                op_src,
                 is_final_output: false,
             });
         }
@@ -340,8 +498,10 @@ pub fn check_partial_products(
             op_src: VecOpSrc::VV,
             is_final_output: false,
         });
-        mem.free("num_chunk_product");
-        mem.free("den_chunk_product");
+        if !functional {
+            mem.free("num_chunk_product");
+            mem.free("den_chunk_product");
+        }
     }
 
     vec_ops
@@ -352,17 +512,32 @@ pub fn evaluate_gate_constraints_base_batch<F: RichField + Extendable<D>, const
     common_data: &CommonCircuitData<F, D>,
     vars_batch: EvaluationVarsBaseBatch,
     addr_constraints_batch: usize,
-) -> Vec<VecOpConfig> {
+) -> (Vec<VecOpConfig>, Vec<(String, Range<usize>)>) {
     debug!("evaluate_gate_constraints_base_batch");
 
     let mut res = Vec::new();
+    // the ops of each gate, by gate type
+    let mut gate_ranges = Vec::new();
+    // the registry of the lowerings is behind a lock, look each gate up once
+    let lowerings = common_data
+        .gates
+        .iter()
+        .map(gate_lowering::lowered)
+        .collect::<Vec<_>>();
     for (i, gate) in common_data.gates.iter().enumerate() {
         let selector_index = common_data.selectors_info.selector_indices[i];
 
+        // in functional mode the ops of the gates run after all of them are generated, the
+        // model reuses one buffer
+        let res_batch_id = if sys.functional {
+            sys.mem.temp_name("res_batch")
+        } else {
+            "res_batch".to_string()
+        };
         let addr_res_batch = sys
             .mem
             .alloc(
-                "res_batch",
+                &res_batch_id,
                 vars_batch.len() * gate.0.num_constraints() * SIZE_F,
             )
             .unwrap();
@@ -379,10 +554,11 @@ pub fn evaluate_gate_constraints_base_batch<F: RichField + Extendable<D>, const
                     addr_output: addr_group,
                     input_length: group_size,
                 },
-                unsafe { ENABLE_CONFIG.other },
+                sys.hw.enable.other,
+                &sys.hw,
             );
-            group_cp.drain.clear();
-            group_cp.write_request.clear();
+            group_cp.edit(FetchEdit::ClearDrain);
+            group_cp.edit(FetchEdit::ClearWriteRequest);
             sys.run_once(&group_cp);
         }
 
@@ -395,8 +571,9 @@ pub fn evaluate_gate_constraints_base_batch<F: RichField + Extendable<D>, const
             addr_group,
             common_data.selectors_info.groups[selector_index].clone(),
             common_data.selectors_info.num_selectors(),
-            0,
+            common_data.num_lookup_selectors,
             addr_res_batch,
+            lowerings[i].as_deref(),
         );
         gate_eval_ops.push(VecOpConfig {
             vector_length: vars_batch.len() * gate.0.num_constraints(),
@@ -407,9 +584,19 @@ pub fn evaluate_gate_constraints_base_batch<F: RichField + Extendable<D>, const
             op_src: VecOpSrc::VV,
             is_final_output: true,
         });
+        // gates without a port are told apart by their whole id
+        let gate_type = match GateDescriptor::of(gate) {
+            Ok(descriptor) => descriptor.name().to_string(),
+            Err(_) => gate.0.id(),
+        };
+        gate_ranges.push((gate_type, res.len()..res.len() + gate_eval_ops.len()));
         res.extend(gate_eval_ops);
 
-        sys.mem.free("res_batch");
+        if sys.functional {
+            sys.mem.defer_free(&res_batch_id);
+        } else {
+            sys.mem.free(&res_batch_id);
+        }
     }
-    res
+    (res, gate_ranges)
 }
@@ -1,3 +1,6 @@
+use plonky2::field::goldilocks_field::GoldilocksField as F;
+use plonky2::field::types::Field;
+
 use crate::kernel::vector_chain::VectorChain;
 use crate::kernel::vector_operation::{VecOpConfig, VecOpSrc, VecOpType};
 
@@ -5,18 +8,31 @@ use crate::memory::memory_allocator::MemAlloc;
 use crate::system::system::System;
 use crate::util::SIZE_F;
 
+/// Like plonky2's ZeroPolyOnCoset: Z_H on the coset of the LDE of a trace of 2^n_log rows,
+/// which only takes rate values, g^n * w^i - 1 for the rate-th roots of unity w^i.
 pub struct ZeroPolyOnCoset {
     pub rate: usize,
     addr_evals: usize,
     pub addr_inverses: usize,
+    addr_scalars: usize, // ONE and n, in functional mode
 }
 
 impl ZeroPolyOnCoset {
-    pub fn new(sys: &mut System, rate_bits: usize) -> Self {
+    pub fn new(sys: &mut System, n_log: usize, rate_bits: usize) -> Self {
         let rate = 1 << rate_bits;
         let addr_g_pow_n = sys.mem.alloc("g_pow_n_cpu", SIZE_F).unwrap();
         let addr_evals = sys.mem.alloc("addr_evals_cpu", rate * SIZE_F).unwrap(); // subgroup from cpu
         let addr_inverses = sys.mem.alloc("addr_inverses", rate * SIZE_F).unwrap();
+        // the constant scalars are only in memory in functional mode, at 0 otherwise
+        let addr_scalars = if sys.functional {
+            let addr = sys.mem.alloc("zero_poly_scalars_cpu", 2 * SIZE_F).unwrap();
+            sys.write_cpu(addr_g_pow_n, &[F::coset_shift().exp_power_of_2(n_log)]);
+            sys.write_cpu(addr_evals, &F::two_adic_subgroup(rate_bits));
+            sys.write_cpu(addr, &[F::ONE, F::from_canonical_usize(1 << n_log)]);
+            addr
+        } else {
+            0
+        };
 
         let mut vec_ops = Vec::new();
         // g_pow_n * x
@@ -33,30 +49,92 @@ impl ZeroPolyOnCoset {
         vec_ops.push(VecOpConfig {
             vector_length: rate,
             addr_input_0: addr_evals,
-            addr_input_1: 0, // const scalar
+            addr_input_1: addr_scalars,
             addr_output: addr_evals,
             op_type: VecOpType::SUB,
             op_src: VecOpSrc::VS,
             is_final_output: true,
         });
-        vec_ops.extend(VecOpConfig::inv(
+        let deferred_mark = sys.mem.deferred_mark();
+        let inv = if sys.functional {
+            VecOpConfig::inv_deferred
+        } else {
+            VecOpConfig::inv
+        };
+        vec_ops.extend(inv(
             &mut sys.mem,
             rate,
             addr_evals,
             addr_inverses,
             true,
+            sys.hw.arch.rich_vec_isa,
         ));
-        let kernel = VectorChain::new(vec_ops, &sys.mem);
+        let kernel = VectorChain::new(vec_ops, &sys.mem, &sys.hw);
         sys.run_once(&kernel);
+        sys.mem.free_deferred(deferred_mark);
 
         Self {
             rate,
             addr_evals,
             addr_inverses,
+            addr_scalars,
         }
     }
 
-    pub fn eval_l_0(&self, sys: &mut System, n: usize, addr_x: usize, addr_res: usize) {
+    // L_0(x) = Z_H(x) / (n * (x - 1)) of the n points x of indices i_start.., into addr_res
+    pub fn eval_l_0(
+        &self,
+        sys: &mut System,
+        n: usize,
+        i_start: usize,
+        addr_x: usize,
+        addr_res: usize,
+    ) {
+        if !sys.functional {
+            return self.eval_l_0_model(sys, n, addr_x, addr_res);
+        }
+        let mut vec_ops = Vec::new();
+        let addr_denominators = sys.mem.alloc("l_0_denominators", n * SIZE_F).unwrap();
+
+        // x - F::ONE
+        vec_ops.push(VecOpConfig {
+            vector_length: n,
+            addr_input_0: addr_x,
+            addr_input_1: self.addr_scalars,
+            addr_output: addr_denominators,
+            op_type: VecOpType::SUB,
+            op_src: VecOpSrc::VS,
+            is_final_output: false,
+        });
+        // self.n * _
+        vec_ops.push(VecOpConfig {
+            vector_length: n,
+            addr_input_0: addr_denominators,
+            addr_input_1: self.addr_scalars + SIZE_F,
+            addr_output: addr_denominators,
+            op_type: VecOpType::MUL,
+            op_src: VecOpSrc::VS,
+            is_final_output: false,
+        });
+        let deferred_mark = sys.mem.deferred_mark();
+        vec_ops.extend(VecOpConfig::inv_deferred(
+            &mut sys.mem,
+            n,
+            addr_denominators,
+            addr_res,
+            false,
+            sys.hw.arch.rich_vec_isa,
+        ));
+        vec_ops.extend(self.mul_periodic(self.addr_evals, n, i_start, addr_res, addr_res, false));
+
+        sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+        sys.mem.free_deferred(deferred_mark);
+        sys.mem.free("l_0_denominators");
+    }
+
+    // the traffic of eval_l_0, without the values: every rate points are multiplied by the
+    // first rate values of Z_H
+    fn eval_l_0_model(&self, sys: &mut System, n: usize, addr_x: usize, addr_res: usize) {
         let mut vec_ops = Vec::new();
 
         // x - F::ONE
@@ -91,17 +169,76 @@ impl ZeroPolyOnCoset {
                 is_final_output: false,
             });
         }
-        vec_ops.extend(VecOpConfig::inv(&mut sys.mem, n, addr_res, addr_res, true));
+        vec_ops.extend(VecOpConfig::inv(
+            &mut sys.mem,
+            n,
+            addr_res,
+            addr_res,
+            true,
+            sys.hw.arch.rich_vec_isa,
+        ));
 
-        sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
+        sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
     }
 
+    // the address of 1 / Z_H at the point of index i, the next rate - i % rate follow it
     pub fn eval_inverse(&self, i: usize) -> usize {
         self.addr_inverses + (i % self.rate) * SIZE_F
     }
 
+    // multiplies the n values of the points of indices i_start.. by 1 / Z_H
+    pub fn mul_inverses(
+        &self,
+        n: usize,
+        i_start: usize,
+        addr_input: usize,
+        addr_output: usize,
+        is_final_output: bool,
+    ) -> Vec<VecOpConfig> {
+        self.mul_periodic(
+            self.addr_inverses,
+            n,
+            i_start,
+            addr_input,
+            addr_output,
+            is_final_output,
+        )
+    }
+
+    // the values of Z_H repeat every rate points, so a batch is multiplied piecewise
+    fn mul_periodic(
+        &self,
+        addr_periodic: usize,
+        n: usize,
+        i_start: usize,
+        addr_input: usize,
+        addr_output: usize,
+        is_final_output: bool,
+    ) -> Vec<VecOpConfig> {
+        let mut vec_ops = Vec::new();
+        let mut k = 0;
+        while k < n {
+            let offset = (i_start + k) % self.rate;
+            let vector_length = (self.rate - offset).min(n - k);
+            vec_ops.push(VecOpConfig {
+                vector_length,
+                addr_input_0: addr_input + k * SIZE_F,
+                addr_input_1: addr_periodic + offset * SIZE_F,
+                addr_output: addr_output + k * SIZE_F,
+                op_type: VecOpType::MUL,
+                op_src: VecOpSrc::VV,
+                is_final_output,
+            });
+            k += vector_length;
+        }
+        vec_ops
+    }
+
     pub fn preload(&self, mem: &mut MemAlloc) {
         mem.preload(self.addr_evals, self.rate);
         mem.preload(self.addr_inverses, self.rate);
+        if self.addr_scalars != 0 {
+            mem.preload(self.addr_scalars, 2);
+        }
     }
 }
@@ -104,4 +104,20 @@ impl ConstraintConsumer {
     pub fn accumulators(&self) -> &Vec<usize> {
         &self.addr_constraint_accs
     }
+
+    pub fn addr_alphas(&self) -> usize {
+        self.addr_alphas
+    }
+
+    pub fn addr_z_last(&self) -> usize {
+        self.addr_z_last
+    }
+
+    pub fn addr_lagrange_basis_first(&self) -> usize {
+        self.addr_lagrange_basis_first
+    }
+
+    pub fn addr_lagrange_basis_last(&self) -> usize {
+        self.addr_lagrange_basis_last
+    }
 }
@@ -0,0 +1,566 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::iter::{Product, Sum};
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use plonky2::field::extension::Extendable;
+use plonky2::field::goldilocks_field::GoldilocksField as F;
+use plonky2::field::packed::PackedField;
+use plonky2::field::types::{Field, Sample};
+use starky::constraint_consumer::ConstraintConsumer as StarkyConstraintConsumer;
+use starky::evaluation_frame::StarkEvaluationFrame;
+use starky::stark::Stark;
+
+use crate::kernel::vec_builder::{Operand, VecBuilder, VecHandle};
+use crate::kernel::vector_operation::{VecOpConfig, VecOpType};
+use crate::starky::constraint_consumer::ConstraintConsumer;
+use crate::starky::stark::EvaluationFrame;
+use crate::system::system::System;
+use crate::util::BATCH_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Input {
+    Local(usize),
+    Next(usize),
+    PublicInput(usize),
+    Alpha(usize),
+    ZLast,
+    LagrangeFirst,
+    LagrangeLast,
+    // of a plonky2 gate, see plonk::gate_lowering
+    Wire(usize),
+    GateConstant(usize),
+    PublicInputsHash(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Node {
+    Const(F),
+    Input(Input),
+    Op(VecOpType, Sym, Sym), // ADD, SUB or MUL
+}
+
+// A value of the recorded DAG, the index of its node. It is only a PackedField so that
+// Stark::eval_packed_generic and the packed gate evaluation can run on it. It has no field
+// elements, but it has the layout of one, so that plonky2's strided views and constraint
+// consumer can hold it in a slice of scalars, see as_scalars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct Sym(usize);
+
+const _: () = assert!(std::mem::size_of::<Sym>() == std::mem::size_of::<F>());
+const _: () = assert!(std::mem::align_of::<Sym>() == std::mem::align_of::<F>());
+
+// The symbolic values as the scalars holding their indices, and back
+pub(crate) fn as_scalars(syms: &[Sym]) -> &[F] {
+    // SAFETY: Sym is a usize and F a u64, both repr(transparent) with the same size and
+    // alignment (asserted above), and every bit pattern is a valid value of both.
+    unsafe { std::slice::from_raw_parts(syms.as_ptr().cast(), syms.len()) }
+}
+
+pub(crate) fn as_scalars_mut(syms: &mut [Sym]) -> &mut [F] {
+    // SAFETY: as in as_scalars, and the borrow of syms moves to the result.
+    unsafe { std::slice::from_raw_parts_mut(syms.as_mut_ptr().cast(), syms.len()) }
+}
+
+fn as_syms(scalars: &[F]) -> &[Sym] {
+    // SAFETY: as in as_scalars.
+    unsafe { std::slice::from_raw_parts(scalars.as_ptr().cast(), scalars.len()) }
+}
+
+fn as_syms_mut(scalars: &mut [F]) -> &mut [Sym] {
+    // SAFETY: as in as_scalars_mut.
+    unsafe { std::slice::from_raw_parts_mut(scalars.as_mut_ptr().cast(), scalars.len()) }
+}
+
+#[derive(Default)]
+struct Dag {
+    nodes: Vec<Node>,
+    ids: HashMap<Node, Sym>,
+    // the scalars of the frame are random values, which are recorded as inputs
+    scalars: HashMap<F, Input>,
+}
+
+thread_local! {
+    static DAG: RefCell<Dag> = RefCell::new(Dag::new());
+}
+
+impl Dag {
+    fn new() -> Self {
+        let mut dag = Dag::default();
+        // Sym::ZEROS, Sym::ONES and the constants of impl Sym
+        for value in [
+            F::ZERO,
+            F::ONE,
+            F::TWO,
+            F::NEG_ONE,
+            F::MULTIPLICATIVE_GROUP_GENERATOR,
+            F::POWER_OF_TWO_GENERATOR,
+        ] {
+            dag.intern(Node::Const(value));
+        }
+        dag
+    }
+
+    fn intern(&mut self, node: Node) -> Sym {
+        if let Some(sym) = self.ids.get(&node) {
+            return *sym;
+        }
+        let sym = Sym(self.nodes.len());
+        self.nodes.push(node);
+        self.ids.insert(node, sym);
+        sym
+    }
+
+    fn scalar(&mut self, value: F) -> Sym {
+        match self.scalars.get(&value) {
+            Some(input) => self.intern(Node::Input(*input)),
+            None => self.intern(Node::Const(value)),
+        }
+    }
+
+    fn op(&mut self, op_type: VecOpType, a: Sym, b: Sym) -> Sym {
+        let value = |sym: Sym| match self.nodes[sym.0] {
+            Node::Const(value) => Some(value),
+            _ => None,
+        };
+        match (op_type, value(a), value(b)) {
+            (VecOpType::ADD, Some(x), Some(y)) => return self.intern(Node::Const(x + y)),
+            (VecOpType::SUB, Some(x), Some(y)) => return self.intern(Node::Const(x - y)),
+            (VecOpType::MUL, Some(x), Some(y)) => return self.intern(Node::Const(x * y)),
+            (VecOpType::ADD, Some(x), _) if x.is_zero() => return b,
+            (VecOpType::ADD | VecOpType::SUB, _, Some(y)) if y.is_zero() => return a,
+            (VecOpType::MUL, Some(x), _) | (VecOpType::MUL, _, Some(x)) if x.is_zero() => {
+                return Sym::ZEROS
+            }
+            (VecOpType::MUL, Some(x), _) if x.is_one() => return b,
+            (VecOpType::MUL, _, Some(y)) if y.is_one() => return a,
+            _ => {}
+        }
+        let (a, b) = match op_type {
+            VecOpType::ADD | VecOpType::MUL => (a.min(b), a.max(b)),
+            _ => (a, b),
+        };
+        self.intern(Node::Op(op_type, a, b))
+    }
+}
+
+// the other constants of Field, for gate_lowering::SymField
+impl Sym {
+    pub(crate) const TWO: Sym = Sym(2);
+    pub(crate) const NEG_ONE: Sym = Sym(3);
+    pub(crate) const MULTIPLICATIVE_GROUP_GENERATOR: Sym = Sym(4);
+    pub(crate) const POWER_OF_TWO_GENERATOR: Sym = Sym(5);
+}
+
+impl PartialOrd for Sym {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sym {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+fn record(op_type: VecOpType, a: Sym, b: Sym) -> Sym {
+    DAG.with(|dag| dag.borrow_mut().op(op_type, a, b))
+}
+
+// starts a new recording on this thread
+pub(crate) fn reset() {
+    DAG.with(|dag| *dag.borrow_mut() = Dag::new());
+}
+
+pub(crate) fn input(input: Input) -> Sym {
+    DAG.with(|dag| dag.borrow_mut().intern(Node::Input(input)))
+}
+
+// a random scalar, recorded as the input wherever it is used
+pub(crate) fn scalar_input(input: Input) -> F {
+    let value = F::rand();
+    DAG.with(|dag| dag.borrow_mut().scalars.insert(value, input));
+    value
+}
+
+impl From<F> for Sym {
+    fn from(value: F) -> Self {
+        DAG.with(|dag| dag.borrow_mut().scalar(value))
+    }
+}
+
+impl Default for Sym {
+    fn default() -> Self {
+        Sym::ZEROS
+    }
+}
+
+macro_rules! impl_op {
+    ($op:ident, $op_fn:ident, $op_assign:ident, $op_assign_fn:ident, $op_type:expr) => {
+        impl $op for Sym {
+            type Output = Sym;
+            fn $op_fn(self, rhs: Sym) -> Sym {
+                record($op_type, self, rhs)
+            }
+        }
+
+        impl $op<F> for Sym {
+            type Output = Sym;
+            fn $op_fn(self, rhs: F) -> Sym {
+                record($op_type, self, Sym::from(rhs))
+            }
+        }
+
+        impl $op<Sym> for F {
+            type Output = Sym;
+            fn $op_fn(self, rhs: Sym) -> Sym {
+                record($op_type, Sym::from(self), rhs)
+            }
+        }
+
+        impl $op_assign for Sym {
+            fn $op_assign_fn(&mut self, rhs: Sym) {
+                *self = record($op_type, *self, rhs);
+            }
+        }
+
+        impl $op_assign<F> for Sym {
+            fn $op_assign_fn(&mut self, rhs: F) {
+                *self = record($op_type, *self, Sym::from(rhs));
+            }
+        }
+    };
+}
+
+impl_op!(Add, add, AddAssign, add_assign, VecOpType::ADD);
+impl_op!(Sub, sub, SubAssign, sub_assign, VecOpType::SUB);
+impl_op!(Mul, mul, MulAssign, mul_assign, VecOpType::MUL);
+
+impl Div<F> for Sym {
+    type Output = Sym;
+    fn div(self, rhs: F) -> Sym {
+        record(VecOpType::MUL, self, Sym::from(rhs.inverse()))
+    }
+}
+
+impl Neg for Sym {
+    type Output = Sym;
+    fn neg(self) -> Sym {
+        Sym::ZEROS - self
+    }
+}
+
+impl Sum for Sym {
+    fn sum<I: Iterator<Item = Sym>>(iter: I) -> Sym {
+        iter.fold(Sym::ZEROS, |acc, x| acc + x)
+    }
+}
+
+impl Product for Sym {
+    fn product<I: Iterator<Item = Sym>>(iter: I) -> Sym {
+        iter.fold(Sym::ONES, |acc, x| acc * x)
+    }
+}
+
+// SAFETY: a Sym has the layout of its one scalar, see as_scalars
+unsafe impl PackedField for Sym {
+    type Scalar = F;
+
+    const WIDTH: usize = 1;
+    const ZEROS: Self = Sym(0);
+    const ONES: Self = Sym(1);
+
+    fn from_slice(slice: &[F]) -> &Self {
+        assert_eq!(slice.len(), Self::WIDTH);
+        &as_syms(slice)[0]
+    }
+    fn from_slice_mut(slice: &mut [F]) -> &mut Self {
+        assert_eq!(slice.len(), Self::WIDTH);
+        &mut as_syms_mut(slice)[0]
+    }
+    fn as_slice(&self) -> &[F] {
+        as_scalars(std::slice::from_ref(self))
+    }
+    fn as_slice_mut(&mut self) -> &mut [F] {
+        as_scalars_mut(std::slice::from_mut(self))
+    }
+
+    fn interleave(&self, other: Self, _block_len: usize) -> (Self, Self) {
+        (*self, other)
+    }
+}
+
+// The recorded DAG of some constraints, and when each of its values is needed
+pub(crate) struct Program {
+    nodes: Vec<Node>,
+    roots: Vec<Sym>,              // the outputs, e.g. one accumulator for each alpha
+    needed: Vec<bool>,            // some output depends on the node
+    last_use: Vec<Option<usize>>, // the last node reading each node
+    home: Vec<Option<usize>>,     // the output an op writes directly
+}
+
+impl Program {
+    // takes the DAG recorded since reset
+    pub(crate) fn new(roots: Vec<Sym>) -> Self {
+        let nodes = DAG.with(|dag| std::mem::take(&mut *dag.borrow_mut())).nodes;
+
+        let mut last_use = vec![None; nodes.len()];
+        let mut home = vec![None; nodes.len()];
+        let mut needed = vec![false; nodes.len()];
+        for (i, root) in roots.iter().enumerate() {
+            needed[root.0] = true;
+            if matches!(nodes[root.0], Node::Op(..)) && home[root.0].is_none() {
+                home[root.0] = Some(i);
+            }
+        }
+        for i in (0..nodes.len()).rev() {
+            if let (true, Node::Op(_, a, b)) = (needed[i], nodes[i]) {
+                for sym in [a, b] {
+                    needed[sym.0] = true;
+                    last_use[sym.0].get_or_insert(i);
+                }
+            }
+        }
+
+        Self {
+            nodes,
+            roots,
+            needed,
+            last_use,
+            home,
+        }
+    }
+
+    pub(crate) fn num_ops(&self) -> usize {
+        (0..self.nodes.len())
+            .filter(|i| matches!(self.nodes[*i], Node::Op(..)) && self.needed[*i])
+            .count()
+    }
+
+    pub(crate) fn num_roots(&self) -> usize {
+        self.roots.len()
+    }
+
+    // emits the ops writing root j to the vector at addr_roots[j]
+    pub(crate) fn eval(
+        &self,
+        b: &mut VecBuilder,
+        input: impl Fn(&VecBuilder, Input) -> VecHandle,
+        addr_roots: &[usize],
+    ) {
+        let mut vecs: Vec<Option<VecHandle>> = vec![None; self.nodes.len()];
+        let mut temps = vec![false; self.nodes.len()];
+
+        // each constant gets a slot, but 0, which is read at address 0
+        let consts = (0..self.nodes.len())
+            .filter_map(|i| match self.nodes[i] {
+                Node::Const(c) if self.needed[i] && !c.is_zero() => Some((i, c)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !consts.is_empty() {
+            let values = consts.iter().map(|(_, c)| *c).collect::<Vec<_>>();
+            for ((i, _), slot) in consts.iter().zip(b.alloc_scalars(&values)) {
+                vecs[*i] = Some(slot);
+            }
+        }
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !self.needed[i] {
+                continue;
+            }
+            vecs[i] = Some(match *node {
+                Node::Const(_) => vecs[i].unwrap_or(b.constant()),
+                Node::Input(value) => input(b, value),
+                Node::Op(op_type, x, y) => {
+                    // a temporary read for the last time holds the result
+                    let dying = [x, y]
+                        .into_iter()
+                        .filter(|sym| temps[sym.0] && self.last_use[sym.0] == Some(i))
+                        .collect::<Vec<_>>();
+                    let out = match (self.home[i], dying.first()) {
+                        (Some(root), _) => b.vector(addr_roots[root]),
+                        (None, Some(sym)) => {
+                            temps[sym.0] = false;
+                            vecs[sym.0].unwrap()
+                        }
+                        (None, None) => b.temp(),
+                    };
+                    temps[i] = self.home[i].is_none();
+                    let neg = matches!(self.nodes[x.0], Node::Const(c) if c.is_zero());
+                    let (x, y) = (vecs[x.0].unwrap(), vecs[y.0].unwrap());
+                    match op_type {
+                        VecOpType::ADD => b.add_into(out, x, y),
+                        VecOpType::SUB if neg && y.kind() == Operand::Vector => b.neg_into(out, y),
+                        VecOpType::SUB => b.sub_into(out, x, y),
+                        _ => b.mul_into(out, x, y),
+                    }
+                    for sym in dying {
+                        if temps[sym.0] {
+                            temps[sym.0] = false;
+                            b.free(vecs[sym.0].unwrap());
+                        }
+                    }
+                    out
+                }
+            });
+        }
+
+        // an output that is not the result of its own op is copied
+        for (j, root) in self.roots.iter().enumerate() {
+            if self.home[root.0] != Some(j) {
+                let out = b.vector(addr_roots[j]);
+                b.add_into(out, vecs[root.0].unwrap(), b.constant());
+            }
+        }
+    }
+}
+
+// Runs the constraints of the stark once on symbolic values and lowers the recorded DAG to
+// the vector ops of each batch, so that a stark needs no hand-written eval_packed_generic.
+pub struct LoweredStark {
+    program: Program,
+}
+
+impl LoweredStark {
+    pub fn new<S, const D: usize>(stark: &S, num_challenges: usize) -> Self
+    where
+        F: Extendable<D>,
+        S: Stark<F, D>,
+    {
+        reset();
+        let public_inputs = (0..S::PUBLIC_INPUTS)
+            .map(|i| scalar_input(Input::PublicInput(i)))
+            .collect::<Vec<_>>();
+        let alphas = (0..num_challenges)
+            .map(|i| scalar_input(Input::Alpha(i)))
+            .collect::<Vec<_>>();
+        let local_values = (0..S::COLUMNS)
+            .map(|i| input(Input::Local(i)))
+            .collect::<Vec<_>>();
+        let next_values = (0..S::COLUMNS)
+            .map(|i| input(Input::Next(i)))
+            .collect::<Vec<_>>();
+
+        let vars = <S::EvaluationFrame<F, Sym, 1> as StarkEvaluationFrame<Sym, F>>::from_values(
+            &local_values,
+            &next_values,
+            &public_inputs,
+        );
+        let mut consumer = StarkyConstraintConsumer::new(
+            alphas,
+            input(Input::ZLast),
+            input(Input::LagrangeFirst),
+            input(Input::LagrangeLast),
+        );
+        stark.eval_packed_generic(&vars, &mut consumer);
+
+        Self {
+            program: Program::new(consumer.accumulators()),
+        }
+    }
+
+    pub fn num_ops(&self) -> usize {
+        self.program.num_ops()
+    }
+
+    // the same signature as a hand-written eval_packed_generic
+    pub fn eval(
+        &self,
+        sys: &mut System,
+        vars: &EvaluationFrame,
+        consumer: &ConstraintConsumer,
+    ) -> Vec<VecOpConfig> {
+        let rich_isa = sys.hw.arch.rich_vec_isa;
+        let mut b = VecBuilder::new(&mut sys.mem, BATCH_SIZE).with_rich_isa(rich_isa);
+        let input = |b: &VecBuilder, input| match input {
+            Input::Local(j) => b.column(vars.addr_local_values, j),
+            Input::Next(j) => b.column(vars.addr_next_values, j),
+            Input::PublicInput(j) => b.scalar_at(vars.addr_public_inputs, j),
+            Input::Alpha(j) => b.scalar_at(consumer.addr_alphas(), j),
+            Input::ZLast => b.vector(consumer.addr_z_last()),
+            Input::LagrangeFirst => b.vector(consumer.addr_lagrange_basis_first()),
+            Input::LagrangeLast => b.vector(consumer.addr_lagrange_basis_last()),
+            _ => unreachable!("{:?} is not an input of a stark", input),
+        };
+        self.program.eval(&mut b, input, consumer.accumulators());
+        b.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{HwConfig, RamConfig};
+    use crate::kernel::kernel::Kernel;
+    use crate::kernel::vector_chain::VectorChain;
+    use crate::memory::memory_allocator::MemAlloc;
+    use crate::util::SIZE_F;
+    use starky::evaluation_frame::StarkFrame;
+    use starky::fibonacci_stark::FibonacciStark;
+
+    #[test]
+    fn test_lowered_stark() {
+        type S = FibonacciStark<F, 2>;
+        let stark = S::new(1 << 10);
+        let lowered = LoweredStark::new(&stark, 2);
+        assert!(lowered.num_ops() > 0);
+
+        let mut sys = System::new(
+            MemAlloc::new(1, 4096),
+            RamConfig::new("test_lowered_stark"),
+            HwConfig::default(),
+        );
+        let mut alloc = |name: &str, len: usize, values: &[F]| {
+            let addr = sys.mem.alloc(name, len * SIZE_F).unwrap();
+            sys.mem.write_elems(addr, values);
+            addr
+        };
+        let lv = F::rand_vec(2 * BATCH_SIZE);
+        let nv = F::rand_vec(2 * BATCH_SIZE);
+        let public_inputs = F::rand_vec(3);
+        let alphas = F::rand_vec(2);
+        let selectors = F::rand_vec(3 * BATCH_SIZE);
+        let addr_lv = alloc("local_values", lv.len(), &lv);
+        let addr_nv = alloc("next_values", nv.len(), &nv);
+        let addr_public_inputs = alloc("public_inputs", 3, &public_inputs);
+        let addr_alphas = alloc("alphas", 2, &alphas);
+        let addr_selectors = alloc("selectors", selectors.len(), &selectors);
+        let addr_accs = alloc("accs", 2 * BATCH_SIZE, &[]);
+        let consumer = ConstraintConsumer::new(
+            2,
+            addr_alphas,
+            addr_selectors,
+            addr_selectors + BATCH_SIZE * SIZE_F,
+            addr_selectors + 2 * BATCH_SIZE * SIZE_F,
+            vec![addr_accs, addr_accs + BATCH_SIZE * SIZE_F],
+        );
+        let vars = EvaluationFrame::from_values(addr_lv, addr_nv, 2, addr_public_inputs, 3);
+
+        let vec_ops = lowered.eval(&mut sys, &vars, &consumer);
+        VectorChain::new(vec_ops, &sys.mem, &sys.hw).execute(&mut sys.mem);
+        sys.mem.free_deferred(0);
+        let accs = sys.mem.read_elems(addr_accs, 2 * BATCH_SIZE);
+
+        // the same constraints evaluated by starky, one row at a time
+        for r in 0..BATCH_SIZE {
+            let row = |values: &[F]| [values[r], values[BATCH_SIZE + r]];
+            let vars = StarkFrame::<F, F, 2, 3>::from_values(&row(&lv), &row(&nv), &public_inputs);
+            let mut consumer = StarkyConstraintConsumer::new(
+                alphas.clone(),
+                selectors[r],
+                selectors[BATCH_SIZE + r],
+                selectors[2 * BATCH_SIZE + r],
+            );
+            stark.eval_packed_generic(&vars, &mut consumer);
+            assert_eq!(
+                consumer.accumulators(),
+                vec![accs[r], accs[BATCH_SIZE + r]],
+                "row {}",
+                r
+            );
+        }
+    }
+}
@@ -9,6 +9,7 @@ pub mod constraint_consumer;
 pub mod stark;
 pub mod vanishing_poly;
 pub mod proof;
+pub mod lowering;
 
 pub fn lde_onto_coset(
     sys: &mut System,
@@ -34,7 +35,7 @@ pub fn lde_onto_coset(
         coset: false,
         extension: 1,
         transposed_input: false,
-    });
+    }, &sys.hw);
     sys.run_once(&ifft_kernel);
 
     let fft_kernel = Fft::new(FftConfig {
@@ -49,7 +50,7 @@ pub fn lde_onto_coset(
         coset: true,
         extension: 1,
         transposed_input: false,
-    });
+    }, &sys.hw);
     sys.run_once(&fft_kernel);
 
     sys.mem.free("lde_onto_coset_coeffs");
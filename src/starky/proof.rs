@@ -1,5 +1,4 @@
-use crate::config::arch_config::ARCH_CONFIG;
-use crate::config::enable_config::ENABLE_CONFIG;
+use crate::kernel::kernel::FetchEdit;
 use crate::kernel::memory_copy::{MemCpy, MemCpyConfig};
 use crate::kernel::vector_chain::VectorChain;
 use crate::kernel::vector_operation::{VecOpExtension, VecOpSrc};
@@ -38,7 +37,7 @@ impl<const D: usize> StarkOpeningSet<D> {
         sys.mem.preload(addr_zeta, D);
         sys.mem.preload(addr_zeta_next, D);
 
-        sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
+        sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
 
         let eval_commitment =
             |sys: &mut System, z: Vec<usize>, c: &PolynomialBatch, res: Vec<usize>| {
@@ -50,7 +49,7 @@ impl<const D: usize> StarkOpeningSet<D> {
                     sys.mem.preload(*addr_res, num_polys * D);
                 }
 
-                let batch_size = unsafe { ARCH_CONFIG.num_elems() / num_polys };
+                let batch_size = sys.hw.arch.num_elems() / num_polys;
                 for i_start in (0..degree).step_by(batch_size).rev() {
                     let mut vec_ops = Vec::new();
                     let mut mks = Vec::new();
@@ -62,10 +61,11 @@ impl<const D: usize> StarkOpeningSet<D> {
                                 addr_output: 0,
                                 input_length: i_len,
                             },
-                            unsafe { ENABLE_CONFIG.other },
+                            sys.hw.enable.other,
+                            &sys.hw,
                         );
-                        m.drain.clear();
-                        m.write_request.clear();
+                        m.edit(FetchEdit::ClearDrain);
+                        m.edit(FetchEdit::ClearWriteRequest);
                         mks.push(m);
                     }
                     sys.run_vec(mks);
@@ -81,6 +81,7 @@ impl<const D: usize> StarkOpeningSet<D> {
                                 *addr_res,
                                 VecOpSrc::VS,
                                 false,
+                                sys.hw.arch.rich_vec_isa,
                             ));
                             // _ + c
                             vec_ops.extend(VecOpExtension::<D>::add(
@@ -93,7 +94,7 @@ impl<const D: usize> StarkOpeningSet<D> {
                             ));
                         }
                     }
-                    sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
+                    sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
                 }
 
                 for addr_res in res.iter() {
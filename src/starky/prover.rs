@@ -1,5 +1,3 @@
-use crate::config::arch_config::ARCH_CONFIG;
-use crate::config::enable_config::ENABLE_CONFIG;
 use crate::kernel::fft::{Fft, FftConfig, FftDirection};
 use crate::kernel::memory_copy::{MemCpy, MemCpyConfig};
 use crate::kernel::vector_chain::VectorChain;
@@ -13,7 +11,7 @@ use crate::starky::proof::StarkOpeningSet;
 use crate::starky::stark::{fri_instance, EvaluationFrame};
 use crate::starky::vanishing_poly::eval_vanishing_poly;
 use crate::system::system::System;
-use crate::util::{BATCH_SIZE, SIZE_F, SPONGE_RATE};
+use crate::util::{BATCH_SIZE, SIZE_F};
 use plonky2::field::extension::Extendable;
 use plonky2::field::polynomial::PolynomialValues;
 use plonky2::field::types::Field;
@@ -22,6 +20,7 @@ use plonky2::plonk::config::GenericConfig;
 use plonky2::util::{log2_ceil, log2_strict};
 use starky::config::StarkConfig;
 use starky::stark::Stark;
+use std::any::type_name;
 
 pub fn prove<F, C, S, const D: usize>(
     sys: &mut System,
@@ -29,7 +28,7 @@ pub fn prove<F, C, S, const D: usize>(
     config: &StarkConfig,
     trace_poly_values: Vec<PolynomialValues<F>>,
     public_inputs: &[F],
-    eval_packed_generic: fn(&mut System, &EvaluationFrame, &ConstraintConsumer) -> Vec<VecOpConfig>,
+    eval_packed_generic: impl Fn(&mut System, &EvaluationFrame, &ConstraintConsumer) -> Vec<VecOpConfig>,
 ) where
     F: RichField + Extendable<D>,
     C: GenericConfig<D, F = F>,
@@ -69,7 +68,10 @@ pub fn prove<F, C, S, const D: usize>(
 
     let addr_chanllenger_output_buffer = sys
         .mem
-        .alloc("Challenger.output_buffer", SPONGE_RATE * SIZE_F)
+        .alloc(
+            "Challenger.output_buffer",
+            Challenger::buffer_size(sys.functional),
+        )
         .unwrap();
     let mut challenger = Challenger::new(addr_chanllenger_output_buffer);
     challenger.observe_cap(sys, trace_commitment.addr_cap, cap_height);
@@ -142,8 +144,10 @@ pub fn prove<F, C, S, const D: usize>(
             addr_zeta_g,
             VecOpSrc::VS,
             true,
+            sys.hw.arch.rich_vec_isa,
         ),
         &sys.mem,
+        &sys.hw,
     );
     sys.run_once(&zeta_g_kernel);
 
@@ -169,7 +173,7 @@ fn compute_quotient_polys<F, S, const D: usize>(
     addr_alphas: usize,
     degree_bits: usize,
     config: &StarkConfig,
-    eval_packed_generic: fn(&mut System, &EvaluationFrame, &ConstraintConsumer) -> Vec<VecOpConfig>,
+    eval_packed_generic: impl Fn(&mut System, &EvaluationFrame, &ConstraintConsumer) -> Vec<VecOpConfig>,
     addr_res: usize,
 ) where
     F: RichField + Extendable<D>,
@@ -221,7 +225,7 @@ fn compute_quotient_polys<F, S, const D: usize>(
         quotient_degree_bits,
     );
 
-    let z_h_on_coset = ZeroPolyOnCoset::new(sys, quotient_degree_bits);
+    let z_h_on_coset = ZeroPolyOnCoset::new(sys, degree_bits, quotient_degree_bits);
     sys.mem.preload(
         z_h_on_coset.addr_inverses,
         z_h_on_coset.rate.max(BATCH_SIZE),
@@ -241,6 +245,9 @@ fn compute_quotient_polys<F, S, const D: usize>(
         .alloc("quotient_values", config.num_challenges * size * SIZE_F)
         .unwrap();
 
+    // the ops saved by the CSE are counted under the name of the stark
+    let stark_name = type_name::<S>().split('<').next().unwrap();
+    let stark_name = stark_name.rsplit("::").next().unwrap().to_string();
     for i_start in (0..size).step_by(BATCH_SIZE) {
         let mut vec_ops = Vec::new();
         let i_next_start = (i_start + next_step) % size;
@@ -267,7 +274,8 @@ fn compute_quotient_polys<F, S, const D: usize>(
                 addr_output: addr_lagrange_basis_first,
                 input_length: BATCH_SIZE,
             },
-            unsafe { ENABLE_CONFIG.other },
+            sys.hw.enable.other,
+            &sys.hw,
         ));
         mks.push(MemCpy::new(
             MemCpyConfig {
@@ -275,7 +283,8 @@ fn compute_quotient_polys<F, S, const D: usize>(
                 addr_output: addr_lagrange_basis_last,
                 input_length: BATCH_SIZE,
             },
-            unsafe { ENABLE_CONFIG.other },
+            sys.hw.enable.other,
+            &sys.hw,
         ));
         sys.mem.preload(addr_lagrange_basis_first, BATCH_SIZE);
         sys.mem.preload(addr_lagrange_basis_last, BATCH_SIZE);
@@ -317,12 +326,12 @@ fn compute_quotient_polys<F, S, const D: usize>(
                     addr_output: addr_v_i,
                     input_length: *len,
                 };
-                let mk = MemCpy::new(config, unsafe { ENABLE_CONFIG.other });
+                let mk = MemCpy::new(config, sys.hw.enable.other, &sys.hw);
                 mks.push(mk);
             }
         };
 
-        let preload_values = BATCH_SIZE * lv[0].1 <= unsafe { ARCH_CONFIG.num_elems() / 4 };
+        let preload_values = BATCH_SIZE * lv[0].1 <= sys.hw.arch.num_elems() / 4;
         if preload_values {
             sys.mem.preload(addr_lv, BATCH_SIZE * lv[0].1);
             sys.mem.preload(addr_nv, BATCH_SIZE * nv[0].1);
@@ -341,11 +350,12 @@ fn compute_quotient_polys<F, S, const D: usize>(
         let addr_constraint = sys.mem.alloc("constraint", BATCH_SIZE * SIZE_F).unwrap();
         sys.mem.preload(addr_constraint, BATCH_SIZE);
 
+        let deferred_mark = sys.mem.deferred_mark();
         vec_ops.extend(eval_vanishing_poly(
             sys,
             &vars,
             &consumer,
-            eval_packed_generic,
+            &eval_packed_generic,
         ));
 
         let addr_constraints_evals = consumer.accumulators();
@@ -363,7 +373,10 @@ fn compute_quotient_polys<F, S, const D: usize>(
             });
         }
         sys.run_vec(mks);
-        sys.run_once(&VectorChain::new(vec_ops, &sys.mem));
+        let tags = [(stark_name.clone(), 0..vec_ops.len())];
+        sys.run_once(&VectorChain::new_eliminated(
+            vec_ops, &tags, &sys.mem, &sys.hw,
+        ));
 
         sys.mem.unpreload(addr_lagrange_basis_first);
         sys.mem.unpreload(addr_lagrange_basis_last);
@@ -377,6 +390,7 @@ fn compute_quotient_polys<F, S, const D: usize>(
         }
         sys.mem.unpreload(addr_constraint);
 
+        sys.mem.free_deferred(deferred_mark);
         sys.mem.free("z_last");
         sys.mem.free("local_values");
         sys.mem.free("next_values");
@@ -384,18 +398,21 @@ fn compute_quotient_polys<F, S, const D: usize>(
     }
     sys.mem.clear_preload();
 
-    let coset_ifft = Fft::new(FftConfig {
-        lg_n: log2_strict(size),
-        k: config.num_challenges,
-        direction: FftDirection::NN,
-        addr_input: addr_quotient_values,
-        addr_tmp: 1 << 60,
-        addr_output: addr_res,
-        inverse: true,
-        rate_bits: 0,
-        coset: true,
-        extension: 1,
-        transposed_input: false,
-    });
+    let coset_ifft = Fft::new(
+        FftConfig {
+            lg_n: log2_strict(size),
+            k: config.num_challenges,
+            direction: FftDirection::NN,
+            addr_input: addr_quotient_values,
+            addr_tmp: 1 << 60,
+            addr_output: addr_res,
+            inverse: true,
+            rate_bits: 0,
+            coset: true,
+            extension: 1,
+            transposed_input: false,
+        },
+        &sys.hw,
+    );
     sys.run_once(&coset_ifft);
 }
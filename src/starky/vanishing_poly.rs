@@ -7,7 +7,7 @@ pub fn eval_vanishing_poly(
     sys: &mut System,
     vars: &EvaluationFrame,
     consumer: &ConstraintConsumer,
-    eval_packed_generic: fn(&mut System, &EvaluationFrame, &ConstraintConsumer) -> Vec<VecOpConfig>,
+    eval_packed_generic: impl Fn(&mut System, &EvaluationFrame, &ConstraintConsumer) -> Vec<VecOpConfig>,
 ) -> Vec<VecOpConfig> {
     eval_packed_generic(sys, vars, consumer)
 }
@@ -0,0 +1,2 @@
+pub mod sweep;
+pub mod workload;
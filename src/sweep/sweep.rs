@@ -0,0 +1,311 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use log::info;
+use serde::Serialize;
+
+use crate::config::{ArchConfig, Backend, HwConfig, RamConfig, Replacement};
+use crate::memory::memory_allocator::MemAlloc;
+use crate::sweep::workload::Workload;
+use crate::system::replay::{replay, RecordedDecisions};
+use crate::system::system::System;
+
+pub struct SweepConfig {
+    pub base: HwConfig,
+    // an empty list keeps the value of base
+    pub num_tiles: Vec<usize>,
+    pub buf_sz_kb: Vec<usize>, // both read and write buffer
+    pub array_length: Vec<usize>,
+    pub replacement: Vec<Replacement>,
+    // RamSim config files, traceFileName is redirected to the trace of each point
+    pub dram_configs: Vec<String>,
+    pub mem_size_gb: usize,
+    pub run_ramsim: bool,
+    pub keep_traces: bool,
+    // prove only on the first point of each set of RecordedDecisions, and replay its
+    // kernel invocations on the other points of the set, see system::replay
+    pub replay: bool,
+}
+
+impl SweepConfig {
+    pub fn new(base: HwConfig) -> Self {
+        Self {
+            base,
+            num_tiles: Vec::new(),
+            buf_sz_kb: Vec::new(),
+            array_length: Vec::new(),
+            replacement: Vec::new(),
+            dram_configs: Vec::new(),
+            mem_size_gb: 256,
+            run_ramsim: true,
+            keep_traces: false,
+            replay: false,
+        }
+    }
+
+    pub fn points(&self) -> Result<Vec<HwConfig>> {
+        fn or_base<T: Copy>(values: &[T], base: T) -> Vec<T> {
+            if values.is_empty() {
+                vec![base]
+            } else {
+                values.to_vec()
+            }
+        }
+        let mut points = Vec::new();
+        for num_tiles in or_base(&self.num_tiles, self.base.arch.num_tiles) {
+            for buf_sz_kb in or_base(&self.buf_sz_kb, self.base.arch.rdbuf_sz_kb) {
+                for array_length in or_base(&self.array_length, self.base.arch.array_length) {
+                    for replacement in or_base(&self.replacement, self.base.arch.replacement) {
+                        let mut hw = self.base;
+                        if !self.run_ramsim {
+                            hw.timing.backend = Backend::Analytical;
+                        }
+                        hw.arch.num_tiles = num_tiles;
+                        hw.arch.rdbuf_sz_kb = buf_sz_kb;
+                        hw.arch.wrbuf_sz_kb = buf_sz_kb;
+                        hw.arch.array_length = array_length;
+                        hw.arch.replacement = replacement;
+                        hw.arch
+                            .validate()
+                            .with_context(|| format!("invalid sweep point {:?}", hw.arch))?;
+                        points.push(hw);
+                    }
+                }
+            }
+        }
+        Ok(points)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepRecord {
+    pub workload: String,
+    pub point: String,
+    pub arch: ArchConfig,
+    pub dram_config: String,
+    pub op_cnt: usize,
+    pub total_bytes: u64,
+    pub computation: BTreeMap<String, usize>,
+    pub estimated_seconds: f64,
+    pub cycles: Option<u64>,
+}
+
+pub fn point_name(workload: &str, arch: &ArchConfig) -> String {
+    let name = format!(
+        "{}_t{}_b{}_a{}",
+        workload, arch.num_tiles, arch.rdbuf_sz_kb, arch.array_length
+    );
+    // the names of the points with the default policy stay as before
+    match arch.replacement {
+        Replacement::Lru => name,
+        replacement => format!("{}_{}", name, replacement.as_str()),
+    }
+}
+
+/// Simulate the workload on every point of the grid, and run RamSim on each trace
+/// with every DRAM config.
+pub fn run_sweep<W: Workload + ?Sized>(
+    workload: &W,
+    config: &SweepConfig,
+) -> Result<Vec<SweepRecord>> {
+    ensure!(
+        !config.run_ramsim || !config.dram_configs.is_empty(),
+        "no DRAM config given for RamSim"
+    );
+    let points = config.points()?;
+    // one log per set of decisions, the first one named after the workload
+    let mut logs: Vec<(RecordedDecisions, String)> = Vec::new();
+    let mut records = Vec::new();
+    for (i, hw) in points.iter().enumerate() {
+        let name = point_name(workload.name(), &hw.arch);
+        info!("Sweep point {}/{} {}", i + 1, points.len(), name);
+
+        let ramsim = RamConfig::new(&name);
+        let mem = MemAlloc::new(config.mem_size_gb, 4096);
+        let mut sys = System::new(mem, ramsim, *hw);
+        let decisions = RecordedDecisions::of(hw);
+        match logs.iter().find(|(d, _)| *d == decisions) {
+            Some((_, log_path)) if config.replay => {
+                replay(log_path, &mut sys)?;
+            }
+            _ => {
+                if config.replay {
+                    let log_path = if logs.is_empty() {
+                        format!("./traces/{}.kernels.zst", workload.name())
+                    } else {
+                        format!("./traces/{}.kernels.zst", name)
+                    };
+                    sys.record_invocations(&log_path)?;
+                    logs.push((decisions, log_path));
+                }
+                workload.run(&mut sys);
+                sys.finish_recording()?;
+            }
+        }
+
+        let report = sys.report();
+        let record = SweepRecord {
+            workload: workload.name().to_string(),
+            point: name.clone(),
+            arch: hw.arch,
+            dram_config: String::new(),
+            op_cnt: report.op_cnt,
+            total_bytes: report.total_bytes,
+            computation: report.computation,
+            estimated_seconds: report.analytical.seconds,
+            cycles: None,
+        };
+        let mut ramsim_configs = Vec::new();
+        if config.run_ramsim {
+            for dram in config.dram_configs.iter() {
+                let config_path = ramsim_config_for(dram, &name)?;
+                sys.ramsim.config_path = config_path.clone();
+                let stats = sys.ramsim.run()?;
+                records.push(SweepRecord {
+                    dram_config: dram.clone(),
+                    cycles: Some(stats.cycles),
+                    ..record.clone()
+                });
+                ramsim_configs.push(config_path);
+            }
+        } else {
+            records.push(record);
+        }
+        let traces = sys.ramsim.output_paths();
+        drop(sys);
+
+        if !config.keep_traces {
+            for file in traces.iter().chain(ramsim_configs.iter()) {
+                let _ = fs::remove_file(file);
+            }
+        }
+    }
+    if !config.keep_traces {
+        for (_, log_path) in logs {
+            let _ = fs::remove_file(log_path);
+        }
+    }
+    Ok(records)
+}
+
+/// Copy a RamSim config into ./traces with traceFileName pointing to the trace of `name`.
+fn ramsim_config_for(template: &str, name: &str) -> Result<String> {
+    let text = fs::read_to_string(template)
+        .with_context(|| format!("failed to read DRAM config {}", template))?;
+    let mut found = false;
+    let lines = text
+        .lines()
+        .map(|line| {
+            let key = line.trim_start();
+            if key.starts_with("traceFileName:") {
+                found = true;
+                let indent = &line[..line.len() - key.len()];
+                format!("{}traceFileName: ./traces/{}.bin", indent, name)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    ensure!(found, "DRAM config {} has no traceFileName", template);
+
+    let stem = Path::new(template)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| anyhow!("invalid DRAM config path {}", template))?;
+    let path = format!("./traces/{}.{}.yaml", name, stem);
+    fs::write(&path, lines.join("\n") + "\n")?;
+    Ok(path)
+}
+
+/// Write the records as .json, or as .csv with one column per kernel type.
+pub fn write_records(records: &[SweepRecord], path: &str) -> Result<()> {
+    let text = if path.ends_with(".json") {
+        serde_json::to_string_pretty(records)?
+    } else {
+        records_to_csv(records)
+    };
+    fs::write(path, text).with_context(|| format!("failed to write {}", path))
+}
+
+fn records_to_csv(records: &[SweepRecord]) -> String {
+    let kernel_types = records
+        .iter()
+        .flat_map(|r| r.computation.keys().cloned())
+        .collect::<BTreeSet<_>>();
+
+    let mut header = vec![
+        "workload",
+        "point",
+        "rdbuf_sz_kb",
+        "wrbuf_sz_kb",
+        "active_buf_frac",
+        "mvl",
+        "num_tiles",
+        "array_length",
+        "replacement",
+        "dram_config",
+        "op_cnt",
+        "total_bytes",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect::<Vec<_>>();
+    header.extend(kernel_types.iter().map(|k| format!("computation_{}", k)));
+    header.push("estimated_seconds".to_string());
+    header.push("cycles".to_string());
+
+    let mut lines = vec![header.join(",")];
+    for r in records {
+        let mut row = vec![
+            r.workload.clone(),
+            r.point.clone(),
+            r.arch.rdbuf_sz_kb.to_string(),
+            r.arch.wrbuf_sz_kb.to_string(),
+            r.arch.active_buf_frac.to_string(),
+            r.arch.mvl.to_string(),
+            r.arch.num_tiles.to_string(),
+            r.arch.array_length.to_string(),
+            r.arch.replacement.as_str().to_string(),
+            r.dram_config.clone(),
+            r.op_cnt.to_string(),
+            r.total_bytes.to_string(),
+        ];
+        row.extend(
+            kernel_types
+                .iter()
+                .map(|k| r.computation.get(k).unwrap_or(&0).to_string()),
+        );
+        row.push(r.estimated_seconds.to_string());
+        row.push(r.cycles.map(|c| c.to_string()).unwrap_or_default());
+        lines.push(row.join(","));
+    }
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sweep_points() -> Result<()> {
+        let mut config = SweepConfig::new(HwConfig::default());
+        config.num_tiles = vec![8, 16, 32];
+        config.buf_sz_kb = vec![2048, 4096];
+        let points = config.points()?;
+        assert_eq!(points.len(), 6);
+        assert!(points.iter().all(|hw| hw.arch.array_length == 12));
+        assert_eq!(points[1].arch.wrbuf_sz_kb, 4096);
+
+        config.replacement = vec![Replacement::Lru, Replacement::Belady];
+        let points = config.points()?;
+        assert_eq!(points.len(), 12);
+        assert_eq!(point_name("w", &points[0].arch), "w_t8_b2048_a12");
+        assert_eq!(point_name("w", &points[1].arch), "w_t8_b2048_a12_belady");
+
+        config.array_length = vec![2];
+        assert!(config.points().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use std::marker::PhantomData;
+
+use plonky2::field::extension::Extendable;
+use plonky2::field::polynomial::PolynomialValues;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::witness::PartitionWitness;
+use plonky2::plonk::circuit_data::{CommonCircuitData, ProverOnlyCircuitData};
+use plonky2::plonk::config::GenericConfig;
+use starky::config::StarkConfig;
+use starky::stark::Stark;
+
+use crate::kernel::vector_operation::VecOpConfig;
+use crate::plonk::prover::prove_with_partition_witness;
+use crate::starky::constraint_consumer::ConstraintConsumer;
+use crate::starky::prover::prove;
+use crate::starky::stark::EvaluationFrame;
+use crate::system::system::System;
+
+pub type EvalPackedGeneric =
+    fn(&mut System, &EvaluationFrame, &ConstraintConsumer) -> Vec<VecOpConfig>;
+
+/// A proof that can be simulated again on a fresh System for every design point.
+pub trait Workload {
+    fn name(&self) -> &str;
+    fn run(&self, sys: &mut System);
+}
+
+pub struct PlonkWorkload<
+    'a,
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub name: String,
+    pub prover_data: &'a ProverOnlyCircuitData<F, C, D>,
+    pub common_data: &'a CommonCircuitData<F, D>,
+    pub partition_witness: PartitionWitness<'a, F>,
+}
+
+impl<'a, F, C, const D: usize> Workload for PlonkWorkload<'a, F, C, D>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&self, sys: &mut System) {
+        prove_with_partition_witness(
+            sys,
+            self.prover_data,
+            self.common_data,
+            self.partition_witness.clone(),
+        );
+    }
+}
+
+pub struct StarkWorkload<F, C, S, const D: usize>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D> + Clone,
+{
+    pub name: String,
+    pub stark: S,
+    pub config: StarkConfig,
+    pub trace: Vec<PolynomialValues<F>>,
+    pub public_inputs: Vec<F>,
+    pub eval_packed_generic: EvalPackedGeneric,
+    pub _phantom: PhantomData<C>,
+}
+
+impl<F, C, S, const D: usize> StarkWorkload<F, C, S, D>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D> + Clone,
+{
+    pub fn new(
+        name: &str,
+        stark: S,
+        config: StarkConfig,
+        trace: Vec<PolynomialValues<F>>,
+        public_inputs: Vec<F>,
+        eval_packed_generic: EvalPackedGeneric,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            stark,
+            config,
+            trace,
+            public_inputs,
+            eval_packed_generic,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, C, S, const D: usize> Workload for StarkWorkload<F, C, S, D>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D> + Clone,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&self, sys: &mut System) {
+        prove::<F, C, S, D>(
+            sys,
+            self.stark.clone(),
+            &self.config,
+            self.trace.clone(),
+            &self.public_inputs,
+            self.eval_packed_generic,
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::TimingConfig;
+
+/// Roofline estimate of the trace. Every fetch step of System::run_trace reads and
+/// writes its bytes at the peak bandwidth while the kernel computes for delay +
+/// interval cycles, the two overlap and the longer one bounds the step.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnalyticalEstimate {
+    pub seconds: f64,
+    pub memory_seconds: f64,
+    pub compute_seconds: f64,
+    pub bytes: usize,
+    pub num_steps: usize,
+    pub memory_bound_steps: usize,
+}
+
+impl AnalyticalEstimate {
+    // returns the estimated time of this step in seconds
+    pub fn step(
+        &mut self,
+        timing: &TimingConfig,
+        read_bytes: usize,
+        write_bytes: usize,
+        compute_cycles: usize,
+    ) -> f64 {
+        let bytes = read_bytes + write_bytes;
+        let mut memory = bytes as f64 / (timing.bandwidth_gbps * 1e9);
+        if bytes > 0 {
+            memory += timing.latency_ns * 1e-9;
+        }
+        let compute = compute_cycles as f64 / (timing.freq_ghz * 1e9);
+
+        self.bytes += bytes;
+        self.num_steps += 1;
+        self.memory_seconds += memory;
+        self.compute_seconds += compute;
+        if memory > compute {
+            self.memory_bound_steps += 1;
+        }
+        let seconds = memory.max(compute);
+        self.seconds += seconds;
+        seconds
+    }
+
+    // estimated cycles of the accelerator clock
+    pub fn cycles(&self, timing: &TimingConfig) -> u64 {
+        (self.seconds * timing.freq_ghz * 1e9).round() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::config::{HwConfig, RamConfig};
+    use crate::kernel::memory_copy::{MemCpy, MemCpyConfig};
+    use crate::memory::memory_allocator::MemAlloc;
+    use crate::system::system::System;
+    use crate::util::SIZE_F;
+
+    #[test]
+    fn test_analytical_estimate() {
+        let timing = TimingConfig {
+            freq_ghz: 1.0,
+            bandwidth_gbps: 100.0,
+            latency_ns: 10.0,
+            ..Default::default()
+        };
+        let mut estimate = AnalyticalEstimate::default();
+        // memory bound: 1000 B at 100 GB/s = 10 ns, plus 10 ns latency
+        let t = estimate.step(&timing, 600, 400, 5);
+        assert!((t - 20e-9).abs() < 1e-15);
+        // compute bound: 100 cycles at 1 GHz
+        let t = estimate.step(&timing, 0, 100, 100);
+        assert!((t - 100e-9).abs() < 1e-15);
+        assert_eq!(estimate.num_steps, 2);
+        assert_eq!(estimate.memory_bound_steps, 1);
+        assert_eq!(estimate.bytes, 1100);
+        assert_eq!(estimate.cycles(&timing), 120);
+    }
+
+    // the estimate of a copy of 8 MB, more than the buffers hold, is within a factor
+    // of 4 of RamSim either way. Skipped if RamSim has not been built.
+    #[test]
+    fn test_analytical_against_ramsim() -> Result<()> {
+        let mut hw = HwConfig::default();
+        // the peak of configs/fibonacci.yaml, 128 HBM2 channels of 128 bits at 2 GT/s
+        hw.timing.bandwidth_gbps = 4096.0;
+        let mut sys = System::new(
+            MemAlloc::new(1, 4096),
+            RamConfig::new("test_analytical_ramsim"),
+            hw,
+        );
+        let n = 1 << 20;
+        let a = sys.mem.alloc("a", n * SIZE_F).unwrap();
+        let b = sys.mem.alloc("b", n * SIZE_F).unwrap();
+        let memcpy = MemCpy::new(
+            MemCpyConfig {
+                addr_input: a,
+                addr_output: b,
+                input_length: n,
+            },
+            true,
+            &sys.hw,
+        );
+        sys.run_once(&memcpy);
+        let analytical = sys.analytical.cycles(&sys.hw.timing) as f64;
+        assert!(analytical > 0.0);
+
+        if !Path::new(&sys.ramsim.excutable_path).is_file() {
+            return Ok(());
+        }
+        sys.ramsim.config_path = "./configs/fibonacci.yaml".to_string();
+        let ramsim = sys.ramsim.run()?.cycles as f64;
+        let ratio = ramsim / analytical;
+        assert!(
+            (0.25..=4.0).contains(&ratio),
+            "RamSim {} cycles against {} estimated",
+            ramsim,
+            analytical
+        );
+        Ok(())
+    }
+}
@@ -1 +1,4 @@
 pub mod system;
+pub mod report;
+pub mod analytical;
+pub mod replay;
\ No newline at end of file
@@ -0,0 +1,364 @@
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::enable_config::EnableConfig;
+use crate::config::HwConfig;
+use crate::kernel::kernel::KernelCall;
+use crate::memory::memory_allocator::{MemAlloc, MemEvent};
+use crate::system::system::System;
+
+pub const LOG_VERSION: u64 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogHeader {
+    pub version: u64,
+    pub workload: String,
+    pub hw: HwConfig, // of the recorded run
+    pub mem_size_gb: usize,
+    pub mem_align: usize,
+    pub creator: String,
+}
+
+/// What System received, in order. The memory events are written right before the
+/// invocation that follows them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Invocation {
+    Mem(MemEvent),
+    PushPhase(String),
+    PopPhase,
+    Once(KernelCall),
+    Vec(Vec<KernelCall>),
+}
+
+/// Kernel invocation log, the header and then one invocation per line as JSON,
+/// compressed with zstd.
+pub struct InvocationWriter {
+    path: String,
+    encoder: zstd::Encoder<'static, BufWriter<File>>,
+    pub num_invocations: u64,
+}
+
+impl InvocationWriter {
+    pub fn create(path: &str, header: &LogHeader) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("failed to create {}", path))?;
+        let mut encoder = zstd::Encoder::new(BufWriter::new(file), 3)?;
+        serde_json::to_writer(&mut encoder, header)?;
+        encoder.write_all(b"\n")?;
+        Ok(Self {
+            path: path.to_string(),
+            encoder,
+            num_invocations: 0,
+        })
+    }
+
+    pub fn write(&mut self, invocation: &Invocation) -> Result<()> {
+        serde_json::to_writer(&mut self.encoder, invocation)?;
+        self.encoder.write_all(b"\n")?;
+        self.num_invocations += 1;
+        Ok(())
+    }
+
+    // returns the path of the log
+    pub fn finish(self) -> Result<String> {
+        self.encoder
+            .finish()?
+            .flush()
+            .with_context(|| format!("failed to write {}", self.path))?;
+        Ok(self.path)
+    }
+}
+
+pub struct InvocationReader {
+    lines: Lines<BufReader<zstd::Decoder<'static, BufReader<File>>>>,
+    pub header: LogHeader,
+}
+
+impl InvocationReader {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+        let mut lines = BufReader::new(zstd::Decoder::new(file)?).lines();
+        let header: LogHeader = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)
+                .with_context(|| format!("{} is not a kernel invocation log", path))?,
+            None => return Err(anyhow!("{} is empty", path)),
+        };
+        ensure!(
+            header.version == LOG_VERSION,
+            "unsupported kernel invocation log version {}",
+            header.version
+        );
+        Ok(Self { lines, header })
+    }
+}
+
+impl Iterator for InvocationReader {
+    type Item = Result<Invocation>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?;
+        Some(
+            line.map_err(|e| e.into())
+                .and_then(|l| serde_json::from_str(&l).context("invalid kernel invocation")),
+        )
+    }
+}
+
+/// What the prover decides from the config while it runs, and the recorded invocations
+/// keep: batch and chunk sizes from the active buffer, transposes by the array length,
+/// the ops of the vector ISA, which kernels run, and the rewrites of the gate evaluation.
+/// A log thus replays under the ArchConfigs that keep rdbuf_sz_kb * active_buf_frac,
+/// array_length and rich_vec_isa, not under any ArchConfig: sweeping these takes one
+/// recording per value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordedDecisions {
+    pub num_elems: usize,
+    pub array_length: usize,
+    pub rich_vec_isa: bool,
+    pub enable: EnableConfig,
+    pub symbolic_gates: bool,
+    pub cse_vec_ops: bool,
+}
+
+impl RecordedDecisions {
+    pub fn of(hw: &HwConfig) -> Self {
+        Self {
+            num_elems: hw.arch.num_elems(),
+            array_length: hw.arch.array_length,
+            rich_vec_isa: hw.arch.rich_vec_isa,
+            enable: hw.enable,
+            symbolic_gates: hw.opt.symbolic_gates,
+            cse_vec_ops: hw.opt.cse_vec_ops,
+        }
+    }
+
+    // the decisions that differ, with the config fields they come from
+    pub fn differences(&self, other: &Self) -> Vec<String> {
+        let mut res = Vec::new();
+        let mut check = |differ: bool, name: &str, a: &dyn Debug, b: &dyn Debug| {
+            if differ {
+                res.push(format!("{} {:?} instead of {:?}", name, b, a));
+            }
+        };
+        check(
+            self.num_elems != other.num_elems,
+            "num_elems (arch.rdbuf_sz_kb, arch.active_buf_frac)",
+            &self.num_elems,
+            &other.num_elems,
+        );
+        check(
+            self.array_length != other.array_length,
+            "arch.array_length",
+            &self.array_length,
+            &other.array_length,
+        );
+        check(
+            self.rich_vec_isa != other.rich_vec_isa,
+            "arch.rich_vec_isa",
+            &self.rich_vec_isa,
+            &other.rich_vec_isa,
+        );
+        check(
+            self.enable != other.enable,
+            "enable",
+            &self.enable,
+            &other.enable,
+        );
+        check(
+            self.symbolic_gates != other.symbolic_gates,
+            "opt.symbolic_gates",
+            &self.symbolic_gates,
+            &other.symbolic_gates,
+        );
+        check(
+            self.cse_vec_ops != other.cse_vec_ops,
+            "opt.cse_vec_ops",
+            &self.cse_vec_ops,
+            &other.cse_vec_ops,
+        );
+        res
+    }
+}
+
+/// Run the invocations of the log on `sys`, which regenerates the memory trace of
+/// the recorded run under sys.hw. The config may differ in everything but the
+/// RecordedDecisions, e.g. in the number of tiles, the replacement policy or the
+/// DRAM, and the log is refused otherwise. Returns the number of invocations.
+pub fn replay(path: &str, sys: &mut System) -> Result<u64> {
+    let reader = InvocationReader::open(path)?;
+    let header = &reader.header;
+    let differences =
+        RecordedDecisions::of(&header.hw).differences(&RecordedDecisions::of(&sys.hw));
+    ensure!(
+        differences.is_empty(),
+        "{} cannot be replayed, the prover decided on the recorded config and this one has {}; \
+         record the workload again with it",
+        path,
+        differences.join(", ")
+    );
+    let events = sys.mem.events.take();
+    sys.mem = MemAlloc::new(header.mem_size_gb, header.mem_align);
+    sys.mem.events = events;
+
+    let mut num_invocations = 0;
+    for invocation in reader {
+        match invocation? {
+            Invocation::Mem(event) => sys.mem.apply(&event)?,
+            Invocation::PushPhase(name) => sys.push_phase(&name),
+            Invocation::PopPhase => sys.pop_phase(),
+            Invocation::Once(call) => sys.run_once(call.build(&sys.hw).as_ref()),
+            Invocation::Vec(calls) => {
+                let kernels = calls.iter().map(|c| c.build(&sys.hw)).collect::<Vec<_>>();
+                sys.run_kernels(&kernels.iter().map(|k| k.as_ref()).collect::<Vec<_>>());
+            }
+        }
+        num_invocations += 1;
+    }
+    Ok(num_invocations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Backend, RamConfig, Replacement};
+    use crate::kernel::fft::{Fft, FftConfig, FftDirection};
+    use crate::kernel::kernel::FetchEdit;
+    use crate::kernel::memory_copy::{MemCpy, MemCpyConfig};
+    use crate::kernel::vector_chain::VectorChain;
+    use crate::kernel::vector_operation::{VecOpConfig, VecOpSrc, VecOpType};
+    use crate::util::SIZE_F;
+
+    fn run(sys: &mut System) {
+        let n = 1 << 10;
+        let a = sys.mem.alloc("a", n * SIZE_F).unwrap();
+        let b = sys.mem.alloc("b", n * SIZE_F).unwrap();
+        let c = sys.mem.alloc("c", n * SIZE_F).unwrap();
+
+        sys.push_phase("fft");
+        let mut fft = Fft::new(
+            FftConfig {
+                lg_n: 10,
+                k: 1,
+                direction: FftDirection::NN,
+                transposed_input: false,
+                addr_input: a,
+                addr_tmp: c,
+                addr_output: b,
+                inverse: false,
+                rate_bits: 0,
+                coset: false,
+                extension: 1,
+            },
+            &sys.hw,
+        );
+        let memcpy = MemCpyConfig {
+            addr_input: c,
+            addr_output: a,
+            input_length: 16,
+        };
+        fft.edit(FetchEdit::PrefetchAddrTrans(vec![memcpy]));
+        sys.run_once(&fft);
+        sys.pop_phase();
+
+        // the chain is created while b is preloaded and run after that changed
+        sys.mem.preload(b, n);
+        let vec_op = VecOpConfig {
+            vector_length: n,
+            addr_input_0: a,
+            addr_input_1: b,
+            addr_output: c,
+            is_final_output: true,
+            op_type: VecOpType::MUL,
+            op_src: VecOpSrc::VV,
+        };
+        let chain = VectorChain::new(vec![vec_op], &sys.mem, &sys.hw);
+        sys.mem.clear_preload();
+        sys.run_once(&chain);
+
+        let copies = (0..4)
+            .map(|i| {
+                let mut m = MemCpy::new(
+                    MemCpyConfig {
+                        addr_input: a + i * 64 * SIZE_F,
+                        addr_output: c + i * 64 * SIZE_F,
+                        input_length: 64,
+                    },
+                    true,
+                    &sys.hw,
+                );
+                m.edit(FetchEdit::ClearDrain);
+                m.edit(FetchEdit::ClearWriteRequest);
+                m
+            })
+            .collect::<Vec<_>>();
+        sys.run_vec(copies);
+        sys.mem.free("b");
+    }
+
+    fn new_system(name: &str, hw: HwConfig) -> System {
+        let mut hw = hw;
+        hw.timing.backend = Backend::Analytical;
+        System::new(MemAlloc::new(1, 4096), RamConfig::new(name), hw)
+    }
+
+    #[test]
+    fn test_replay() -> Result<()> {
+        let path = "./traces/test_replay.kernels.zst";
+        let mut sys = new_system("test_replay", HwConfig::default());
+        sys.record_invocations(path)?;
+        run(&mut sys);
+        assert_eq!(sys.finish_recording()?, Some(path.to_string()));
+        let recorded = sys.report();
+
+        let mut replayed = new_system("test_replay_same", HwConfig::default());
+        assert!(replay(path, &mut replayed)? > 3);
+        let report = replayed.report();
+        assert_eq!(report.op_cnt, recorded.op_cnt);
+        assert_eq!(report.total_bytes, recorded.total_bytes);
+        assert_eq!(report.computation, recorded.computation);
+        assert_eq!(
+            report
+                .phases
+                .iter()
+                .map(|p| p.bytes_read)
+                .collect::<Vec<_>>(),
+            recorded
+                .phases
+                .iter()
+                .map(|p| p.bytes_read)
+                .collect::<Vec<_>>()
+        );
+        assert!(replayed.mem.get_addr("b").is_none());
+
+        let mut hw = HwConfig::default();
+        hw.arch.num_tiles = 8;
+        hw.arch.replacement = Replacement::Fifo;
+        let mut other = new_system("test_replay_other", hw);
+        replay(path, &mut other)?;
+        let report = other.report();
+        assert_eq!(report.phases.len(), recorded.phases.len());
+        assert_eq!(report.computation, recorded.computation);
+
+        // the prover sized its batches and picked its ops for the recorded config
+        let mut refused = Vec::new();
+        let mut smaller_buffer = hw;
+        smaller_buffer.arch.rdbuf_sz_kb = 256;
+        smaller_buffer.arch.wrbuf_sz_kb = 256;
+        refused.push((smaller_buffer, "arch.rdbuf_sz_kb"));
+        let mut rich_isa = hw;
+        rich_isa.arch.rich_vec_isa = true;
+        refused.push((rich_isa, "arch.rich_vec_isa"));
+        let mut disabled = hw;
+        disabled.enable.fft = false;
+        refused.push((disabled, "enable"));
+        for (hw, field) in refused {
+            let mut sys = new_system("test_replay_refused", hw);
+            let err = replay(path, &mut sys).unwrap_err().to_string();
+            assert!(err.contains(field), "{}", err);
+        }
+        Ok(())
+    }
+}
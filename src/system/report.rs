@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{HwConfig, RamSimStats};
+use crate::kernel::vec_cse::CseStats;
+use crate::kernel::vec_schedule::ScheduleStats;
+use crate::system::analytical::AnalyticalEstimate;
+
+// traffic and computation of the ops issued while the phase is the innermost one
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhaseStats {
+    pub name: String,
+    pub num_requests: usize,
+    pub bytes_read: usize,
+    pub bytes_written: usize,
+    pub computation: BTreeMap<String, usize>,
+    pub cycles: Option<u64>,
+    pub estimated_seconds: f64,
+    pub vec_schedule: Option<ScheduleStats>, // if the vector ops are scheduled
+    #[serde(default)]
+    pub vec_cse: BTreeMap<String, CseStats>, // by gate type, if the ops are eliminated
+}
+
+impl PhaseStats {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationReport {
+    pub name: String,
+    pub hw: HwConfig,
+    pub op_cnt: usize,
+    pub total_bytes: u64,
+    // log2 of the request size -> number of requests
+    pub size_histogram: BTreeMap<usize, usize>,
+    pub computation: BTreeMap<String, usize>,
+    pub phases: Vec<PhaseStats>,
+    pub analytical: AnalyticalEstimate,
+    pub ramsim: Option<RamSimStats>,
+    pub vec_schedule: Option<ScheduleStats>,
+    #[serde(default)]
+    pub vec_cse: BTreeMap<String, CseStats>,
+    // ids of the gates costed from their shape, see plonk::gates::eval_unfiltered_base_estimated
+    #[serde(default)]
+    pub estimated_gates: Vec<String>,
+}
+
+impl SimulationReport {
+    pub fn set_ramsim(&mut self, stats: RamSimStats) {
+        // a phase segment lasts until the next segment is issued, the last one until
+        // the end of the simulation
+        let ends = stats
+            .phase_issue_cycles
+            .iter()
+            .skip(1)
+            .map(|x| x.1)
+            .chain([stats.cycles]);
+        for (&(phase, start), end) in stats.phase_issue_cycles.iter().zip(ends) {
+            if let Some(phase) = self.phases.get_mut(phase) {
+                *phase.cycles.get_or_insert(0) += end.saturating_sub(start);
+            }
+        }
+        self.ramsim = Some(stats);
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn write_json(&self, path: &str) -> Result<()> {
+        fs::write(path, self.to_json()).with_context(|| format!("failed to write {}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulation_report() -> Result<()> {
+        let output = "Phase 0 issued in cycle 0
+Phase 1 issued in cycle 200
+Phase 0 issued in cycle 1000
+TraceGen finished in cycle 1234
+";
+        let mut report = SimulationReport {
+            name: "test".to_string(),
+            hw: HwConfig::default(),
+            op_cnt: 96,
+            total_bytes: 96 * 64,
+            size_histogram: BTreeMap::from([(6, 96)]),
+            computation: BTreeMap::from([("Fft".to_string(), 10)]),
+            phases: vec![PhaseStats::new("main"), PhaseStats::new("fri")],
+            analytical: AnalyticalEstimate::default(),
+            ramsim: None,
+            vec_schedule: None,
+            vec_cse: BTreeMap::new(),
+            estimated_gates: vec!["U32AddManyGate".to_string()],
+        };
+        report.set_ramsim(RamSimStats::parse(output)?);
+        assert_eq!(report.phases[0].cycles, Some(434));
+        assert_eq!(report.phases[1].cycles, Some(800));
+
+        let json: SimulationReport = serde_json::from_str(&report.to_json())?;
+        assert_eq!(json.ramsim, report.ramsim);
+        assert_eq!(json.size_histogram, report.size_histogram);
+        assert_eq!(json.phases, report.phases);
+        assert_eq!(json.estimated_gates, report.estimated_gates);
+        Ok(())
+    }
+}
@@ -1,44 +1,181 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+use anyhow::{ensure, Result};
+use log::{info, warn};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::{Field, PrimeField64};
 use plonky2::util::ceil_div_usize;
 
 use crate::trace::trace::{Fetch, FetchType, Request};
-use crate::config::arch_config::ARCH_CONFIG;
 use crate::config::ram_config::OpRecord;
-use crate::config::RamConfig;
+use crate::config::{Backend, HwConfig, RamConfig};
 use crate::kernel::filter_drain::merge;
 use crate::kernel::kernel::Kernel;
 use crate::memory::memory_allocator::MemAlloc;
+use crate::system::analytical::AnalyticalEstimate;
+use crate::trace::sink::NullSink;
+use crate::system::replay::{Invocation, InvocationWriter, LogHeader, LOG_VERSION};
+use crate::system::report::{PhaseStats, SimulationReport};
 pub struct System {
     pub mem: MemAlloc,
     pub ramsim: RamConfig,
+    pub hw: HwConfig,
     pub last_prefetch_block: Vec<(u64, u64)>,
     pub last_drain_block: Vec<(u64, u64)>,
 
     pub computation: HashMap<String, usize>,
+
+    // phases[0] is the top level, see push_phase
+    pub phases: Vec<PhaseStats>,
+    phase_stack: Vec<String>,
+    phase: usize,
+
+    pub analytical: AnalyticalEstimate,
+
+    // kernel invocation log, see record_invocations
+    recorder: Option<InvocationWriter>,
+
+    // the kernels also compute their outputs from the field elements in mem
+    pub functional: bool,
+
+    // ids of the gates without a port or a lowering, whose ops are estimated
+    pub estimated_gates: BTreeSet<String>,
 }
 
 impl System {
-    pub fn new(mem: MemAlloc, ramsim: RamConfig) -> System {
+    pub fn new(mem: MemAlloc, mut ramsim: RamConfig, hw: HwConfig) -> System {
+        ramsim
+            .log_hw_config(&hw)
+            .expect("failed to write hardware config to log");
+        match hw.timing.backend {
+            Backend::RamSim => {}
+            Backend::Analytical => ramsim.set_sinks(vec![Box::new(NullSink::default())]),
+            Backend::RamSimLib => {
+                if let Err(e) = ramsim.use_ramsim_lib() {
+                    warn!("{:#}, writing the trace file instead", e);
+                }
+            }
+        }
         let last_prefetch_block = vec![];
         let last_drain_block = vec![];
         System {
             mem,
             ramsim,
+            hw,
 
             last_prefetch_block,
             last_drain_block,
             computation: HashMap::new(),
+
+            phases: vec![PhaseStats::new(Self::TOP_PHASE)],
+            phase_stack: Vec::new(),
+            phase: 0,
+
+            analytical: AnalyticalEstimate::default(),
+            recorder: None,
+            functional: false,
+            estimated_gates: BTreeSet::new(),
         }
     }
 
     pub fn reset(&mut self) {
         self.computation.clear();
+        self.phases = vec![PhaseStats::new(Self::TOP_PHASE)];
+        self.phase_stack.clear();
+        self.phase = 0;
+        self.analytical = AnalyticalEstimate::default();
+        self.estimated_gates.clear();
         self.last_prefetch_block.clear();
         self.last_drain_block.clear();
         self.ramsim.reset();
     }
 
+    /// Write every kernel invocation, phase change and allocator event from now on
+    /// to `path`, see system::replay. Has to be called before memory is allocated.
+    pub fn record_invocations(&mut self, path: &str) -> Result<()> {
+        ensure!(self.recorder.is_none(), "invocations are already recorded");
+        ensure!(
+            self.mem.blocks.iter().filter(|b| !b.free).count() <= 1
+                && self.mem.preload_vecs.is_empty(),
+            "memory was allocated before recording started"
+        );
+        let header = LogHeader {
+            version: LOG_VERSION,
+            workload: self.ramsim.file_name.clone(),
+            hw: self.hw,
+            mem_size_gb: self.mem.size >> 30,
+            mem_align: self.mem.align,
+            creator: format!("unizk {}", env!("CARGO_PKG_VERSION")),
+        };
+        self.recorder = Some(InvocationWriter::create(path, &header)?);
+        self.mem.events = Some(Vec::new());
+        Ok(())
+    }
+
+    // returns the path of the log if invocations were recorded
+    pub fn finish_recording(&mut self) -> Result<Option<String>> {
+        self.flush_mem_events()?;
+        self.mem.events = None;
+        match self.recorder.take() {
+            Some(recorder) => {
+                let num_invocations = recorder.num_invocations;
+                let path = recorder.finish()?;
+                info!("{} kernel invocations written to {}", num_invocations, path);
+                Ok(Some(path))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn flush_mem_events(&mut self) -> Result<()> {
+        if let (Some(recorder), Some(events)) = (self.recorder.as_mut(), self.mem.events.as_mut()) {
+            for event in events.drain(..) {
+                recorder.write(&Invocation::Mem(event))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn record<I: FnOnce() -> Invocation>(&mut self, invocation: I) {
+        if self.recorder.is_none() {
+            return;
+        }
+        self.flush_mem_events()
+            .and_then(|_| self.recorder.as_mut().unwrap().write(&invocation()))
+            .expect("failed to write kernel invocation log");
+    }
+
+    const TOP_PHASE: &'static str = "main";
+
+    /// Tag every op and computation until the matching pop_phase with `name`, like
+    /// plonky2's TimingTree. Nested phases are named as "outer/inner".
+    pub fn push_phase(&mut self, name: &str) {
+        self.record(|| Invocation::PushPhase(name.to_string()));
+        self.phase_stack.push(name.to_string());
+        self.update_phase();
+    }
+
+    pub fn pop_phase(&mut self) {
+        assert!(self.phase_stack.pop().is_some(), "no phase to pop");
+        self.record(|| Invocation::PopPhase);
+        self.update_phase();
+    }
+
+    fn update_phase(&mut self) {
+        let name = if self.phase_stack.is_empty() {
+            Self::TOP_PHASE.to_string()
+        } else {
+            self.phase_stack.join("/")
+        };
+        self.phase = match self.phases.iter().position(|p| p.name == name) {
+            Some(phase) => phase,
+            None => {
+                self.phases.push(PhaseStats::new(&name));
+                self.phases.len() - 1
+            }
+        };
+    }
+
     fn run_trace(
         &mut self,
         mut prefetch: Fetch,
@@ -70,12 +207,10 @@ impl System {
         self.last_drain_block = drain.addr.last().unwrap_or(&vec![]).clone();
 
         assert_eq!(prefetch.systolic, drain.systolic);
-        let parallel_level = unsafe {
-            if prefetch.systolic {
-                ARCH_CONFIG.num_tiles
-            } else {
-                ARCH_CONFIG.num_tiles * ARCH_CONFIG.array_length
-            }
+        let parallel_level = if prefetch.systolic {
+            self.hw.arch.num_tiles
+        } else {
+            self.hw.arch.num_tiles * self.hw.arch.array_length
         };
 
         let fetch_len = (prefetch.addr.len()).max(drain.addr.len());
@@ -94,6 +229,7 @@ impl System {
                     addr: addr.0,
                     dependencies: Vec::new(),
                     size: (addr.1 - addr.0 + 1) as u32,
+                    phase: self.phase,
                 })
                 .collect::<Vec<_>>();
             let mut write_op = write_sg
@@ -106,24 +242,25 @@ impl System {
                     addr: addr.0,
                     dependencies: Vec::new(),
                     size: (addr.1 - addr.0 + 1) as u32,
+                    phase: self.phase,
                 })
                 .collect::<Vec<_>>();
             for op in write_op.iter_mut() {
                 op.dependencies.extend(read_op.iter().map(|x| x.id));
             }
+            let delay_usize = prefetch.delay.get(fetch_idx).unwrap_or(&0)
+                + drain.delay.get(fetch_idx).unwrap_or(&0)
+                + prefetch.interval as usize
+                    * ceil_div_usize(
+                        *read_request.num_lines.get(fetch_idx).unwrap_or(&0),
+                        parallel_level,
+                    )
+                + drain.interval as usize
+                    * ceil_div_usize(
+                        *write_request.num_lines.get(fetch_idx).unwrap_or(&0),
+                        parallel_level,
+                    );
             if let Some(op) = write_op.first_mut() {
-                let delay_usize = prefetch.delay.get(fetch_idx).unwrap_or(&0)
-                    + drain.delay.get(fetch_idx).unwrap_or(&0)
-                    + prefetch.interval as usize
-                        * ceil_div_usize(
-                            *read_request.num_lines.get(fetch_idx).unwrap_or(&0),
-                            parallel_level,
-                        )
-                    + drain.interval as usize
-                        * ceil_div_usize(
-                            *write_request.num_lines.get(fetch_idx).unwrap_or(&0),
-                            parallel_level,
-                        );
                 op.delay = delay_usize as u32;
             }
             let first_write_id = write_op.first().unwrap_or(&OpRecord::default()).id;
@@ -132,19 +269,56 @@ impl System {
                 .skip(1)
                 .for_each(|x| x.dependencies.push(first_write_id));
 
+            let phase = &mut self.phases[self.phase];
+            let (bytes_read, bytes_written) = (phase.bytes_read, phase.bytes_written);
+            for op in read_op.iter().chain(write_op.iter()) {
+                phase.num_requests += 1;
+                match op.fetch_type {
+                    FetchType::Read => phase.bytes_read += op.size as usize,
+                    FetchType::Write => phase.bytes_written += op.size as usize,
+                }
+            }
+            phase.estimated_seconds += self.analytical.step(
+                &self.hw.timing,
+                phase.bytes_read - bytes_read,
+                phase.bytes_written - bytes_written,
+                delay_usize,
+            );
+
             if self.ramsim.add_trace(read_op, write_op).is_err() {
                 panic!("Error adding trace");
             }
         }
     }
 
-    pub fn run_once<K: Kernel>(&mut self, kernel: &K) {
+    // in functional mode, fill a buffer computed on the CPU with its values
+    pub fn write_cpu<F: PrimeField64>(&mut self, addr: usize, values: &[F]) {
+        if self.functional {
+            let values = values
+                .iter()
+                .map(|v| GoldilocksField::from_canonical_u64(v.to_canonical_u64()))
+                .collect::<Vec<_>>();
+            self.mem.write_elems(addr, &values);
+        }
+    }
+
+    pub fn run_once<K: Kernel + ?Sized>(&mut self, kernel: &K) {
+        self.record(|| Invocation::Once(kernel.get_call()));
+        if self.functional {
+            kernel.execute(&mut self.mem);
+        }
         //add computation to self.computation
         let comp = self
             .computation
             .entry(kernel.get_kernel_type())
             .or_insert(0);
         *comp += kernel.get_computation();
+        *self.phases[self.phase]
+            .computation
+            .entry(kernel.get_kernel_type())
+            .or_insert(0) += kernel.get_computation();
+        self.add_schedule_stats(kernel);
+        self.add_cse_stats(kernel);
 
         let prefetch = kernel.get_prefetch();
         let read_request = kernel.get_read_request();
@@ -154,6 +328,18 @@ impl System {
     }
 
     pub fn run_vec<T: Kernel>(&mut self, kernels: Vec<T>) {
+        self.run_kernels(&kernels.iter().map(|k| k as &dyn Kernel).collect::<Vec<_>>());
+    }
+
+    pub(crate) fn run_kernels(&mut self, kernels: &[&dyn Kernel]) {
+        self.record(|| Invocation::Vec(kernels.iter().map(|k| k.get_call()).collect()));
+        if self.functional {
+            kernels.iter().for_each(|k| k.execute(&mut self.mem));
+        }
+        kernels.iter().for_each(|k| {
+            self.add_schedule_stats(*k);
+            self.add_cse_stats(*k);
+        });
         let prefetches = kernels.iter().map(|x| x.get_prefetch()).collect::<Vec<_>>();
         let read_requests = kernels
             .iter()
@@ -189,9 +375,97 @@ impl System {
         self.run_trace(prefetch, read_request, write_request, drain);
     }
 
+    fn add_schedule_stats<K: Kernel + ?Sized>(&mut self, kernel: &K) {
+        if let Some(stats) = kernel.get_schedule_stats() {
+            *self.phases[self.phase]
+                .vec_schedule
+                .get_or_insert_with(Default::default) += stats;
+        }
+    }
+
+    fn add_cse_stats<K: Kernel + ?Sized>(&mut self, kernel: &K) {
+        for (tag, stats) in kernel.get_cse_stats() {
+            *self.phases[self.phase].vec_cse.entry(tag).or_default() += stats;
+        }
+    }
+
     pub fn get_computation(&mut self) -> HashMap<String, usize> {
         self.computation.clone()
     }
+
+    pub fn report(&self) -> SimulationReport {
+        SimulationReport {
+            name: self.ramsim.file_name.clone(),
+            hw: self.hw,
+            op_cnt: self.ramsim.op_cnt,
+            total_bytes: self.ramsim.total_bytes(),
+            size_histogram: self.ramsim.size_histogram(),
+            computation: self.computation.clone().into_iter().collect(),
+            phases: self.phases.clone(),
+            analytical: self.analytical,
+            ramsim: None,
+            vec_schedule: self
+                .phases
+                .iter()
+                .filter_map(|p| p.vec_schedule)
+                .reduce(|mut a, b| {
+                    a += b;
+                    a
+                }),
+            vec_cse: self.phases.iter().flat_map(|p| p.vec_cse.iter()).fold(
+                BTreeMap::new(),
+                |mut res, (tag, &stats)| {
+                    *res.entry(tag.clone()).or_default() += stats;
+                    res
+                },
+            ),
+            estimated_gates: self.estimated_gates.iter().cloned().collect(),
+        }
+    }
+
+    // run RamSim on the trace unless the backend is analytical, and write the report
+    // to <name>.json next to <name>.log, without RamSim statistics if RamSim fails.
+    // A kernel invocation log that is being recorded is finished first.
+    pub fn finish(&mut self) -> Result<SimulationReport> {
+        self.finish_recording()?;
+        let mut report = self.report();
+        info!(
+            "Analytical estimate: {:.6} s, {} of {} steps memory bound",
+            self.analytical.seconds, self.analytical.memory_bound_steps, self.analytical.num_steps
+        );
+        if let Some(s) = report.vec_schedule {
+            info!(
+                "Vector op scheduling: {} -> {} ops, {} -> {} convoys, {} -> {} bytes read, {} -> {} bytes written",
+                s.ops_before,
+                s.ops_after,
+                s.convoys_before,
+                s.convoys_after,
+                s.bytes_read_before,
+                s.bytes_read_after,
+                s.bytes_written_before,
+                s.bytes_written_after
+            );
+        }
+        for (tag, s) in report.vec_cse.iter() {
+            info!(
+                "Vector op elimination {}: {} of {} ops saved, {} duplicate, {} dead",
+                tag,
+                s.ops_saved(),
+                s.ops_before,
+                s.duplicates,
+                s.dead
+            );
+        }
+        let stats = match self.hw.timing.backend {
+            Backend::RamSim | Backend::RamSimLib => self.ramsim.run().map(Some),
+            Backend::Analytical => Ok(None),
+        };
+        if let Ok(Some(stats)) = &stats {
+            report.set_ramsim(stats.clone());
+        }
+        report.write_json(&format!("{}.json", report.name))?;
+        stats.map(|_| report)
+    }
 }
 
 pub fn find_consecutive_segments(nums: &Vec<usize>) -> Vec<(u64, u64)> {
@@ -222,3 +496,104 @@ pub fn find_consecutive_segments(nums: &Vec<usize>) -> Vec<(u64, u64)> {
 
     ranges
 }
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField as F;
+    use plonky2::field::types::{Field, Sample};
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::plonk::config::Hasher;
+
+    use super::*;
+    use crate::kernel::hash_no_pad::{HashNoPad, HashNoPadConfig};
+    use crate::kernel::memory_copy::{MemCpy, MemCpyConfig};
+    use crate::kernel::transpose::{Transpose, TransposeConfig};
+    use crate::kernel::vector_chain::VectorChain;
+    use crate::kernel::vector_operation::{VecOpConfig, VecOpSrc, VecOpType};
+    use crate::util::{bit_reverse, SIZE_F};
+
+    #[test]
+    fn test_functional() {
+        let mut hw = HwConfig::default();
+        hw.timing.backend = Backend::Analytical;
+        let mut sys = System::new(
+            MemAlloc::new(1, 4096),
+            RamConfig::new("test_functional_kernels"),
+            hw,
+        );
+        let (height, width) = (4, 8);
+        let n = height * width;
+        let a = sys.mem.alloc("a", n * SIZE_F).unwrap();
+        let b = sys.mem.alloc("b", n * SIZE_F).unwrap();
+        let c = sys.mem.alloc("c", n * SIZE_F).unwrap();
+        let x = F::rand_vec(n);
+        sys.mem.write_elems(a, &x);
+
+        let memcpy = MemCpy::new(
+            MemCpyConfig {
+                addr_input: a,
+                addr_output: b,
+                input_length: n,
+            },
+            true,
+            &sys.hw,
+        );
+        sys.run_once(&memcpy);
+        assert_eq!(sys.mem.read_elems(b, n), vec![F::ZERO; n]);
+
+        sys.functional = true;
+        sys.run_vec(vec![memcpy]);
+        assert_eq!(sys.mem.read_elems(b, n), x);
+
+        let transpose = TransposeConfig {
+            addr_input: a,
+            addr_output: c,
+            width,
+            height,
+            start: 0,
+            end: width,
+            reverse: true,
+            extension: 1,
+        };
+        sys.run_once(&Transpose::new(transpose, &sys.hw));
+        let y = sys.mem.read_elems(c, n);
+        for i in 0..height {
+            for j in 0..width {
+                assert_eq!(y[bit_reverse(j, 3) * height + i], x[i * width + j]);
+            }
+        }
+
+        // b = (a + b) * a[0]
+        let op = |addr_input_1, op_type, op_src| VecOpConfig {
+            vector_length: n,
+            addr_input_0: b,
+            addr_input_1,
+            addr_output: b,
+            is_final_output: true,
+            op_type,
+            op_src,
+        };
+        let vec_ops = vec![
+            op(a, VecOpType::ADD, VecOpSrc::VV),
+            op(a, VecOpType::MUL, VecOpSrc::VS),
+        ];
+        sys.run_once(&VectorChain::new(vec_ops, &sys.mem, &sys.hw));
+        let z = x.iter().map(|v| (*v + *v) * x[0]).collect::<Vec<_>>();
+        assert_eq!(sys.mem.read_elems(b, n), z);
+
+        let hash = HashNoPad::new(
+            HashNoPadConfig {
+                addr_input: vec![a, b],
+                addr_output: c,
+                input_length: vec![n, 3],
+                output_length: 4,
+                addr_state: None,
+            },
+            &sys.hw,
+        );
+        sys.run_once(&hash);
+        let expected = PoseidonHash::hash_no_pad(&[x.clone(), z[..3].to_vec()].concat());
+        assert_eq!(sys.mem.read_elems(c, 4), expected.elements);
+        assert_eq!(sys.mem.num_stray_accesses.get(), 0);
+    }
+}
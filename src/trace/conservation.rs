@@ -0,0 +1,516 @@
+use crate::kernel::kernel::Kernel;
+use crate::trace::trace::Fetch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionRole {
+    Input,   // only read by the kernel
+    Scratch, // written and read back by the kernel
+    Output,  // written once by the kernel, may be read back
+}
+
+/// A buffer of the kernel, elem_sz bytes per element. Fetches address the first
+/// byte of an element.
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub name: String,
+    pub addr: usize,
+    pub num_elems: usize,
+    pub elem_sz: usize,
+    pub role: RegionRole,
+}
+
+impl Region {
+    pub fn new(
+        name: &str,
+        addr: usize,
+        num_elems: usize,
+        elem_sz: usize,
+        role: RegionRole,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            addr,
+            num_elems,
+            elem_sz,
+            role,
+        }
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        let addr = addr as usize;
+        addr >= self.addr && addr < self.addr + self.num_elems * self.elem_sz
+    }
+
+    // the elements that start in the segment
+    fn elems(&self, (start, end): (u64, u64)) -> std::ops::Range<usize> {
+        let (start, end) = (start as usize, end as usize);
+        if end < self.addr {
+            return 0..0;
+        }
+        let first = start.saturating_sub(self.addr).div_ceil(self.elem_sz);
+        let last = ((end - self.addr) / self.elem_sz + 1).min(self.num_elems);
+        first..last.max(first)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ElemState {
+    num_reads: usize,
+    last_drain: Option<usize>, // block index
+    read_since_drain: bool,
+}
+
+/// Checks that the prefetch and drain streams of a kernel move its data the way the
+/// computation needs it. Block i of the prefetch is read before block i of the drain
+/// is written, like in System::run_trace. Every input element has to be prefetched,
+/// every output element drained exactly once, no element may be read before it was
+/// drained, and no scratch value may be drained again before it was read. Address 0
+/// is the zero padding and is ignored.
+///
+/// Fetch::push merges addresses less than a line apart, so a segment also covers the
+/// elements in between. The elements of the fetches are checked instead if they were
+/// kept, see Fetch::elems.
+#[derive(Debug, Default)]
+pub struct Conservation {
+    pub num_reads: usize,
+    pub num_drains: usize,
+    pub num_errors: usize,
+    pub errors: Vec<String>, // the first MAX_ERRORS errors
+}
+
+impl Conservation {
+    pub const MAX_ERRORS: usize = 16;
+
+    pub fn check(prefetch: &Fetch, drain: &Fetch, regions: &[Region]) -> Self {
+        let mut check = Self::default();
+        if prefetch.len() != drain.len() && prefetch.len() > 0 && drain.len() > 0 {
+            check.error(format!(
+                "{} prefetch blocks but {} drain blocks",
+                prefetch.len(),
+                drain.len()
+            ));
+        }
+        let mut states = regions
+            .iter()
+            .map(|r| vec![ElemState::default(); r.num_elems])
+            .collect::<Vec<_>>();
+        let (prefetch, drain) = (Self::blocks(prefetch), Self::blocks(drain));
+
+        for block in 0..prefetch.len().max(drain.len()) {
+            for &segment in prefetch.get(block).unwrap_or(&vec![]) {
+                if !check.covered(segment, regions, "prefetch", block) {
+                    continue;
+                }
+                for (region, states) in regions.iter().zip(states.iter_mut()) {
+                    for i in region.elems(segment) {
+                        let state = &mut states[i];
+                        if region.role != RegionRole::Input && state.last_drain.is_none() {
+                            check.error(format!(
+                                "block {} prefetches {}[{}] before it is drained",
+                                block, region.name, i
+                            ));
+                        }
+                        state.num_reads += 1;
+                        state.read_since_drain = true;
+                        check.num_reads += 1;
+                    }
+                }
+            }
+            for &segment in drain.get(block).unwrap_or(&vec![]) {
+                if !check.covered(segment, regions, "drain", block) {
+                    continue;
+                }
+                for (region, states) in regions.iter().zip(states.iter_mut()) {
+                    for i in region.elems(segment) {
+                        let state = &mut states[i];
+                        if region.role == RegionRole::Input {
+                            check.error(format!(
+                                "block {} drains into the input {}[{}]",
+                                block, region.name, i
+                            ));
+                        } else if let Some(last) = state
+                            .last_drain
+                            .filter(|_| region.role == RegionRole::Output)
+                        {
+                            check.error(format!(
+                                "block {} drains the output {}[{}] again, it was drained in block {}",
+                                block, region.name, i, last
+                            ));
+                        } else if let Some(last) =
+                            state.last_drain.filter(|_| !state.read_since_drain)
+                        {
+                            check.error(format!(
+                                "block {} drains {}[{}] again, it was drained in block {} and not read since",
+                                block, region.name, i, last
+                            ));
+                        }
+                        state.last_drain = Some(block);
+                        state.read_since_drain = false;
+                        check.num_drains += 1;
+                    }
+                }
+            }
+        }
+
+        for (region, states) in regions.iter().zip(states.iter()) {
+            for (i, state) in states.iter().enumerate() {
+                match region.role {
+                    RegionRole::Input if state.num_reads == 0 => {
+                        check.error(format!("{}[{}] is never prefetched", region.name, i))
+                    }
+                    RegionRole::Output if state.last_drain.is_none() => {
+                        check.error(format!("{}[{}] is never drained", region.name, i))
+                    }
+                    _ => (),
+                }
+            }
+        }
+        check
+    }
+
+    pub fn check_kernel(kernel: &dyn Kernel, regions: &[Region]) -> Self {
+        Self::check(&kernel.get_prefetch(), &kernel.get_drain(), regions)
+    }
+
+    // an element is a segment of its own
+    fn blocks(fetch: &Fetch) -> Vec<Vec<(u64, u64)>> {
+        match &fetch.elems {
+            Some(elems) if elems.len() == fetch.addr.len() => elems
+                .iter()
+                .map(|block| {
+                    let mut block = block
+                        .iter()
+                        .map(|&a| (a as u64, a as u64))
+                        .collect::<Vec<_>>();
+                    block.sort();
+                    block.dedup();
+                    block
+                })
+                .collect(),
+            _ => fetch.addr.clone(),
+        }
+    }
+
+    // both ends of a segment have to be in a region, the gaps that Fetch::push merges
+    // may be outside
+    fn covered(
+        &mut self,
+        segment: (u64, u64),
+        regions: &[Region],
+        fetch: &str,
+        block: usize,
+    ) -> bool {
+        if segment == (0, 0) {
+            return false;
+        }
+        for addr in [segment.0, segment.1] {
+            if !regions.iter().any(|r| r.contains(addr)) {
+                self.error(format!(
+                    "block {} of the {} accesses {:#x}, which is in no region",
+                    block, fetch, addr
+                ));
+                return false;
+            }
+        }
+        true
+    }
+
+    fn error(&mut self, msg: String) {
+        self.num_errors += 1;
+        if self.errors.len() < Self::MAX_ERRORS {
+            self.errors.push(msg);
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.num_errors == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::arch_config::ArchConfig;
+    use crate::config::HwConfig;
+    use crate::kernel::fft::{Fft, FftConfig, FftDirection};
+    use crate::kernel::transpose::{Transpose, TransposeConfig};
+    use crate::kernel::tree::{Tree, TreeConfig};
+    use crate::trace::trace::FetchType;
+    use crate::util::{bit_reverse, SIZE_F};
+    use plonky2::util::log2_strict;
+
+    // far enough apart that no fetch merges two buffers
+    const BASE: usize = 1 << 30;
+    const GAP: usize = 1 << 26;
+
+    // the default, a small buffer that needs several passes and a narrow array
+    fn hws() -> Vec<HwConfig> {
+        let small = ArchConfig {
+            rdbuf_sz_kb: 16,
+            wrbuf_sz_kb: 16,
+            array_length: 8,
+            ..ArchConfig::default()
+        };
+        let narrow = ArchConfig {
+            rdbuf_sz_kb: 64,
+            wrbuf_sz_kb: 64,
+            array_length: 6,
+            ..ArchConfig::default()
+        };
+        [ArchConfig::default(), small, narrow]
+            .into_iter()
+            .map(|arch| {
+                let mut hw = HwConfig {
+                    arch,
+                    ..HwConfig::default()
+                };
+                // the kernels are initialized by the tests, after elems is set
+                hw.enable.fft = false;
+                hw.enable.hash = false;
+                hw.enable.other = false;
+                hw
+            })
+            .collect()
+    }
+
+    fn assert_valid(check: Conservation, what: String) {
+        assert!(
+            check.is_valid(),
+            "{}: {} errors, {:#?}",
+            what,
+            check.num_errors,
+            check.errors
+        );
+    }
+
+    #[test]
+    fn test_conservation() {
+        let regions = [
+            Region::new("input", 1 << 12, 8, SIZE_F, RegionRole::Input),
+            Region::new("tmp", 2 << 12, 8, SIZE_F, RegionRole::Scratch),
+            Region::new("output", 3 << 12, 8, SIZE_F, RegionRole::Output),
+        ];
+        let addrs = |base: usize, elems: std::ops::Range<usize>| {
+            vec![elems.map(|i| base + i * SIZE_F).collect::<Vec<_>>()]
+        };
+        let mut prefetch = Fetch::new(FetchType::Read);
+        let mut drain = Fetch::new(FetchType::Write);
+        prefetch.push(addrs(1 << 12, 0..8));
+        drain.push(addrs(2 << 12, 0..8));
+        prefetch.push(addrs(2 << 12, 0..8));
+        drain.push(addrs(3 << 12, 0..8));
+        let check = Conservation::check(&prefetch, &drain, &regions);
+        assert!(check.is_valid(), "{:?}", check.errors);
+        assert_eq!((check.num_reads, check.num_drains), (16, 16));
+
+        // input[7] is not read, tmp[0] is read but never drained, output[0] is drained
+        // twice and output[7] never
+        let mut prefetch = Fetch::new(FetchType::Read);
+        let mut drain = Fetch::new(FetchType::Write);
+        prefetch.push(addrs(1 << 12, 0..7));
+        drain.push(addrs(2 << 12, 1..8));
+        prefetch.push([addrs(2 << 12, 0..8), vec![vec![0]]].concat());
+        drain.push(addrs(3 << 12, 0..7));
+        prefetch.push(vec![]);
+        drain.push(addrs(3 << 12, 0..1));
+        let check = Conservation::check(&prefetch, &drain, &regions);
+        assert_eq!(check.num_errors, 4, "{:?}", check.errors);
+
+        // an output is drained once, even if it was read in between
+        let mut prefetch = Fetch::new(FetchType::Read);
+        let mut drain = Fetch::new(FetchType::Write);
+        prefetch.push(vec![]);
+        drain.push(addrs(3 << 12, 0..8));
+        prefetch.push(addrs(3 << 12, 0..8));
+        drain.push(addrs(3 << 12, 0..1));
+        let check = Conservation::check(&prefetch, &drain, &regions[2..]);
+        assert_eq!(check.num_errors, 1, "{:?}", check.errors);
+
+        // the segments of a strided drain cover the elements in between
+        let mut drain = Fetch::new(FetchType::Write);
+        drain.push(vec![(0..4).map(|i| (3 << 12) + 2 * i * SIZE_F).collect()]);
+        drain.push(vec![(0..4)
+            .map(|i| (3 << 12) + (2 * i + 1) * SIZE_F)
+            .collect()]);
+        let check = Conservation::check(&Fetch::new(FetchType::Read), &drain, &regions[2..]);
+        assert!(!check.is_valid());
+        drain.elems = Some(vec![]);
+        drain.clear();
+        drain.push(vec![(0..4).map(|i| (3 << 12) + 2 * i * SIZE_F).collect()]);
+        drain.push(vec![(0..4)
+            .map(|i| (3 << 12) + (2 * i + 1) * SIZE_F)
+            .collect()]);
+        let check = Conservation::check(&Fetch::new(FetchType::Read), &drain, &regions[2..]);
+        assert!(check.is_valid(), "{:?}", check.errors);
+    }
+
+    #[test]
+    fn test_fft_conservation() {
+        for hw in hws() {
+            for (lg_n, k) in [(3, 5), (5, 1), (9, 5), (13, 2)] {
+                for (extension, rate_bits) in [(1, 0), (1, 2), (2, 0)] {
+                    for transposed_input in [false, true] {
+                        for direction in [FftDirection::NN, FftDirection::NR] {
+                            let n = 1 << lg_n;
+                            let config = FftConfig {
+                                lg_n,
+                                k,
+                                direction,
+                                transposed_input,
+                                addr_input: BASE,
+                                addr_tmp: BASE + GAP,
+                                addr_output: BASE + 2 * GAP,
+                                inverse: false,
+                                rate_bits,
+                                coset: false,
+                                extension,
+                            };
+                            let sz = SIZE_F * extension;
+                            let mut fft = Fft::new(config, &hw);
+                            // the rounds alternate between tmp and output, so with more than
+                            // two the output also holds the values of the earlier odd rounds
+                            let output_role = if fft.num_rounds() > 2 {
+                                RegionRole::Scratch
+                            } else {
+                                RegionRole::Output
+                            };
+                            let regions = [
+                                Region::new(
+                                    "input",
+                                    BASE,
+                                    k * (n >> rate_bits),
+                                    sz,
+                                    RegionRole::Input,
+                                ),
+                                Region::new("tmp", BASE + GAP, k * n, sz, RegionRole::Scratch),
+                                Region::new("output", BASE + 2 * GAP, k * n, sz, output_role),
+                            ];
+                            fft.prefetch.elems = Some(vec![]);
+                            fft.drain.elems = Some(vec![]);
+                            fft.init();
+                            assert_valid(
+                                Conservation::check_kernel(&fft, &regions),
+                                format!("{:?} {:?}", config, hw.arch),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tree_conservation() {
+        for hw in hws() {
+            for (lg_leaves, cap_height) in [(1, 0), (4, 2), (8, 0), (8, 4), (10, 2)] {
+                for leaf_length in [4, 12, 135] {
+                    for transposed_leaves in [false, true] {
+                        let num_leaves = 1 << lg_leaves;
+                        let config = TreeConfig {
+                            leaf_length,
+                            cap_height,
+                            num_leaves,
+                            addr_leaves: BASE,
+                            addr_transposed_leaves: BASE + GAP,
+                            addr_digest_buf: BASE + 2 * GAP,
+                            addr_cap_buf: BASE + 3 * GAP,
+                            transposed_leaves,
+                        };
+                        let num_digests = Tree::num_digests(num_leaves, cap_height);
+                        let num_caps = Tree::num_caps(cap_height);
+                        let mut regions = vec![
+                            Region::new(
+                                "leaves",
+                                BASE,
+                                num_leaves * leaf_length,
+                                SIZE_F,
+                                RegionRole::Input,
+                            ),
+                            Region::new(
+                                "digests",
+                                BASE + 2 * GAP,
+                                num_digests * Tree::DIGEST_LENGTH,
+                                SIZE_F,
+                                RegionRole::Output,
+                            ),
+                            Region::new(
+                                "cap",
+                                BASE + 3 * GAP,
+                                num_caps * Tree::DIGEST_LENGTH,
+                                SIZE_F,
+                                RegionRole::Output,
+                            ),
+                        ];
+                        if transposed_leaves {
+                            regions.push(Region::new(
+                                "transposed_leaves",
+                                BASE + GAP,
+                                num_leaves * leaf_length,
+                                SIZE_F,
+                                RegionRole::Output,
+                            ));
+                        }
+                        let mut tree = Tree::new(config.clone(), &hw);
+                        tree.prefetch.elems = Some(vec![]);
+                        tree.drain.elems = Some(vec![]);
+                        tree.init();
+                        assert_valid(
+                            Conservation::check_kernel(&tree, &regions),
+                            format!("{:?} {:?}", config, hw.arch),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_transpose_conservation() {
+        for hw in hws() {
+            for (width, height) in [(4, 4), (8, 32), (64, 100), (256, 16), (16, 1)] {
+                for extension in [1, 2] {
+                    for reverse in [false, true] {
+                        for (start, end) in [(0, width), (width / 4, width / 2)] {
+                            let config = TransposeConfig {
+                                addr_input: BASE,
+                                addr_output: BASE + GAP,
+                                width,
+                                height,
+                                start,
+                                end,
+                                reverse,
+                                extension,
+                            };
+                            // the columns start..end of the input, the rows of the output
+                            // are indexed by the column
+                            let sz = SIZE_F * extension;
+                            let mut regions = (0..height)
+                                .map(|i| {
+                                    let addr = BASE + (i * width + start) * sz;
+                                    Region::new("input", addr, end - start, sz, RegionRole::Input)
+                                })
+                                .collect::<Vec<_>>();
+                            regions.extend((start..end).map(|j| {
+                                let j = if reverse {
+                                    bit_reverse(j, log2_strict(width))
+                                } else {
+                                    j
+                                };
+                                let addr = BASE + GAP + j * height * sz;
+                                Region::new("output", addr, height, sz, RegionRole::Output)
+                            }));
+                            let mut transpose = Transpose::new(config, &hw);
+                            transpose.prefetch.elems = Some(vec![]);
+                            transpose.drain.elems = Some(vec![]);
+                            transpose.init();
+                            assert_valid(
+                                Conservation::check_kernel(&transpose, &regions),
+                                format!("{:?} {:?}", config, hw.arch),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::ram_config::OpRecord;
+use crate::config::ArchConfig;
+use crate::util::BUFSIZE;
+
+// version 1 traces only have this magic word in front of the records
+pub const LEGACY_MAGIC: &[u8; BUFSIZE] = b"BINFILE\0";
+pub const TRACE_MAGIC: &[u8; BUFSIZE] = b"UZKTRACE";
+pub const TRACE_VERSION: u64 = 2;
+
+// the record count of a trace that is still being written, or was compressed
+pub const UNKNOWN_COUNT: u64 = u64::MAX;
+// records between two entries of the footer index
+pub const INDEX_INTERVAL: u64 = 1 << 16;
+
+// magic, version, header length, record count, index offset, metadata length
+const FIXED_LEN: u64 = 6 * BUFSIZE as u64;
+const NUM_RECORDS_OFFSET: u64 = 3 * BUFSIZE as u64;
+
+fn read_u64<R: Read>(r: &mut R) -> Result<u64> {
+    let mut buf = [0u8; BUFSIZE];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn padded(len: u64) -> u64 {
+    len.div_ceil(BUFSIZE as u64) * BUFSIZE as u64
+}
+
+// bytes of a record in the trace
+pub fn record_len(op: &OpRecord) -> u64 {
+    ((6 + op.dependencies.len()) * BUFSIZE) as u64
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TraceMetadata {
+    pub workload: String,
+    pub arch: Option<ArchConfig>,
+    pub created_unix: u64,
+    pub creator: String,
+}
+
+impl TraceMetadata {
+    pub fn new(workload: &str) -> Self {
+        Self {
+            workload: workload.to_string(),
+            arch: None,
+            created_unix: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            creator: format!("unizk {}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+/// Layout of a version 2 trace, every field is a little endian u64:
+///
+/// magic | version | header length | record count | index offset | metadata length |
+/// metadata as JSON, padded to 8 bytes | records | index
+///
+/// The index offset is 0 if the trace has no index, otherwise the records end there.
+/// The index is the number of entries followed by (record id, byte offset) pairs, one
+/// every INDEX_INTERVAL records. Both are patched in by write_footer.
+#[derive(Debug, Clone)]
+pub struct TraceHeader {
+    pub version: u64,
+    pub header_len: u64,
+    pub num_records: Option<u64>,
+    pub index_offset: Option<u64>,
+    pub metadata: TraceMetadata,
+}
+
+impl TraceHeader {
+    pub fn new(metadata: &TraceMetadata) -> Self {
+        let json = serde_json::to_vec(metadata).unwrap();
+        Self {
+            version: TRACE_VERSION,
+            header_len: FIXED_LEN + padded(json.len() as u64),
+            num_records: None,
+            index_offset: None,
+            metadata: metadata.clone(),
+        }
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        let mut json = serde_json::to_vec(&self.metadata)?;
+        let json_len = json.len() as u64;
+        json.resize(padded(json_len) as usize, 0);
+        ensure!(FIXED_LEN + json.len() as u64 == self.header_len);
+
+        w.write_all(TRACE_MAGIC)?;
+        for field in [
+            self.version,
+            self.header_len,
+            self.num_records.unwrap_or(UNKNOWN_COUNT),
+            self.index_offset.unwrap_or(0),
+            json_len,
+        ] {
+            w.write_all(&field.to_le_bytes())?;
+        }
+        w.write_all(&json)?;
+        Ok(())
+    }
+
+    // reads exactly header_len bytes, the records follow
+    pub fn read<R: Read>(r: &mut R) -> Result<Self> {
+        let mut magic = [0u8; BUFSIZE];
+        r.read_exact(&mut magic)
+            .context("trace is shorter than the magic word")?;
+        if magic == *LEGACY_MAGIC {
+            return Ok(Self {
+                version: 1,
+                header_len: BUFSIZE as u64,
+                num_records: None,
+                index_offset: None,
+                metadata: TraceMetadata::default(),
+            });
+        }
+        ensure!(magic == *TRACE_MAGIC, "not a trace file");
+        let version = read_u64(r)?;
+        if version != TRACE_VERSION {
+            bail!("unsupported trace version {}", version);
+        }
+        let header_len = read_u64(r)?;
+        let num_records = Some(read_u64(r)?).filter(|&n| n != UNKNOWN_COUNT);
+        let index_offset = Some(read_u64(r)?).filter(|&o| o != 0);
+        let json_len = read_u64(r)?;
+        ensure!(
+            header_len == FIXED_LEN + padded(json_len),
+            "invalid trace header length {}",
+            header_len
+        );
+        let mut json = vec![0u8; (header_len - FIXED_LEN) as usize];
+        r.read_exact(&mut json)?;
+        let metadata =
+            serde_json::from_slice(&json[..json_len as usize]).context("invalid trace metadata")?;
+        Ok(Self {
+            version,
+            header_len,
+            num_records,
+            index_offset,
+            metadata,
+        })
+    }
+}
+
+// tracks the record count and byte offsets while a trace is written
+#[derive(Debug, Clone, Default)]
+pub struct TraceIndex {
+    pub num_records: u64,
+    pub entries: Vec<(u64, u64)>, // (record id, byte offset)
+    pub end: u64,                 // byte offset after the last record
+}
+
+impl TraceIndex {
+    pub fn new(header: &TraceHeader) -> Self {
+        Self {
+            end: header.header_len,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, op: &OpRecord) {
+        if self.num_records.is_multiple_of(INDEX_INTERVAL) {
+            self.entries.push((op.id, self.end));
+        }
+        self.num_records += 1;
+        self.end += record_len(op);
+    }
+
+    pub fn read<R: Read + Seek>(r: &mut R, header: &TraceHeader) -> Result<Vec<(u64, u64)>> {
+        let Some(offset) = header.index_offset else {
+            return Ok(vec![]);
+        };
+        r.seek(SeekFrom::Start(offset))?;
+        let num_entries = read_u64(r)?;
+        (0..num_entries)
+            .map(|_| Ok((read_u64(r)?, read_u64(r)?)))
+            .collect()
+    }
+}
+
+/// Append the index after the last record and patch the record count and index
+/// offset of the header. The file is left at the end of the records, see
+/// clear_footer.
+pub fn write_footer<F: Write + Seek>(f: &mut F, index: &TraceIndex) -> Result<()> {
+    f.seek(SeekFrom::Start(index.end))?;
+    f.write_all(&(index.entries.len() as u64).to_le_bytes())?;
+    for (id, offset) in index.entries.iter() {
+        f.write_all(&id.to_le_bytes())?;
+        f.write_all(&offset.to_le_bytes())?;
+    }
+    f.seek(SeekFrom::Start(NUM_RECORDS_OFFSET))?;
+    f.write_all(&index.num_records.to_le_bytes())?;
+    f.write_all(&index.end.to_le_bytes())?;
+    f.seek(SeekFrom::Start(index.end))?;
+    Ok(())
+}
+
+// undo write_footer before more records are appended
+pub fn clear_footer<F: Write + Seek>(f: &mut F, index: &TraceIndex) -> Result<()> {
+    f.seek(SeekFrom::Start(NUM_RECORDS_OFFSET))?;
+    f.write_all(&UNKNOWN_COUNT.to_le_bytes())?;
+    f.write_all(&0u64.to_le_bytes())?;
+    f.seek(SeekFrom::Start(index.end))?;
+    Ok(())
+}
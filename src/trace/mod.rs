@@ -1 +1,5 @@
-pub mod trace;
\ No newline at end of file
+pub mod trace;
+pub mod sink;
+pub mod reader;
+pub mod header;
+pub mod conservation;
\ No newline at end of file
@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+
+use crate::config::ram_config::OpRecord;
+use crate::trace::header::{TraceHeader, TraceIndex, TraceMetadata};
+use crate::trace::trace::FetchType;
+use crate::util::BUFSIZE;
+
+/// Reads back the traces written by BinSink and ZstdSink, both version 2 and the
+/// legacy BINFILE traces. Phases are not stored in the trace, so every op has phase 0.
+pub struct TraceReader<R: Read> {
+    reader: R,
+    num_ops: u64,
+    offset: u64, // bytes read so far
+    pub header: TraceHeader,
+}
+
+impl TraceReader<Box<dyn Read>> {
+    // .zst files are decompressed on the fly
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let reader: Box<dyn Read> = if path.extension().is_some_and(|e| e == "zst") {
+            Box::new(zstd::Decoder::new(file)?)
+        } else {
+            Box::new(BufReader::new(file))
+        };
+        Self::new(reader).with_context(|| format!("invalid trace {}", path.display()))
+    }
+
+    /// Open the trace at op `start`. Uncompressed traces with an index seek to the
+    /// closest indexed op first, the others read all ops before `start`.
+    pub fn open_at<P: AsRef<Path>>(path: P, start: u64) -> Result<Self> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|e| e == "zst") {
+            return Self::open(path)?.skip_ops(start);
+        }
+        let mut file = BufReader::new(File::open(path)?);
+        let header = TraceHeader::read(&mut file)
+            .with_context(|| format!("invalid trace {}", path.display()))?;
+        let index = TraceIndex::read(&mut file, &header)?;
+        let (id, offset) = index
+            .iter()
+            .take_while(|x| x.0 <= start)
+            .last()
+            .copied()
+            .unwrap_or((0, header.header_len));
+        file.seek(SeekFrom::Start(offset))?;
+        let reader = Self {
+            reader: Box::new(file) as Box<dyn Read>,
+            num_ops: id,
+            offset,
+            header,
+        };
+        reader.skip_ops(start - id)
+    }
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let header = TraceHeader::read(&mut reader)?;
+        Ok(Self {
+            reader,
+            num_ops: 0,
+            offset: header.header_len,
+            header,
+        })
+    }
+
+    fn skip_ops(mut self, n: u64) -> Result<Self> {
+        for _ in 0..n {
+            if self.read_op()?.is_none() {
+                break;
+            }
+        }
+        Ok(self)
+    }
+
+    // None at the end of the trace
+    fn read_field(&mut self) -> Result<Option<u64>> {
+        let mut buf = [0u8; BUFSIZE];
+        let mut len = 0;
+        while len < BUFSIZE {
+            match self.reader.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.offset += len as u64;
+        match len {
+            0 => Ok(None),
+            BUFSIZE => Ok(Some(u64::from_le_bytes(buf))),
+            _ => bail!("trace is truncated"),
+        }
+    }
+
+    fn field(&mut self, name: &str) -> Result<u64> {
+        self.read_field()?
+            .ok_or_else(|| anyhow!("trace is truncated in the {} of op {}", name, self.num_ops))
+    }
+
+    fn field_u32(&mut self, name: &str) -> Result<u32> {
+        let value = self.field(name)?;
+        u32::try_from(value)
+            .map_err(|_| anyhow!("{} {} of op {} is too large", name, value, self.num_ops))
+    }
+
+    fn read_op(&mut self) -> Result<Option<OpRecord>> {
+        // the index follows the records
+        if self
+            .header
+            .index_offset
+            .is_some_and(|end| self.offset >= end)
+        {
+            return Ok(None);
+        }
+        let Some(id) = self.read_field()? else {
+            return Ok(None);
+        };
+        let addr = self.field("addr")?;
+        let fetch_type = match self.field("fetch_type")? {
+            0 => FetchType::Read,
+            1 => FetchType::Write,
+            t => bail!("invalid fetch_type {} of op {}", t, self.num_ops),
+        };
+        let delay = self.field_u32("delay")?;
+        let size = self.field_u32("size")?;
+        let num_deps = self.field("dependency count")?;
+        let dependencies = (0..num_deps)
+            .map(|_| self.field("dependencies"))
+            .collect::<Result<Vec<_>>>()?;
+        self.num_ops += 1;
+        Ok(Some(OpRecord {
+            id,
+            addr,
+            fetch_type,
+            delay,
+            dependencies,
+            size,
+            phase: 0,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = Result<OpRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_op().transpose()
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TraceSummary {
+    pub version: u64,
+    pub metadata: TraceMetadata,
+    pub num_ops: usize,
+    pub num_reads: usize,
+    pub num_writes: usize,
+    pub read_bytes: usize,
+    pub write_bytes: usize,
+    pub num_dependencies: usize,
+    pub total_delay: u64,
+    // longest chain of dependent ops
+    pub max_dependency_depth: u32,
+    // log2 of the request size -> number of requests
+    pub size_histogram: BTreeMap<usize, usize>,
+    pub num_errors: usize,
+    pub errors: Vec<String>, // the first MAX_ERRORS errors
+
+    #[serde(skip)]
+    depths: Vec<u32>,
+}
+
+impl TraceSummary {
+    pub const MAX_ERRORS: usize = 16;
+
+    /// Validate and summarize a whole trace. Invalid ops are counted in num_errors,
+    /// a trace that can't be parsed at all is an Err.
+    pub fn from_reader<R: Read>(reader: TraceReader<R>) -> Result<Self> {
+        let mut summary = Self {
+            version: reader.header.version,
+            metadata: reader.header.metadata.clone(),
+            ..Default::default()
+        };
+        let num_records = reader.header.num_records;
+        for op in reader {
+            summary.add(&op?);
+        }
+        if let Some(n) = num_records.filter(|&n| n != summary.num_ops as u64) {
+            summary.error(format!(
+                "header has {} ops, but the trace has {}",
+                n, summary.num_ops
+            ));
+        }
+        Ok(summary)
+    }
+
+    fn error(&mut self, msg: String) {
+        self.num_errors += 1;
+        if self.errors.len() < Self::MAX_ERRORS {
+            self.errors.push(msg);
+        }
+    }
+
+    pub fn add(&mut self, op: &OpRecord) {
+        // RamSim requires continuous ids starting from 0
+        let expected = self.num_ops as u64;
+        if op.id != expected {
+            self.error(format!(
+                "op {} has id {}, expected {}",
+                self.num_ops, op.id, expected
+            ));
+        }
+        if op.size == 0 {
+            self.error(format!("op {} has size 0", op.id));
+        }
+        let mut depth = 0;
+        for &dep in op.dependencies.iter() {
+            if dep >= op.id {
+                self.error(format!("op {} depends on a later op {}", op.id, dep));
+            } else if let Some(&d) = self.depths.get(dep as usize) {
+                depth = depth.max(d);
+            }
+        }
+        self.depths.push(depth + 1);
+        self.max_dependency_depth = self.max_dependency_depth.max(depth + 1);
+
+        self.num_ops += 1;
+        self.num_dependencies += op.dependencies.len();
+        self.total_delay += op.delay as u64;
+        match op.fetch_type {
+            FetchType::Read => {
+                self.num_reads += 1;
+                self.read_bytes += op.size as usize;
+            }
+            FetchType::Write => {
+                self.num_writes += 1;
+                self.write_bytes += op.size as usize;
+            }
+        }
+        let lg_size = (op.size as usize).next_power_of_two().trailing_zeros() as usize;
+        *self.size_histogram.entry(lg_size).or_insert(0) += 1;
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.num_errors == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::header::{LEGACY_MAGIC, TraceMetadata};
+    use crate::trace::sink::{write_record, BinSink, TraceSink, ZstdSink};
+
+    fn op(id: u64, dependencies: Vec<u64>, size: u32) -> OpRecord {
+        OpRecord {
+            id,
+            addr: id * 64,
+            fetch_type: if id % 2 == 0 {
+                FetchType::Read
+            } else {
+                FetchType::Write
+            },
+            delay: 1,
+            dependencies,
+            size,
+            phase: 0,
+        }
+    }
+
+    #[test]
+    fn test_trace_reader() -> Result<()> {
+        let ops = vec![op(0, vec![], 64), op(1, vec![0], 128), op(2, vec![1], 64)];
+        let metadata = TraceMetadata::new("test_trace_reader");
+        let mut bin = BinSink::create("./traces/test_trace_reader.bin", &metadata)?;
+        let mut zst = ZstdSink::create("./traces/test_trace_reader.bin.zst", 3, &metadata)?;
+        for op in ops.iter() {
+            bin.write(op)?;
+            zst.write(op)?;
+        }
+        bin.finish()?;
+        zst.finish()?;
+
+        for path in [
+            "./traces/test_trace_reader.bin",
+            "./traces/test_trace_reader.bin.zst",
+        ] {
+            let read = TraceReader::open(path)?.collect::<Result<Vec<_>>>()?;
+            assert_eq!(read, ops);
+        }
+        // what RamSim reads from the pipe
+        let stream = TraceReader::new(zst.trace_stream()?.unwrap())?;
+        assert_eq!(stream.collect::<Result<Vec<_>>>()?, ops);
+        let summary =
+            TraceSummary::from_reader(TraceReader::open("./traces/test_trace_reader.bin")?)?;
+        assert!(summary.is_valid());
+        assert_eq!(summary.version, 2);
+        assert_eq!(summary.metadata.workload, "test_trace_reader");
+        assert_eq!((summary.num_reads, summary.read_bytes), (2, 128));
+        assert_eq!((summary.num_writes, summary.write_bytes), (1, 128));
+        assert_eq!(summary.max_dependency_depth, 3);
+        assert_eq!(summary.size_histogram, BTreeMap::from([(6, 2), (7, 1)]));
+
+        let reader = TraceReader::open_at("./traces/test_trace_reader.bin", 1)?;
+        assert_eq!(reader.header.num_records, Some(3));
+        assert_eq!(reader.collect::<Result<Vec<_>>>()?, ops[1..]);
+        let reader = TraceReader::open_at("./traces/test_trace_reader.bin.zst", 2)?;
+        assert_eq!(reader.header.num_records, None);
+        assert_eq!(reader.collect::<Result<Vec<_>>>()?, ops[2..]);
+
+        // legacy trace without header
+        let mut buf = LEGACY_MAGIC.to_vec();
+        for op in [op(0, vec![], 64), op(2, vec![2], 0)] {
+            write_record(&mut buf, &op)?;
+        }
+        let summary = TraceSummary::from_reader(TraceReader::new(&buf[..])?)?;
+        assert_eq!(summary.version, 1);
+        assert_eq!(summary.num_errors, 3);
+
+        // truncated in the middle of an op
+        let reader = TraceReader::new(&buf[..buf.len() - 8])?;
+        assert!(TraceSummary::from_reader(reader).is_err());
+        assert!(TraceReader::new(&b"NOTBIN\0\0"[..]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,434 @@
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::config::ram_config::OpRecord;
+use crate::config::ramsim_lib::RamSimLib;
+use crate::trace::header::{clear_footer, write_footer, TraceHeader, TraceIndex, TraceMetadata};
+use crate::util::BUFSIZE;
+
+/// Destination of the OpRecords of RamConfig::write_trace.
+pub trait TraceSink {
+    fn write(&mut self, op: &OpRecord) -> Result<()>;
+
+    // drop everything written so far, used by RamConfig::reset
+    fn reset(&mut self) -> Result<()>;
+
+    /// Flush the trace, and return the path of a binary trace RamSim can read if the
+    /// sink has one.
+    fn finish(&mut self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    // called after RamSim has read the trace returned by finish
+    fn release(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The binary trace as a stream RamSim reads through a pipe, for sinks that only
+    /// have it compressed. Called after finish.
+    fn trace_stream(&mut self) -> Result<Option<Box<dyn Read + Send>>> {
+        Ok(None)
+    }
+
+    // metadata of the trace header, set before the first op is written
+    fn set_metadata(&mut self, _metadata: &TraceMetadata) -> Result<()> {
+        Ok(())
+    }
+
+    // the files the sink writes, removed by the sweep unless it keeps the traces
+    fn output_paths(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    // sinks that simulate the ops themselves return the RamSim output
+    fn simulate(&mut self) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+fn to_buf(a: &[u8]) -> [u8; BUFSIZE] {
+    let mut buf = [0; BUFSIZE];
+    buf[..a.len()].copy_from_slice(a);
+    buf
+}
+
+// every field is padded to 8 bytes, see TraceInputStream in trace_gen.cpp
+pub fn write_record<W: Write>(w: &mut W, op: &OpRecord) -> Result<()> {
+    w.write_all(&to_buf(&op.id.to_le_bytes()))?;
+    w.write_all(&to_buf(&op.addr.to_le_bytes()))?;
+    w.write_all(&to_buf(&(op.fetch_type as u32).to_le_bytes()))?;
+    w.write_all(&to_buf(&op.delay.to_le_bytes()))?;
+    w.write_all(&to_buf(&op.size.to_le_bytes()))?;
+    w.write_all(&to_buf(&(op.dependencies.len() as u64).to_le_bytes()))?;
+    for dep in op.dependencies.iter() {
+        w.write_all(&to_buf(&dep.to_le_bytes()))?;
+    }
+    Ok(())
+}
+
+pub fn write_text<W: Write>(w: &mut W, op: &OpRecord) -> Result<()> {
+    writeln!(w, "id: {}", op.id)?;
+    writeln!(w, "addr: {}", op.addr)?;
+    writeln!(w, "fetch_type: {:?}", op.fetch_type)?;
+    writeln!(w, "delay: {}", op.delay)?;
+    writeln!(w, "size: {}", op.size)?;
+    writeln!(w, "phase: {}", op.phase)?;
+    write!(w, "dependencies: ")?;
+    for dep in op.dependencies.iter() {
+        write!(w, "{}, ", dep)?;
+    }
+    writeln!(w)?;
+    Ok(())
+}
+
+// the binary trace read by RamSim, see header.rs for the layout
+pub struct BinSink {
+    path: String,
+    writer: BufWriter<File>,
+    header: TraceHeader,
+    index: TraceIndex,
+    // the footer is removed again by the next write
+    finished: bool,
+}
+
+impl BinSink {
+    pub fn create(path: &str, metadata: &TraceMetadata) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("failed to create {}", path))?;
+        let header = TraceHeader::new(metadata);
+        let mut writer = BufWriter::new(file);
+        header.write(&mut writer)?;
+        Ok(Self {
+            path: path.to_string(),
+            writer,
+            index: TraceIndex::new(&header),
+            header,
+            finished: false,
+        })
+    }
+}
+
+impl TraceSink for BinSink {
+    fn write(&mut self, op: &OpRecord) -> Result<()> {
+        if self.finished {
+            clear_footer(&mut self.writer, &self.index)?;
+            self.writer.flush()?;
+            self.writer.get_ref().set_len(self.index.end)?;
+            self.finished = false;
+        }
+        self.index.add(op);
+        write_record(&mut self.writer, op)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().set_len(0)?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.index = TraceIndex::new(&self.header);
+        self.finished = false;
+        self.header.write(&mut self.writer)
+    }
+
+    fn finish(&mut self) -> Result<Option<String>> {
+        if !self.finished {
+            write_footer(&mut self.writer, &self.index)?;
+            self.finished = true;
+        }
+        self.writer.flush()?;
+        Ok(Some(self.path.clone()))
+    }
+
+    fn output_paths(&self) -> Vec<String> {
+        vec![self.path.clone()]
+    }
+
+    fn set_metadata(&mut self, metadata: &TraceMetadata) -> Result<()> {
+        ensure!(
+            self.index.num_records == 0,
+            "trace metadata must be set before the first op"
+        );
+        self.header = TraceHeader::new(metadata);
+        self.reset()
+    }
+}
+
+// human readable trace, only for debugging small workloads
+pub struct TextSink {
+    path: String,
+    writer: BufWriter<File>,
+}
+
+impl TextSink {
+    pub fn create(path: &str) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("failed to create {}", path))?;
+        Ok(Self {
+            path: path.to_string(),
+            writer: BufWriter::new(file),
+        })
+    }
+}
+
+impl TraceSink for TextSink {
+    fn write(&mut self, op: &OpRecord) -> Result<()> {
+        write_text(&mut self.writer, op)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().set_len(0)?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<Option<String>> {
+        self.writer.flush()?;
+        Ok(None)
+    }
+
+    fn output_paths(&self) -> Vec<String> {
+        vec![self.path.clone()]
+    }
+}
+
+// keeps the ops in memory, clones share the same ops
+#[derive(Clone, Default)]
+pub struct MemorySink {
+    pub ops: Rc<RefCell<Vec<OpRecord>>>,
+}
+
+impl TraceSink for MemorySink {
+    fn write(&mut self, op: &OpRecord) -> Result<()> {
+        self.ops.borrow_mut().push(op.clone());
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.ops.borrow_mut().clear();
+        Ok(())
+    }
+}
+
+// drops the ops, only counts them
+#[derive(Debug, Default)]
+pub struct NullSink {
+    pub num_ops: usize,
+    pub bytes: usize,
+}
+
+impl TraceSink for NullSink {
+    fn write(&mut self, op: &OpRecord) -> Result<()> {
+        self.num_ops += 1;
+        self.bytes += op.size as usize;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.num_ops = 0;
+        self.bytes = 0;
+        Ok(())
+    }
+}
+
+/// Binary trace compressed with zstd. RamSim can't read it directly, so RamConfig::run
+/// pipes it in decompressed by trace_stream, without a decompressed copy on disk. The
+/// compressed trace has no record count or index, RamSim reads it to the end.
+pub struct ZstdSink {
+    path: String,
+    level: i32,
+    metadata: TraceMetadata,
+    index: TraceIndex,
+    // None after finish, the next write appends a new zstd frame
+    encoder: Option<zstd::Encoder<'static, BufWriter<File>>>,
+}
+
+impl ZstdSink {
+    pub fn create(path: &str, level: i32, metadata: &TraceMetadata) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("failed to create {}", path))?;
+        let mut encoder = zstd::Encoder::new(BufWriter::new(file), level)?;
+        let header = TraceHeader::new(metadata);
+        header.write(&mut encoder)?;
+        Ok(Self {
+            path: path.to_string(),
+            level,
+            metadata: metadata.clone(),
+            index: TraceIndex::new(&header),
+            encoder: Some(encoder),
+        })
+    }
+
+    fn encoder(&mut self) -> Result<&mut zstd::Encoder<'static, BufWriter<File>>> {
+        if self.encoder.is_none() {
+            let file = OpenOptions::new().append(true).open(&self.path)?;
+            self.encoder = Some(zstd::Encoder::new(BufWriter::new(file), self.level)?);
+        }
+        Ok(self.encoder.as_mut().unwrap())
+    }
+}
+
+impl TraceSink for ZstdSink {
+    fn write(&mut self, op: &OpRecord) -> Result<()> {
+        self.index.add(op);
+        write_record(self.encoder()?, op)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        *self = Self::create(&self.path, self.level, &self.metadata)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<Option<String>> {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.finish()?.flush()?;
+        }
+        Ok(None)
+    }
+
+    // the frames appended after a finish are decoded one after the other
+    fn trace_stream(&mut self) -> Result<Option<Box<dyn Read + Send>>> {
+        let file =
+            File::open(&self.path).with_context(|| format!("failed to open {}", self.path))?;
+        Ok(Some(Box::new(zstd::Decoder::new(file)?)))
+    }
+
+    fn output_paths(&self) -> Vec<String> {
+        vec![self.path.clone()]
+    }
+
+    fn set_metadata(&mut self, metadata: &TraceMetadata) -> Result<()> {
+        ensure!(
+            self.index.num_records == 0,
+            "trace metadata must be set before the first op"
+        );
+        self.metadata = metadata.clone();
+        self.reset()
+    }
+}
+
+/// Streams the ops into RamSim in this process instead of writing a trace, see
+/// RamSimLib. The simulation can't be rewound, so reset starts a new one.
+pub struct RamSimSink {
+    lib_path: String,
+    config_path: String,
+    sim: RamSimLib,
+    phase: Option<usize>,
+    output: Option<String>,
+}
+
+impl RamSimSink {
+    pub fn create(lib_path: &str, config_path: &str) -> Result<Self> {
+        Ok(Self {
+            lib_path: lib_path.to_string(),
+            config_path: config_path.to_string(),
+            sim: RamSimLib::load(lib_path, config_path, &[])?,
+            phase: None,
+            output: None,
+        })
+    }
+}
+
+impl TraceSink for RamSimSink {
+    fn write(&mut self, op: &OpRecord) -> Result<()> {
+        if self.phase != Some(op.phase) {
+            self.sim.phase(op.id, op.phase);
+            self.phase = Some(op.phase);
+        }
+        self.sim.send(op)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        *self = Self::create(&self.lib_path, &self.config_path)?;
+        Ok(())
+    }
+
+    fn simulate(&mut self) -> Result<Option<String>> {
+        if self.output.is_none() {
+            self.output = Some(self.sim.finish()?);
+        }
+        Ok(self.output.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::trace::FetchType;
+
+    #[test]
+    fn test_trace_sinks() -> Result<()> {
+        let ops = (0..100)
+            .map(|i| OpRecord {
+                id: i,
+                addr: i * 4096,
+                fetch_type: if i % 3 == 0 {
+                    FetchType::Write
+                } else {
+                    FetchType::Read
+                },
+                delay: i as u32,
+                dependencies: (0..i).rev().take(2).collect(),
+                size: 64,
+                phase: 0,
+            })
+            .collect::<Vec<_>>();
+
+        let metadata = TraceMetadata::new("test_sink");
+        let header_len = TraceHeader::new(&metadata).header_len as usize;
+        let mut bin = BinSink::create("./traces/test_sink.bin", &metadata)?;
+        let mut zst = ZstdSink::create("./traces/test_sink.bin.zst", 3, &metadata)?;
+        let mut mem = MemorySink::default();
+        let mut null = NullSink::default();
+        for op in ops.iter() {
+            bin.write(op)?;
+            zst.write(op)?;
+            mem.clone().write(op)?;
+            null.write(op)?;
+        }
+        let bin_path = bin.finish()?.unwrap();
+        assert_eq!(zst.finish()?, None);
+        let expected = std::fs::read(&bin_path)?;
+        // header, records and an index with one entry
+        let records_end = header_len + 100 * 6 * 8 + 197 * 8;
+        assert_eq!(expected.len(), records_end + 3 * 8);
+        // the stream has the records, but neither their count nor the index
+        let streamed = |zst: &mut ZstdSink| -> Result<Vec<u8>> {
+            let mut bytes = Vec::new();
+            zst.trace_stream()?.unwrap().read_to_end(&mut bytes)?;
+            Ok(bytes)
+        };
+        let stream = streamed(&mut zst)?;
+        assert_eq!(stream[header_len..], expected[header_len..records_end]);
+        assert_eq!(TraceHeader::read(&mut &stream[..])?.num_records, None);
+        assert!(std::fs::metadata("./traces/test_sink.bin.zst")?.len() < expected.len() as u64);
+        assert_eq!(*mem.ops.borrow(), ops);
+        assert_eq!((null.num_ops, null.bytes), (100, 6400));
+        assert_eq!(zst.output_paths(), ["./traces/test_sink.bin.zst"]);
+        assert!(null.output_paths().is_empty());
+
+        // writing after finish appends a new frame
+        zst.write(&ops[0])?;
+        zst.finish()?;
+        assert_eq!(streamed(&mut zst)?.len(), stream.len() + 6 * 8);
+
+        // writing after finish moves the footer of the binary trace
+        bin.write(&ops[0])?;
+        bin.finish()?;
+        assert_eq!(
+            streamed(&mut zst)?[header_len..],
+            std::fs::read(&bin_path)?[header_len..records_end + 6 * 8]
+        );
+
+        bin.reset()?;
+        zst.reset()?;
+        let empty = std::fs::read(bin.finish()?.unwrap())?;
+        assert_eq!(empty.len(), header_len + 8);
+        zst.finish()?;
+        assert_eq!(streamed(&mut zst)?.len(), header_len);
+        let header = TraceHeader::read(&mut &empty[..])?;
+        assert_eq!(header.num_records, Some(0));
+        assert_eq!(header.metadata.workload, "test_sink");
+        Ok(())
+    }
+}
@@ -1,8 +1,10 @@
 use std::ops::Index;
 
-use crate::{kernel::memory_copy::MemCpy, system::system::find_consecutive_segments, util::SIZE_F};
+use crate::{
+    kernel::memory_copy::MemCpyConfig, system::system::find_consecutive_segments, util::SIZE_F,
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FetchType {
     Read,
     Write,
@@ -21,6 +23,9 @@ pub struct Fetch {
     pub mergable: bool,
     pub delay: Vec<usize>,
     pub interval: f32, // cycles interval between two request lines
+    // the element addresses of each block before push merges them, only kept if
+    // Some before the kernel is initialized, see trace::conservation
+    pub elems: Option<Vec<Vec<usize>>>,
 }
 
 impl Fetch {
@@ -33,18 +38,24 @@ impl Fetch {
             mergable: false,
             delay: Vec::new(),
             interval: 1.0,
+            elems: None,
         }
     }
 
     pub fn push(&mut self, fetch: Vec<Vec<usize>>) {
-        self.addr.push(find_consecutive_segments(
-            &fetch.into_iter().flatten().collect(),
-        ));
+        let fetch = fetch.into_iter().flatten().collect();
+        self.addr.push(find_consecutive_segments(&fetch));
+        if let Some(elems) = self.elems.as_mut() {
+            elems.push(fetch);
+        }
     }
 
     pub fn extend(&mut self, fetch: Fetch) {
         self.addr.extend(fetch.addr);
         self.delay.extend(fetch.delay);
+        if let (Some(elems), Some(other)) = (self.elems.as_mut(), fetch.elems) {
+            elems.extend(other);
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -55,7 +66,9 @@ impl Fetch {
         self.addr.iter().map(|x| x.len()).sum()
     }
 
-    pub fn addr_trans(&mut self, memcpy: &MemCpy) {
+    // the translations work on the segments, the elements are dropped
+    pub fn addr_trans(&mut self, memcpy: &MemCpyConfig) {
+        self.elems = None;
         self.addr.iter_mut().for_each(|x| {
             let mut x_new = x.clone();
             x.clear();
@@ -68,7 +81,8 @@ impl Fetch {
         })
     }
 
-    pub fn addr_trans_vec(&mut self, memcpys: &Vec<MemCpy>) {
+    pub fn addr_trans_vec(&mut self, memcpys: &[MemCpyConfig]) {
+        self.elems = None;
         self.addr.iter_mut().for_each(|x| {
             let mut x_new = x.clone();
             x.clear();
@@ -89,6 +103,9 @@ impl Fetch {
 
     pub fn clear(&mut self) {
         self.addr.clear();
+        if let Some(elems) = self.elems.as_mut() {
+            elems.clear();
+        }
         self.delay.clear();
         self.interval = 0.0;
     }
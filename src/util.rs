@@ -1,5 +1,5 @@
-use crate::config::{arch_config::ARCH_CONFIG, enable_config::ENABLE_CONFIG};
-use clap::{value_parser, Arg, Command};
+use crate::config::{Backend, HwConfig};
+use clap::{parser::ValueSource, value_parser, Arg, ArgAction, Command};
 use log::info;
 
 pub const SIZE_F: usize = 8;
@@ -50,10 +50,17 @@ pub fn log2(x: usize) -> usize {
     y - 1
 }
 
-pub fn set_config() {
+pub fn set_config() -> HwConfig {
     let args = Command::new("simulator_v2")
         .version("1.0")
         .about("Demonstrates command line argument parsing")
+        .arg(
+            Arg::new("hw")
+                .short('c')
+                .long("hw")
+                .help("Hardware description file (.yaml or .toml)")
+                .value_parser(value_parser!(String)),
+        )
         .arg(
             Arg::new("ram")
                 .short('r')
@@ -75,33 +82,100 @@ pub fn set_config() {
                 .default_value("-1")
                 .value_parser(value_parser!(i32)),
         )
+        .arg(
+            Arg::new("analytical")
+                .long("analytical")
+                .help("Estimate the time with the analytical model instead of RamSim")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("in-process")
+                .long("in-process")
+                .help("Run RamSim through libramulator.so instead of writing a trace file")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("analytical"),
+        )
+        .arg(
+            Arg::new("schedule-vec-ops")
+                .long("schedule-vec-ops")
+                .help("Reorder the ops of each vector chain, see kernel::vec_schedule")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("cse-vec-ops")
+                .long("cse-vec-ops")
+                .help("Drop duplicate and dead ops of gate evaluation, see kernel::vec_cse")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("symbolic-gates")
+                .long("symbolic-gates")
+                .help("Lower plonky2 gates by symbolic evaluation, see plonk::gate_lowering")
+                .action(ArgAction::SetTrue),
+        )
+        // positional workload arguments, e.g. the number of hashes of sha256_starky
+        .arg(Arg::new("workload").num_args(0..).hide(true))
         .get_matches();
     let ram_size: &usize = args.get_one::<usize>("ram").unwrap();
     let tiles: &usize = args.get_one::<usize>("tiles").unwrap();
     let enable: &i32 = args.get_one::<i32>("enable").unwrap();
 
-    unsafe {
-        ARCH_CONFIG.rdbuf_sz_kb = ram_size * 1024 / 2;
-        ARCH_CONFIG.wrbuf_sz_kb = ram_size * 1024 / 2;
-        ARCH_CONFIG.num_tiles = *tiles;
-
-        if *enable >= 0 {
-            ENABLE_CONFIG.fft = false;
-            ENABLE_CONFIG.hash = false;
-            ENABLE_CONFIG.other = false;
-            match enable {
-                0 => {
-                    ENABLE_CONFIG.fft = true;
-                }
-                1 => {
-                    ENABLE_CONFIG.hash = true;
-                }
-                _ => {
-                    panic!("Invalid enable option")
-                }
+    let hw_file = args.get_one::<String>("hw");
+    let mut hw = match hw_file {
+        Some(path) => HwConfig::from_file(path).unwrap_or_else(|e| panic!("{:#}", e)),
+        None => HwConfig::default(),
+    };
+    // with a hardware file, only explicitly given options override it
+    let is_set =
+        |id: &str| hw_file.is_none() || args.value_source(id) == Some(ValueSource::CommandLine);
+
+    if is_set("ram") {
+        hw.arch.rdbuf_sz_kb = ram_size * 1024 / 2;
+        hw.arch.wrbuf_sz_kb = ram_size * 1024 / 2;
+    }
+    if is_set("tiles") {
+        hw.arch.num_tiles = *tiles;
+    }
+
+    if *enable >= 0 {
+        hw.enable.fft = false;
+        hw.enable.hash = false;
+        hw.enable.other = false;
+        match enable {
+            0 => {
+                hw.enable.fft = true;
+            }
+            1 => {
+                hw.enable.hash = true;
+            }
+            _ => {
+                panic!("Invalid enable option")
             }
         }
+    }
 
-        println!("ARCH_CONFIG: {:?}", ARCH_CONFIG);
+    if args.get_flag("analytical") {
+        hw.timing.backend = Backend::Analytical;
+    }
+    if args.get_flag("in-process") {
+        hw.timing.backend = Backend::RamSimLib;
     }
-}
\ No newline at end of file
+    if args.get_flag("schedule-vec-ops") {
+        hw.opt.schedule_vec_ops = true;
+    }
+    if args.get_flag("cse-vec-ops") {
+        hw.opt.cse_vec_ops = true;
+    }
+    if args.get_flag("symbolic-gates") {
+        hw.opt.symbolic_gates = true;
+    }
+
+    hw.validate()
+        .unwrap_or_else(|e| panic!("Invalid hardware config: {:#}", e));
+
+    info!("ARCH_CONFIG: {:?}", hw.arch);
+    info!("ENABLE_CONFIG: {:?}", hw.enable);
+    info!("TIMING_CONFIG: {:?}", hw.timing);
+    info!("OPT_CONFIG: {:?}", hw.opt);
+    hw
+}
@@ -34,6 +34,7 @@ struct Request {
   std::function<void(Request&)> callback;
   uint64_t id; // memory request id, assigned by the trace frontend
   bool last_subid = false; // whether this is the last subrequest of a memory request
+  bool is_stat_updated = false; // whether the row buffer outcome has been counted
 
   Request(Addr_t addr, int type);
   Request(AddrVec_t addr_vec, int type);
@@ -21,9 +21,15 @@ class GenericDRAMController final : public IDRAMController, public Implementatio
 
     std::vector<IControllerPlugin*> m_plugins;
 
+    size_t s_num_read_reqs = 0;
+    size_t s_num_write_reqs = 0;
     size_t s_num_row_hits = 0;
     size_t s_num_row_misses = 0;
     size_t s_num_row_conflicts = 0;
+    size_t s_num_rejected_reqs = 0;     // requests not enqueued because the buffer is full
+    size_t s_num_busy_cycles = 0;       // a command is issued
+    size_t s_num_stall_cycles = 0;      // requests are buffered but none of them is ready
+    size_t s_num_idle_cycles = 0;       // no request is buffered
 
 
   public:
@@ -40,6 +46,16 @@ class GenericDRAMController final : public IDRAMController, public Implementatio
           m_plugins.push_back(create_child_ifce<IControllerPlugin>(*it));
         }
       }
+
+      register_stat(s_num_read_reqs).name("num_read_reqs");
+      register_stat(s_num_write_reqs).name("num_write_reqs");
+      register_stat(s_num_row_hits).name("num_row_hits");
+      register_stat(s_num_row_misses).name("num_row_misses");
+      register_stat(s_num_row_conflicts).name("num_row_conflicts");
+      register_stat(s_num_rejected_reqs).name("num_rejected_reqs");
+      register_stat(s_num_busy_cycles).name("num_busy_cycles");
+      register_stat(s_num_stall_cycles).name("num_stall_cycles");
+      register_stat(s_num_idle_cycles).name("num_idle_cycles");
     };
 
     void setup(IFrontEnd* frontend, IMemorySystem* memory_system) override {
@@ -77,6 +93,7 @@ class GenericDRAMController final : public IDRAMController, public Implementatio
       if (!is_success) {
         // We could not enqueue the request
         req.arrive = -1;
+        s_num_rejected_reqs++;
         return false;
       }
 
@@ -111,6 +128,8 @@ class GenericDRAMController final : public IDRAMController, public Implementatio
 
       // 4. Finally, issue the commands to serve the request
       if (request_found) {
+        s_num_busy_cycles++;
+        update_request_stats(req_it);
         // If we find a real request to serve
         m_dram->issue_command(req_it->command, req_it->addr_vec);
 
@@ -119,8 +138,9 @@ class GenericDRAMController final : public IDRAMController, public Implementatio
           if (req_it->type_id == Request::Type::Read) {
             req_it->depart = m_clk + m_dram->m_read_latency;
             pending.push_back(*req_it);
+            s_num_read_reqs++;
           } else if (req_it->type_id == Request::Type::Write) {
-            // TODO: Add code to update statistics
+            s_num_write_reqs++;
           }
           buffer->remove(req_it);
         } else {
@@ -130,12 +150,36 @@ class GenericDRAMController final : public IDRAMController, public Implementatio
           }
         }
 
+      } else if (m_active_buffer.size() + m_priority_buffer.size() + m_read_buffer.size() + m_write_buffer.size() != 0) {
+        s_num_stall_cycles++;
+      } else {
+        s_num_idle_cycles++;
       }
 
     };
 
 
   private:
+    /**
+     * @brief    Count the row buffer outcome of a request when its first command is issued
+     * @details
+     * A request that starts with its final command hits the open row, one that starts
+     * with an opening command (e.g., ACT) misses, and one that has to close a row first
+     * (e.g., PRE) conflicts.
+     */
+    void update_request_stats(ReqBuffer::iterator& req) {
+      if (req->is_stat_updated) return;
+      if (req->type_id != Request::Type::Read && req->type_id != Request::Type::Write) return;
+      req->is_stat_updated = true;
+      if (req->command == req->final_command) {
+        s_num_row_hits++;
+      } else if (m_dram->m_command_meta(req->command).is_opening) {
+        s_num_row_misses++;
+      } else if (m_dram->m_command_meta(req->command).is_closing) {
+        s_num_row_conflicts++;
+      }
+    };
+
     /**
      * @brief    Helper function to serve the completed read requests
      * @details
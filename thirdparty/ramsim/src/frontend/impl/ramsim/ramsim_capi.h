@@ -0,0 +1,46 @@
+#ifndef     RAMSIM_CAPI_H
+#define     RAMSIM_CAPI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+// C API of libramulator.so, used by the simulator to stream requests into RamSim
+// without writing a trace file. The frontend of the config is replaced by StreamGen,
+// which issues the requests in the same way as TraceGen.
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RamsimHandle RamsimHandle;
+
+// Returns NULL if the config can't be loaded, the error is printed to stderr.
+// params are KEY=VALUE overrides like the -p option of ramulator2.
+RamsimHandle* ramsim_create(const char* config_file, const char* const* params, size_t num_params);
+
+// Errors of ramsim_send, the request is dropped.
+#define RAMSIM_BAD_TYPE -1 // neither RAMSIM_READREQ nor RAMSIM_WRITEREQ
+#define RAMSIM_BAD_ID -2   // not the id after the previous request
+
+// Requests must have continuous ids starting from 0. Simulates until at most
+// max_queue requests are waiting to be issued. Returns 0 on success.
+int ramsim_send(RamsimHandle* handle, uint64_t id, uint64_t addr, uint32_t type, uint32_t delay,
+                uint32_t size, const uint64_t* deps, size_t num_deps);
+
+// The requests from id first_id on belong to phase.
+void ramsim_phase(RamsimHandle* handle, uint64_t first_id, uint64_t phase);
+
+// Simulates until all requests are done and returns what ramulator2 prints for a
+// trace file, free it with ramsim_free_string.
+char* ramsim_finish(RamsimHandle* handle);
+
+void ramsim_free_string(char* s);
+
+// Frees the frontend and memory system of handle, and handle.
+void ramsim_destroy(RamsimHandle* handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,203 @@
+#include <cstdlib>
+#include <cstring>
+#include <deque>
+#include <iostream>
+#include <memory>
+#include <sstream>
+
+#include "ramsim.h"
+#include "ramsim_capi.h"
+#include "base/config.h"
+#include "frontend/frontend.h"
+#include "memory_system/memory_system.h"
+
+namespace Ramulator {
+
+// Same as TraceGen, but the records are pushed through ramsim_send instead of being
+// read from a trace file.
+class StreamGen : public IFrontEnd, public Implementation {
+    RAMULATOR_REGISTER_IMPLEMENTATION(IFrontEnd, StreamGen, "StreamGen", "StreamGen")
+
+private:
+    bool closed = false;
+    bool reportedFinish = false;
+    uint64_t expectRecordId = 0;
+    uint64_t reqIdCnt = 0;
+    RamsimCallback m_callback;
+
+    RamSim* ramsim = nullptr;
+    std::deque<MemReq> pendReq;
+    std::deque<std::pair<uint64_t, uint64_t>> phaseStarts; // (first record id, phase id)
+
+    bool handleRequest(MemReq& req) {
+        return true;
+    };
+
+    void reportPhase(const MemReq& req) {
+        while (!phaseStarts.empty() && req.id >= phaseStarts.front().first) {
+            output += "Phase " + std::to_string(phaseStarts.front().second) + " issued in cycle " +
+                std::to_string(ramsim->get_cycles()) + "\n";
+            phaseStarts.pop_front();
+        }
+    }
+
+public:
+    std::string output; // what TraceGen prints to stdout
+
+    void init() override {
+        m_clock_ratio = param<uint>("clock_ratio").required();
+        m_callback = [this](MemReq& req) { return this->handleRequest(req); };
+        ramsim = dynamic_cast<RamSim*>(create_child_ifce<IFrontEnd>());
+    };
+
+    void setup(IFrontEnd* frontend, IMemorySystem* memory_system) override {
+        ramsim->setup(this, m_callback, memory_system);
+    }
+
+    // false if the id is not the next one, the record is dropped
+    bool push(MemReq record) {
+        if (record.id != expectRecordId) {
+            std::cerr << "ramsim_send: OpRecord id is not continuous, should be " << expectRecordId
+                      << " but " << record.id << " encountered." << std::endl;
+            return false;
+        }
+        expectRecordId++;
+        record.ids_0 = reqIdCnt;
+        record.ids.resize(ceil(record.size / 64.0));
+        for (size_t i = 0; i < record.ids.size(); i++) {
+            record.ids[i] = reqIdCnt;
+            reqIdCnt++;
+        }
+        pendReq.push_back(std::move(record));
+        return true;
+    }
+
+    void addPhase(uint64_t firstId, uint64_t phase) {
+        phaseStarts.push_back({firstId, phase});
+    }
+
+    void close() { closed = true; }
+
+    size_t numQueued() const { return pendReq.size(); }
+
+    void tick() override {
+        if (is_finished()) return;
+        ramsim->tick();
+        // like TraceGen, offer one record per cycle
+        if (!pendReq.empty() && ramsim->send(pendReq.front())) {
+            reportPhase(pendReq.front());
+            pendReq.pop_front();
+        }
+    };
+
+    bool is_finished() override {
+        bool finished = closed && pendReq.empty() && ramsim->is_finished();
+        if (finished && !reportedFinish) {
+            output += "TraceGen finished in cycle " + std::to_string(ramsim->get_cycles()) + "\n";
+            reportedFinish = true;
+        }
+        return finished;
+    };
+};
+
+} // namespace Ramulator
+
+using namespace Ramulator;
+
+struct RamsimHandle {
+    IFrontEnd* frontend = nullptr;
+    IMemorySystem* memory_system = nullptr;
+    StreamGen* stream;
+    size_t maxQueue;
+    int frontendTick;
+    int memTick;
+    uint64_t tick = 0;
+
+    ~RamsimHandle() {
+        delete frontend;
+        delete memory_system;
+    }
+
+    // one iteration of the loop in main.cpp
+    bool step() {
+        int tickMult = frontendTick * memTick;
+        if (((tick % tickMult) % memTick) == 0) {
+            frontend->tick();
+        }
+        if (frontend->is_finished()) {
+            return true;
+        }
+        if ((tick % tickMult) % frontendTick == 0) {
+            memory_system->tick();
+        }
+        tick++;
+        return false;
+    }
+};
+
+extern "C" {
+
+RamsimHandle* ramsim_create(const char* config_file, const char* const* params, size_t num_params) {
+    try {
+        std::vector<std::string> overrides(params, params + num_params);
+        overrides.push_back("Frontend.impl=StreamGen");
+        YAML::Node config = Config::parse_config_file(config_file, overrides);
+        std::unique_ptr<RamsimHandle> handle(new RamsimHandle());
+        handle->frontend = Factory::create_frontend(config);
+        handle->memory_system = Factory::create_memory_system(config);
+        handle->stream = dynamic_cast<StreamGen*>(handle->frontend);
+        handle->frontend->connect_memory_system(handle->memory_system);
+        handle->memory_system->connect_frontend(handle->frontend);
+        handle->frontendTick = handle->frontend->get_clock_ratio();
+        handle->memTick = handle->memory_system->get_clock_ratio();
+        handle->maxQueue = 4096;
+        return handle.release();
+    } catch (const std::exception& e) {
+        std::cerr << "ramsim_create: " << e.what() << std::endl;
+        return nullptr;
+    }
+}
+
+int ramsim_send(RamsimHandle* handle, uint64_t id, uint64_t addr, uint32_t type, uint32_t delay,
+                uint32_t size, const uint64_t* deps, size_t num_deps) {
+    if (type != RAMSIM_READREQ && type != RAMSIM_WRITEREQ) return RAMSIM_BAD_TYPE;
+    MemReq req;
+    req.id = id;
+    req.addr = addr;
+    req.type = type;
+    req.delay = delay;
+    req.size = size;
+    req.deps.assign(deps, deps + num_deps);
+    if (!handle->stream->push(std::move(req))) return RAMSIM_BAD_ID;
+    while (handle->stream->numQueued() > handle->maxQueue) {
+        handle->step();
+    }
+    return 0;
+}
+
+void ramsim_phase(RamsimHandle* handle, uint64_t first_id, uint64_t phase) {
+    handle->stream->addPhase(first_id, phase);
+}
+
+char* ramsim_finish(RamsimHandle* handle) {
+    handle->stream->close();
+    while (!handle->step()) {}
+
+    // the stats are printed to std::cout by finalize
+    std::ostringstream stats;
+    auto old = std::cout.rdbuf(stats.rdbuf());
+    handle->frontend->finalize();
+    handle->memory_system->finalize();
+    std::cout.rdbuf(old);
+    return strdup((handle->stream->output + stats.str()).c_str());
+}
+
+void ramsim_free_string(char* s) {
+    free(s);
+}
+
+void ramsim_destroy(RamsimHandle* handle) {
+    delete handle;
+}
+
+}
@@ -1,56 +1,97 @@
 #include <unordered_set>
 #include <type_traits>
 #include <fstream>
+#include <deque>
 
 #include "ramsim.h"
 #include "frontend/frontend.h"
 
 namespace Ramulator {
 
+// Version 2 traces start with "UZKTRACE", followed by version, header length, record
+// count, index offset and metadata length (8 bytes each) and the metadata. The records
+// end at the index offset if it is not 0. Version 1 traces only have "BINFILE".
+// The trace may be a pipe, e.g. /dev/stdin, so it is only read forward.
 class TraceInputStream {
     private:
+        static constexpr uint64_t unknownCount = UINT64_MAX;
+
         std::ifstream file;
         uint64_t expectRecordId;
         uint64_t reqIdCnt;
+        uint64_t version = 0;
+        uint64_t numRecords = unknownCount;
+        uint64_t recordsEnd = 0;
+        uint64_t offset = 0; // bytes read, a pipe can't tell
+
+        void read(void* dst, size_t len) {
+            file.read(reinterpret_cast<char *>(dst), len);
+            offset += len;
+        }
 
     public:
         TraceInputStream(const std::string& traceFileName)
             : file(traceFileName.c_str(), std::ios::in | std::ios::binary) {
-            reset();
+            if (is_open()) reset();
         }
 
         bool is_open() const { return file.is_open(); }
 
-        bool checkMagic() {
-            const uint32_t size = 8;
+        uint64_t readField() {
+            uint64_t value = 0;
+            read(&value, 8);
+            return value;
+        }
+
+        bool readHeader() {
             char magic[8];
-            file.read(reinterpret_cast<char *>(&magic), size);
-            return strcmp(magic, "BINFILE") == 0;
+            read(magic, 8);
+            if (!file) return false;
+            if (memcmp(magic, "BINFILE\0", 8) == 0) {
+                version = 1;
+                return true;
+            }
+            if (memcmp(magic, "UZKTRACE", 8) != 0) return false;
+            version = readField();
+            if (version != 2) return false;
+            uint64_t headerLen = readField();
+            numRecords = readField();
+            recordsEnd = readField();
+            if (headerLen < offset) return false;
+            file.ignore(headerLen - offset);
+            offset = headerLen;
+            return bool(file);
         }
 
         void reset() {
             file.clear();
-            file.seekg(0);
-            bool correctMagic = checkMagic();
-            assert(correctMagic);
+            if (offset != 0) file.seekg(0);
+            offset = 0;
+            if (!readHeader())
+                panic("Trace file has an invalid header or unsupported version %ld.", version);
             expectRecordId = 0;
             reqIdCnt = 0;
         }
 
         bool parseRecord(MemReq* record, bool* eof) {
-            *eof = file.peek() == EOF;
-            if (*eof) return false;
+            *eof = file.peek() == EOF || (recordsEnd != 0 && offset >= recordsEnd);
+            if (*eof) {
+                if (numRecords != unknownCount && expectRecordId != numRecords)
+                    panic("Trace file has 0x%lx records, but the header says 0x%lx.",
+                            expectRecordId, numRecords);
+                return false;
+            }
             const uint32_t size = 8;
-            file.read(reinterpret_cast<char *>(&record->id), size);
-            file.read(reinterpret_cast<char *>(&record->addr), size);
-            file.read(reinterpret_cast<char *>(&record->type), size);
-            file.read(reinterpret_cast<char *>(&record->delay), size);
-            file.read(reinterpret_cast<char *>(&record->size), size);
+            read(&record->id, size);
+            read(&record->addr, size);
+            read(&record->type, size);
+            read(&record->delay, size);
+            read(&record->size, size);
             uint64_t depCount;
-            file.read(reinterpret_cast<char *>(&depCount), size);
+            read(&depCount, size);
             record->deps.resize(depCount);
             for (size_t i = 0; i < depCount; i++) {
-                file.read(reinterpret_cast<char *>(&record->deps[i]), size);
+                read(&record->deps[i], size);
             }
             record->ids_0 = reqIdCnt;
             record->ids.resize(ceil(record->size / 64.0));
@@ -93,6 +134,8 @@ private:
     // parameter
     uint32_t maxPendEntry = 0;
     std::string traceFileName;
+    std::string phaseFileName;
+    std::deque<std::pair<uint64_t, uint64_t>> phaseStarts; // (first record id, phase id)
 
     bool handleRequest(MemReq& req) {
         // do nothing for a trace generator
@@ -126,8 +169,25 @@ public:
         if (!traceInput->is_open())
             panic("Trace file %s failed to open.", traceFileName.c_str());
         ramsim = dynamic_cast<RamSim*>(create_child_ifce<IFrontEnd>());
+        // optional phase file written by the simulator, one "first_record_id phase_id" per line
+        phaseFileName = param<std::string>("phaseFileName").desc("phaseFileName").default_val("");
+        if (!phaseFileName.empty()) {
+            std::ifstream phaseFile(phaseFileName);
+            if (!phaseFile.is_open())
+                panic("Phase file %s failed to open.", phaseFileName.c_str());
+            uint64_t id, phase;
+            while (phaseFile >> id >> phase)
+                phaseStarts.push_back({id, phase});
+        }
     };
 
+    void reportPhase(const MemReq& req) {
+        while (!phaseStarts.empty() && req.id >= phaseStarts.front().first) {
+            printf("Phase %ld issued in cycle %ld\n", phaseStarts.front().second, ramsim->get_cycles());
+            phaseStarts.pop_front();
+        }
+    }
+
     void setup(IFrontEnd* frontend, IMemorySystem* memory_system) override {
         ramsim->setup(this, m_callback, memory_system);
     }
@@ -137,6 +197,7 @@ public:
         ramsim->tick();
         for (auto it = pendReq.begin() ; it < pendReq.end(); it++) {
             if (ramsim->send(*it)) {
+                reportPhase(*it);
                 pendReq.erase(it);
             }
         }